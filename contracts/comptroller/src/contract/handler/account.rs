@@ -0,0 +1,84 @@
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage,
+};
+
+use crate::contract::liquidity::{account_liquidity_in, query_account_snapshot};
+use crate::state::{get_account_markets, get_market, set_account_markets};
+
+pub fn try_enter_markets<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    markets: Vec<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let account_raw = deps.api.canonical_address(&env.message.sender)?;
+    let mut entered = get_account_markets(&deps.storage, &account_raw)?;
+    for market in &markets {
+        let market_raw = deps.api.canonical_address(market)?;
+        if get_market(&deps.storage, &market_raw)?.is_none() {
+            return Err(StdError::generic_err(format!(
+                "Market is not listed: market: {}",
+                market
+            )));
+        }
+        if !entered.contains(&market_raw) {
+            entered.push(market_raw);
+        }
+    }
+    set_account_markets(&mut deps.storage, &account_raw, &entered)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "enter_markets"),
+            log("sender", env.message.sender.as_str()),
+            log("entered_markets", entered.len()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_exit_market<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    market: HumanAddr,
+) -> StdResult<HandleResponse> {
+    let account_raw = deps.api.canonical_address(&env.message.sender)?;
+    let market_raw = deps.api.canonical_address(&market)?;
+    let mut entered = get_account_markets(&deps.storage, &account_raw)?;
+    if !entered.contains(&market_raw) {
+        return Err(StdError::generic_err(format!(
+            "Market was not entered: market: {}",
+            market
+        )));
+    }
+
+    let snapshot = query_account_snapshot(&deps.querier, market.clone(), env.message.sender.clone())?;
+    if !snapshot.borrow_balance.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Cannot exit a market with an outstanding borrow: borrow_balance: {}",
+            snapshot.borrow_balance
+        )));
+    }
+
+    entered.retain(|entered_market| *entered_market != market_raw);
+    let (_, shortfall) = account_liquidity_in(deps, &env.message.sender, &entered)?;
+    if !shortfall.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Exiting the market leaves a shortfall: shortfall: {}",
+            shortfall
+        )));
+    }
+    set_account_markets(&mut deps.storage, &account_raw, &entered)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "exit_market"),
+            log("sender", env.message.sender.as_str()),
+            log("market", market.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
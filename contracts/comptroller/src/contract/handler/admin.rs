@@ -0,0 +1,394 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage,
+    Uint128,
+};
+
+use quasar_interfaces::market::MarketHandleMsg;
+
+use crate::msg::LpPoolMsg;
+use crate::state::{
+    get_collateral_factor_change, get_config, get_global_pause, get_market, get_markets, get_pair_ceiling,
+    remove_pair_ceiling, set_collateral_factor_change, set_config, set_global_pause, set_market, set_pair_ceiling,
+    CollateralFactorChange, GlobalPause, LpPool, Market, PairCeiling, MAX_MIN_BORROW_HEALTH_FACTOR,
+};
+
+pub fn try_register_market<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    market: HumanAddr,
+    symbol: String,
+    collateral_factor: Uint128,
+    borrow_cap: Option<Uint128>,
+    lp_pool: Option<LpPoolMsg>,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    validate_collateral_factor(collateral_factor)?;
+
+    let market_raw = deps.api.canonical_address(&market)?;
+    let lp_pool = match lp_pool {
+        Some(lp_pool) => Some(LpPool {
+            pair: deps.api.canonical_address(&lp_pool.pair)?,
+            symbols: lp_pool.symbols,
+        }),
+        None => None,
+    };
+    if get_market(&deps.storage, &market_raw)?.is_some() {
+        return Err(StdError::generic_err(format!(
+            "Market is already listed: market: {}",
+            market
+        )));
+    }
+    set_market(&mut deps.storage, &Market {
+        address: market_raw,
+        symbol: symbol.clone(),
+        collateral_factor,
+        borrow_cap,
+        lp_pool,
+    })?;
+
+    // A market listed during an incident starts out paused like the others
+    let pause = get_global_pause(&deps.storage)?;
+    let mut messages = vec![];
+    if pause.epoch > 0 {
+        messages.push(MarketHandleMsg::SyncGlobalPause {
+            paused: pause.paused,
+            epoch: pause.epoch,
+        }.into_cosmos_msg(market.clone())?);
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "register_market"),
+            log("sender", env.message.sender.as_str()),
+            log("market", market.as_str()),
+            log("symbol", symbol),
+            log("collateral_factor", collateral_factor),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_update_market<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    market: HumanAddr,
+    collateral_factor: Uint128,
+    borrow_cap: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    validate_collateral_factor(collateral_factor)?;
+
+    let market_raw = deps.api.canonical_address(&market)?;
+    let mut listed = match get_market(&deps.storage, &market_raw)? {
+        Some(listed) => listed,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Market is not listed: market: {}",
+                market
+            )));
+        }
+    };
+    // Accounts get the grace period to react to a cut before it counts towards liquidations,
+    // the factor before the first of overlapping cuts stays in force until the last one's ends
+    let grace = get_config(&deps.storage)?.collateral_factor_grace;
+    let active = get_collateral_factor_change(&deps.storage, &market_raw)?
+        .filter(|change| env.block.height < change.effective_at);
+    let change = match collateral_factor < listed.collateral_factor && grace > 0 {
+        true => Some(CollateralFactorChange {
+            previous_collateral_factor: active.map_or(listed.collateral_factor, |change| {
+                change.previous_collateral_factor.max(listed.collateral_factor)
+            }),
+            effective_at: env.block.height + grace,
+        }),
+        // Raising the factor back to where it was ends the grace period early
+        false => active.filter(|change| collateral_factor < change.previous_collateral_factor),
+    };
+    set_collateral_factor_change(&mut deps.storage, &market_raw, change.as_ref())?;
+    listed.collateral_factor = collateral_factor;
+    listed.borrow_cap = borrow_cap;
+    set_market(&mut deps.storage, &listed)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "update_market"),
+            log("sender", env.message.sender.as_str()),
+            log("market", market.as_str()),
+            log("collateral_factor", collateral_factor),
+        ],
+        data: None,
+    };
+    if let Some(change) = change {
+        res.log.push(log("previous_collateral_factor", change.previous_collateral_factor));
+        res.log.push(log("effective_at", change.effective_at));
+    }
+    Ok(res)
+}
+
+/// Applies to cuts made from now on, cuts already in their grace period keep their end
+pub fn try_set_collateral_factor_grace<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    blocks: u64,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    let mut config = get_config(&deps.storage)?;
+    config.collateral_factor_grace = blocks;
+    set_config(&mut deps.storage, &config)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_collateral_factor_grace"),
+            log("sender", env.message.sender.as_str()),
+            log("collateral_factor_grace", blocks),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// None lifts the ceiling, debt is counted from when it is set as accounts are touched
+pub fn try_set_pair_ceiling<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    collateral: HumanAddr,
+    borrow: HumanAddr,
+    ceiling: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    if collateral == borrow {
+        return Err(StdError::generic_err("Collateral and borrow market must differ"));
+    }
+    let collateral_raw = deps.api.canonical_address(&collateral)?;
+    let borrow_raw = deps.api.canonical_address(&borrow)?;
+    for (market, market_raw) in &[(&collateral, &collateral_raw), (&borrow, &borrow_raw)] {
+        if get_market(&deps.storage, market_raw)?.is_none() {
+            return Err(StdError::generic_err(format!(
+                "Market is not listed: market: {}",
+                market
+            )));
+        }
+    }
+
+    let total_debt = match ceiling {
+        Some(ceiling) => {
+            let total_debt = get_pair_ceiling(&deps.storage, &collateral_raw, &borrow_raw)?
+                .map(|pair| pair.total_debt)
+                .unwrap_or_default();
+            set_pair_ceiling(&mut deps.storage, &PairCeiling {
+                collateral: collateral_raw,
+                borrow: borrow_raw,
+                ceiling,
+                total_debt,
+            })?;
+            total_debt
+        }
+        None => {
+            remove_pair_ceiling(&mut deps.storage, &collateral_raw, &borrow_raw);
+            Uint128::zero()
+        }
+    };
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_pair_ceiling"),
+            log("sender", env.message.sender.as_str()),
+            log("collateral", collateral.as_str()),
+            log("borrow", borrow.as_str()),
+            log("ceiling", ceiling.map(|ceiling| ceiling.to_string()).unwrap_or_default()),
+            log("total_debt", total_debt),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_set_min_borrow_health_factor<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    min_borrow_health_factor: Option<Decimal256>,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    let mut config = get_config(&deps.storage)?;
+    config.min_borrow_health_factor = validate_min_borrow_health_factor(min_borrow_health_factor)?;
+    set_config(&mut deps.storage, &config)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_min_borrow_health_factor"),
+            log("sender", env.message.sender.as_str()),
+            log(
+                "min_borrow_health_factor",
+                config.min_borrow_health_factor.map(|factor| factor.to_string()).unwrap_or_default(),
+            ),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Must be within [1, MAX_MIN_BORROW_HEALTH_FACTOR], a factor of 1 is stored as None
+pub fn validate_min_borrow_health_factor(
+    min_borrow_health_factor: Option<Decimal256>,
+) -> StdResult<Option<Decimal256>> {
+    let factor = match min_borrow_health_factor {
+        Some(factor) => factor,
+        None => return Ok(None),
+    };
+    let max = Decimal256::from_uint256(Uint256::from(MAX_MIN_BORROW_HEALTH_FACTOR));
+    if factor < Decimal256::one() || factor > max {
+        return Err(StdError::generic_err(format!(
+            "Minimum borrow health factor must be within [1, {}]: min_borrow_health_factor: {}",
+            max, factor
+        )));
+    }
+    Ok(if factor == Decimal256::one() { None } else { Some(factor) })
+}
+
+pub fn try_set_global_pause<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    paused: bool,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let sender_raw = deps.api.canonical_address(&env.message.sender)?;
+    let is_guardian = config.guardian.as_ref() == Some(&sender_raw);
+    if sender_raw != config.admin && !(is_guardian && paused) {
+        return Err(StdError::unauthorized());
+    }
+
+    let pause = GlobalPause {
+        paused,
+        epoch: get_global_pause(&deps.storage)?.epoch + 1,
+    };
+    set_global_pause(&mut deps.storage, &pause)?;
+
+    let messages = get_markets(&deps.storage)?
+        .iter()
+        .map(|market| {
+            MarketHandleMsg::SyncGlobalPause {
+                paused: pause.paused,
+                epoch: pause.epoch,
+            }.into_cosmos_msg(deps.api.human_address(&market.address)?)
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "set_global_pause"),
+            log("sender", env.message.sender.as_str()),
+            log("paused", paused),
+            log("epoch", pause.epoch),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn assert_admin<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, env: &Env) -> StdResult<()> {
+    let config = get_config(&deps.storage)?;
+    if deps.api.canonical_address(&env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    Ok(())
+}
+
+fn validate_collateral_factor(collateral_factor: Uint128) -> StdResult<()> {
+    if collateral_factor.u128() > 100_000_000 {
+        return Err(StdError::generic_err(format!(
+            "Collateral factor must not exceed 1: collateral_factor: {}",
+            collateral_factor
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::from_binary;
+
+    use crate::contract::testing::{list_market, mock_comptroller, mock_init_msg};
+    use crate::contract::{handle, query};
+    use crate::msg::{AccountLiquidityResponse, HandleMsg, LiquidationShortfallResponse, QueryMsg};
+
+    fn update_at(collateral_factor: u128, height: u64) -> (Env, HandleMsg) {
+        let mut env = mock_env("admin", &[]);
+        env.block.height = height;
+        let msg = HandleMsg::UpdateMarket {
+            market: HumanAddr::from("usd_market"),
+            collateral_factor: Uint128::from(collateral_factor),
+            borrow_cap: None,
+        };
+        (env, msg)
+    }
+
+    fn liquidation_shortfall(deps: &Extern<impl Storage, impl Api, impl Querier>, block: u64) -> Uint256 {
+        let msg = QueryMsg::LiquidationShortfall { address: HumanAddr::from("alice"), block };
+        from_binary::<LiquidationShortfallResponse>(&query(deps, msg).unwrap()).unwrap().shortfall
+    }
+
+    fn change(deps: &Extern<impl Storage, impl Api, impl Querier>) -> Option<CollateralFactorChange> {
+        get_collateral_factor_change(&deps.storage, &deps.api.canonical_address(&HumanAddr::from("usd_market")).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn collateral_factor_cuts_reach_liquidations_after_the_grace_period() {
+        let mut msg = mock_init_msg();
+        msg.collateral_factor_grace = Some(100);
+        let mut deps = mock_comptroller(msg);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::one(), 80_000_000);
+        handle(&mut deps, mock_env("alice", &[]), HandleMsg::EnterMarkets {
+            markets: vec![HumanAddr::from("usd_market"), HumanAddr::from("luna_market")],
+        })
+        .unwrap();
+        deps.querier.set_position("usd_market", "alice", 1_000, 0);
+        deps.querier.set_position("luna_market", "alice", 0, 750);
+
+        let (env, update) = update_at(70_000_000, 20_000);
+        let res = handle(&mut deps, env, update).unwrap();
+        assert!(res.log.contains(&log("effective_at", 20_100)));
+        // New borrows and withdrawals already see the cut, liquidations only once it is effective
+        let liquidity: AccountLiquidityResponse =
+            from_binary(&query(&deps, QueryMsg::AccountLiquidity { address: HumanAddr::from("alice") }).unwrap()).unwrap();
+        assert_eq!(liquidity.shortfall, Uint256::from(50u64));
+        assert_eq!(liquidation_shortfall(&deps, 20_099), Uint256::zero());
+        assert_eq!(liquidation_shortfall(&deps, 20_100), Uint256::from(50u64));
+
+        // A price drop still makes the account liquidatable during the grace period
+        deps.querier.set_price("oracle", "UST", Decimal256::percent(90));
+        assert_eq!(liquidation_shortfall(&deps, 20_050), Uint256::from(30u64));
+    }
+
+    #[test]
+    fn overlapping_cuts_keep_the_first_factor_until_the_last_grace_ends() {
+        let mut msg = mock_init_msg();
+        msg.collateral_factor_grace = Some(100);
+        let mut deps = mock_comptroller(msg);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+
+        let (env, update) = update_at(70_000_000, 20_000);
+        handle(&mut deps, env, update).unwrap();
+        let (env, update) = update_at(60_000_000, 20_010);
+        handle(&mut deps, env, update).unwrap();
+        assert_eq!(
+            change(&deps),
+            Some(CollateralFactorChange { previous_collateral_factor: Uint128::from(80_000_000u128), effective_at: 20_110 })
+        );
+
+        // Raising the factor back ends the grace period
+        let (env, update) = update_at(80_000_000, 20_020);
+        handle(&mut deps, env, update).unwrap();
+        assert_eq!(change(&deps), None);
+    }
+}
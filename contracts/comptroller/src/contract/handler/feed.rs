@@ -0,0 +1,206 @@
+use cosmwasm_bignumber::Decimal256;
+use cosmwasm_std::{
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage,
+};
+
+use crate::contract::liquidity::market_price_from;
+use crate::state::{
+    get_config, get_feed_price, get_market, get_market_freeze, set_config, set_feed_price, set_market_freeze,
+    FeedPrice, Market, MarketFreeze,
+};
+
+use crate::contract::handler::admin::assert_admin;
+
+/// The oracle gives no other signal for a deregistered feed than refusing the price query,
+/// so any refusal freezes the market until the admin resumes it
+pub fn try_check_feed<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    market: HumanAddr,
+) -> StdResult<HandleResponse> {
+    let listed = listed_market(deps, &market)?;
+    if get_market_freeze(&deps.storage, &listed.address)?.is_some() {
+        return Err(StdError::generic_err(format!("Market is already frozen: market: {}", market)));
+    }
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "check_feed"),
+            log("sender", env.message.sender.as_str()),
+            log("market", market.as_str()),
+        ],
+        data: None,
+    };
+    match oracle_price(deps, &listed) {
+        Ok(price) => {
+            set_feed_price(&mut deps.storage, &listed.address, &FeedPrice { price, block: env.block.height })?;
+            res.log.push(log("price", price));
+        }
+        Err(error) => {
+            let price = get_feed_price(&deps.storage, &listed.address)?.map(|feed| feed.price);
+            set_market_freeze(&mut deps.storage, &listed.address, Some(&MarketFreeze {
+                price,
+                frozen_at: env.block.height,
+            }))?;
+            res.log.push(log("frozen", true));
+            res.log.push(log("last_good_price", price.map(|price| price.to_string()).unwrap_or_default()));
+            res.log.push(log("feed_error", error));
+        }
+    }
+    Ok(res)
+}
+
+/// Admin only, the oracle must price the market again
+pub fn try_resume_market<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    market: HumanAddr,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    let listed = listed_market(deps, &market)?;
+    if get_market_freeze(&deps.storage, &listed.address)?.is_none() {
+        return Err(StdError::generic_err(format!("Market is not frozen: market: {}", market)));
+    }
+    let price = oracle_price(deps, &listed)?;
+    set_feed_price(&mut deps.storage, &listed.address, &FeedPrice { price, block: env.block.height })?;
+    set_market_freeze(&mut deps.storage, &listed.address, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "resume_market"),
+            log("sender", env.message.sender.as_str()),
+            log("market", market.as_str()),
+            log("price", price),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_set_freeze_haircut<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    freeze_haircut: Decimal256,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    let mut config = get_config(&deps.storage)?;
+    config.freeze_haircut = validate_freeze_haircut(freeze_haircut)?;
+    set_config(&mut deps.storage, &config)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_freeze_haircut"),
+            log("sender", env.message.sender.as_str()),
+            log("freeze_haircut", freeze_haircut),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Must be below 1 so frozen collateral keeps some value
+pub fn validate_freeze_haircut(freeze_haircut: Decimal256) -> StdResult<Decimal256> {
+    if freeze_haircut >= Decimal256::one() {
+        return Err(StdError::generic_err(format!(
+            "Freeze haircut must be below 1: freeze_haircut: {}",
+            freeze_haircut
+        )));
+    }
+    Ok(freeze_haircut)
+}
+
+fn listed_market<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, market: &HumanAddr) -> StdResult<Market> {
+    let market_raw: CanonicalAddr = deps.api.canonical_address(market)?;
+    match get_market(&deps.storage, &market_raw)? {
+        Some(listed) => Ok(listed),
+        None => Err(StdError::generic_err(format!("Market is not listed: market: {}", market))),
+    }
+}
+
+/// Price from the configured oracle, ignoring any freeze
+fn oracle_price<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, market: &Market) -> StdResult<Decimal256> {
+    let config = get_config(&deps.storage)?;
+    market_price_from(deps, &config.oracle, &config.oracle_kind, config.quote, market)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_bignumber::Uint256;
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::{from_binary, Uint128};
+
+    use quasar_interfaces::comptroller::MarketAction;
+
+    use crate::contract::testing::{list_market, mock_comptroller, mock_init_msg, MOCK_ORACLE};
+    use crate::contract::{handle, query};
+    use crate::msg::{AccountLiquidityResponse, HandleMsg, QueryMsg};
+
+    fn check_feed() -> HandleMsg {
+        HandleMsg::CheckFeed { market: HumanAddr::from("luna_market") }
+    }
+
+    fn borrow_hook() -> HandleMsg {
+        HandleMsg::MarketHook {
+            action: MarketAction::Borrow,
+            account: HumanAddr::from("bob"),
+            amount: Uint128::from(1u128),
+        }
+    }
+
+    fn liquidity(deps: &Extern<impl Storage, impl Api, impl Querier>) -> AccountLiquidityResponse {
+        from_binary(&query(deps, QueryMsg::AccountLiquidity { address: HumanAddr::from("alice") }).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn removed_feed_freezes_borrows_and_haircuts_collateral() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        handle(&mut deps, mock_env("alice", &[]), HandleMsg::EnterMarkets {
+            markets: vec![HumanAddr::from("luna_market"), HumanAddr::from("usd_market")],
+        })
+        .unwrap();
+        // 500 LUNA of borrow limit at 2 against 950 of debt
+        deps.querier.set_position("luna_market", "alice", 1_000, 0);
+        deps.querier.set_position("usd_market", "alice", 0, 950);
+        handle(&mut deps, mock_env("keeper", &[]), check_feed()).unwrap();
+        assert_eq!(liquidity(&deps).liquidity, Uint256::from(50u64));
+
+        deps.querier.prices.remove(&(HumanAddr::from(MOCK_ORACLE), "LUNA".to_string()));
+        let res = handle(&mut deps, mock_env("keeper", &[]), check_feed()).unwrap();
+        assert!(res.log.contains(&log("frozen", true)));
+        // Valued at the last good price less 10%, 1.8 * 500 = 900
+        assert_eq!(liquidity(&deps).shortfall, Uint256::from(50u64));
+        assert_eq!(
+            handle(&mut deps, mock_env("luna_market", &[]), borrow_hook()).unwrap_err(),
+            StdError::generic_err("Market is frozen until the admin resumes it: market: luna_market")
+        );
+        assert!(handle(&mut deps, mock_env("keeper", &[]), check_feed()).is_err());
+    }
+
+    #[test]
+    fn only_the_admin_resumes_a_market_the_oracle_prices_again() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        deps.querier.prices.clear();
+        handle(&mut deps, mock_env("keeper", &[]), check_feed()).unwrap();
+        // Frozen before any price was read, the market cannot be valued
+        let freeze = get_market_freeze(&deps.storage, &deps.api.canonical_address(&HumanAddr::from("luna_market")).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(freeze.price, None);
+
+        let resume = HandleMsg::ResumeMarket { market: HumanAddr::from("luna_market") };
+        assert_eq!(handle(&mut deps, mock_env("keeper", &[]), resume.clone()).unwrap_err(), StdError::unauthorized());
+        assert!(handle(&mut deps, mock_env("admin", &[]), resume.clone()).is_err());
+        deps.querier.set_price(MOCK_ORACLE, "LUNA", Decimal256::percent(150));
+        let res = handle(&mut deps, mock_env("admin", &[]), resume).unwrap();
+        assert!(res.log.contains(&log("price", Decimal256::percent(150))));
+        deps.querier.set_position("luna_market", "bob", 1_000, 1);
+        handle(&mut deps, mock_env("luna_market", &[]), borrow_hook()).unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+use cosmwasm_bignumber::Uint256;
+use cosmwasm_std::{
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult,
+    Storage, Uint128,
+};
+
+use quasar_interfaces::comptroller::MarketAction;
+use quasar_interfaces::market::MarketHandleMsg;
+
+use crate::contract::liquidity::{
+    account_liquidity, account_position, collateral_price, market_positions, market_price, query_account_snapshot,
+};
+use crate::state::{
+    get_account_markets, get_account_pair_debt, get_config, get_market, get_market_freeze, get_pair_ceilings, set_account_markets,
+    set_account_pair_debt, set_pair_ceiling, PairCeiling,
+};
+
+/// The market has already applied the operation when the hook runs, so every check
+/// is against the resulting positions and a failure reverts the whole tx
+pub fn try_market_hook<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    action: MarketAction,
+    account: HumanAddr,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let market_raw = deps.api.canonical_address(&env.message.sender)?;
+    let market = match get_market(&deps.storage, &market_raw)? {
+        Some(market) => market,
+        None => return Err(StdError::unauthorized()),
+    };
+
+    let account_raw = deps.api.canonical_address(&account)?;
+    let mut entered = get_account_markets(&deps.storage, &account_raw)?;
+    match action {
+        // A seized account is underwater by definition, its positions are only recounted
+        MarketAction::Mint | MarketAction::RepayBorrow | MarketAction::Seize => {}
+        MarketAction::Redeem | MarketAction::Transfer => {
+            if entered.contains(&market_raw) {
+                assert_no_shortfall(deps, &account)?;
+            }
+        }
+        MarketAction::Borrow => {
+            if get_market_freeze(&deps.storage, &market_raw)?.is_some() {
+                return Err(StdError::generic_err(format!(
+                    "Market is frozen until the admin resumes it: market: {}",
+                    env.message.sender
+                )));
+            }
+            // Borrowing enters the market so the debt is always counted
+            if !entered.contains(&market_raw) {
+                entered.push(market_raw.clone());
+                set_account_markets(&mut deps.storage, &account_raw, &entered)?;
+            }
+            if let Some(borrow_cap) = market.borrow_cap {
+                let snapshot = query_account_snapshot(&deps.querier, env.message.sender.clone(), account.clone())?;
+                if snapshot.total_borrows > borrow_cap {
+                    return Err(StdError::generic_err(format!(
+                        "Market borrow cap reached: total_borrows: {}, borrow_cap: {}",
+                        snapshot.total_borrows, borrow_cap
+                    )));
+                }
+            }
+            assert_borrow_health(deps, &account)?;
+        }
+    }
+
+    // Only a borrow may push a pair over its ceiling, repaying or moving
+    // collateral merely recounts the account
+    let exceeded = update_pair_debts(deps, &account, &account_raw, &entered)?;
+    if let MarketAction::Borrow = action {
+        if let Some(pair) = exceeded.iter().find(|pair| pair.borrow == market_raw) {
+            return Err(StdError::generic_err(format!(
+                "Pair borrow ceiling reached: collateral: {}, total_debt: {}, ceiling: {}",
+                deps.api.human_address(&pair.collateral)?,
+                pair.total_debt,
+                pair.ceiling
+            )));
+        }
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "market_hook"),
+            log("market", env.message.sender.as_str()),
+            log("market_action", format!("{:?}", action)),
+            log("account", account.as_str()),
+            log("amount", amount),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// The repaid value is converted to the collateral market's underlying at oracle prices,
+/// that market adds its own liquidation incentive and protocol share when seizing
+pub fn try_seize_collateral<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    collateral_market: HumanAddr,
+    borrower: HumanAddr,
+    liquidator: HumanAddr,
+    repay_amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let market_raw = deps.api.canonical_address(&env.message.sender)?;
+    let repay_market = match get_market(&deps.storage, &market_raw)? {
+        Some(market) => market,
+        None => return Err(StdError::unauthorized()),
+    };
+    let collateral_raw = deps.api.canonical_address(&collateral_market)?;
+    let collateral = match get_market(&deps.storage, &collateral_raw)? {
+        Some(market) => market,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Collateral market is not listed: collateral_market: {}",
+                collateral_market
+            )));
+        }
+    };
+    if collateral_raw == market_raw {
+        return Err(StdError::generic_err("Collateral in the repaid market is seized by the market itself"));
+    }
+    // Only qTokens counted as collateral may be seized
+    let borrower_raw = deps.api.canonical_address(&borrower)?;
+    if !get_account_markets(&deps.storage, &borrower_raw)?.contains(&collateral_raw) {
+        return Err(StdError::generic_err(format!(
+            "Borrower has not entered the collateral market: collateral_market: {}",
+            collateral_market
+        )));
+    }
+
+    let repay_price = market_price(deps, &repay_market)?;
+    // Frozen collateral is seized at its last good price less the haircut
+    let collateral_price = collateral_price(deps, &collateral, market_price(deps, &collateral)?)?;
+    if collateral_price.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Collateral market has no price: collateral_market: {}",
+            collateral_market
+        )));
+    }
+    let repay_value: Uint128 = (Uint256::from(repay_amount) * (repay_price / collateral_price)).into();
+
+    let seize = MarketHandleMsg::SeizeCollateral {
+        borrower: borrower.clone(),
+        liquidator: liquidator.clone(),
+        repay_value,
+    };
+    let res = HandleResponse {
+        messages: vec![seize.into_cosmos_msg(collateral_market.clone())?],
+        log: vec![
+            log("action", "seize_collateral"),
+            log("market", env.message.sender.as_str()),
+            log("collateral_market", collateral_market.as_str()),
+            log("borrower", borrower.as_str()),
+            log("liquidator", liquidator.as_str()),
+            log("repay_amount", repay_amount),
+            log("repay_value", repay_value),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+fn assert_no_shortfall<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, account: &HumanAddr) -> StdResult<()> {
+    let (_, shortfall) = account_liquidity(deps, account)?;
+    if !shortfall.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Account has insufficient collateral: shortfall: {}",
+            shortfall
+        )));
+    }
+    Ok(())
+}
+
+/// A borrow must leave the account at the minimum borrow health factor, which sits
+/// above the liquidation threshold so a borrow to the limit is not liquidatable at once
+fn assert_borrow_health<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, account: &HumanAddr) -> StdResult<()> {
+    let min_health_factor = match get_config(&deps.storage)?.min_borrow_health_factor {
+        Some(min_health_factor) => min_health_factor,
+        None => return assert_no_shortfall(deps, account),
+    };
+    let markets = get_account_markets(&deps.storage, &deps.api.canonical_address(account)?)?;
+    let position = account_position(deps, account, &markets, |market| market_price(deps, market))?;
+    if position.collateral_value < position.borrow_value * min_health_factor {
+        return Err(StdError::generic_err(format!(
+            "Borrow leaves the account below the minimum health factor: collateral_value: {}, borrow_value: {}, min_borrow_health_factor: {}",
+            position.collateral_value, position.borrow_value, min_health_factor
+        )));
+    }
+    Ok(())
+}
+
+/// Recounts the account's debt against every pair ceiling and returns the pairs
+/// the account added debt to while they are over their ceiling
+fn update_pair_debts<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    account: &HumanAddr,
+    account_raw: &CanonicalAddr,
+    entered: &[CanonicalAddr],
+) -> StdResult<Vec<PairCeiling>> {
+    let pairs = get_pair_ceilings(&deps.storage)?;
+    if pairs.is_empty() {
+        return Ok(vec![]);
+    }
+    let positions = market_positions(deps, account, entered, |market| market_price(deps, market))?;
+    let total_collateral = positions
+        .iter()
+        .fold(Uint256::zero(), |total, position| total + position.collateral_value);
+
+    let mut exceeded = vec![];
+    for mut pair in pairs {
+        let collateral = positions.iter().find(|position| position.market.address == pair.collateral);
+        let borrow = positions.iter().find(|position| position.market.address == pair.borrow);
+        let debt: u128 = match (collateral, borrow) {
+            (Some(collateral), Some(borrow)) if !total_collateral.is_zero() => Uint256::from(borrow.borrow_balance)
+                .multiply_ratio(collateral.collateral_value.0, total_collateral.0)
+                .into(),
+            _ => 0,
+        };
+        let previous = get_account_pair_debt(&deps.storage, account_raw, &pair.collateral, &pair.borrow)?.u128();
+        if debt == previous {
+            continue;
+        }
+        // Saturating as debt attributed before the pair was last capped is not in the total
+        pair.total_debt = Uint128::from(pair.total_debt.u128().saturating_sub(previous) + debt);
+        set_pair_ceiling(&mut deps.storage, &pair)?;
+        set_account_pair_debt(&mut deps.storage, account_raw, &pair.collateral, &pair.borrow, Uint128::from(debt))?;
+        if debt > previous && pair.total_debt > pair.ceiling {
+            exceeded.push(pair);
+        }
+    }
+    Ok(exceeded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_bignumber::Decimal256;
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::{from_binary, CosmosMsg, WasmMsg};
+
+    use crate::contract::handle;
+    use crate::contract::testing::{list_market, mock_comptroller, mock_init_msg};
+    use crate::msg::HandleMsg;
+    use crate::state::get_pair_ceiling;
+
+    fn hook(action: MarketAction, amount: u128) -> HandleMsg {
+        HandleMsg::MarketHook {
+            action,
+            account: HumanAddr::from("alice"),
+            amount: Uint128::from(amount),
+        }
+    }
+
+    fn enter(deps: &mut Extern<impl Storage, impl Api, impl Querier>, markets: &[&str]) {
+        let markets = markets.iter().map(|market| HumanAddr::from(*market)).collect();
+        handle(deps, mock_env("alice", &[]), HandleMsg::EnterMarkets { markets }).unwrap();
+    }
+
+    fn seize(collateral_market: &str, repay_amount: u128) -> HandleMsg {
+        HandleMsg::SeizeCollateral {
+            collateral_market: HumanAddr::from(collateral_market),
+            borrower: HumanAddr::from("alice"),
+            liquidator: HumanAddr::from("bob"),
+            repay_amount: Uint128::from(repay_amount),
+        }
+    }
+
+    #[test]
+    fn redeems_and_transfers_into_shortfall_are_rejected() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        enter(&mut deps, &["luna_market", "usd_market"]);
+        deps.querier.set_position("usd_market", "alice", 0, 500);
+
+        // The market already applied the redemption, 500 LUNA at 2 back exactly the debt
+        deps.querier.set_position("luna_market", "alice", 500, 0);
+        handle(&mut deps, mock_env("luna_market", &[]), hook(MarketAction::Redeem, 500)).unwrap();
+        deps.querier.set_position("luna_market", "alice", 499, 0);
+        for action in &[MarketAction::Redeem, MarketAction::Transfer] {
+            assert_eq!(
+                handle(&mut deps, mock_env("luna_market", &[]), hook(*action, 1)).unwrap_err(),
+                StdError::generic_err("Account has insufficient collateral: shortfall: 2")
+            );
+        }
+        // Neither minting nor repaying makes the account worse off
+        handle(&mut deps, mock_env("luna_market", &[]), hook(MarketAction::Mint, 1)).unwrap();
+        handle(&mut deps, mock_env("usd_market", &[]), hook(MarketAction::RepayBorrow, 1)).unwrap();
+    }
+
+    #[test]
+    fn supply_outside_the_entered_markets_moves_freely() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        enter(&mut deps, &["usd_market"]);
+        deps.querier.set_position("usd_market", "alice", 0, 500);
+        handle(&mut deps, mock_env("luna_market", &[]), hook(MarketAction::Transfer, 1_000)).unwrap();
+    }
+
+    #[test]
+    fn borrowing_enters_the_market() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        enter(&mut deps, &["luna_market"]);
+        deps.querier.set_position("luna_market", "alice", 1_000, 0);
+        deps.querier.set_position("usd_market", "alice", 0, 1_000);
+        handle(&mut deps, mock_env("usd_market", &[]), hook(MarketAction::Borrow, 1_000)).unwrap();
+
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        let usd_market = deps.api.canonical_address(&HumanAddr::from("usd_market")).unwrap();
+        assert!(get_account_markets(&deps.storage, &alice).unwrap().contains(&usd_market));
+        // The debt now counts, so one more unit is over the limit
+        deps.querier.set_position("usd_market", "alice", 0, 1_001);
+        assert!(handle(&mut deps, mock_env("usd_market", &[]), hook(MarketAction::Borrow, 1)).is_err());
+    }
+
+    #[test]
+    fn repay_value_converts_between_the_two_markets_prices() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(250), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        enter(&mut deps, &["luna_market"]);
+
+        // 1_000 UST repaid is worth 400 LUNA at 2.5
+        let res = handle(&mut deps, mock_env("usd_market", &[]), seize("luna_market", 1_000)).unwrap();
+        assert!(res.log.contains(&log("repay_value", 400)));
+        match &res.messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. }) => {
+                assert_eq!(contract_addr, &HumanAddr::from("luna_market"));
+                assert_eq!(from_binary::<MarketHandleMsg>(msg).unwrap(), MarketHandleMsg::SeizeCollateral {
+                    borrower: HumanAddr::from("alice"),
+                    liquidator: HumanAddr::from("bob"),
+                    repay_value: Uint128::from(400u128),
+                });
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+        // Only listed markets ask for seizures
+        assert_eq!(
+            handle(&mut deps, mock_env("bob", &[]), seize("luna_market", 1_000)).unwrap_err(),
+            StdError::unauthorized()
+        );
+    }
+
+    #[test]
+    fn collateral_is_only_seized_in_an_entered_market() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(250), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        enter(&mut deps, &["usd_market"]);
+        assert_eq!(
+            handle(&mut deps, mock_env("usd_market", &[]), seize("luna_market", 1_000)).unwrap_err(),
+            StdError::generic_err("Borrower has not entered the collateral market: collateral_market: luna_market")
+        );
+    }
+
+    fn pair_hook(action: MarketAction, account: &str) -> HandleMsg {
+        HandleMsg::MarketHook {
+            action,
+            account: HumanAddr::from(account),
+            amount: Uint128::from(1u128),
+        }
+    }
+
+    fn cap_pair(deps: &mut Extern<impl Storage, impl Api, impl Querier>, ceiling: Option<u128>) {
+        handle(deps, mock_env("admin", &[]), HandleMsg::SetPairCeiling {
+            collateral: HumanAddr::from("luna_market"),
+            borrow: HumanAddr::from("usd_market"),
+            ceiling: ceiling.map(Uint128::from),
+        })
+        .unwrap();
+    }
+
+    fn pair_total_debt(deps: &Extern<impl Storage, impl Api, impl Querier>) -> Uint128 {
+        let luna_market = deps.api.canonical_address(&HumanAddr::from("luna_market")).unwrap();
+        let usd_market = deps.api.canonical_address(&HumanAddr::from("usd_market")).unwrap();
+        get_pair_ceiling(&deps.storage, &luna_market, &usd_market).unwrap().unwrap().total_debt
+    }
+
+    #[test]
+    fn pair_debt_follows_the_collateral_share_up_to_the_ceiling() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::one(), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        cap_pair(&mut deps, Some(1_000));
+        for account in &["alice", "bob", "carol"] {
+            let markets = vec![HumanAddr::from("luna_market"), HumanAddr::from("usd_market")];
+            handle(&mut deps, mock_env(*account, &[]), HandleMsg::EnterMarkets { markets }).unwrap();
+        }
+
+        // Half of Alice's collateral value is LUNA, so half her debt counts against the pair
+        deps.querier.set_position("luna_market", "alice", 1_000, 0);
+        deps.querier.set_position("usd_market", "alice", 625, 400);
+        handle(&mut deps, mock_env("usd_market", &[]), pair_hook(MarketAction::Borrow, "alice")).unwrap();
+        assert_eq!(pair_total_debt(&deps), Uint128::from(200u128));
+        deps.querier.set_position("luna_market", "bob", 2_000, 0);
+        deps.querier.set_position("usd_market", "bob", 0, 800);
+        handle(&mut deps, mock_env("usd_market", &[]), pair_hook(MarketAction::Borrow, "bob")).unwrap();
+        assert_eq!(pair_total_debt(&deps), Uint128::from(1_000u128));
+
+        // Repaying is allowed over a lowered ceiling
+        cap_pair(&mut deps, Some(500));
+        deps.querier.set_position("usd_market", "alice", 625, 0);
+        handle(&mut deps, mock_env("usd_market", &[]), pair_hook(MarketAction::RepayBorrow, "alice")).unwrap();
+        assert_eq!(pair_total_debt(&deps), Uint128::from(800u128));
+
+        deps.querier.set_position("luna_market", "carol", 2_000, 0);
+        deps.querier.set_position("usd_market", "carol", 0, 1);
+        assert_eq!(
+            handle(&mut deps, mock_env("usd_market", &[]), pair_hook(MarketAction::Borrow, "carol")).unwrap_err(),
+            StdError::generic_err("Pair borrow ceiling reached: collateral: luna_market, total_debt: 801, ceiling: 500")
+        );
+    }
+
+    #[test]
+    fn debt_attributed_before_a_recap_is_not_subtracted_twice() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::one(), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        cap_pair(&mut deps, Some(1_000));
+        enter(&mut deps, &["luna_market", "usd_market"]);
+        deps.querier.set_position("luna_market", "alice", 1_000, 0);
+        deps.querier.set_position("usd_market", "alice", 0, 400);
+        handle(&mut deps, mock_env("usd_market", &[]), hook(MarketAction::Borrow, 400)).unwrap();
+        assert_eq!(pair_total_debt(&deps), Uint128::from(400u128));
+
+        // Lifting and restoring the ceiling starts the total over, Alice's 400 is still on her record
+        cap_pair(&mut deps, None);
+        cap_pair(&mut deps, Some(1_000));
+        assert_eq!(pair_total_debt(&deps), Uint128::zero());
+        deps.querier.set_position("usd_market", "alice", 0, 100);
+        handle(&mut deps, mock_env("usd_market", &[]), hook(MarketAction::RepayBorrow, 300)).unwrap();
+        assert_eq!(pair_total_debt(&deps), Uint128::from(100u128));
+    }
+}
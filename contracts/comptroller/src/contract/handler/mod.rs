@@ -0,0 +1,67 @@
+use cosmwasm_std::{Api, Empty, Env, Extern, HandleResponse, Querier, StdResult, Storage};
+
+use crate::msg::HandleMsg;
+
+mod account;
+pub(crate) mod admin;
+pub(crate) mod feed;
+mod hook;
+pub(crate) mod oracle;
+
+/// General handler for contract tx input
+/// tx inputs are defined HandleMsg enum in msg.rs file
+pub fn handle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: HandleMsg,
+) -> StdResult<HandleResponse<Empty>> {
+    match msg {
+        HandleMsg::RegisterMarket {
+            market,
+            symbol,
+            collateral_factor,
+            borrow_cap,
+            lp_pool,
+        } => admin::try_register_market(deps, env, market, symbol, collateral_factor, borrow_cap, lp_pool),
+        HandleMsg::UpdateMarket {
+            market,
+            collateral_factor,
+            borrow_cap,
+        } => admin::try_update_market(deps, env, market, collateral_factor, borrow_cap),
+        HandleMsg::EnterMarkets { markets } => account::try_enter_markets(deps, env, markets),
+        HandleMsg::ExitMarket { market } => account::try_exit_market(deps, env, market),
+        HandleMsg::SetGlobalPause { paused } => admin::try_set_global_pause(deps, env, paused),
+        HandleMsg::SetMinBorrowHealthFactor { min_borrow_health_factor } => {
+            admin::try_set_min_borrow_health_factor(deps, env, min_borrow_health_factor)
+        }
+        HandleMsg::SetPairCeiling {
+            collateral,
+            borrow,
+            ceiling,
+        } => admin::try_set_pair_ceiling(deps, env, collateral, borrow, ceiling),
+        HandleMsg::ProposeOracle {
+            oracle,
+            oracle_kind,
+            max_divergence,
+            dual_read_blocks,
+        } => oracle::try_propose_oracle(deps, env, oracle, oracle_kind, max_divergence, dual_read_blocks),
+        HandleMsg::CheckOracle {} => oracle::try_check_oracle(deps, env),
+        HandleMsg::FinalizeOracle {} => oracle::try_finalize_oracle(deps, env),
+        HandleMsg::CancelOracle {} => oracle::try_cancel_oracle(deps, env),
+        HandleMsg::CheckFeed { market } => feed::try_check_feed(deps, env, market),
+        HandleMsg::ResumeMarket { market } => feed::try_resume_market(deps, env, market),
+        HandleMsg::SetCollateralFactorGrace { blocks } => admin::try_set_collateral_factor_grace(deps, env, blocks),
+        HandleMsg::SetFreezeHaircut { freeze_haircut } => feed::try_set_freeze_haircut(deps, env, freeze_haircut),
+        HandleMsg::MarketHook {
+            action,
+            account,
+            amount,
+        } => hook::try_market_hook(deps, env, action, account, amount),
+        HandleMsg::SeizeCollateral {
+            collateral_market,
+            borrower,
+            liquidator,
+            repay_amount,
+        } => hook::try_seize_collateral(deps, env, collateral_market, borrower, liquidator, repay_amount),
+    }
+}
@@ -0,0 +1,264 @@
+use cosmwasm_bignumber::Decimal256;
+use cosmwasm_std::{log, Api, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage};
+
+use quasar_interfaces::oracle::OracleKind;
+
+use crate::contract::liquidity::{market_price, market_price_from};
+use crate::msg::MarketDivergence;
+use crate::state::{get_config, get_markets, get_pending_oracle, set_config, set_pending_oracle, PendingOracle};
+
+use crate::contract::handler::admin::assert_admin;
+
+/// Admin only, replaces any switch already in progress
+pub fn try_propose_oracle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    oracle: HumanAddr,
+    oracle_kind: OracleKind,
+    max_divergence: Decimal256,
+    dual_read_blocks: u64,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    if max_divergence >= Decimal256::one() {
+        return Err(StdError::generic_err(format!(
+            "Max divergence must be below 1: max_divergence: {}",
+            max_divergence
+        )));
+    }
+    if dual_read_blocks == 0 {
+        return Err(StdError::generic_err("Dual-read period must be at least one block"));
+    }
+    let oracle_raw = deps.api.canonical_address(&oracle)?;
+    if oracle_raw == get_config(&deps.storage)?.oracle {
+        return Err(StdError::generic_err("Proposed oracle is already configured"));
+    }
+    let pending = PendingOracle {
+        oracle: oracle_raw,
+        oracle_kind,
+        max_divergence,
+        dual_read_blocks,
+        start: env.block.height,
+        diverged_at: None,
+    };
+    set_pending_oracle(&mut deps.storage, Some(&pending))?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "propose_oracle"),
+            log("sender", env.message.sender.as_str()),
+            log("oracle", oracle.as_str()),
+            log("max_divergence", max_divergence),
+            log("finalize_after", pending.finalize_after()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Records a divergence instead of failing, so it stays visible on chain
+pub fn try_check_oracle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let mut pending = match get_pending_oracle(&deps.storage)? {
+        Some(pending) => pending,
+        None => return Err(StdError::generic_err("No oracle switch is in progress")),
+    };
+    let divergences = oracle_divergences(deps, &pending)?;
+    let diverged: Vec<&MarketDivergence> = divergences.iter().filter(|market| market.diverged).collect();
+    if !diverged.is_empty() {
+        pending.diverged_at = Some(env.block.height);
+        set_pending_oracle(&mut deps.storage, Some(&pending))?;
+    }
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "check_oracle"),
+            log("sender", env.message.sender.as_str()),
+            log("markets", divergences.len()),
+            log("diverged", diverged.len()),
+            log("finalize_after", pending.finalize_after()),
+        ],
+        data: None,
+    };
+    for market in diverged {
+        res.log.push(log("diverged_market", market.market.as_str()));
+        res.log.push(log("divergence", market.divergence));
+    }
+    Ok(res)
+}
+
+pub fn try_finalize_oracle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    let pending = match get_pending_oracle(&deps.storage)? {
+        Some(pending) => pending,
+        None => return Err(StdError::generic_err("No oracle switch is in progress")),
+    };
+    if env.block.height < pending.finalize_after() {
+        return Err(StdError::generic_err(format!(
+            "Dual-read period is not over: current_block: {}, finalize_after: {}",
+            env.block.height,
+            pending.finalize_after()
+        )));
+    }
+    if let Some(market) = oracle_divergences(deps, &pending)?.into_iter().find(|market| market.diverged) {
+        return Err(StdError::generic_err(format!(
+            "Proposed oracle diverges: market: {}, divergence: {}, max_divergence: {}",
+            market.market, market.divergence, pending.max_divergence
+        )));
+    }
+
+    let mut config = get_config(&deps.storage)?;
+    let old_oracle = deps.api.human_address(&config.oracle)?;
+    config.oracle = pending.oracle.clone();
+    config.oracle_kind = pending.oracle_kind;
+    set_config(&mut deps.storage, &config)?;
+    set_pending_oracle(&mut deps.storage, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "finalize_oracle"),
+            log("sender", env.message.sender.as_str()),
+            log("old_oracle", old_oracle.as_str()),
+            log("oracle", deps.api.human_address(&config.oracle)?.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_cancel_oracle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    assert_admin(deps, &env)?;
+    if get_pending_oracle(&deps.storage)?.is_none() {
+        return Err(StdError::generic_err("No oracle switch is in progress"));
+    }
+    set_pending_oracle(&mut deps.storage, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "cancel_oracle"),
+            log("sender", env.message.sender.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Both oracles' prices of every listed market, a proposed oracle that cannot
+/// price a market fails the whole comparison
+pub fn oracle_divergences<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    pending: &PendingOracle,
+) -> StdResult<Vec<MarketDivergence>> {
+    let quote = get_config(&deps.storage)?.quote;
+    get_markets(&deps.storage)?
+        .iter()
+        .map(|market| {
+            let price = market_price(deps, market)?;
+            let proposed_price = market_price_from(deps, &pending.oracle, &pending.oracle_kind, quote.clone(), market)?;
+            let difference = match proposed_price > price {
+                true => proposed_price - price,
+                false => price - proposed_price,
+            };
+            // A zero price only agrees with a zero price
+            let divergence = match price.is_zero() {
+                true if difference.is_zero() => Decimal256::zero(),
+                true => Decimal256::one(),
+                false => difference / price,
+            };
+            Ok(MarketDivergence {
+                market: deps.api.human_address(&market.address)?,
+                price,
+                proposed_price,
+                divergence,
+                diverged: divergence > pending.max_divergence,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+
+    use crate::contract::handle;
+    use crate::contract::testing::{list_market, mock_comptroller, mock_init_msg};
+    use crate::msg::HandleMsg;
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    fn propose() -> HandleMsg {
+        HandleMsg::ProposeOracle {
+            oracle: HumanAddr::from("oracle2"),
+            oracle_kind: OracleKind::TeFi,
+            max_divergence: Decimal256::percent(2),
+            dual_read_blocks: 100,
+        }
+    }
+
+    #[test]
+    fn divergence_restarts_the_dual_read_period() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        deps.querier.set_price("oracle2", "LUNA", Decimal256::percent(201));
+        handle(&mut deps, env_at("admin", 20_000), propose()).unwrap();
+        assert!(handle(&mut deps, env_at("admin", 20_050), HandleMsg::FinalizeOracle {}).is_err());
+
+        // 5% apart is beyond the 2% allowed
+        deps.querier.set_price("oracle2", "LUNA", Decimal256::percent(210));
+        let res = handle(&mut deps, env_at("keeper", 20_060), HandleMsg::CheckOracle {}).unwrap();
+        assert!(res.log.contains(&log("diverged_market", "luna_market")));
+        assert!(res.log.contains(&log("finalize_after", 20_160)));
+        deps.querier.set_price("oracle2", "LUNA", Decimal256::percent(201));
+        assert_eq!(
+            handle(&mut deps, env_at("admin", 20_100), HandleMsg::FinalizeOracle {}).unwrap_err(),
+            StdError::generic_err("Dual-read period is not over: current_block: 20100, finalize_after: 20160")
+        );
+
+        assert_eq!(
+            handle(&mut deps, env_at("keeper", 20_160), HandleMsg::FinalizeOracle {}).unwrap_err(),
+            StdError::unauthorized()
+        );
+        handle(&mut deps, env_at("admin", 20_160), HandleMsg::FinalizeOracle {}).unwrap();
+        let config = get_config(&deps.storage).unwrap();
+        assert_eq!(deps.api.human_address(&config.oracle).unwrap(), HumanAddr::from("oracle2"));
+        assert_eq!(get_pending_oracle(&deps.storage).unwrap(), None);
+    }
+
+    #[test]
+    fn finalize_refuses_a_diverging_oracle() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        deps.querier.set_price("oracle2", "LUNA", Decimal256::percent(200));
+        handle(&mut deps, env_at("admin", 20_000), propose()).unwrap();
+        // The proposed oracle cannot price every market yet
+        assert!(handle(&mut deps, env_at("admin", 20_100), HandleMsg::FinalizeOracle {}).is_err());
+
+        deps.querier.set_price("oracle2", "UST", Decimal256::percent(90));
+        assert_eq!(
+            handle(&mut deps, env_at("admin", 20_100), HandleMsg::FinalizeOracle {}).unwrap_err(),
+            StdError::generic_err("Proposed oracle diverges: market: usd_market, divergence: 0.1, max_divergence: 0.02")
+        );
+        let config = get_config(&deps.storage).unwrap();
+        assert_eq!(deps.api.human_address(&config.oracle).unwrap(), HumanAddr::from("oracle"));
+
+        deps.querier.set_price("oracle2", "UST", Decimal256::percent(99));
+        handle(&mut deps, env_at("admin", 20_100), HandleMsg::FinalizeOracle {}).unwrap();
+    }
+}
@@ -0,0 +1,35 @@
+use cosmwasm_bignumber::Decimal256;
+use cosmwasm_std::{Api, Env, Extern, InitResponse, Querier, StdResult, Storage};
+
+use crate::contract::handler::admin::validate_min_borrow_health_factor;
+use crate::contract::handler::feed::validate_freeze_haircut;
+use crate::msg::InitMsg;
+use crate::state::{set_config, Config, DEFAULT_FREEZE_HAIRCUT_PERCENT};
+
+/// Contract instantiation tx
+/// tx inputs are specified in InitMsg in msg.rs file
+pub fn init<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: InitMsg,
+) -> StdResult<InitResponse> {
+    let admin = msg.admin.unwrap_or(env.message.sender);
+    let init_config = Config {
+        admin: deps.api.canonical_address(&admin)?,
+        guardian: match msg.guardian {
+            Some(guardian) => Some(deps.api.canonical_address(&guardian)?),
+            None => None,
+        },
+        oracle: deps.api.canonical_address(&msg.oracle)?,
+        oracle_kind: msg.oracle_kind,
+        quote: msg.quote,
+        min_borrow_health_factor: validate_min_borrow_health_factor(msg.min_borrow_health_factor)?,
+        freeze_haircut: validate_freeze_haircut(
+            msg.freeze_haircut.unwrap_or_else(|| Decimal256::percent(DEFAULT_FREEZE_HAIRCUT_PERCENT)),
+        )?,
+        collateral_factor_grace: msg.collateral_factor_grace.unwrap_or(0),
+    };
+    set_config(&mut deps.storage, &init_config)?;
+
+    Ok(InitResponse::default())
+}
@@ -0,0 +1,251 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{
+    to_binary, Api, CanonicalAddr, Extern, HumanAddr, Querier, QueryRequest, StdError, StdResult,
+    Storage, WasmQuery,
+};
+
+use quasar_interfaces::market::{AccountSnapshotResponse, MarketQueryMsg, MarketTotalsResponse};
+use quasar_interfaces::oracle::{query_lp_price, query_price, OracleKind};
+
+use crate::state::{get_account_markets, get_collateral_factor_change, get_config, get_market, get_market_freeze, Market};
+
+/// 10^8, the scale of exchange rates and collateral factors
+const SCALE: u128 = 100_000_000;
+
+/// Position of `account` in `market` as reported by the market itself
+pub fn query_account_snapshot<Q: Querier>(
+    querier: &Q,
+    market: HumanAddr,
+    account: HumanAddr,
+) -> StdResult<AccountSnapshotResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: market,
+        msg: to_binary(&MarketQueryMsg::AccountSnapshot { address: account })?,
+    }))
+}
+
+/// Cash, borrows and reserves of `market` as reported by the market itself
+pub fn query_market_totals<Q: Querier>(querier: &Q, market: HumanAddr) -> StdResult<MarketTotalsResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: market,
+        msg: to_binary(&MarketQueryMsg::MarketTotals {})?,
+    }))
+}
+
+/// Liquidity and shortfall of `account` in the quote denom, at most one of them is non zero.
+/// Collateral counts only in entered markets, debt is summed over them as well since
+/// borrowing enters the market and exiting requires a zero borrow balance
+pub fn account_liquidity<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+) -> StdResult<(Uint256, Uint256)> {
+    let account_raw = deps.api.canonical_address(account)?;
+    let markets = get_account_markets(&deps.storage, &account_raw)?;
+    account_liquidity_in(deps, account, &markets)
+}
+
+/// Same as account_liquidity over an explicit list of entered markets
+pub fn account_liquidity_in<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+    markets: &[CanonicalAddr],
+) -> StdResult<(Uint256, Uint256)> {
+    let position = account_position(deps, account, markets, |market| market_price(deps, market))?;
+
+    if position.collateral_value >= position.borrow_value {
+        Ok((position.collateral_value - position.borrow_value, Uint256::zero()))
+    } else {
+        Ok((Uint256::zero(), position.borrow_value - position.collateral_value))
+    }
+}
+
+/// Shortfall of `account` at `block` as liquidations see it, collateral in markets whose
+/// collateral factor was cut within the grace period counts at the factor before the cut
+pub fn liquidation_shortfall<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+    block: u64,
+) -> StdResult<Uint256> {
+    let markets = get_account_markets(&deps.storage, &deps.api.canonical_address(account)?)?;
+    let mut collateral_value = Uint256::zero();
+    let mut borrow_value = Uint256::zero();
+    for position in market_positions(deps, account, &markets, |market| market_price(deps, market))? {
+        collateral_value += match get_collateral_factor_change(&deps.storage, &position.market.address)? {
+            Some(change) => {
+                let collateral_factor = change.liquidation_collateral_factor(position.market.collateral_factor, block);
+                position.supply_value.multiply_ratio(Uint256::from(collateral_factor).0, Uint256::from(SCALE).0)
+            }
+            None => position.collateral_value,
+        };
+        borrow_value += Uint256::from(position.borrow_balance) * position.price;
+    }
+    Ok(match borrow_value > collateral_value {
+        true => borrow_value - collateral_value,
+        false => Uint256::zero(),
+    })
+}
+
+/// Oracle price of one unit of the market's underlying in the quote denom,
+/// the last good price while the market is frozen
+pub fn market_price<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, market: &Market) -> StdResult<Decimal256> {
+    if let Some(freeze) = get_market_freeze(&deps.storage, &market.address)? {
+        return freeze.price.ok_or_else(|| {
+            StdError::generic_err(format!("Frozen market has no last good price: symbol: {}", market.symbol))
+        });
+    }
+    let config = get_config(&deps.storage)?;
+    market_price_from(deps, &config.oracle, &config.oracle_kind, config.quote, market)
+}
+
+/// `price` less the freeze haircut while the market is frozen, what its supply counts for
+pub fn collateral_price<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    market: &Market,
+    price: Decimal256,
+) -> StdResult<Decimal256> {
+    if get_market_freeze(&deps.storage, &market.address)?.is_none() {
+        return Ok(price);
+    }
+    Ok(price * (Decimal256::one() - get_config(&deps.storage)?.freeze_haircut))
+}
+
+/// Price of one unit of the market's underlying read from `oracle` instead of the configured one
+pub fn market_price_from<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    oracle: &CanonicalAddr,
+    oracle_kind: &OracleKind,
+    quote: String,
+    market: &Market,
+) -> StdResult<Decimal256> {
+    let oracle = deps.api.human_address(oracle)?;
+    let price = match &market.lp_pool {
+        Some(lp_pool) => query_lp_price(
+            &deps.querier,
+            oracle,
+            oracle_kind,
+            deps.api.human_address(&lp_pool.pair)?,
+            &lp_pool.symbols,
+            quote,
+        )?,
+        None => query_price(&deps.querier, oracle, oracle_kind, market.symbol.clone(), quote)?,
+    };
+    Ok(price.rate)
+}
+
+/// Value of an account across markets in the quote denom
+pub struct AccountPosition {
+    /// Supplied value weighted by collateral factors
+    pub collateral_value: Uint256,
+    /// Supplied value in full, what a liquidation could recover at most
+    pub supply_value: Uint256,
+    pub borrow_value: Uint256,
+}
+
+/// Values the account in `markets` at the prices `price_of` returns
+pub fn account_position<S: Storage, A: Api, Q: Querier, F>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+    markets: &[CanonicalAddr],
+    price_of: F,
+) -> StdResult<AccountPosition>
+where
+    F: Fn(&Market) -> StdResult<Decimal256>,
+{
+    let mut position = AccountPosition {
+        collateral_value: Uint256::zero(),
+        supply_value: Uint256::zero(),
+        borrow_value: Uint256::zero(),
+    };
+    for market_position in market_positions(deps, account, markets, price_of)? {
+        position.collateral_value += market_position.collateral_value;
+        position.supply_value += market_position.supply_value;
+        position.borrow_value += Uint256::from(market_position.borrow_balance) * market_position.price;
+    }
+    Ok(position)
+}
+
+/// Value of an account in one market
+pub struct MarketPosition {
+    pub market: Market,
+    pub price: Decimal256,
+    pub collateral_value: Uint256,
+    pub supply_value: Uint256,
+    /// Debt in the market's underlying
+    pub borrow_balance: u128,
+}
+
+/// The account's position in each of `markets`, in the same order
+pub fn market_positions<S: Storage, A: Api, Q: Querier, F>(
+    deps: &Extern<S, A, Q>,
+    account: &HumanAddr,
+    markets: &[CanonicalAddr],
+    price_of: F,
+) -> StdResult<Vec<MarketPosition>>
+where
+    F: Fn(&Market) -> StdResult<Decimal256>,
+{
+    let mut positions = vec![];
+    for market_raw in markets {
+        let market = match get_market(&deps.storage, market_raw)? {
+            Some(market) => market,
+            None => return Err(StdError::generic_err("Entered market is not listed")),
+        };
+        let snapshot = query_account_snapshot(
+            &deps.querier,
+            deps.api.human_address(market_raw)?,
+            account.clone(),
+        )?;
+        let price = price_of(&market)?;
+        let supply_price = collateral_price(deps, &market, price)?;
+
+        let underlying = snapshot.balance.u128() * snapshot.exchange_rate.u128() / SCALE;
+        let limit = underlying * market.collateral_factor.u128() / SCALE;
+        positions.push(MarketPosition {
+            market,
+            price,
+            collateral_value: Uint256::from(limit) * supply_price,
+            supply_value: Uint256::from(underlying) * supply_price,
+            borrow_balance: snapshot.borrow_balance.u128(),
+        });
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::Uint128;
+
+    use crate::contract::handle;
+    use crate::contract::testing::{list_market, mock_comptroller, mock_init_msg};
+    use crate::msg::HandleMsg;
+
+    #[test]
+    fn liquidity_values_entered_supply_at_the_exchange_rate() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        list_market(&mut deps, "eth_market", "ETH", Decimal256::percent(300), 80_000_000);
+        handle(&mut deps, mock_env("alice", &[]), HandleMsg::EnterMarkets {
+            markets: vec![HumanAddr::from("luna_market"), HumanAddr::from("usd_market")],
+        })
+        .unwrap();
+        // 100 qTokens at 1.5 LUNA each, half of it counted at 2
+        deps.querier.set_position("luna_market", "alice", 100, 0);
+        let key = (HumanAddr::from("luna_market"), HumanAddr::from("alice"));
+        deps.querier.snapshots.get_mut(&key).unwrap().exchange_rate = Uint128::from(150_000_000u128);
+        deps.querier.set_position("usd_market", "alice", 0, 100);
+        // Supply in a market that was not entered is no collateral
+        deps.querier.set_position("eth_market", "alice", 1_000, 0);
+
+        let alice = HumanAddr::from("alice");
+        assert_eq!(account_liquidity(&deps, &alice).unwrap(), (Uint256::from(50u64), Uint256::zero()));
+        let markets = get_account_markets(&deps.storage, &deps.api.canonical_address(&alice).unwrap()).unwrap();
+        let position = account_position(&deps, &alice, &markets, |market| market_price(&deps, market)).unwrap();
+        assert_eq!(position.supply_value, Uint256::from(300u64));
+
+        deps.querier.set_position("usd_market", "alice", 0, 175);
+        assert_eq!(account_liquidity(&deps, &alice).unwrap(), (Uint256::zero(), Uint256::from(25u64)));
+    }
+}
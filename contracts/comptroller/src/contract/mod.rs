@@ -0,0 +1,12 @@
+pub mod handler;
+pub mod init;
+pub mod liquidity;
+pub mod querier;
+#[cfg(test)]
+pub(crate) mod testing;
+
+pub use handler::handle;
+
+pub use init::init;
+
+pub use querier::query;
@@ -0,0 +1,308 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{Api, CanonicalAddr, Extern, HumanAddr, Querier, StdError, StdResult, Storage};
+
+use quasar_interfaces::pagination::PageRequest;
+
+use crate::contract::liquidity::{market_positions, market_price};
+use crate::msg::{CascadeContinuation, LiquidationCascadeResponse, MarketDepth, PriceShock, SeizedCollateral};
+use crate::state::{get_accounts_markets, get_config, get_markets};
+
+/// Rounds a cascade runs for unless the query asks for fewer
+const DEFAULT_CASCADE_ROUNDS: u32 = 10;
+const MAX_CASCADE_ROUNDS: u32 = 20;
+
+/// A listed market as the current round sees it
+struct CascadeMarket {
+    address: CanonicalAddr,
+    human: HumanAddr,
+    /// Oracle price, multipliers apply on top of it
+    price: Decimal256,
+    multiplier: Decimal256,
+    previous_multiplier: Option<Decimal256>,
+    seized: Uint256,
+    depth: Option<Uint256>,
+}
+
+/// Accounts are not tracked between rounds, so each round closes out those that are
+/// in shortfall at its prices but were not at the previous round's, in full
+pub fn query_liquidation_cascade<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    shocks: Vec<PriceShock>,
+    depths: Vec<MarketDepth>,
+    liquidation_incentive: Decimal256,
+    max_rounds: Option<u32>,
+    limit: Option<u32>,
+    continuation: Option<CascadeContinuation>,
+) -> StdResult<LiquidationCascadeResponse> {
+    if liquidation_incentive < Decimal256::one() {
+        return Err(StdError::generic_err(format!(
+            "Liquidation incentive must be at least 1: liquidation_incentive: {}",
+            liquidation_incentive
+        )));
+    }
+    let max_rounds = max_rounds.unwrap_or(DEFAULT_CASCADE_ROUNDS).clamp(1, MAX_CASCADE_ROUNDS);
+
+    let mut markets = vec![];
+    for market in get_markets(&deps.storage)? {
+        let human = deps.api.human_address(&market.address)?;
+        let (multiplier, previous_multiplier, seized) = match continuation.as_ref() {
+            Some(continuation) => (
+                find_multiplier(&continuation.multipliers, &human).unwrap_or_else(Decimal256::one),
+                find_multiplier(&continuation.previous_multipliers, &human),
+                continuation
+                    .seized
+                    .iter()
+                    .find(|seized| seized.market == human)
+                    .map(|seized| seized.value)
+                    .unwrap_or_default(),
+            ),
+            None => (find_multiplier(&shocks, &human).unwrap_or_else(Decimal256::one), None, Uint256::zero()),
+        };
+        markets.push(CascadeMarket {
+            address: market.address.clone(),
+            price: market_price(deps, &market)?,
+            depth: depths.iter().find(|depth| depth.market == human).map(|depth| depth.depth),
+            human,
+            multiplier,
+            previous_multiplier,
+            seized,
+        });
+    }
+
+    let (round, start_after, mut bad_debt, mut accounts_liquidated, mut round_liquidated) = match continuation {
+        Some(continuation) => (
+            continuation.round,
+            match continuation.start_after {
+                Some(start_after) => Some(deps.api.canonical_address(&start_after)?),
+                None => None,
+            },
+            continuation.bad_debt,
+            continuation.accounts_liquidated,
+            continuation.round_liquidated,
+        ),
+        None => (1, None, Uint256::zero(), 0, 0),
+    };
+
+    // One account past the page tells whether the round goes on
+    let limit = PageRequest { start_after: None, limit }.limit();
+    let mut accounts = get_accounts_markets(&deps.storage, start_after.as_ref(), limit + 1)?;
+    let has_more = accounts.len() > limit;
+    accounts.truncate(limit);
+
+    for (account_raw, account_markets) in &accounts {
+        let account = deps.api.human_address(account_raw)?;
+        // Valued at a price of one, so each round's prices can be applied below
+        let positions = market_positions(deps, &account, account_markets, |_| Ok(Decimal256::one()))?;
+
+        let mut collateral = Uint256::zero();
+        let mut supply = Uint256::zero();
+        let mut borrow = Uint256::zero();
+        let mut previous_collateral = Uint256::zero();
+        let mut previous_borrow = Uint256::zero();
+        let mut supplies = vec![];
+        for position in positions.iter() {
+            let market = match markets.iter().position(|market| market.address == position.market.address) {
+                Some(index) => index,
+                None => return Err(StdError::generic_err("Entered market is not listed")),
+            };
+            let price = markets[market].price * markets[market].multiplier;
+            let market_supply = position.supply_value * price;
+            collateral += position.collateral_value * price;
+            supply += market_supply;
+            borrow += Uint256::from(position.borrow_balance) * price;
+            supplies.push((market, market_supply));
+
+            if let Some(previous_multiplier) = markets[market].previous_multiplier {
+                let previous_price = markets[market].price * previous_multiplier;
+                previous_collateral += position.collateral_value * previous_price;
+                previous_borrow += Uint256::from(position.borrow_balance) * previous_price;
+            }
+        }
+
+        let first_round = markets.iter().all(|market| market.previous_multiplier.is_none());
+        let previously_short = !first_round && previous_borrow > previous_collateral;
+        if borrow <= collateral || previously_short {
+            continue;
+        }
+
+        // Liquidators repay what the seized collateral covers at the incentive
+        let seize = supply.min(borrow * liquidation_incentive);
+        let repaid = seize / liquidation_incentive;
+        if borrow > repaid {
+            bad_debt += borrow - repaid;
+        }
+        if !supply.is_zero() {
+            for (market, market_supply) in supplies {
+                markets[market].seized += seize.multiply_ratio(market_supply.0, supply.0);
+            }
+        }
+        accounts_liquidated += 1;
+        round_liquidated += 1;
+    }
+
+    let quote = get_config(&deps.storage)?.quote;
+    let depth = if round_liquidated > 0 { round } else { round - 1 };
+    if has_more {
+        let start_after = match accounts.last() {
+            Some((last, _)) => Some(deps.api.human_address(last)?),
+            None => None,
+        };
+        return Ok(LiquidationCascadeResponse {
+            quote,
+            depth,
+            bad_debt,
+            accounts_liquidated,
+            multipliers: multipliers(&markets, |market| market.multiplier),
+            continuation: Some(CascadeContinuation {
+                round,
+                multipliers: multipliers(&markets, |market| market.multiplier),
+                previous_multipliers: markets
+                    .iter()
+                    .filter_map(|market| {
+                        market.previous_multiplier.map(|price_multiplier| PriceShock {
+                            market: market.human.clone(),
+                            price_multiplier,
+                        })
+                    })
+                    .collect(),
+                seized: markets
+                    .iter()
+                    .filter(|market| !market.seized.is_zero())
+                    .map(|market| SeizedCollateral {
+                        market: market.human.clone(),
+                        value: market.seized,
+                    })
+                    .collect(),
+                start_after,
+                bad_debt,
+                accounts_liquidated,
+                round_liquidated,
+            }),
+        });
+    }
+
+    // The round is complete, seized collateral is sold before the next one
+    let next_multipliers = multipliers(&markets, |market| match market.depth {
+        Some(depth) if !market.seized.is_zero() => {
+            if market.seized >= depth {
+                Decimal256::zero()
+            } else {
+                market.multiplier * (Decimal256::one() - Decimal256::from_ratio(market.seized.0, depth.0))
+            }
+        }
+        _ => market.multiplier,
+    });
+    let selling = markets.iter().any(|market| !market.seized.is_zero() && market.depth.is_some());
+    let continuation = match round < max_rounds && selling {
+        true => Some(CascadeContinuation {
+            round: round + 1,
+            multipliers: next_multipliers.clone(),
+            previous_multipliers: multipliers(&markets, |market| market.multiplier),
+            seized: vec![],
+            start_after: None,
+            bad_debt,
+            accounts_liquidated,
+            round_liquidated: 0,
+        }),
+        false => None,
+    };
+    Ok(LiquidationCascadeResponse {
+        quote,
+        depth,
+        bad_debt,
+        accounts_liquidated,
+        multipliers: next_multipliers,
+        continuation,
+    })
+}
+
+fn find_multiplier(shocks: &[PriceShock], market: &HumanAddr) -> Option<Decimal256> {
+    shocks
+        .iter()
+        .find(|shock| shock.market == *market)
+        .map(|shock| shock.price_multiplier)
+}
+
+fn multipliers<F: Fn(&CascadeMarket) -> Decimal256>(markets: &[CascadeMarket], multiplier_of: F) -> Vec<PriceShock> {
+    markets
+        .iter()
+        .map(|market| PriceShock {
+            market: market.human.clone(),
+            price_multiplier: multiplier_of(market),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
+
+    use crate::contract::handle;
+    use crate::contract::testing::{list_market, mock_comptroller, mock_init_msg, MarketsMockQuerier};
+    use crate::msg::HandleMsg;
+
+    /// Three accounts with 1_000 LUNA each at a collateral factor of 0.5, owing 450, 380 and 100 UST
+    fn cascade_comptroller() -> Extern<MockStorage, MockApi, MarketsMockQuerier> {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::one(), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        for (account, borrow) in &[("alice", 450), ("bob", 380), ("carol", 100)] {
+            handle(&mut deps, mock_env(*account, &[]), HandleMsg::EnterMarkets {
+                markets: vec![HumanAddr::from("luna_market"), HumanAddr::from("usd_market")],
+            })
+            .unwrap();
+            deps.querier.set_position("luna_market", account, 1_000, 0);
+            deps.querier.set_position("usd_market", account, 0, *borrow);
+        }
+        deps
+    }
+
+    /// Follows the continuations to the end of the cascade
+    fn run_cascade(deps: &Extern<MockStorage, MockApi, MarketsMockQuerier>, limit: u32) -> (LiquidationCascadeResponse, u32) {
+        let mut continuation = None;
+        let mut calls = 0;
+        loop {
+            let res = query_liquidation_cascade(
+                deps,
+                vec![PriceShock { market: HumanAddr::from("luna_market"), price_multiplier: Decimal256::percent(80) }],
+                vec![MarketDepth { market: HumanAddr::from("luna_market"), depth: Uint256::from(4_950u64) }],
+                Decimal256::percent(110),
+                None,
+                Some(limit),
+                continuation,
+            )
+            .unwrap();
+            calls += 1;
+            match res.continuation.clone() {
+                Some(next) => continuation = Some(next),
+                None => return (res, calls),
+            }
+        }
+    }
+
+    #[test]
+    fn selling_seized_collateral_liquidates_the_next_round() {
+        let deps = cascade_comptroller();
+        let (res, _) = run_cascade(&deps, 30);
+        // Alice falls short at 0.8, selling her 495 of seized LUNA into 4_950 of depth takes
+        // the price to 0.72 where Bob follows. Carol stays solvent and the third round stops
+        assert_eq!(res.accounts_liquidated, 2);
+        assert_eq!(res.depth, 2);
+        assert_eq!(res.bad_debt, Uint256::zero());
+        let luna = res.multipliers.iter().find(|shock| shock.market == HumanAddr::from("luna_market")).unwrap();
+        assert!(luna.price_multiplier < Decimal256::percent(72));
+    }
+
+    #[test]
+    fn paged_cascades_end_where_unpaged_ones_do() {
+        let deps = cascade_comptroller();
+        let (unpaged, unpaged_calls) = run_cascade(&deps, 30);
+        let (paged, paged_calls) = run_cascade(&deps, 1);
+        assert!(paged_calls > unpaged_calls);
+        assert_eq!(paged.depth, unpaged.depth);
+        assert_eq!(paged.accounts_liquidated, unpaged.accounts_liquidated);
+        assert_eq!(paged.bad_debt, unpaged.bad_debt);
+        assert_eq!(paged.multipliers, unpaged.multipliers);
+    }
+}
@@ -0,0 +1,428 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{to_binary, Api, Binary, CanonicalAddr, Extern, HumanAddr, Querier, StdError, StdResult, Storage, Uint128};
+
+use quasar_interfaces::pagination::PageRequest;
+
+mod cascade;
+
+use crate::contract::handler::oracle::oracle_divergences;
+use crate::contract::liquidity::{
+    account_liquidity, account_position, liquidation_shortfall, market_price, query_account_snapshot, query_market_totals,
+};
+use crate::msg::{
+    AccountLiquidityResponse, AssetsInResponse, LiquidationShortfallResponse, ConfigResponse, GlobalPauseResponse, LpPoolMsg, MarketFreezeResponse, MarketResponse, MarketsResponse, MaxBorrowResponse,
+    ProtocolTotalsResponse,
+    OracleDivergenceResponse, PairCeilingResponse, PairCeilingsResponse, PendingOracleResponse, PriceShock, QueryMsg,
+    StressContinuation, StressTestResponse,
+};
+use crate::state::{
+    get_account_markets, get_accounts_markets, get_collateral_factor_change, get_config, get_global_pause, get_market, get_markets, get_pair_ceilings,
+    get_market_freeze, get_pending_oracle,
+    Market,
+};
+
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => {
+            let config = get_config(&deps.storage)?;
+            let out = to_binary(&ConfigResponse {
+                admin: deps.api.human_address(&config.admin)?,
+                guardian: match config.guardian {
+                    Some(guardian) => Some(deps.api.human_address(&guardian)?),
+                    None => None,
+                },
+                oracle: deps.api.human_address(&config.oracle)?,
+                oracle_kind: config.oracle_kind,
+                quote: config.quote,
+                min_borrow_health_factor: config.min_borrow_health_factor,
+                freeze_haircut: config.freeze_haircut,
+                collateral_factor_grace: config.collateral_factor_grace,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::Market { market } => {
+            let market_raw = deps.api.canonical_address(&market)?;
+            let listed = match get_market(&deps.storage, &market_raw)? {
+                Some(listed) => listed,
+                None => {
+                    return Err(StdError::generic_err(format!(
+                        "Market is not listed: market: {}",
+                        market
+                    )));
+                }
+            };
+            let out = to_binary(&market_response(deps, listed)?)?;
+            Ok(out)
+        }
+        QueryMsg::Markets {} => {
+            let markets = get_markets(&deps.storage)?
+                .into_iter()
+                .map(|market| market_response(deps, market))
+                .collect::<StdResult<Vec<_>>>()?;
+            let out = to_binary(&MarketsResponse { markets })?;
+            Ok(out)
+        }
+        QueryMsg::AssetsIn { address } => {
+            let address_raw = deps.api.canonical_address(&address)?;
+            let markets = get_account_markets(&deps.storage, &address_raw)?
+                .iter()
+                .map(|market| deps.api.human_address(market))
+                .collect::<StdResult<Vec<HumanAddr>>>()?;
+            let out = to_binary(&AssetsInResponse { markets })?;
+            Ok(out)
+        }
+        QueryMsg::PendingOracle {} => {
+            let pending = match get_pending_oracle(&deps.storage)? {
+                Some(pending) => Some(PendingOracleResponse {
+                    oracle: deps.api.human_address(&pending.oracle)?,
+                    finalize_after: pending.finalize_after(),
+                    oracle_kind: pending.oracle_kind,
+                    max_divergence: pending.max_divergence,
+                    dual_read_blocks: pending.dual_read_blocks,
+                    start: pending.start,
+                    diverged_at: pending.diverged_at,
+                }),
+                None => None,
+            };
+            let out = to_binary(&pending)?;
+            Ok(out)
+        }
+        QueryMsg::MarketFreeze { market } => {
+            let freeze = get_market_freeze(&deps.storage, &deps.api.canonical_address(&market)?)?.map(|freeze| {
+                MarketFreezeResponse {
+                    price: freeze.price,
+                    frozen_at: freeze.frozen_at,
+                }
+            });
+            let out = to_binary(&freeze)?;
+            Ok(out)
+        }
+        QueryMsg::OracleDivergence {} => {
+            let pending = match get_pending_oracle(&deps.storage)? {
+                Some(pending) => pending,
+                None => return Err(StdError::generic_err("No oracle switch is in progress")),
+            };
+            let out = to_binary(&OracleDivergenceResponse {
+                markets: oracle_divergences(deps, &pending)?,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::GlobalPause {} => {
+            let pause = get_global_pause(&deps.storage)?;
+            let out = to_binary(&GlobalPauseResponse {
+                paused: pause.paused,
+                epoch: pause.epoch,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::AccountLiquidity { address } => {
+            let (liquidity, shortfall) = account_liquidity(deps, &address)?;
+            let out = to_binary(&AccountLiquidityResponse {
+                quote: get_config(&deps.storage)?.quote,
+                liquidity,
+                shortfall,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::LiquidationShortfall { address, block } => {
+            let out = to_binary(&LiquidationShortfallResponse {
+                quote: get_config(&deps.storage)?.quote,
+                shortfall: liquidation_shortfall(deps, &address, block)?,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::MaxBorrow { account, market, health_factor } => {
+            let out = to_binary(&query_max_borrow(deps, account, market, health_factor)?)?;
+            Ok(out)
+        }
+        QueryMsg::ProtocolTotals {} => {
+            let out = to_binary(&query_protocol_totals(deps)?)?;
+            Ok(out)
+        }
+        QueryMsg::StressTest { shocks, limit, continuation } => {
+            let out = to_binary(&query_stress_test(deps, shocks, limit, continuation)?)?;
+            Ok(out)
+        }
+        QueryMsg::LiquidationCascade { shocks, depths, liquidation_incentive, max_rounds, limit, continuation } => {
+            let out = to_binary(&cascade::query_liquidation_cascade(
+                deps,
+                shocks,
+                depths,
+                liquidation_incentive,
+                max_rounds,
+                limit,
+                continuation,
+            )?)?;
+            Ok(out)
+        }
+        QueryMsg::PairCeilings {} => {
+            let pairs = get_pair_ceilings(&deps.storage)?
+                .into_iter()
+                .map(|pair| {
+                    Ok(PairCeilingResponse {
+                        collateral: deps.api.human_address(&pair.collateral)?,
+                        borrow: deps.api.human_address(&pair.borrow)?,
+                        ceiling: pair.ceiling,
+                        total_debt: pair.total_debt,
+                    })
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            let out = to_binary(&PairCeilingsResponse { pairs })?;
+            Ok(out)
+        }
+    }
+}
+
+/// Borrowing enters the market, so its supply counts as collateral as the hook would count it
+fn query_max_borrow<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    account: HumanAddr,
+    market: HumanAddr,
+    health_factor: Option<Decimal256>,
+) -> StdResult<MaxBorrowResponse> {
+    let market_raw = deps.api.canonical_address(&market)?;
+    let listed = match get_market(&deps.storage, &market_raw)? {
+        Some(listed) => listed,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Market is not listed: market: {}",
+                market
+            )));
+        }
+    };
+    let min_health_factor = get_config(&deps.storage)?.min_borrow_health_factor.unwrap_or_else(Decimal256::one);
+    let health_factor = match health_factor {
+        Some(health_factor) if health_factor > min_health_factor => health_factor,
+        _ => min_health_factor,
+    };
+
+    let mut markets = get_account_markets(&deps.storage, &deps.api.canonical_address(&account)?)?;
+    if !markets.contains(&market_raw) {
+        markets.push(market_raw);
+    }
+    let position = account_position(deps, &account, &markets, |market| market_price(deps, market))?;
+    let allowed_value = position.collateral_value / health_factor;
+    let price = market_price(deps, &listed)?;
+    let mut max_borrow: Uint128 = if allowed_value > position.borrow_value && !price.is_zero() {
+        ((allowed_value - position.borrow_value) / price).into()
+    } else {
+        Uint128::zero()
+    };
+    if let Some(borrow_cap) = listed.borrow_cap {
+        let total_borrows = query_account_snapshot(&deps.querier, market, account)?.total_borrows;
+        max_borrow = max_borrow.min(Uint128::from(borrow_cap.u128().saturating_sub(total_borrows.u128())));
+    }
+
+    Ok(MaxBorrowResponse {
+        health_factor,
+        max_borrow,
+        collateral_value: position.collateral_value,
+        borrow_value: position.borrow_value,
+    })
+}
+
+fn query_protocol_totals<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<ProtocolTotalsResponse> {
+    let mut res = ProtocolTotalsResponse {
+        quote: get_config(&deps.storage)?.quote,
+        total_supplied: Uint256::zero(),
+        total_borrowed: Uint256::zero(),
+        total_reserves: Uint256::zero(),
+    };
+    for market in get_markets(&deps.storage)? {
+        let totals = query_market_totals(&deps.querier, deps.api.human_address(&market.address)?)?;
+        let price = market_price(deps, &market)?;
+        let supplied = (totals.cash.u128() + totals.total_borrows.u128()).saturating_sub(totals.total_reserves.u128());
+        res.total_supplied += Uint256::from(supplied) * price;
+        res.total_borrowed += Uint256::from(totals.total_borrows) * price;
+        res.total_reserves += Uint256::from(totals.total_reserves) * price;
+    }
+    Ok(res)
+}
+
+fn query_stress_test<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    shocks: Vec<PriceShock>,
+    limit: Option<u32>,
+    continuation: Option<StressContinuation>,
+) -> StdResult<StressTestResponse> {
+    // Price every market once per call rather than once per account
+    let mut prices: Vec<(CanonicalAddr, Decimal256)> = vec![];
+    for market in get_markets(&deps.storage)? {
+        let market_human = deps.api.human_address(&market.address)?;
+        let multiplier = shocks
+            .iter()
+            .find(|shock| shock.market == market_human)
+            .map(|shock| shock.price_multiplier)
+            .unwrap_or_else(Decimal256::one);
+        let price = market_price(deps, &market)?;
+        prices.push((market.address.clone(), price * multiplier));
+    }
+    let price_of = |market: &Market| {
+        prices
+            .iter()
+            .find(|(address, _)| *address == market.address)
+            .map(|(_, price)| *price)
+            .ok_or_else(|| StdError::generic_err("Entered market is not listed"))
+    };
+
+    let (mut bad_debt, mut accounts_in_shortfall, mut accounts_checked, start_after) = match continuation {
+        Some(continuation) => (
+            continuation.bad_debt,
+            continuation.accounts_in_shortfall,
+            continuation.accounts_checked,
+            Some(deps.api.canonical_address(&continuation.start_after)?),
+        ),
+        None => (Uint256::zero(), 0, 0, None),
+    };
+
+    // One account past the page tells whether another call is needed
+    let limit = PageRequest { start_after: None, limit }.limit();
+    let mut accounts = get_accounts_markets(&deps.storage, start_after.as_ref(), limit + 1)?;
+    let has_more = accounts.len() > limit;
+    accounts.truncate(limit);
+
+    for (account_raw, markets) in &accounts {
+        let account = deps.api.human_address(account_raw)?;
+        let position = account_position(deps, &account, markets, price_of)?;
+        if position.borrow_value > position.collateral_value {
+            accounts_in_shortfall += 1;
+        }
+        if position.borrow_value > position.supply_value {
+            bad_debt += position.borrow_value - position.supply_value;
+        }
+        accounts_checked += 1;
+    }
+
+    let continuation = match accounts.last() {
+        Some((last, _)) if has_more => Some(StressContinuation {
+            start_after: deps.api.human_address(last)?,
+            bad_debt,
+            accounts_in_shortfall,
+            accounts_checked,
+        }),
+        _ => None,
+    };
+    Ok(StressTestResponse {
+        quote: get_config(&deps.storage)?.quote,
+        bad_debt,
+        accounts_in_shortfall,
+        accounts_checked,
+        continuation,
+    })
+}
+
+fn market_response<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, market: Market) -> StdResult<MarketResponse> {
+    Ok(MarketResponse {
+        collateral_factor_change: get_collateral_factor_change(&deps.storage, &market.address)?,
+        market: deps.api.human_address(&market.address)?,
+        symbol: market.symbol,
+        collateral_factor: market.collateral_factor,
+        borrow_cap: market.borrow_cap,
+        lp_pool: match market.lp_pool {
+            Some(lp_pool) => Some(LpPoolMsg {
+                pair: deps.api.human_address(&lp_pool.pair)?,
+                symbols: lp_pool.symbols,
+            }),
+            None => None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::from_binary;
+
+    use quasar_interfaces::comptroller::MarketAction;
+
+    use crate::contract::handle;
+    use crate::contract::testing::{list_market, mock_comptroller, mock_init_msg};
+    use crate::msg::HandleMsg;
+
+    fn max_borrow(msg: QueryMsg, deps: &Extern<impl Storage, impl Api, impl Querier>) -> MaxBorrowResponse {
+        from_binary(&query(deps, msg).unwrap()).unwrap()
+    }
+
+    fn max_borrow_msg(health_factor: Option<Decimal256>) -> QueryMsg {
+        QueryMsg::MaxBorrow {
+            account: HumanAddr::from("alice"),
+            market: HumanAddr::from("usd_market"),
+            health_factor,
+        }
+    }
+
+    fn borrow_hook(amount: u128) -> HandleMsg {
+        HandleMsg::MarketHook {
+            action: MarketAction::Borrow,
+            account: HumanAddr::from("alice"),
+            amount: Uint128::from(amount),
+        }
+    }
+
+    #[test]
+    fn max_borrow_keeps_the_account_at_the_safety_buffer() {
+        let mut msg = mock_init_msg();
+        msg.min_borrow_health_factor = Some(Decimal256::percent(125));
+        let mut deps = mock_comptroller(msg);
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(200), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        handle(&mut deps, mock_env("alice", &[]), HandleMsg::EnterMarkets { markets: vec![HumanAddr::from("luna_market")] })
+            .unwrap();
+        // 1_000 collateral value against 100 of debt
+        deps.querier.set_position("luna_market", "alice", 1_000, 0);
+        deps.querier.set_position("usd_market", "alice", 0, 100);
+
+        let res = max_borrow(max_borrow_msg(None), &deps);
+        assert_eq!(res.health_factor, Decimal256::percent(125));
+        assert_eq!(res.collateral_value, Uint256::from(1_000u64));
+        assert_eq!(res.borrow_value, Uint256::from(100u64));
+        assert_eq!(res.max_borrow, Uint128::from(700u128));
+        // A larger buffer borrows less, a smaller one is raised to the minimum
+        assert_eq!(max_borrow(max_borrow_msg(Some(Decimal256::percent(200))), &deps).max_borrow, Uint128::from(400u128));
+        assert_eq!(max_borrow(max_borrow_msg(Some(Decimal256::one())), &deps).max_borrow, Uint128::from(700u128));
+
+        // Borrowing the quoted amount passes the borrow hook, one more does not
+        deps.querier.set_position("usd_market", "alice", 0, 801);
+        assert!(handle(&mut deps, mock_env("usd_market", &[]), borrow_hook(701)).is_err());
+        deps.querier.set_position("usd_market", "alice", 0, 800);
+        handle(&mut deps, mock_env("usd_market", &[]), borrow_hook(700)).unwrap();
+        assert_eq!(max_borrow(max_borrow_msg(None), &deps).max_borrow, Uint128::zero());
+    }
+
+    #[test]
+    fn max_borrow_fits_under_the_borrow_cap() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        deps.querier.set_position("usd_market", "alice", 1_000, 0);
+        assert_eq!(max_borrow(max_borrow_msg(None), &deps).max_borrow, Uint128::from(800u128));
+
+        handle(&mut deps, mock_env("admin", &[]), HandleMsg::UpdateMarket {
+            market: HumanAddr::from("usd_market"),
+            collateral_factor: Uint128::from(80_000_000u128),
+            borrow_cap: Some(Uint128::from(5_000u128)),
+        })
+        .unwrap();
+        deps.querier.set_totals("usd_market", 0, 4_700, 0);
+        assert_eq!(max_borrow(max_borrow_msg(None), &deps).max_borrow, Uint128::from(300u128));
+    }
+
+    #[test]
+    fn protocol_totals_value_every_market_at_its_price() {
+        let mut deps = mock_comptroller(mock_init_msg());
+        list_market(&mut deps, "luna_market", "LUNA", Decimal256::percent(250), 50_000_000);
+        list_market(&mut deps, "usd_market", "UST", Decimal256::one(), 80_000_000);
+        deps.querier.set_totals("luna_market", 1_000, 600, 100);
+        deps.querier.set_totals("usd_market", 5_000, 2_000, 40);
+
+        let res: ProtocolTotalsResponse = from_binary(&query(&deps, QueryMsg::ProtocolTotals {}).unwrap()).unwrap();
+        assert_eq!(res.quote, "USD");
+        // 1_500 LUNA at 2.5 and 6_960 UST
+        assert_eq!(res.total_supplied, Uint256::from(3_750u64 + 6_960));
+        assert_eq!(res.total_borrowed, Uint256::from(1_500u64 + 2_000));
+        assert_eq!(res.total_reserves, Uint256::from(250u64 + 40));
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use cosmwasm_bignumber::Decimal256;
+use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
+use cosmwasm_std::{
+    from_binary, from_slice, to_binary, Empty, Extern, HumanAddr, Querier, QuerierResult, QueryRequest, StdError,
+    SystemError, Uint128, WasmQuery,
+};
+
+use quasar_interfaces::market::{AccountSnapshotResponse, MarketQueryMsg, MarketTotalsResponse};
+use quasar_interfaces::oracle::{OracleKind, TeFiPriceResponse, TeFiQueryMsg};
+
+use crate::contract::{handle, init};
+use crate::msg::{HandleMsg, InitMsg};
+
+pub const MOCK_ORACLE: &str = "oracle";
+pub const MOCK_QUOTE: &str = "USD";
+
+/// Comptroller reading the TeFi mock oracle without a minimum borrow health factor
+pub fn mock_init_msg() -> InitMsg {
+    InitMsg {
+        admin: None,
+        guardian: None,
+        oracle: HumanAddr::from(MOCK_ORACLE),
+        oracle_kind: OracleKind::TeFi,
+        quote: MOCK_QUOTE.to_string(),
+        min_borrow_health_factor: None,
+        freeze_haircut: None,
+        collateral_factor_grace: None,
+    }
+}
+
+/// MockQuerier that answers AccountSnapshot and MarketTotals queries of markets and TeFi price queries
+/// of oracles from the tables below
+/// Accounts without a snapshot hold nothing, symbols without a price fail the query
+pub struct MarketsMockQuerier {
+    pub base: MockQuerier,
+    /// Keyed by oracle and symbol
+    pub prices: HashMap<(HumanAddr, String), Decimal256>,
+    /// Keyed by market and account
+    pub snapshots: HashMap<(HumanAddr, HumanAddr), AccountSnapshotResponse>,
+    /// Market-wide balances, the borrows are also reported in every snapshot of the market
+    pub totals: HashMap<HumanAddr, MarketTotalsResponse>,
+}
+
+impl MarketsMockQuerier {
+    pub fn set_price(&mut self, oracle: &str, symbol: &str, price: Decimal256) {
+        self.prices.insert((HumanAddr::from(oracle), symbol.to_string()), price);
+    }
+
+    /// Position at an exchange rate of 1
+    pub fn set_position(&mut self, market: &str, account: &str, balance: u128, borrow_balance: u128) {
+        self.snapshots.insert((HumanAddr::from(market), HumanAddr::from(account)), AccountSnapshotResponse {
+            balance: Uint128::from(balance),
+            borrow_balance: Uint128::from(borrow_balance),
+            exchange_rate: Uint128::from(100_000_000u128),
+            total_borrows: Uint128::zero(),
+        });
+    }
+
+    pub fn set_totals(&mut self, market: &str, cash: u128, total_borrows: u128, total_reserves: u128) {
+        self.totals.insert(HumanAddr::from(market), MarketTotalsResponse {
+            cash: Uint128::from(cash),
+            total_borrows: Uint128::from(total_borrows),
+            total_reserves: Uint128::from(total_reserves),
+        });
+    }
+
+    fn market_totals(&self, market: &HumanAddr) -> MarketTotalsResponse {
+        self.totals.get(market).cloned().unwrap_or_default()
+    }
+
+    fn snapshot(&self, market: &HumanAddr, account: HumanAddr) -> AccountSnapshotResponse {
+        let mut snapshot = self.snapshots.get(&(market.clone(), account)).cloned().unwrap_or(AccountSnapshotResponse {
+            balance: Uint128::zero(),
+            borrow_balance: Uint128::zero(),
+            exchange_rate: Uint128::from(100_000_000u128),
+            total_borrows: Uint128::zero(),
+        });
+        snapshot.total_borrows = self.market_totals(market).total_borrows;
+        snapshot
+    }
+}
+
+impl Querier for MarketsMockQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let request: QueryRequest<Empty> = match from_slice(bin_request) {
+            Ok(request) => request,
+            Err(e) => {
+                return Err(SystemError::InvalidRequest {
+                    error: format!("Parsing query request: {}", e),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        let (contract_addr, msg) = match &request {
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => (contract_addr, msg),
+            _ => return self.base.handle_query(&request),
+        };
+        match from_binary(msg) {
+            Ok(MarketQueryMsg::AccountSnapshot { address }) => return Ok(to_binary(&self.snapshot(contract_addr, address))),
+            Ok(MarketQueryMsg::MarketTotals {}) => return Ok(to_binary(&self.market_totals(contract_addr))),
+            Err(_) => {}
+        }
+        if let Ok(TeFiQueryMsg::Price { base, .. }) = from_binary(msg) {
+            return Ok(match self.prices.get(&(contract_addr.clone(), base.clone())) {
+                Some(rate) => to_binary(&TeFiPriceResponse {
+                    rate: *rate,
+                    last_updated_base: 0,
+                    last_updated_quote: 0,
+                }),
+                None => Err(StdError::generic_err(format!("No price: oracle: {}, base: {}", contract_addr, base))),
+            });
+        }
+        Err(SystemError::NoSuchContract { addr: contract_addr.clone() })
+    }
+}
+
+/// Comptroller instantiated by `admin` from `msg`
+pub fn mock_comptroller(msg: InitMsg) -> Extern<MockStorage, MockApi, MarketsMockQuerier> {
+    let deps = mock_dependencies(20, &[]);
+    let mut deps = Extern {
+        storage: deps.storage,
+        api: deps.api,
+        querier: MarketsMockQuerier {
+            base: deps.querier,
+            prices: HashMap::new(),
+            snapshots: HashMap::new(),
+            totals: HashMap::new(),
+        },
+    };
+    init(&mut deps, mock_env("admin", &[]), msg).unwrap();
+    deps
+}
+
+/// Lists `market` priced under `symbol` at `price` by the mock oracle
+pub fn list_market(
+    deps: &mut Extern<MockStorage, MockApi, MarketsMockQuerier>,
+    market: &str,
+    symbol: &str,
+    price: Decimal256,
+    collateral_factor: u128,
+) {
+    deps.querier.set_price(MOCK_ORACLE, symbol, price);
+    handle(deps, mock_env("admin", &[]), HandleMsg::RegisterMarket {
+        market: HumanAddr::from(market),
+        symbol: symbol.to_string(),
+        collateral_factor: Uint128::from(collateral_factor),
+        borrow_cap: None,
+        lp_pool: None,
+    })
+    .unwrap();
+}
@@ -0,0 +1,373 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{HumanAddr, Uint128};
+
+use quasar_interfaces::comptroller::MarketAction;
+use quasar_interfaces::oracle::OracleKind;
+
+use crate::state::CollateralFactorChange;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InitMsg {
+    /// Defaults to the instantiating address
+    pub admin: Option<HumanAddr>,
+    pub guardian: Option<HumanAddr>,
+    pub oracle: HumanAddr,
+    pub oracle_kind: OracleKind,
+    /// Denom account liquidity is expressed in
+    pub quote: String,
+    pub min_borrow_health_factor: Option<Decimal256>,
+    /// Defaults to 10%
+    pub freeze_haircut: Option<Decimal256>,
+    /// Defaults to zero, cuts then apply to liquidations at once
+    pub collateral_factor_grace: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleMsg {
+    /// Admin only, list a q_native market
+    RegisterMarket {
+        market: HumanAddr,
+        symbol: String,
+        collateral_factor: Uint128,
+        borrow_cap: Option<Uint128>,
+        /// List an LP share market, `symbol` is then only a label
+        lp_pool: Option<LpPoolMsg>,
+    },
+    /// Admin only, change the risk parameters of a listed market. A lower collateral factor
+    /// only counts towards liquidations once the collateral factor grace period passed
+    UpdateMarket {
+        market: HumanAddr,
+        collateral_factor: Uint128,
+        borrow_cap: Option<Uint128>,
+    },
+    /// Count the sender's supply in these markets as collateral
+    EnterMarkets {
+        markets: Vec<HumanAddr>,
+    },
+    /// Stop counting a market as collateral, the sender must not owe it anything
+    /// and must stay solvent without it
+    ExitMarket {
+        market: HumanAddr,
+    },
+    /// Admin or guardian, the guardian may only pause. Pushed to every listed market
+    SetGlobalPause {
+        paused: bool,
+    },
+    /// Admin only, collateral over debt a borrow must leave the account at, None only
+    /// requires the account to stay out of shortfall
+    SetMinBorrowHealthFactor {
+        min_borrow_health_factor: Option<Decimal256>,
+    },
+    /// Admin only, cap the debt in `borrow` that supply in `collateral` may back
+    /// across all accounts, None lifts the ceiling
+    SetPairCeiling {
+        collateral: HumanAddr,
+        borrow: HumanAddr,
+        ceiling: Option<Uint128>,
+    },
+    /// Admin only, start reading `oracle` alongside the configured one. It can replace
+    /// it once no price diverged by more than `max_divergence` for `dual_read_blocks`
+    ProposeOracle {
+        oracle: HumanAddr,
+        oracle_kind: OracleKind,
+        max_divergence: Decimal256,
+        dual_read_blocks: u64,
+    },
+    /// Compare both oracles' prices of every listed market, a divergence restarts the
+    /// dual-read period. Anyone may call it
+    CheckOracle {},
+    /// Admin only, switch to the proposed oracle after a final comparison
+    FinalizeOracle {},
+    /// Admin only, drop the proposed oracle
+    CancelOracle {},
+    /// Read the oracle price of a market, anyone may call it. A price the oracle refuses to
+    /// report means the feed was deregistered and freezes the market: no new borrows, and
+    /// its collateral is valued at the last good price less the freeze haircut
+    CheckFeed {
+        market: HumanAddr,
+    },
+    /// Admin only, lift the freeze once the oracle prices the market again
+    ResumeMarket {
+        market: HumanAddr,
+    },
+    /// Admin only, blocks a collateral factor cut waits before it counts towards liquidations,
+    /// zero applies cuts at once
+    SetCollateralFactorGrace {
+        blocks: u64,
+    },
+    /// Admin only, share taken off the last good price of frozen collateral
+    SetFreezeHaircut {
+        freeze_haircut: Decimal256,
+    },
+    /// Sent by listed markets after every mint, redeem, borrow, repay, transfer and seize
+    MarketHook {
+        action: MarketAction,
+        account: HumanAddr,
+        amount: Uint128,
+    },
+    /// Sent by a listed market that repaid a liquidation, has `collateral_market`
+    /// seize the repaid value at oracle prices
+    SeizeCollateral {
+        collateral_market: HumanAddr,
+        borrower: HumanAddr,
+        liquidator: HumanAddr,
+        repay_amount: Uint128,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Config {},
+    Market {
+        market: HumanAddr,
+    },
+    Markets {},
+    AssetsIn {
+        address: HumanAddr,
+    },
+    GlobalPause {},
+    /// Borrow capacity left, or debt over the limit, across all entered markets
+    AccountLiquidity {
+        address: HumanAddr,
+    },
+    /// Shortfall liquidations are measured against at `block`, collateral factor cuts
+    /// still in their grace period count at the factor before the cut
+    LiquidationShortfall {
+        address: HumanAddr,
+        block: u64,
+    },
+    /// Largest borrow from `market` that leaves `account` at `health_factor`, which
+    /// defaults to and never goes below the minimum borrow health factor, and fits
+    /// under the market's borrow cap
+    MaxBorrow {
+        account: HumanAddr,
+        market: HumanAddr,
+        health_factor: Option<Decimal256>,
+    },
+    /// Supplied, borrowed and reserve value summed over every listed market in the quote denom
+    ProtocolTotals {},
+    /// Bad debt left if prices moved by `shocks`, computed over up to `limit` accounts
+    /// per call. Pass the returned continuation back until it is None
+    StressTest {
+        shocks: Vec<PriceShock>,
+        limit: Option<u32>,
+        continuation: Option<StressContinuation>,
+    },
+    PairCeilings {},
+    /// Oracle switch in progress, None if none was proposed
+    PendingOracle {},
+    /// None unless the market's feed was found deregistered
+    MarketFreeze {
+        market: HumanAddr,
+    },
+    /// Prices of every listed market from the configured and the proposed oracle
+    OracleDivergence {},
+    /// Repeats StressTest in rounds, closing out the accounts that fall into shortfall
+    /// and selling their collateral into `depths` before the next round, until a round
+    /// liquidates nobody or `max_rounds` is reached. Up to `limit` accounts are visited
+    /// per call, pass the returned continuation back until it is None
+    LiquidationCascade {
+        shocks: Vec<PriceShock>,
+        depths: Vec<MarketDepth>,
+        /// Collateral seized per unit of debt repaid, e.g. 1.08
+        liquidation_incentive: Decimal256,
+        max_rounds: Option<u32>,
+        limit: Option<u32>,
+        continuation: Option<CascadeContinuation>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LpPoolMsg {
+    pub pair: HumanAddr,
+    /// Oracle symbols of the pool assets, in the order the pair reports them
+    pub symbols: [String; 2],
+}
+
+/// Scenario price of a market's underlying, as a multiple of the oracle price
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceShock {
+    pub market: HumanAddr,
+    /// e.g. 0.7 for a 30% drop, markets without a shock keep the oracle price
+    pub price_multiplier: Decimal256,
+}
+
+/// Totals over the accounts already visited and where to resume
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StressContinuation {
+    pub start_after: HumanAddr,
+    pub bad_debt: Uint256,
+    pub accounts_in_shortfall: u64,
+    pub accounts_checked: u64,
+}
+
+/// Liquidity a market's underlying can be sold into, the price falls by the share
+/// of `depth` sold, so selling `depth` of quote value takes it to zero
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MarketDepth {
+    pub market: HumanAddr,
+    pub depth: Uint256,
+}
+
+/// Quote value of collateral seized from a market
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SeizedCollateral {
+    pub market: HumanAddr,
+    pub value: Uint256,
+}
+
+/// Round being simulated, the accounts already visited in it and totals so far
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CascadeContinuation {
+    pub round: u32,
+    /// Prices of this round as multiples of the oracle price, one per market
+    pub multipliers: Vec<PriceShock>,
+    /// Prices of the previous round, empty in the first one
+    pub previous_multipliers: Vec<PriceShock>,
+    pub seized: Vec<SeizedCollateral>,
+    pub start_after: Option<HumanAddr>,
+    pub bad_debt: Uint256,
+    pub accounts_liquidated: u64,
+    /// Part of accounts_liquidated closed out in this round
+    pub round_liquidated: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub admin: HumanAddr,
+    pub guardian: Option<HumanAddr>,
+    pub oracle: HumanAddr,
+    pub oracle_kind: OracleKind,
+    pub quote: String,
+    pub min_borrow_health_factor: Option<Decimal256>,
+    pub freeze_haircut: Decimal256,
+    pub collateral_factor_grace: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MarketFreezeResponse {
+    /// Last good price, None if none was read before the freeze
+    pub price: Option<Decimal256>,
+    pub frozen_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PendingOracleResponse {
+    pub oracle: HumanAddr,
+    pub oracle_kind: OracleKind,
+    pub max_divergence: Decimal256,
+    pub dual_read_blocks: u64,
+    pub start: u64,
+    pub diverged_at: Option<u64>,
+    /// First block FinalizeOracle is accepted at
+    pub finalize_after: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MarketDivergence {
+    pub market: HumanAddr,
+    pub price: Decimal256,
+    pub proposed_price: Decimal256,
+    /// Difference relative to `price`
+    pub divergence: Decimal256,
+    pub diverged: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct OracleDivergenceResponse {
+    pub markets: Vec<MarketDivergence>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct GlobalPauseResponse {
+    pub paused: bool,
+    pub epoch: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MarketResponse {
+    pub market: HumanAddr,
+    pub symbol: String,
+    pub collateral_factor: Uint128,
+    pub borrow_cap: Option<Uint128>,
+    pub lp_pool: Option<LpPoolMsg>,
+    /// Last collateral factor cut, liquidations use `previous_collateral_factor` before `effective_at`
+    pub collateral_factor_change: Option<CollateralFactorChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MarketsResponse {
+    pub markets: Vec<MarketResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AssetsInResponse {
+    pub markets: Vec<HumanAddr>,
+}
+
+pub use quasar_interfaces::comptroller::{AccountLiquidityResponse, LiquidationShortfallResponse};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MaxBorrowResponse {
+    /// Health factor the borrow was sized for
+    pub health_factor: Decimal256,
+    /// In the market's underlying
+    pub max_borrow: Uint128,
+    /// Collateral and debt value in the quote denom before the borrow
+    pub collateral_value: Uint256,
+    pub borrow_value: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ProtocolTotalsResponse {
+    pub quote: String,
+    /// Cash plus borrows less reserves, what suppliers are owed
+    pub total_supplied: Uint256,
+    pub total_borrowed: Uint256,
+    pub total_reserves: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PairCeilingResponse {
+    pub collateral: HumanAddr,
+    pub borrow: HumanAddr,
+    pub ceiling: Uint128,
+    /// Debt attributed to the pair as of each account's last hook
+    pub total_debt: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PairCeilingsResponse {
+    pub pairs: Vec<PairCeilingResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct StressTestResponse {
+    pub quote: String,
+    /// Debt exceeding the full shocked value of the account's supply, summed over accounts
+    pub bad_debt: Uint256,
+    /// Accounts that could be liquidated at the shocked prices
+    pub accounts_in_shortfall: u64,
+    pub accounts_checked: u64,
+    /// None once every account was visited, the totals above are then final
+    pub continuation: Option<StressContinuation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct LiquidationCascadeResponse {
+    pub quote: String,
+    /// Rounds that liquidated at least one account
+    pub depth: u32,
+    /// Debt the closed out accounts' collateral could not cover
+    pub bad_debt: Uint256,
+    pub accounts_liquidated: u64,
+    /// Prices the cascade ends at, as multiples of the oracle price
+    pub multipliers: Vec<PriceShock>,
+    /// None once the cascade settled, the figures above are then final
+    pub continuation: Option<CascadeContinuation>,
+}
@@ -0,0 +1,338 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_bignumber::Decimal256;
+use cosmwasm_std::{CanonicalAddr, Order, StdResult, Storage, Uint128};
+use cosmwasm_storage::{Bucket, ReadonlyBucket, ReadonlySingleton, Singleton};
+
+use quasar_interfaces::oracle::OracleKind;
+use quasar_interfaces::pagination::calc_range_start;
+
+pub static CONFIG_KEY: &[u8] = b"config";
+pub static MARKET_PREFIX: &[u8] = b"market";
+pub static ACCOUNT_MARKETS_PREFIX: &[u8] = b"account_markets";
+pub static GLOBAL_PAUSE_KEY: &[u8] = b"global_pause";
+pub static PAIR_CEILING_PREFIX: &[u8] = b"pair_ceiling";
+pub static ACCOUNT_PAIR_DEBT_PREFIX: &[u8] = b"account_pair_debt";
+pub static PENDING_ORACLE_KEY: &[u8] = b"pending_oracle";
+pub static FEED_PRICE_PREFIX: &[u8] = b"feed_price";
+pub static MARKET_FREEZE_PREFIX: &[u8] = b"market_freeze";
+pub static COLLATERAL_FACTOR_CHANGE_PREFIX: &[u8] = b"collateral_factor_change";
+
+/// Config struct
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub admin: CanonicalAddr,
+    /// May pause every market at once, only the admin can unpause
+    pub guardian: Option<CanonicalAddr>,
+    /// Price feed every market is valued against
+    pub oracle: CanonicalAddr,
+    pub oracle_kind: OracleKind,
+    /// Denom account liquidity is expressed in, e.g. "USD"
+    pub quote: String,
+    /// Collateral over debt a borrow must leave the account at, e.g. 1.1, None only requires no shortfall
+    pub min_borrow_health_factor: Option<Decimal256>,
+    /// Taken off the last good price of a frozen market's collateral, e.g. 0.1
+    pub freeze_haircut: Decimal256,
+    /// Blocks a collateral factor cut waits before it counts towards liquidations
+    pub collateral_factor_grace: u64,
+}
+
+/// Highest minimum health factor the admin may require of new borrows
+pub const MAX_MIN_BORROW_HEALTH_FACTOR: u64 = 2;
+/// Haircut on frozen collateral when the init message sets none
+pub const DEFAULT_FREEZE_HAIRCUT_PERCENT: u64 = 10;
+
+/// Market struct
+/// `collateral_factor` is scaled by 10^8 and replaces the one set on the market
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Market {
+    pub address: CanonicalAddr,
+    /// Symbol the oracle prices the market's underlying under
+    pub symbol: String,
+    pub collateral_factor: Uint128,
+    /// Largest total borrows of the market, None is uncapped
+    pub borrow_cap: Option<Uint128>,
+    /// Set when the underlying is a DEX LP share, priced from the pool instead of `symbol`
+    pub lp_pool: Option<LpPool>,
+}
+
+/// Collateral factor cut of a market still in its grace period until `effective_at`,
+/// liquidations keep using `previous_collateral_factor` until then
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollateralFactorChange {
+    pub previous_collateral_factor: Uint128,
+    pub effective_at: u64,
+}
+
+impl CollateralFactorChange {
+    /// Collateral factor liquidations use at `block`, never below the current one
+    pub fn liquidation_collateral_factor(&self, collateral_factor: Uint128, block: u64) -> Uint128 {
+        match block < self.effective_at {
+            true => self.previous_collateral_factor.max(collateral_factor),
+            false => collateral_factor,
+        }
+    }
+}
+
+/// Pair contract of an LP underlying and the oracle symbols of its assets in pool order
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LpPool {
+    pub pair: CanonicalAddr,
+    pub symbols: [String; 2],
+}
+
+/// Debt in the `borrow` market's underlying that may be backed by supply in the
+/// `collateral` market, summed over accounts
+/// An account's debt is attributed to its entered markets by their share of its
+/// collateral value, and recounted whenever the account is touched
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairCeiling {
+    pub collateral: CanonicalAddr,
+    pub borrow: CanonicalAddr,
+    pub ceiling: Uint128,
+    pub total_debt: Uint128,
+}
+
+/// Oracle read alongside the configured one until it replaces it
+/// Any price diverging by more than `max_divergence` restarts the dual-read period
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOracle {
+    pub oracle: CanonicalAddr,
+    pub oracle_kind: OracleKind,
+    /// Largest relative difference from the configured oracle's price
+    pub max_divergence: Decimal256,
+    /// Blocks both oracles must agree for before the switch can be finalized
+    pub dual_read_blocks: u64,
+    pub start: u64,
+    /// Last block a check saw a divergence, None if none was seen
+    pub diverged_at: Option<u64>,
+}
+
+impl PendingOracle {
+    /// First block the switch can be finalized at
+    pub fn finalize_after(&self) -> u64 {
+        self.diverged_at.unwrap_or(self.start).max(self.start) + self.dual_read_blocks
+    }
+}
+
+/// Price of a market's underlying the last successful CheckFeed or ResumeMarket read
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeedPrice {
+    pub price: Decimal256,
+    pub block: u64,
+}
+
+/// Market whose oracle feed was found deregistered, valued at the last good price until
+/// the admin resumes it. None if no price was ever read, the market then cannot be valued
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MarketFreeze {
+    pub price: Option<Decimal256>,
+    pub frozen_at: u64,
+}
+
+/// Protocol-wide pause, `epoch` increases with every change so markets can drop stale updates
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct GlobalPause {
+    pub paused: bool,
+    pub epoch: u64,
+}
+
+/// Get config
+pub fn get_config<S: Storage>(storage: &S) -> StdResult<Config> {
+    ReadonlySingleton::new(storage, CONFIG_KEY).load()
+}
+
+/// Set config
+pub fn set_config<S: Storage>(storage: &mut S, config: &Config) -> StdResult<()> {
+    Singleton::new(storage, CONFIG_KEY).save(config)
+}
+
+/// Get a registered market, None if it was never registered
+pub fn get_market<S: Storage>(storage: &S, address: &CanonicalAddr) -> StdResult<Option<Market>> {
+    ReadonlyBucket::new(MARKET_PREFIX, storage).may_load(address.as_slice())
+}
+
+/// Set market
+pub fn set_market<S: Storage>(storage: &mut S, market: &Market) -> StdResult<()> {
+    Bucket::new(MARKET_PREFIX, storage).save(market.address.as_slice(), market)
+}
+
+/// All registered markets in key order
+pub fn get_markets<S: Storage>(storage: &S) -> StdResult<Vec<Market>> {
+    ReadonlyBucket::new(MARKET_PREFIX, storage)
+        .range(None, None, Order::Ascending)
+        .map(|item| item.map(|(_, market)| market))
+        .collect()
+}
+
+/// Markets the account entered, in the order they were entered
+pub fn get_account_markets<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<Vec<CanonicalAddr>> {
+    Ok(ReadonlyBucket::new(ACCOUNT_MARKETS_PREFIX, storage)
+        .may_load(account.as_slice())?
+        .unwrap_or_default())
+}
+
+/// Up to `limit` accounts with entered markets, in key order after `start_after`
+pub fn get_accounts_markets<S: Storage>(
+    storage: &S,
+    start_after: Option<&CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<(CanonicalAddr, Vec<CanonicalAddr>)>> {
+    let start = calc_range_start(start_after.map(|account| account.as_slice()));
+    ReadonlyBucket::new(ACCOUNT_MARKETS_PREFIX, storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(key, markets)| (CanonicalAddr::from(key), markets)))
+        .collect()
+}
+
+/// Set entered markets, an empty list removes the entry
+pub fn set_account_markets<S: Storage>(storage: &mut S, account: &CanonicalAddr, markets: &[CanonicalAddr]) -> StdResult<()> {
+    let mut bucket: Bucket<S, Vec<CanonicalAddr>> = Bucket::new(ACCOUNT_MARKETS_PREFIX, storage);
+    if markets.is_empty() {
+        bucket.remove(account.as_slice());
+        Ok(())
+    } else {
+        bucket.save(account.as_slice(), &markets.to_vec())
+    }
+}
+
+pub fn get_pending_oracle<S: Storage>(storage: &S) -> StdResult<Option<PendingOracle>> {
+    ReadonlySingleton::new(storage, PENDING_ORACLE_KEY).may_load()
+}
+
+/// Set the pending oracle switch, None cancels it
+pub fn set_pending_oracle<S: Storage>(storage: &mut S, pending: Option<&PendingOracle>) -> StdResult<()> {
+    match pending {
+        Some(pending) => Singleton::new(storage, PENDING_ORACLE_KEY).save(pending),
+        None => {
+            Singleton::<S, PendingOracle>::new(storage, PENDING_ORACLE_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+pub fn get_feed_price<S: Storage>(storage: &S, market: &CanonicalAddr) -> StdResult<Option<FeedPrice>> {
+    ReadonlyBucket::new(FEED_PRICE_PREFIX, storage).may_load(market.as_slice())
+}
+
+pub fn set_feed_price<S: Storage>(storage: &mut S, market: &CanonicalAddr, price: &FeedPrice) -> StdResult<()> {
+    Bucket::new(FEED_PRICE_PREFIX, storage).save(market.as_slice(), price)
+}
+
+/// Get the freeze of a market, None while its feed works
+pub fn get_market_freeze<S: Storage>(storage: &S, market: &CanonicalAddr) -> StdResult<Option<MarketFreeze>> {
+    ReadonlyBucket::new(MARKET_FREEZE_PREFIX, storage).may_load(market.as_slice())
+}
+
+/// Set the freeze of a market, None resumes it
+pub fn set_market_freeze<S: Storage>(
+    storage: &mut S,
+    market: &CanonicalAddr,
+    freeze: Option<&MarketFreeze>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(MARKET_FREEZE_PREFIX, storage);
+    match freeze {
+        Some(freeze) => bucket.save(market.as_slice(), freeze),
+        None => {
+            bucket.remove(market.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Get the last collateral factor cut of a market, None if its factor was never cut
+/// with a grace period or was raised back since
+pub fn get_collateral_factor_change<S: Storage>(
+    storage: &S,
+    market: &CanonicalAddr,
+) -> StdResult<Option<CollateralFactorChange>> {
+    ReadonlyBucket::new(COLLATERAL_FACTOR_CHANGE_PREFIX, storage).may_load(market.as_slice())
+}
+
+pub fn set_collateral_factor_change<S: Storage>(
+    storage: &mut S,
+    market: &CanonicalAddr,
+    change: Option<&CollateralFactorChange>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(COLLATERAL_FACTOR_CHANGE_PREFIX, storage);
+    match change {
+        Some(change) => bucket.save(market.as_slice(), change),
+        None => {
+            bucket.remove(market.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Get global pause
+pub fn get_global_pause<S: Storage>(storage: &S) -> StdResult<GlobalPause> {
+    Ok(ReadonlySingleton::new(storage, GLOBAL_PAUSE_KEY).may_load()?.unwrap_or_default())
+}
+
+/// Set global pause
+pub fn set_global_pause<S: Storage>(storage: &mut S, pause: &GlobalPause) -> StdResult<()> {
+    Singleton::new(storage, GLOBAL_PAUSE_KEY).save(pause)
+}
+
+fn pair_key(collateral: &CanonicalAddr, borrow: &CanonicalAddr) -> Vec<u8> {
+    let mut key = vec![collateral.len() as u8];
+    key.extend_from_slice(collateral.as_slice());
+    key.extend_from_slice(borrow.as_slice());
+    key
+}
+
+/// Get a pair ceiling, None if the pair is uncapped
+pub fn get_pair_ceiling<S: Storage>(
+    storage: &S,
+    collateral: &CanonicalAddr,
+    borrow: &CanonicalAddr,
+) -> StdResult<Option<PairCeiling>> {
+    ReadonlyBucket::new(PAIR_CEILING_PREFIX, storage).may_load(&pair_key(collateral, borrow))
+}
+
+/// All pair ceilings in key order
+pub fn get_pair_ceilings<S: Storage>(storage: &S) -> StdResult<Vec<PairCeiling>> {
+    ReadonlyBucket::new(PAIR_CEILING_PREFIX, storage)
+        .range(None, None, Order::Ascending)
+        .map(|item| item.map(|(_, pair)| pair))
+        .collect()
+}
+
+pub fn set_pair_ceiling<S: Storage>(storage: &mut S, pair: &PairCeiling) -> StdResult<()> {
+    Bucket::new(PAIR_CEILING_PREFIX, storage).save(&pair_key(&pair.collateral, &pair.borrow), pair)
+}
+
+pub fn remove_pair_ceiling<S: Storage>(storage: &mut S, collateral: &CanonicalAddr, borrow: &CanonicalAddr) {
+    let mut bucket: Bucket<S, PairCeiling> = Bucket::new(PAIR_CEILING_PREFIX, storage);
+    bucket.remove(&pair_key(collateral, borrow));
+}
+
+/// Debt of the account last attributed to the pair
+pub fn get_account_pair_debt<S: Storage>(
+    storage: &S,
+    account: &CanonicalAddr,
+    collateral: &CanonicalAddr,
+    borrow: &CanonicalAddr,
+) -> StdResult<Uint128> {
+    let bucket = ReadonlyBucket::multilevel(&[ACCOUNT_PAIR_DEBT_PREFIX, account.as_slice()], storage);
+    Ok(bucket.may_load(&pair_key(collateral, borrow))?.unwrap_or_default())
+}
+
+/// Set attributed debt, zero removes the entry
+pub fn set_account_pair_debt<S: Storage>(
+    storage: &mut S,
+    account: &CanonicalAddr,
+    collateral: &CanonicalAddr,
+    borrow: &CanonicalAddr,
+    debt: Uint128,
+) -> StdResult<()> {
+    let mut bucket: Bucket<S, Uint128> = Bucket::multilevel(&[ACCOUNT_PAIR_DEBT_PREFIX, account.as_slice()], storage);
+    if debt.is_zero() {
+        bucket.remove(&pair_key(collateral, borrow));
+        Ok(())
+    } else {
+        bucket.save(&pair_key(collateral, borrow), &debt)
+    }
+}
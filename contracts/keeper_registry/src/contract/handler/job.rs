@@ -0,0 +1,353 @@
+use cosmwasm_std::{
+    log, Api, BankMsg, Binary, Coin, CosmosMsg, Env, Extern, HandleResponse, HumanAddr, Querier,
+    StdError, StdResult, Storage, Uint128, WasmMsg,
+};
+
+use crate::state::{get_config, get_job, next_job_id, set_config, set_job, Config, Job, JobKind};
+
+/// Jobs run with the registry as sender, so only the admin may register them
+pub fn try_register_job<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    kind: JobKind,
+    target: HumanAddr,
+    msg: Binary,
+    bounty: Uint128,
+    cooldown: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    assert_admin(deps, &env, &config)?;
+    if cooldown == 0 {
+        return Err(StdError::generic_err("Job cooldown must be at least one block"));
+    }
+    let funds = match env.message.sent_funds.is_empty() {
+        true => Uint128::zero(),
+        false => sent_bounty(&env, &config.denom)?,
+    };
+
+    let job = Job {
+        id: next_job_id(&mut deps.storage)?,
+        kind,
+        target,
+        msg,
+        bounty,
+        cooldown,
+        funds,
+        last_executed: None,
+        active: true,
+    };
+    set_job(&mut deps.storage, &job)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "register_job"),
+            log("job_id", job.id),
+            log("target", job.target.as_str()),
+            log("bounty", bounty),
+            log("cooldown", cooldown),
+            log("funds", funds),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Removing a job refunds its budget to the admin, so only the admin may fund it
+pub fn try_fund_job<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    assert_admin(deps, &env, &config)?;
+    let mut job = get_job(&deps.storage, id)?;
+    if !job.active {
+        return Err(StdError::generic_err(format!("Job is not active: job_id: {}", id)));
+    }
+    let amount = sent_bounty(&env, &config.denom)?;
+    job.funds += amount;
+    set_job(&mut deps.storage, &job)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "fund_job"),
+            log("job_id", id),
+            log("sender", env.message.sender.as_str()),
+            log("amount", amount),
+            log("funds", job.funds),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_update_job<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+    bounty: Uint128,
+    cooldown: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    assert_admin(deps, &env, &config)?;
+    if cooldown == 0 {
+        return Err(StdError::generic_err("Job cooldown must be at least one block"));
+    }
+    let mut job = get_job(&deps.storage, id)?;
+    job.bounty = bounty;
+    job.cooldown = cooldown;
+    set_job(&mut deps.storage, &job)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "update_job"),
+            log("job_id", id),
+            log("bounty", bounty),
+            log("cooldown", cooldown),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_remove_job<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    assert_admin(deps, &env, &config)?;
+    let mut job = get_job(&deps.storage, id)?;
+    if !job.active {
+        return Err(StdError::generic_err(format!("Job is not active: job_id: {}", id)));
+    }
+
+    let refund = job.funds;
+    job.active = false;
+    job.funds = Uint128::zero();
+    set_job(&mut deps.storage, &job)?;
+
+    let mut messages = vec![];
+    if !refund.is_zero() {
+        messages.push(payout(&env, &env.message.sender, &config.denom, refund));
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "remove_job"),
+            log("job_id", id),
+            log("refund", refund),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// The bounty is paid after the job message, so a failing job pays nothing
+pub fn try_execute<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let mut job = get_job(&deps.storage, id)?;
+    if !job.active {
+        return Err(StdError::generic_err(format!("Job is not active: job_id: {}", id)));
+    }
+    let next_executable = job.next_executable();
+    if env.block.height < next_executable {
+        return Err(StdError::generic_err(format!(
+            "Job is cooling down: current_block: {}, next_executable: {}",
+            env.block.height, next_executable
+        )));
+    }
+    if job.funds < job.bounty {
+        return Err(StdError::generic_err(format!(
+            "Job has insufficient funds for its bounty: funds: {}, bounty: {}",
+            job.funds, job.bounty
+        )));
+    }
+
+    job.last_executed = Some(env.block.height);
+    job.funds = (job.funds - job.bounty)?;
+    set_job(&mut deps.storage, &job)?;
+
+    let mut messages = vec![CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: job.target.clone(),
+        msg: job.msg.clone(),
+        send: vec![],
+    })];
+    if !job.bounty.is_zero() {
+        messages.push(payout(&env, &env.message.sender, &config.denom, job.bounty));
+    }
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "execute"),
+            log("job_id", id),
+            log("keeper", env.message.sender.as_str()),
+            log("target", job.target.as_str()),
+            log("bounty", job.bounty),
+            log("funds", job.funds),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_set_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    admin: HumanAddr,
+) -> StdResult<HandleResponse> {
+    let mut config = get_config(&deps.storage)?;
+    assert_admin(deps, &env, &config)?;
+    config.admin = deps.api.canonical_address(&admin)?;
+    set_config(&mut deps.storage, &config)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_admin"),
+            log("admin", admin.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+fn assert_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    config: &Config,
+) -> StdResult<()> {
+    if deps.api.canonical_address(&env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    Ok(())
+}
+
+fn sent_bounty(env: &Env, denom: &str) -> StdResult<Uint128> {
+    match env.message.sent_funds.as_slice() {
+        [coin] if coin.denom == denom && !coin.amount.is_zero() => Ok(coin.amount),
+        _ => Err(StdError::generic_err(format!(
+            "Bounty funds must be sent as a single non-zero {} coin",
+            denom
+        ))),
+    }
+}
+
+fn payout(env: &Env, recipient: &HumanAddr, denom: &str, amount: Uint128) -> CosmosMsg {
+    CosmosMsg::Bank(BankMsg::Send {
+        from_address: env.contract.address.clone(),
+        to_address: recipient.clone(),
+        amount: vec![Coin {
+            denom: denom.to_string(),
+            amount,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::coins;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
+
+    use crate::contract::{handle, init};
+    use crate::msg::{HandleMsg, InitMsg};
+
+    fn env_at(sender: &str, height: u64, funds: &[Coin]) -> Env {
+        let mut env = mock_env(sender, funds);
+        env.block.height = height;
+        env
+    }
+
+    /// Registry with job 1 funded for two bounties of 10 every 100 blocks
+    fn registry() -> Extern<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies(20, &[]);
+        init(&mut deps, mock_env("admin", &[]), InitMsg { admin: None, denom: "uusd".to_string() }).unwrap();
+        handle(&mut deps, env_at("admin", 20_000, &coins(20, "uusd")), HandleMsg::RegisterJob {
+            kind: JobKind::Accrue,
+            target: HumanAddr::from("market"),
+            msg: Binary::from(b"{\"accrue_interest\":{}}".to_vec()),
+            bounty: Uint128::from(10u128),
+            cooldown: 100,
+        })
+        .unwrap();
+        deps
+    }
+
+    #[test]
+    fn keeper_is_paid_after_the_job_message_once_per_cooldown() {
+        let mut deps = registry();
+        let res = handle(&mut deps, env_at("keeper", 20_000, &[]), HandleMsg::Execute { id: 1 }).unwrap();
+        assert_eq!(res.messages, vec![
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: HumanAddr::from("market"),
+                msg: Binary::from(b"{\"accrue_interest\":{}}".to_vec()),
+                send: vec![],
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from("cosmos2contract"),
+                to_address: HumanAddr::from("keeper"),
+                amount: coins(10, "uusd"),
+            }),
+        ]);
+
+        assert_eq!(
+            handle(&mut deps, env_at("keeper", 20_099, &[]), HandleMsg::Execute { id: 1 }).unwrap_err(),
+            StdError::generic_err("Job is cooling down: current_block: 20099, next_executable: 20100")
+        );
+        handle(&mut deps, env_at("other", 20_100, &[]), HandleMsg::Execute { id: 1 }).unwrap();
+        // Both bounties paid out, the job waits for funding
+        assert_eq!(
+            handle(&mut deps, env_at("keeper", 20_200, &[]), HandleMsg::Execute { id: 1 }).unwrap_err(),
+            StdError::generic_err("Job has insufficient funds for its bounty: funds: 0, bounty: 10")
+        );
+        handle(&mut deps, env_at("admin", 20_200, &coins(10, "uusd")), HandleMsg::FundJob { id: 1 }).unwrap();
+        handle(&mut deps, env_at("keeper", 20_200, &[]), HandleMsg::Execute { id: 1 }).unwrap();
+    }
+
+    #[test]
+    fn only_the_admin_funds_jobs_in_the_bounty_denom() {
+        let mut deps = registry();
+        assert_eq!(
+            handle(&mut deps, env_at("keeper", 20_000, &coins(10, "uusd")), HandleMsg::FundJob { id: 1 }).unwrap_err(),
+            StdError::unauthorized()
+        );
+        for funds in &[coins(10, "uluna"), vec![], coins(0, "uusd")] {
+            assert_eq!(
+                handle(&mut deps, env_at("admin", 20_000, funds), HandleMsg::FundJob { id: 1 }).unwrap_err(),
+                StdError::generic_err("Bounty funds must be sent as a single non-zero uusd coin")
+            );
+        }
+        assert!(handle(&mut deps, env_at("admin", 20_000, &[]), HandleMsg::UpdateJob {
+            id: 1,
+            bounty: Uint128::from(10u128),
+            cooldown: 0,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn removing_a_job_refunds_its_budget() {
+        let mut deps = registry();
+        handle(&mut deps, env_at("keeper", 20_000, &[]), HandleMsg::Execute { id: 1 }).unwrap();
+        let res = handle(&mut deps, env_at("admin", 20_001, &[]), HandleMsg::RemoveJob { id: 1 }).unwrap();
+        assert_eq!(res.messages, vec![CosmosMsg::Bank(BankMsg::Send {
+            from_address: HumanAddr::from("cosmos2contract"),
+            to_address: HumanAddr::from("admin"),
+            amount: coins(10, "uusd"),
+        })]);
+        assert_eq!(
+            handle(&mut deps, env_at("keeper", 20_100, &[]), HandleMsg::Execute { id: 1 }).unwrap_err(),
+            StdError::generic_err("Job is not active: job_id: 1")
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use cosmwasm_std::{Api, Empty, Env, Extern, HandleResponse, Querier, StdResult, Storage};
+
+use crate::msg::HandleMsg;
+
+mod job;
+
+/// General handler for contract tx input
+/// tx inputs are defined HandleMsg enum in msg.rs file
+pub fn handle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: HandleMsg,
+) -> StdResult<HandleResponse<Empty>> {
+    match msg {
+        HandleMsg::RegisterJob {
+            kind,
+            target,
+            msg,
+            bounty,
+            cooldown,
+        } => job::try_register_job(deps, env, kind, target, msg, bounty, cooldown),
+        HandleMsg::FundJob { id } => job::try_fund_job(deps, env, id),
+        HandleMsg::UpdateJob { id, bounty, cooldown } => job::try_update_job(deps, env, id, bounty, cooldown),
+        HandleMsg::RemoveJob { id } => job::try_remove_job(deps, env, id),
+        HandleMsg::Execute { id } => job::try_execute(deps, env, id),
+        HandleMsg::SetAdmin { admin } => job::try_set_admin(deps, env, admin),
+    }
+}
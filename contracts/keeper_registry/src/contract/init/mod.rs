@@ -0,0 +1,25 @@
+use cosmwasm_std::{Api, Env, Extern, InitResponse, Querier, StdError, StdResult, Storage};
+
+use crate::msg::InitMsg;
+use crate::state::{set_config, Config};
+
+/// Contract instantiation tx
+/// tx inputs are specified in InitMsg in msg.rs file
+pub fn init<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: InitMsg,
+) -> StdResult<InitResponse> {
+    if msg.denom.is_empty() {
+        return Err(StdError::generic_err("Bounty denom must not be empty"));
+    }
+
+    let admin = msg.admin.unwrap_or(env.message.sender);
+    let init_config = Config {
+        admin: deps.api.canonical_address(&admin)?,
+        denom: msg.denom,
+    };
+    set_config(&mut deps.storage, &init_config)?;
+
+    Ok(InitResponse::default())
+}
@@ -0,0 +1,9 @@
+pub mod handler;
+pub mod init;
+pub mod querier;
+
+pub use handler::handle;
+
+pub use init::init;
+
+pub use querier::query;
@@ -0,0 +1,63 @@
+use cosmwasm_std::{to_binary, Api, Binary, Extern, Querier, StdError, StdResult, Storage};
+
+use quasar_interfaces::pagination::{paginate, PageRequest};
+
+use crate::msg::{ConfigResponse, JobResponse, JobsResponse, QueryMsg};
+use crate::state::{get_config, get_job, get_jobs, Job};
+
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => {
+            let config = get_config(&deps.storage)?;
+            let out = to_binary(&ConfigResponse {
+                admin: deps.api.human_address(&config.admin)?,
+                denom: config.denom,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::Job { id } => to_binary(&job_response(get_job(&deps.storage, id)?)),
+        QueryMsg::Jobs { executable_at, page } => {
+            to_binary(&query_jobs(deps, executable_at, page.unwrap_or_default())?)
+        }
+    }
+}
+
+fn query_jobs<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    executable_at: Option<u64>,
+    page: PageRequest,
+) -> StdResult<JobsResponse> {
+    let start_after = match page.start_after.as_ref() {
+        Some(id) => Some(id.parse::<u64>().map_err(|_| {
+            StdError::generic_err(format!("Invalid job id: start_after: {}", id))
+        })?),
+        None => None,
+    };
+
+    // A job is listed as executable only if it is active, past its cooldown and funded
+    let jobs = get_jobs(&deps.storage, start_after, page.limit() + 1, |job| match executable_at {
+        Some(block) => job.active && job.next_executable() <= block && job.funds >= job.bounty,
+        None => true,
+    })?;
+    let jobs = jobs.into_iter().map(|job| (job.id.to_string(), job_response(job)));
+    let (jobs, page) = paginate(jobs, page.limit(), None);
+    Ok(JobsResponse { jobs, page })
+}
+
+fn job_response(job: Job) -> JobResponse {
+    JobResponse {
+        id: job.id,
+        kind: job.kind,
+        next_executable: job.next_executable(),
+        target: job.target,
+        msg: job.msg,
+        bounty: job.bounty,
+        cooldown: job.cooldown,
+        funds: job.funds,
+        last_executed: job.last_executed,
+        active: job.active,
+    }
+}
@@ -0,0 +1,90 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Binary, HumanAddr, Uint128};
+use quasar_interfaces::pagination::{PageRequest, PageResponse};
+
+use crate::state::JobKind;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InitMsg {
+    /// Defaults to the instantiating address
+    pub admin: Option<HumanAddr>,
+    /// Native denom bounties are funded and paid in
+    pub denom: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleMsg {
+    /// Admin only, attached funds are the initial bounty budget
+    RegisterJob {
+        kind: JobKind,
+        target: HumanAddr,
+        msg: Binary,
+        bounty: Uint128,
+        cooldown: u64,
+    },
+    /// Admin only, adds the attached funds to the job's bounty budget
+    FundJob {
+        id: u64,
+    },
+    /// Admin only, applies from the next execution
+    UpdateJob {
+        id: u64,
+        bounty: Uint128,
+        cooldown: u64,
+    },
+    /// Admin only, deactivates the job and returns its remaining budget to the admin
+    RemoveJob {
+        id: u64,
+    },
+    /// Run the job and collect its bounty, callable by anyone once the cooldown passed
+    Execute {
+        id: u64,
+    },
+    /// Admin only
+    SetAdmin {
+        admin: HumanAddr,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Config {},
+    Job {
+        id: u64,
+    },
+    /// Jobs in id order, with `executable_at` only those a keeper could run at that block
+    Jobs {
+        executable_at: Option<u64>,
+        page: Option<PageRequest>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub admin: HumanAddr,
+    pub denom: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct JobResponse {
+    pub id: u64,
+    pub kind: JobKind,
+    pub target: HumanAddr,
+    pub msg: Binary,
+    pub bounty: Uint128,
+    pub cooldown: u64,
+    pub funds: Uint128,
+    pub last_executed: Option<u64>,
+    pub next_executable: u64,
+    pub active: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct JobsResponse {
+    pub jobs: Vec<JobResponse>,
+    pub page: PageResponse,
+}
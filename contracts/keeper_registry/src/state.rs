@@ -0,0 +1,98 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Binary, CanonicalAddr, HumanAddr, Order, StdResult, Storage, Uint128};
+use cosmwasm_storage::{Bucket, ReadonlyBucket, ReadonlySingleton, Singleton};
+
+pub static CONFIG_KEY: &[u8] = b"config";
+pub static JOB_COUNT_KEY: &[u8] = b"job_count";
+pub static JOB_PREFIX: &[u8] = b"job";
+
+/// Config struct
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub admin: CanonicalAddr,
+    /// Native denom bounties are funded and paid in
+    pub denom: String,
+}
+
+/// Maintenance a job performs, only used to label and filter jobs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Accrue,
+    UpdateTwap,
+    ScheduledRepayment,
+    RewardEpoch,
+    Other,
+}
+
+/// Job struct
+/// `msg` is executed on `target` by the registry, which pays `bounty` out of
+/// `funds` to whoever triggers it at most once per `cooldown` blocks
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    pub target: HumanAddr,
+    pub msg: Binary,
+    pub bounty: Uint128,
+    pub cooldown: u64,
+    pub funds: Uint128,
+    /// None until the job ran for the first time
+    pub last_executed: Option<u64>,
+    pub active: bool,
+}
+
+impl Job {
+    /// First block the job can be executed at
+    pub fn next_executable(&self) -> u64 {
+        match self.last_executed {
+            Some(block) => block + self.cooldown,
+            None => 0,
+        }
+    }
+}
+
+/// Get config
+pub fn get_config<S: Storage>(storage: &S) -> StdResult<Config> {
+    ReadonlySingleton::new(storage, CONFIG_KEY).load()
+}
+
+/// Set config
+pub fn set_config<S: Storage>(storage: &mut S, config: &Config) -> StdResult<()> {
+    Singleton::new(storage, CONFIG_KEY).save(config)
+}
+
+/// Reserve the next job id
+pub fn next_job_id<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    let id: u64 = ReadonlySingleton::new(storage, JOB_COUNT_KEY).may_load()?.unwrap_or(0) + 1;
+    Singleton::new(storage, JOB_COUNT_KEY).save(&id)?;
+    Ok(id)
+}
+
+/// Get job by id
+pub fn get_job<S: Storage>(storage: &S, id: u64) -> StdResult<Job> {
+    ReadonlyBucket::new(JOB_PREFIX, storage).load(&id.to_be_bytes())
+}
+
+/// Set job
+pub fn set_job<S: Storage>(storage: &mut S, job: &Job) -> StdResult<()> {
+    Bucket::new(JOB_PREFIX, storage).save(&job.id.to_be_bytes(), job)
+}
+
+/// Up to `limit` jobs matching `filter` in id order, starting after `start_after`
+pub fn get_jobs<S: Storage, F: Fn(&Job) -> bool>(
+    storage: &S,
+    start_after: Option<u64>,
+    limit: usize,
+    filter: F,
+) -> StdResult<Vec<Job>> {
+    let start = start_after.map(|id| (id + 1).to_be_bytes().to_vec());
+    ReadonlyBucket::<S, Job>::new(JOB_PREFIX, storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .map(|item| item.map(|(_, job)| job))
+        .filter(|item| item.as_ref().map(&filter).unwrap_or(true))
+        .take(limit)
+        .collect()
+}
@@ -1,6 +1,6 @@
 use std::env::current_dir;
 use std::fs::create_dir_all;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
@@ -10,13 +10,17 @@ use q_native::state::*;
 fn main() {
     let mut out_dir: PathBuf = current_dir().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
     out_dir.push("schemas");
-    out_dir.push(format!("{}_schema", option_env!("CARGO_PKG_NAME").unwrap()));
+    out_dir.push(format!("{}_schema", env!("CARGO_PKG_NAME")));
     create_dir_all(&out_dir).unwrap();
     remove_schemas(&out_dir).unwrap();
 
     export_schema(&schema_for!(InitMsg), &out_dir);
     export_schema(&schema_for!(HandleMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
+    export_schema(&schema_for!(Cw20HookMsg), &out_dir);
+    export_schema(&schema_for!(FlashLoanReceiverMsg), &out_dir);
     export_schema(&schema_for!(Config), &out_dir);
     export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(ExportGenesisResponse), &out_dir);
 }
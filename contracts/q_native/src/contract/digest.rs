@@ -0,0 +1,63 @@
+use cosmwasm_std::{to_vec, Binary, CanonicalAddr, HumanAddr, StdResult, Storage, Uint128};
+use sha2::{Digest, Sha256};
+
+use crate::msg::GenesisChunk;
+use crate::state::{
+    get_balance, get_borrow_balance, get_config, get_state, get_state_digest, set_state_digest,
+    SnapshotSection, StateDigest,
+};
+
+/// Extends the state digest with the market state left by a handled message
+/// digest = sha256(prior digest || block height || state || total supply)
+pub fn update_state_digest<S: Storage>(storage: &mut S, block_number: u64) -> StdResult<StateDigest> {
+    let prior = get_state_digest(storage)?;
+    let state = get_state(storage)?;
+    let config = get_config(storage)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(prior.digest.as_slice());
+    hasher.update(block_number.to_be_bytes());
+    hasher.update(&to_vec(&state)?);
+    hasher.update(config.total_supply.u128().to_be_bytes());
+
+    let digest = StateDigest {
+        digest: Binary::from(hasher.finalize().to_vec()),
+        sequence: prior.sequence + 1,
+        block_number,
+    };
+    set_state_digest(storage, &digest)?;
+    Ok(digest)
+}
+
+/// Digest of an account's qToken balance and borrow snapshot
+/// digest = sha256(address || balance || borrow snapshot)
+pub fn account_digest<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<Binary> {
+    let balance = get_balance(storage, account)?;
+    let borrow = get_borrow_balance(storage, account);
+
+    let mut hasher = Sha256::new();
+    hasher.update(account.as_slice());
+    hasher.update(balance.to_be_bytes());
+    hasher.update(&to_vec(&borrow)?);
+    Ok(Binary::from(hasher.finalize().to_vec()))
+}
+
+/// Link of the genesis hash chain, empty `prior` for the first chunk
+/// digest = sha256(prior digest || chunk)
+pub fn genesis_digest(prior: &Binary, chunk: &GenesisChunk) -> StdResult<Binary> {
+    let mut hasher = Sha256::new();
+    hasher.update(prior.as_slice());
+    hasher.update(&to_vec(chunk)?);
+    Ok(Binary::from(hasher.finalize().to_vec()))
+}
+
+/// Link of a liquidity snapshot hash chain, empty `prior` for the first entry
+/// digest = sha256(prior digest || section || address || amount)
+pub fn snapshot_digest(prior: &Binary, section: SnapshotSection, address: &HumanAddr, amount: Uint128) -> Binary {
+    let mut hasher = Sha256::new();
+    hasher.update(prior.as_slice());
+    hasher.update(section.as_str().as_bytes());
+    hasher.update(address.as_str().as_bytes());
+    hasher.update(amount.u128().to_be_bytes());
+    Binary::from(hasher.finalize().to_vec())
+}
@@ -0,0 +1,643 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::msg::{IntentAction, OracleMsg};
+use crate::state::{
+    get_config, get_global_pause, get_interest_rate_model, get_interest_rate_model_bounds, get_pause_flags, get_pending_admin,
+    get_pending_interest_rate_model_bounds, get_shadow_interest_rate_model, get_state, set_config, set_global_pause,
+    set_interest_rate_model, set_interest_rate_model_bounds, set_pause_flags, set_pending_admin,
+    set_pending_interest_rate_model_bounds, set_shadow_interest_rate_model, GlobalPause, InterestRateModel,
+    InterestRateModelBounds, OracleConfig, PauseAction, PendingInterestRateModelBounds, INTEREST_RATE_MODEL_BOUNDS_TIMELOCK,
+    MAX_BORROW_COOLDOWN, MAX_MIN_BORROW_HEALTH_FACTOR, Role, has_role,
+};
+
+use crate::contract::handler::audit::{audit, ROLE_ADMIN, ROLE_COMPTROLLER, ROLE_GUARDIAN};
+use crate::contract::handler::collateral::accrue_interest;
+use crate::contract::handler::interest_model::{
+    validate_blocks_per_year, validate_interest_rate_model, validate_interest_rate_model_bounds, validate_parameter_bounds,
+};
+use crate::contract::handler::roles::assert_role;
+
+pub fn try_update_interest_rate_model<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    model: InterestRateModel,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    validate_interest_rate_model(&model, get_state(&deps.storage)?.max_borrow_rate.u128())?;
+    validate_interest_rate_model_bounds(&model, &get_interest_rate_model_bounds(&deps.storage)?)?;
+
+    // Settle interest owed under the previous model before switching
+    let accrual_log = accrue_interest(deps, env.clone())?;
+    let old_model = get_interest_rate_model(&deps.storage)?;
+    set_interest_rate_model(&mut deps.storage, &model)?;
+    let audit_log = audit(deps, &env, role, "interest_rate_model", &old_model, &model)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "update_interest_rate_model"),
+            log("sender", env.message.sender.as_str()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+pub fn try_set_shadow_interest_rate_model<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    model: Option<InterestRateModel>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    if let Some(model) = model.as_ref() {
+        validate_interest_rate_model(model, get_state(&deps.storage)?.max_borrow_rate.u128())?;
+    }
+    let registered = model.is_some();
+    let old_model = get_shadow_interest_rate_model(&deps.storage)?;
+    let audit_log = audit(deps, &env, role, "shadow_interest_rate_model", &old_model, &model)?;
+    set_shadow_interest_rate_model(&mut deps.storage, model)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_shadow_interest_rate_model"),
+            log("sender", env.message.sender.as_str()),
+            log("registered", registered),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Bounds only move after INTEREST_RATE_MODEL_BOUNDS_TIMELOCK, so a compromised admin
+/// cannot widen them and push an extreme model in the same breath
+/// A later proposal replaces an unapplied one and restarts the timelock, None withdraws it
+pub fn try_propose_interest_rate_model_bounds<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    bounds: Option<InterestRateModelBounds>,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    let pending = match bounds {
+        Some(bounds) => {
+            validate_parameter_bounds(&bounds)?;
+            Some(PendingInterestRateModelBounds {
+                bounds,
+                eta: env.block.height + INTEREST_RATE_MODEL_BOUNDS_TIMELOCK,
+            })
+        }
+        None => None,
+    };
+    let old_pending = get_pending_interest_rate_model_bounds(&deps.storage)?;
+    set_pending_interest_rate_model_bounds(&mut deps.storage, pending.as_ref())?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "pending_interest_rate_model_bounds", &old_pending, &pending)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "propose_interest_rate_model_bounds"),
+            log("sender", env.message.sender.as_str()),
+            log("eta", pending.map(|pending| pending.eta).unwrap_or_default()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Applied bounds only constrain later model updates, the live model is left as is
+pub fn try_apply_interest_rate_model_bounds<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    let pending = match get_pending_interest_rate_model_bounds(&deps.storage)? {
+        Some(pending) => pending,
+        None => return Err(StdError::generic_err("No rate model bounds change proposed")),
+    };
+    if env.block.height < pending.eta {
+        return Err(StdError::generic_err(format!(
+            "Rate model bounds are timelocked: eta: {}, block_height: {}",
+            pending.eta, env.block.height
+        )));
+    }
+    let old_bounds = get_interest_rate_model_bounds(&deps.storage)?;
+    set_interest_rate_model_bounds(&mut deps.storage, &pending.bounds)?;
+    set_pending_interest_rate_model_bounds(&mut deps.storage, None)?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "interest_rate_model_bounds", &old_bounds, &pending.bounds)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "apply_interest_rate_model_bounds"),
+            log("sender", env.message.sender.as_str()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Stale epochs are ignored rather than rejected so a late update never fails the comptroller tx
+pub fn try_sync_global_pause<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    paused: bool,
+    epoch: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if config.comptroller != Some(sender_raw) {
+        return Err(StdError::unauthorized());
+    }
+
+    let old_pause = get_global_pause(&deps.storage)?;
+    let applied = epoch > old_pause.epoch;
+    let mut audit_log = vec![];
+    if applied {
+        let new_pause = GlobalPause { paused, epoch };
+        set_global_pause(&mut deps.storage, &new_pause)?;
+        audit_log = audit(deps, &env, ROLE_COMPTROLLER, "global_pause", &old_pause, &new_pause)?;
+    }
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "sync_global_pause"),
+            log("sender", env.message.sender.as_str()),
+            log("paused", paused),
+            log("epoch", epoch),
+            log("applied", applied),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_transfer_ownership<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    new_owner: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    // A later proposal replaces an unaccepted one
+    let old_pending = match get_pending_admin(&deps.storage)? {
+        Some(pending) => Some(deps.api.human_address(&pending)?),
+        None => None,
+    };
+    set_pending_admin(&mut deps.storage, Some(&canonicalize_address(&deps.api, new_owner)?))?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "pending_admin", &old_pending, &Some(new_owner.clone()))?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "transfer_ownership"),
+            log("sender", env.message.sender.as_str()),
+            log("pending_owner", new_owner.as_str()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_accept_ownership<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if get_pending_admin(&deps.storage)? != Some(sender_raw.clone()) {
+        return Err(StdError::unauthorized());
+    }
+    let mut config = get_config(&deps.storage)?;
+    let previous_owner = deps.api.human_address(&config.admin)?;
+    config.admin = sender_raw;
+    set_config(&mut deps.storage, &config)?;
+    set_pending_admin(&mut deps.storage, None)?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "admin", &previous_owner, &env.message.sender)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "accept_ownership"),
+            log("sender", env.message.sender.as_str()),
+            log("previous_owner", previous_owner.as_str()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_set_blocks_per_year<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    blocks_per_year: Option<u64>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let mut config = get_config(&deps.storage)?;
+    validate_blocks_per_year(blocks_per_year)?;
+    let old_blocks_per_year = config.blocks_per_year;
+    config.blocks_per_year = blocks_per_year;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "blocks_per_year", &old_blocks_per_year, &blocks_per_year)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_blocks_per_year"),
+            log("sender", env.message.sender.as_str()),
+            log("blocks_per_year", config.blocks_per_year()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_set_borrow_cooldown<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    blocks: Option<u64>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let mut config = get_config(&deps.storage)?;
+    let old_borrow_cooldown = config.borrow_cooldown;
+    config.borrow_cooldown = validate_borrow_cooldown(blocks)?;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "borrow_cooldown", &old_borrow_cooldown, &config.borrow_cooldown)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_borrow_cooldown"),
+            log("sender", env.message.sender.as_str()),
+            log("borrow_cooldown", config.borrow_cooldown.unwrap_or_default()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_set_min_borrow_health_factor<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    min_borrow_health_factor: Option<Decimal256>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let mut config = get_config(&deps.storage)?;
+    let old_min_borrow_health_factor = config.min_borrow_health_factor;
+    config.min_borrow_health_factor = validate_min_borrow_health_factor(min_borrow_health_factor)?;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(
+        deps,
+        &env,
+        role,
+        "min_borrow_health_factor",
+        &old_min_borrow_health_factor,
+        &config.min_borrow_health_factor,
+    )?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_min_borrow_health_factor"),
+            log("sender", env.message.sender.as_str()),
+            log(
+                "min_borrow_health_factor",
+                config.min_borrow_health_factor.map(|factor| factor.to_string()).unwrap_or_default(),
+            ),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Must be within [1, MAX_MIN_BORROW_HEALTH_FACTOR], a factor of 1 is stored as None
+pub fn validate_min_borrow_health_factor(
+    min_borrow_health_factor: Option<Decimal256>,
+) -> StdResult<Option<Decimal256>> {
+    let factor = match min_borrow_health_factor {
+        Some(factor) => factor,
+        None => return Ok(None),
+    };
+    let max = Decimal256::from_uint256(Uint256::from(MAX_MIN_BORROW_HEALTH_FACTOR));
+    if factor < Decimal256::one() || factor > max {
+        return Err(StdError::generic_err(format!(
+            "Minimum borrow health factor must be within [1, {}]: min_borrow_health_factor: {}",
+            max, factor
+        )));
+    }
+    Ok(if factor == Decimal256::one() { None } else { Some(factor) })
+}
+
+/// A zero cooldown is stored as None
+pub fn validate_borrow_cooldown(blocks: Option<u64>) -> StdResult<Option<u64>> {
+    match blocks {
+        Some(blocks) if blocks > MAX_BORROW_COOLDOWN => Err(StdError::generic_err(format!(
+            "Borrow cooldown exceeds maximum: blocks: {}, maximum: {}",
+            blocks, MAX_BORROW_COOLDOWN
+        ))),
+        Some(0) | None => Ok(None),
+        Some(blocks) => Ok(Some(blocks)),
+    }
+}
+
+pub fn try_set_guardian<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    guardian: Option<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let mut config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    let old_guardian = match config.guardian.as_ref() {
+        Some(guardian) => Some(deps.api.human_address(guardian)?),
+        None => None,
+    };
+    config.guardian = match guardian.as_ref() {
+        Some(guardian) => Some(canonicalize_address(&deps.api, guardian)?),
+        None => None,
+    };
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "guardian", &old_guardian, &guardian)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_guardian"),
+            log("sender", env.message.sender.as_str()),
+            log("guardian", guardian.as_ref().map(|guardian| guardian.as_str()).unwrap_or("")),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Admin or oracle_admin only, swaps the price feed insurance and account liquidity read
+pub fn try_set_oracle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    oracle: Option<OracleMsg>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::OracleAdmin)?;
+    let mut config = get_config(&deps.storage)?;
+    let old_oracle = match config.oracle.as_ref() {
+        Some(oracle) => Some(OracleMsg {
+            contract: deps.api.human_address(&oracle.contract)?,
+            kind: oracle.kind.clone(),
+            base: oracle.base.clone(),
+            quote: oracle.quote.clone(),
+        }),
+        None => None,
+    };
+    config.oracle = match oracle.as_ref() {
+        Some(oracle) => Some(OracleConfig {
+            contract: canonicalize_address(&deps.api, &oracle.contract)?,
+            kind: oracle.kind.clone(),
+            base: oracle.base.clone(),
+            quote: oracle.quote.clone(),
+        }),
+        None => None,
+    };
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "oracle", &old_oracle, &oracle)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_oracle"),
+            log("sender", env.message.sender.as_str()),
+            log("oracle", oracle.as_ref().map(|oracle| oracle.contract.as_str()).unwrap_or("")),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_set_balance_hook<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    balance_hook: Option<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let mut config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    let old_balance_hook = match config.balance_hook.as_ref() {
+        Some(balance_hook) => Some(deps.api.human_address(balance_hook)?),
+        None => None,
+    };
+    config.balance_hook = match balance_hook.as_ref() {
+        Some(balance_hook) => Some(canonicalize_address(&deps.api, balance_hook)?),
+        None => None,
+    };
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "balance_hook", &old_balance_hook, &balance_hook)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_balance_hook"),
+            log("sender", env.message.sender.as_str()),
+            log("balance_hook", balance_hook.as_ref().map(|hook| hook.as_str()).unwrap_or("")),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_set_paused<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    action: PauseAction,
+    paused: bool,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let is_guardian = config.guardian.as_ref() == Some(&sender_raw);
+    let is_pause_guardian = has_role(&deps.storage, Role::PauseGuardian, &sender_raw)?;
+    if sender_raw != config.admin && !((is_guardian || is_pause_guardian) && paused) {
+        return Err(StdError::unauthorized());
+    }
+
+    let mut flags = get_pause_flags(&deps.storage)?;
+    let was_paused = flags.is_paused(action);
+    flags.set(action, paused);
+    set_pause_flags(&mut deps.storage, &flags)?;
+    let role = if sender_raw == config.admin {
+        ROLE_ADMIN
+    } else if is_guardian {
+        ROLE_GUARDIAN
+    } else {
+        Role::PauseGuardian.as_str()
+    };
+    let parameter = format!("paused.{}", action.as_str());
+    let audit_log = audit(deps, &env, role, &parameter, &was_paused, &paused)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_paused"),
+            log("sender", env.message.sender.as_str()),
+            log("paused_action", action.as_str()),
+            log("paused", paused),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Fails while the admin or guardian has `action` paused
+/// Demand queued up during a pause is only recorded as an event, so reopening
+/// can be sequenced from the log without the market holding any funds or state
+pub fn try_record_intent<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    action: IntentAction,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let global_paused = get_global_pause(&deps.storage)?.paused;
+    let action_paused = match action.pause_action() {
+        Some(pause_action) => get_pause_flags(&deps.storage)?.is_paused(pause_action),
+        None => false,
+    };
+    if !global_paused && !action_paused {
+        return Err(StdError::generic_err(format!(
+            "Action is not paused, submit it directly: action: {}",
+            action.as_str()
+        )));
+    }
+    if amount.is_zero() {
+        return Err(StdError::generic_err("Intent amount must be positive"));
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "intent"),
+            log("sender", env.message.sender.as_str()),
+            log("intent", action.as_str()),
+            log("amount", amount),
+            log("global_paused", global_paused),
+            log("action_paused", action_paused),
+            log("block_number", env.block.height),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn assert_action_not_paused<S: Storage>(storage: &S, action: PauseAction) -> StdResult<()> {
+    if get_pause_flags(storage)?.is_paused(action) {
+        return Err(StdError::generic_err(format!(
+            "Action is paused: action: {}",
+            action.as_str()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::from_binary;
+    use cosmwasm_std::testing::mock_env;
+
+    use crate::contract::testing::{mock_init_msg, mock_market};
+    use crate::contract::{handle, query};
+    use crate::msg::{HandleMsg, InterestRateModelBoundsResponse, QueryMsg};
+    use crate::state::ParameterBound;
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    fn model(multiplier_per_block: u128) -> InterestRateModel {
+        InterestRateModel::Linear {
+            base_rate_per_block: Uint128::zero(),
+            multiplier_per_block: Uint128::from(multiplier_per_block),
+            min_borrow_rate_per_block: None,
+        }
+    }
+
+    #[test]
+    fn bounds_apply_after_the_timelock_and_gate_model_updates() {
+        let mut deps = mock_market(mock_init_msg());
+        let bounds = InterestRateModelBounds {
+            multiplier_per_block: Some(ParameterBound { min: Uint128::zero(), max: Uint128::from(100u128) }),
+            ..InterestRateModelBounds::default()
+        };
+        let propose = HandleMsg::ProposeInterestRateModelBounds { bounds: Some(bounds.clone()) };
+        assert_eq!(handle(&mut deps, env_at("alice", 1_000), propose.clone()).unwrap_err(), StdError::unauthorized());
+        handle(&mut deps, env_at("admin", 1_000), propose).unwrap();
+
+        // Unbounded until the proposal is applied
+        handle(&mut deps, env_at("admin", 1_001), HandleMsg::UpdateInterestRateModel { model: model(1_000) }).unwrap();
+
+        let eta = 1_000 + INTEREST_RATE_MODEL_BOUNDS_TIMELOCK;
+        let res: InterestRateModelBoundsResponse =
+            from_binary(&query(&deps, QueryMsg::InterestRateModelBounds {}).unwrap()).unwrap();
+        assert_eq!(res.bounds, InterestRateModelBounds::default());
+        assert_eq!(res.pending, Some(PendingInterestRateModelBounds { bounds: bounds.clone(), eta }));
+        assert_eq!(
+            handle(&mut deps, env_at("admin", eta - 1), HandleMsg::ApplyInterestRateModelBounds {}).unwrap_err(),
+            StdError::generic_err(format!("Rate model bounds are timelocked: eta: {}, block_height: {}", eta, eta - 1))
+        );
+        handle(&mut deps, env_at("admin", eta), HandleMsg::ApplyInterestRateModelBounds {}).unwrap();
+
+        let res: InterestRateModelBoundsResponse =
+            from_binary(&query(&deps, QueryMsg::InterestRateModelBounds {}).unwrap()).unwrap();
+        assert_eq!(res.bounds, bounds);
+        assert_eq!(res.pending, None);
+        handle(&mut deps, env_at("admin", eta + 1), HandleMsg::UpdateInterestRateModel { model: model(100) }).unwrap();
+        assert_eq!(
+            handle(&mut deps, env_at("admin", eta + 2), HandleMsg::UpdateInterestRateModel { model: model(101) })
+                .unwrap_err(),
+            StdError::generic_err(
+                "Rate model parameter out of bounds: parameter: multiplier_per_block, value: 101, min: 0, max: 100"
+            )
+        );
+        assert_eq!(get_interest_rate_model(&deps.storage).unwrap(), model(100));
+    }
+
+    #[test]
+    fn withdrawn_bounds_cannot_be_applied() {
+        let mut deps = mock_market(mock_init_msg());
+        let propose = HandleMsg::ProposeInterestRateModelBounds { bounds: Some(InterestRateModelBounds::default()) };
+        handle(&mut deps, env_at("admin", 1_000), propose).unwrap();
+        handle(&mut deps, env_at("admin", 1_001), HandleMsg::ProposeInterestRateModelBounds { bounds: None }).unwrap();
+        assert_eq!(
+            handle(&mut deps, env_at("admin", 1_000 + INTEREST_RATE_MODEL_BOUNDS_TIMELOCK), HandleMsg::ApplyInterestRateModelBounds {})
+                .unwrap_err(),
+            StdError::generic_err("No rate model bounds change proposed")
+        );
+    }
+}
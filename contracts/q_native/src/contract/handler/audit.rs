@@ -0,0 +1,45 @@
+use cosmwasm_std::{log, to_vec, Api, Env, Extern, LogAttribute, Querier, StdError, StdResult, Storage};
+use serde::Serialize;
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::state::{append_audit_record, AuditRecord};
+
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLE_GUARDIAN: &str = "guardian";
+pub const ROLE_COMPTROLLER: &str = "comptroller";
+
+/// Appends a change of `parameter` by the sender to the audit log and returns
+/// the audit event logs, effective from the current block
+pub fn audit<S: Storage, A: Api, Q: Querier, T: Serialize>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    role: &str,
+    parameter: &str,
+    old_value: &T,
+    new_value: &T,
+) -> StdResult<Vec<LogAttribute>> {
+    let record = append_audit_record(&mut deps.storage, AuditRecord {
+        id: 0,
+        actor: canonicalize_address(&deps.api, &env.message.sender)?,
+        role: role.to_string(),
+        parameter: parameter.to_string(),
+        old_value: audit_value(old_value)?,
+        new_value: audit_value(new_value)?,
+        block_number: env.block.height,
+    })?;
+    Ok(vec![
+        log("audit_id", record.id),
+        log("audit_actor", env.message.sender.as_str()),
+        log("audit_role", record.role),
+        log("audit_parameter", record.parameter),
+        log("audit_old_value", record.old_value),
+        log("audit_new_value", record.new_value),
+        log("audit_effective_height", record.block_number),
+    ])
+}
+
+/// JSON encoding of a value as the audit log records it
+pub fn audit_value<T: Serialize>(value: &T) -> StdResult<String> {
+    String::from_utf8(to_vec(value)?).map_err(|_| StdError::generic_err("Audit value is not valid UTF-8"))
+}
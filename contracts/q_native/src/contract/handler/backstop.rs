@@ -0,0 +1,350 @@
+use cosmwasm_std::{
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage,
+    Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::state::{
+    append_liquidation, get_backstop_flag, get_balance, get_borrow_balance, get_config, get_insurance_pool, get_state,
+    set_backstop_flag, set_borrow_balance, set_config, set_state, update_leaderboard, update_metrics, BackstopConfig,
+    BorrowSnapshot, LiquidationRecord, PauseAction, Role, TOP_BORROWERS_KEY,
+};
+
+use crate::contract::handler::admin::assert_action_not_paused;
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::balance_hook::balance_hook;
+use crate::contract::handler::collateral::{
+    accrue_interest, current_borrow_balance, get_exchange_rate, market_log, tokens_for_underlying,
+};
+use crate::contract::handler::exponential::SCALE;
+use crate::contract::handler::health_alert::{health_alert_log, health_factor};
+use crate::contract::handler::insurance::settle_premium;
+use crate::contract::handler::interest_deferral::settle_interest_deferral;
+use crate::contract::handler::roles::assert_role;
+use crate::contract::handler::token::perform_transfer;
+use crate::contract::handler::transfer_policy::assert_transfer_allowed;
+
+/// Flags a severely undercollateralized borrower on the first call and, once `delay` blocks
+/// passed without a liquidator acting, repays as much of the debt as free reserves cover and
+/// moves qTokens worth the repayment to the treasury. Debt the collateral cannot cover stays
+/// on the books. Reserves and borrows fall alike, which leaves the exchange rate unchanged
+pub fn try_backstop_liquidate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    borrower: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Liquidation)?;
+    let config = get_config(&deps.storage)?;
+    let backstop = match &config.backstop {
+        Some(backstop) => backstop.clone(),
+        None => return Err(StdError::generic_err("Backstop liquidations are disabled")),
+    };
+    // Collateral in other markets can only be seized through the comptroller
+    if config.comptroller.is_some() {
+        return Err(StdError::generic_err("Backstop liquidations need a standalone market"));
+    }
+
+    let mut accrual_log = accrue_interest(deps, env.clone())?;
+    let borrower_raw = canonicalize_address(&deps.api, borrower)?;
+    accrual_log.extend(settle_interest_deferral(&mut deps.storage, &borrower_raw)?);
+    settle_premium(&mut deps.storage, &borrower_raw)?;
+
+    let flagged_at = get_backstop_flag(&deps.storage, &borrower_raw)?;
+    let health_factor = match health_factor(&deps.storage, &borrower_raw)? {
+        Some(health_factor) if health_factor < backstop.max_health_factor.u128() => health_factor,
+        health_factor => {
+            let health_factor = health_factor.map(|factor| factor.to_string()).unwrap_or_default();
+            if flagged_at.is_none() {
+                return Err(StdError::generic_err(format!(
+                    "Account is not severely undercollateralized: health_factor: {}, max_health_factor: {}",
+                    health_factor, backstop.max_health_factor
+                )));
+            }
+            // The account recovered since it was flagged, a later shortfall starts a new delay
+            set_backstop_flag(&mut deps.storage, &borrower_raw, None)?;
+            let mut res = HandleResponse {
+                messages: vec![],
+                log: vec![
+                    log("action", "clear_backstop_flag"),
+                    log("sender", env.message.sender.as_str()),
+                    log("borrower", borrower.as_str()),
+                    log("health_factor", health_factor),
+                ],
+                data: None,
+            };
+            res.log.extend(accrual_log);
+            return Ok(res);
+        }
+    };
+
+    let flagged_at = match flagged_at {
+        Some(flagged_at) => flagged_at,
+        None => {
+            set_backstop_flag(&mut deps.storage, &borrower_raw, Some(env.block.height))?;
+            let mut res = HandleResponse {
+                messages: vec![],
+                log: vec![
+                    log("action", "flag_backstop"),
+                    log("sender", env.message.sender.as_str()),
+                    log("borrower", borrower.as_str()),
+                    log("health_factor", health_factor),
+                    log("eligible_at", env.block.height + backstop.delay),
+                ],
+                data: None,
+            };
+            res.log.extend(accrual_log);
+            return Ok(res);
+        }
+    };
+    let eligible_at = flagged_at + backstop.delay;
+    if env.block.height < eligible_at {
+        return Err(StdError::generic_err(format!(
+            "Backstop liquidation is not open yet: eligible_at: {}, block: {}",
+            eligible_at, env.block.height
+        )));
+    }
+
+    let state = get_state(&deps.storage)?;
+    let account_borrow = match get_borrow_balance(&deps.storage, &borrower_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    // The insurance pool stays set aside for refunds
+    let available = (state.total_reserves - get_insurance_pool(&deps.storage)?).unwrap_or_default();
+    let repay_amount = account_borrow.min(available.u128());
+    if repay_amount == 0 {
+        return Err(StdError::generic_err("No reserves available to backstop the borrow"));
+    }
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    if exchange_rate == 0 {
+        return Err(StdError::generic_err("Exchange rate is zero, collateral cannot be valued"));
+    }
+    let seize_tokens =
+        tokens_for_underlying(repay_amount, exchange_rate).min(get_balance(&deps.storage, &borrower_raw)?);
+    let treasury = deps.api.human_address(&config.treasury)?;
+    assert_transfer_allowed(deps, Some(borrower), &treasury, Uint128::from(seize_tokens))?;
+    perform_transfer(&mut deps.storage, &borrower_raw, &config.treasury, seize_tokens)?;
+
+    let new_account_borrow = account_borrow - repay_amount;
+    let mut new_state = get_state(&deps.storage)?;
+    new_state.total_borrows = Uint128::from(new_state.total_borrows.u128().saturating_sub(repay_amount));
+    new_state.total_reserves = (new_state.total_reserves - Uint128::from(repay_amount))?;
+    set_state(&mut deps.storage, &new_state)?;
+    set_borrow_balance(&mut deps.storage, &borrower_raw, Some(BorrowSnapshot {
+        principal: Uint128::from(new_account_borrow),
+        interest_index: new_state.borrow_index,
+    }))?;
+    set_backstop_flag(&mut deps.storage, &borrower_raw, None)?;
+    update_leaderboard(&mut deps.storage, TOP_BORROWERS_KEY, &borrower_raw, new_account_borrow)?;
+    update_metrics(&mut deps.storage, |m| m.liquidations += 1)?;
+
+    let messages = balance_hook(&deps.storage, &deps.api, &[&borrower_raw, &config.treasury])?;
+    let alert_log = health_alert_log(&mut deps.storage, &deps.api, &[&borrower_raw])?;
+    let record = append_liquidation(&mut deps.storage, LiquidationRecord {
+        id: 0,
+        liquidator: config.treasury.clone(),
+        borrower: borrower_raw,
+        repay_amount: Uint128::from(repay_amount),
+        seize_tokens: Uint128::from(seize_tokens),
+        protocol_seize_tokens: Uint128::zero(),
+        exchange_rate: Uint128::from(exchange_rate),
+        block_number: env.block.height,
+        collateral_market: None,
+    })?;
+
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "backstop_liquidate"),
+            log("liquidation_id", record.id),
+            log("sender", env.message.sender.as_str()),
+            log("borrower", borrower.as_str()),
+            log("treasury", treasury.as_str()),
+            log("repay_amount", repay_amount),
+            log("seize_tokens", seize_tokens),
+            log("new_account_borrow", new_account_borrow),
+        ],
+        data: None,
+    };
+    res.log.extend(market_log(&deps.storage)?);
+    res.log.extend(alert_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Drops the backstop flag of an account a repayment lifted back to the backstop health factor
+pub fn clear_recovered_backstop_flag<S: Storage>(storage: &mut S, account: &CanonicalAddr) -> StdResult<()> {
+    let backstop = match get_config(storage)?.backstop {
+        Some(backstop) => backstop,
+        None => return Ok(()),
+    };
+    if get_backstop_flag(storage, account)?.is_none() {
+        return Ok(());
+    }
+    match health_factor(storage, account)? {
+        Some(health_factor) if health_factor < backstop.max_health_factor.u128() => Ok(()),
+        _ => set_backstop_flag(storage, account, None),
+    }
+}
+
+pub fn try_set_backstop<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    backstop: Option<BackstopConfig>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let mut config = get_config(&deps.storage)?;
+    let old_backstop = config.backstop.clone();
+    config.backstop = validate_backstop(backstop)?;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "backstop", &old_backstop, &config.backstop)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_backstop"),
+            log("sender", env.message.sender.as_str()),
+            log("backstop_enabled", config.backstop.is_some()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// The delay must be positive and the health factor below 1, so only underwater accounts qualify
+pub fn validate_backstop(backstop: Option<BackstopConfig>) -> StdResult<Option<BackstopConfig>> {
+    if let Some(backstop) = &backstop {
+        if backstop.delay == 0 {
+            return Err(StdError::generic_err("Backstop delay must be positive"));
+        }
+        if backstop.max_health_factor.is_zero() || backstop.max_health_factor.u128() >= SCALE {
+            return Err(StdError::generic_err(format!(
+                "Backstop health factor must be within (0, {}): max_health_factor: {}",
+                SCALE, backstop.max_health_factor
+            )));
+        }
+    }
+    Ok(backstop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockQuerier, MockStorage};
+
+    use crate::contract::handle;
+    use crate::contract::handler::collateral::calculate_exchange_rate;
+    use crate::contract::testing::{mock_init_msg, mock_market};
+    use crate::msg::HandleMsg;
+    use crate::state::{set_balance, State};
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    fn backstop_liquidate() -> HandleMsg {
+        HandleMsg::BackstopLiquidate { borrower: HumanAddr::from("alice") }
+    }
+
+    /// Alice holds all 1,000 qTokens and owes 2,000 against a collateral limit of 1,125,
+    /// a health factor of 0.5625, with 600 of reserves to draw on
+    fn underwater_market() -> Extern<MockStorage, MockApi, MockQuerier> {
+        let mut msg = mock_init_msg();
+        msg.backstop = Some(BackstopConfig { delay: 10, max_health_factor: Uint128::from(90_000_000u128) });
+        let mut deps = mock_market(msg);
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        let mut config = get_config(&deps.storage).unwrap();
+        config.total_supply = Uint128::from(1_000u128);
+        set_config(&mut deps.storage, &config).unwrap();
+        set_balance(&mut deps.storage, &alice, 1_000).unwrap();
+        let state = get_state(&deps.storage).unwrap();
+        set_state(&mut deps.storage, &State {
+            cash: Uint128::from(100u128),
+            block_number: 20_000,
+            total_reserves: Uint128::from(600u128),
+            total_borrows: Uint128::from(2_000u128),
+            ..state.clone()
+        })
+        .unwrap();
+        set_borrow_balance(&mut deps.storage, &alice, Some(BorrowSnapshot {
+            principal: Uint128::from(2_000u128),
+            interest_index: state.borrow_index,
+        }))
+        .unwrap();
+        deps
+    }
+
+    fn log_value(res: &HandleResponse, key: &str) -> String {
+        res.log.iter().find(|attr| attr.key == key).unwrap().value.clone()
+    }
+
+    #[test]
+    fn liquidates_out_of_reserves_once_the_delay_passed_unliquidated() {
+        let mut deps = underwater_market();
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+
+        let res = handle(&mut deps, env_at("keeper", 20_000), backstop_liquidate()).unwrap();
+        assert!(res.log.contains(&log("action", "flag_backstop")));
+        assert!(res.log.contains(&log("eligible_at", 20_010)));
+        let err = handle(&mut deps, env_at("keeper", 20_009), backstop_liquidate()).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Backstop liquidation is not open yet: eligible_at: 20010, block: 20009")
+        );
+
+        let before = get_state(&deps.storage).unwrap();
+        let exchange_rate = calculate_exchange_rate(&get_config(&deps.storage).unwrap(), &before).unwrap();
+        let res = handle(&mut deps, env_at("keeper", 20_010), backstop_liquidate()).unwrap();
+        assert!(res.log.contains(&log("action", "backstop_liquidate")));
+        let state = get_state(&deps.storage).unwrap();
+        // Every free reserve went into the debt, a penalty-free seizure of their value
+        let repay_amount: u128 = log_value(&res, "repay_amount").parse().unwrap();
+        assert_eq!(state.total_reserves, Uint128::zero());
+        let seize_tokens: u128 = log_value(&res, "seize_tokens").parse().unwrap();
+        let treasury = get_config(&deps.storage).unwrap().treasury;
+        assert_eq!(get_balance(&deps.storage, &treasury).unwrap(), seize_tokens);
+        assert_eq!(get_balance(&deps.storage, &alice).unwrap(), 1_000 - seize_tokens);
+        let new_account_borrow: u128 = log_value(&res, "new_account_borrow").parse().unwrap();
+        assert_eq!(state.total_borrows.u128(), new_account_borrow);
+        assert!(repay_amount >= 600 && new_account_borrow > 0);
+        let new_exchange_rate = calculate_exchange_rate(&get_config(&deps.storage).unwrap(), &state).unwrap();
+        assert!(new_exchange_rate >= exchange_rate);
+        assert_eq!(get_backstop_flag(&deps.storage, &alice).unwrap(), None);
+    }
+
+    #[test]
+    fn recovered_accounts_lose_their_flag() {
+        let mut deps = underwater_market();
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        handle(&mut deps, env_at("keeper", 20_000), backstop_liquidate()).unwrap();
+        assert_eq!(get_backstop_flag(&deps.storage, &alice).unwrap(), Some(20_000));
+
+        // Most of the debt was repaid, 1,000 against a limit of 1,125 is no longer severe
+        let index = get_state(&deps.storage).unwrap().borrow_index;
+        set_borrow_balance(&mut deps.storage, &alice, Some(BorrowSnapshot {
+            principal: Uint128::from(1_000u128),
+            interest_index: index,
+        }))
+        .unwrap();
+        let res = handle(&mut deps, env_at("keeper", 20_010), backstop_liquidate()).unwrap();
+        assert!(res.log.contains(&log("action", "clear_backstop_flag")));
+        assert_eq!(get_backstop_flag(&deps.storage, &alice).unwrap(), None);
+        assert!(handle(&mut deps, env_at("keeper", 20_011), backstop_liquidate()).is_err());
+    }
+
+    #[test]
+    fn markets_under_a_comptroller_are_left_to_liquidators() {
+        let mut deps = underwater_market();
+        let mut config = get_config(&deps.storage).unwrap();
+        config.comptroller = Some(deps.api.canonical_address(&HumanAddr::from("comptroller")).unwrap());
+        set_config(&mut deps.storage, &config).unwrap();
+        assert_eq!(
+            handle(&mut deps, env_at("keeper", 20_000), backstop_liquidate()).unwrap_err(),
+            StdError::generic_err("Backstop liquidations need a standalone market")
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use cosmwasm_std::{Api, CanonicalAddr, CosmosMsg, StdResult, Storage, Uint128};
+
+use quasar_interfaces::market::BalanceHookMsg;
+
+use crate::state::{get_balance, get_config};
+
+/// BalanceChanged messages for the registered balance hook with the balances as
+/// stored now, one per account, empty without a hook
+pub fn balance_hook<S: Storage, A: Api>(
+    storage: &S,
+    api: &A,
+    accounts: &[&CanonicalAddr],
+) -> StdResult<Vec<CosmosMsg>> {
+    let config = get_config(storage)?;
+    let hook = match &config.balance_hook {
+        Some(hook) => api.human_address(hook)?,
+        None => return Ok(vec![]),
+    };
+    accounts
+        .iter()
+        .map(|account| {
+            BalanceHookMsg::BalanceChanged {
+                account: api.human_address(account)?,
+                balance: Uint128::from(get_balance(storage, account)?),
+                total_supply: config.total_supply,
+            }
+            .into_cosmos_msg(hook.clone())
+        })
+        .collect()
+}
@@ -0,0 +1,326 @@
+use cosmwasm_std::{
+    log, to_binary, Api, Coin, CosmosMsg, Decimal, Env, Extern, HandleResponse, Querier, StdError,
+    StdResult, Storage, Uint128, WasmMsg,
+};
+use cw20::Cw20HandleMsg;
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::asset::{Asset, AssetInfoRaw};
+use quasar_interfaces::dex::{query_simulation, PairHandleMsg};
+
+use crate::msg::{BuybackMsg, HandleMsg};
+use crate::state::{
+    get_buyback_epoch, get_config, get_insurance_pool, get_pending_buyback, get_state, set_buyback_epoch, set_config,
+    set_pending_buyback, set_state, BuybackConfig, BuybackEpoch, PendingBuyback, Role,
+};
+
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::collateral::accrue_interest;
+use crate::contract::handler::funds::query_underlying_balance;
+use crate::contract::handler::roles::assert_role;
+
+/// Validated buyback config for a market lending `underlying`
+pub fn buyback_from_msg<A: Api>(
+    api: &A,
+    underlying: &AssetInfoRaw,
+    buyback: Option<BuybackMsg>,
+) -> StdResult<Option<BuybackConfig>> {
+    let buyback = match buyback {
+        Some(buyback) => buyback,
+        None => return Ok(None),
+    };
+    if let AssetInfoRaw::Token { .. } = underlying {
+        return Err(StdError::generic_err("Buybacks only support native underlyings"));
+    }
+    if buyback.epoch_blocks == 0 {
+        return Err(StdError::generic_err("Buyback epoch must be at least one block"));
+    }
+    if buyback.max_spread > Decimal::one() {
+        return Err(StdError::generic_err(format!(
+            "Max spread must be within [0, 1]: max_spread: {}",
+            buyback.max_spread
+        )));
+    }
+    Ok(Some(BuybackConfig {
+        pair: canonicalize_address(api, &buyback.pair)?,
+        token: canonicalize_address(api, &buyback.token)?,
+        max_per_epoch: buyback.max_per_epoch,
+        epoch_blocks: buyback.epoch_blocks,
+        max_spread: buyback.max_spread,
+    }))
+}
+
+pub fn buyback_msg<A: Api>(api: &A, buyback: Option<&BuybackConfig>) -> StdResult<Option<BuybackMsg>> {
+    match buyback {
+        Some(buyback) => Ok(Some(BuybackMsg {
+            pair: api.human_address(&buyback.pair)?,
+            token: api.human_address(&buyback.token)?,
+            max_per_epoch: buyback.max_per_epoch,
+            epoch_blocks: buyback.epoch_blocks,
+            max_spread: buyback.max_spread,
+        })),
+        None => Ok(None),
+    }
+}
+
+pub fn try_set_buyback<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    buyback: Option<BuybackMsg>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ReserveManager)?;
+    let mut config = get_config(&deps.storage)?;
+    let old_buyback = buyback_msg(&deps.api, config.buyback.as_ref())?;
+    config.buyback = buyback_from_msg(&deps.api, &config.underlying, buyback.clone())?;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "buyback", &old_buyback, &buyback)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_buyback"),
+            log("sender", env.message.sender.as_str()),
+            log("enabled", config.buyback.is_some()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Swaps `amount` of free reserves for the governance token, within the epoch limit
+/// A pair moved before the swap quotes its moved price, so the caller's `min_receive`
+/// bounds the swap and max_spread only tightens it around the quote. FinishBuyback
+/// checks the tokens received against both before they are burned
+pub fn try_buyback<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+    min_receive: Uint128,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ReserveManager)?;
+    let config = get_config(&deps.storage)?;
+    let buyback = match config.buyback.as_ref() {
+        Some(buyback) => buyback,
+        None => return Err(StdError::generic_err("Buybacks are disabled")),
+    };
+    let denom = match &config.underlying {
+        AssetInfoRaw::NativeToken { denom } => denom.clone(),
+        AssetInfoRaw::Token { .. } => return Err(StdError::generic_err("Buybacks only support native underlyings")),
+    };
+    if amount.is_zero() {
+        return Err(StdError::generic_err("Buyback amount must be positive"));
+    }
+    if min_receive.is_zero() {
+        return Err(StdError::generic_err("Buyback min_receive must be positive"));
+    }
+
+    let mut epoch = get_buyback_epoch(&deps.storage)?;
+    if env.block.height >= epoch.start + buyback.epoch_blocks {
+        epoch = BuybackEpoch {
+            start: env.block.height,
+            spent: Uint128::zero(),
+        };
+    }
+    let remaining = (buyback.max_per_epoch - epoch.spent).unwrap_or_default();
+    if amount > remaining {
+        return Err(StdError::generic_err(format!(
+            "Amount exceeds the buyback epoch limit: amount: {}, remaining: {}",
+            amount, remaining
+        )));
+    }
+
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    // Only free reserves held by the market itself are spent
+    let mut new_state = get_state(&deps.storage)?;
+    let available = (new_state.total_reserves - get_insurance_pool(&deps.storage)?).unwrap_or_default();
+    if amount > available {
+        return Err(StdError::generic_err(format!(
+            "Amount exceeds reserves: amount: {}, total_reserves: {}, available: {}",
+            amount, new_state.total_reserves, available
+        )));
+    }
+    let held = query_underlying_balance(&deps.api, &deps.querier, &config.underlying, &env.contract.address)?;
+    if amount > new_state.cash || amount > held {
+        return Err(StdError::generic_err(format!(
+            "The market holds insufficient cash: amount: {}, cash: {}, hot_cash: {}",
+            amount, new_state.cash, held
+        )));
+    }
+    let old_reserves = new_state.total_reserves;
+    new_state.total_reserves = (new_state.total_reserves - amount)?;
+    new_state.cash = (new_state.cash - amount)?;
+    set_state(&mut deps.storage, &new_state)?;
+    epoch.spent += amount;
+    set_buyback_epoch(&mut deps.storage, &epoch)?;
+
+    let pair = deps.api.human_address(&buyback.pair)?;
+    let offer_asset = Asset {
+        info: config.underlying.to_normal(&deps.api)?,
+        amount,
+    };
+    let expected = query_simulation(&deps.querier, pair.clone(), offer_asset.clone())?;
+    if expected.is_zero() {
+        return Err(StdError::generic_err("Buyback pair quotes nothing for the amount"));
+    }
+    if expected < min_receive {
+        return Err(StdError::generic_err(format!(
+            "Buyback pair quotes less than min_receive: expected: {}, min_receive: {}",
+            expected, min_receive
+        )));
+    }
+    let min_receive = min_receive.max((expected - expected * buyback.max_spread)?);
+    let token = deps.api.human_address(&buyback.token)?;
+    let token_info = AssetInfoRaw::Token { contract_addr: buyback.token.clone() };
+    set_pending_buyback(&mut deps.storage, Some(&PendingBuyback {
+        amount,
+        min_receive,
+        balance_before: query_underlying_balance(&deps.api, &deps.querier, &token_info, &env.contract.address)?,
+    }))?;
+    let audit_log = audit(deps, &env, role, "total_reserves", &old_reserves, &new_state.total_reserves)?;
+
+    let swap = PairHandleMsg::Swap {
+        offer_asset,
+        belief_price: Some(Decimal::from_ratio(amount, expected)),
+        max_spread: Some(buyback.max_spread),
+        to: None,
+    };
+    let messages = vec![
+        swap.into_cosmos_msg(pair, vec![Coin { denom, amount }])?,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.clone(),
+            msg: to_binary(&HandleMsg::FinishBuyback {})?,
+            send: vec![],
+        }),
+    ];
+
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "buyback"),
+            log("sender", env.message.sender.as_str()),
+            log("token", token.as_str()),
+            log("amount", amount),
+            log("expected", expected),
+            log("min_receive", min_receive),
+            log("epoch_start", epoch.start),
+            log("epoch_spent", epoch.spent),
+            log("new_total_reserves", new_state.total_reserves),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Market only, burns the governance tokens the swap returned
+pub fn try_finish_buyback<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    if env.message.sender != env.contract.address {
+        return Err(StdError::unauthorized());
+    }
+    let pending = match get_pending_buyback(&deps.storage)? {
+        Some(pending) => pending,
+        None => return Err(StdError::generic_err("No buyback is pending")),
+    };
+    set_pending_buyback(&mut deps.storage, None)?;
+
+    let config = get_config(&deps.storage)?;
+    let buyback = match config.buyback.as_ref() {
+        Some(buyback) => buyback,
+        None => return Err(StdError::generic_err("Buybacks are disabled")),
+    };
+    let token_info = AssetInfoRaw::Token { contract_addr: buyback.token.clone() };
+    let balance = query_underlying_balance(&deps.api, &deps.querier, &token_info, &env.contract.address)?;
+    let received = (balance - pending.balance_before)?;
+    if received < pending.min_receive {
+        return Err(StdError::generic_err(format!(
+            "Swap returned less than quoted: received: {}, min_receive: {}",
+            received, pending.min_receive
+        )));
+    }
+
+    let token = deps.api.human_address(&buyback.token)?;
+    let res = HandleResponse {
+        messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token.clone(),
+            msg: to_binary(&Cw20HandleMsg::Burn { amount: received })?,
+            send: vec![],
+        })],
+        log: vec![
+            log("action", "finish_buyback"),
+            log("token", token.as_str()),
+            log("amount", pending.amount),
+            log("burned", received),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, from_binary, HumanAddr};
+    use quasar_interfaces::dex::SimulationResponse;
+    use serde::Deserialize;
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market, with_wasm_querier, MOCK_DENOM};
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum PairQuery {
+        Simulation { offer_asset: Asset },
+    }
+
+    #[test]
+    fn buyback_is_bounded_by_the_callers_min_receive_before_the_quote() {
+        let mut msg = mock_init_msg();
+        msg.buyback = Some(BuybackMsg {
+            pair: HumanAddr::from("pair"),
+            token: HumanAddr::from("gov"),
+            max_per_epoch: Uint128::from(1_000u128),
+            epoch_blocks: 100,
+            max_spread: Decimal::percent(10),
+        });
+        let mut deps = with_wasm_querier(mock_market(msg));
+        let mut state = get_state(&deps.storage).unwrap();
+        state.cash = Uint128::from(1_000u128);
+        state.total_reserves = Uint128::from(500u128);
+        set_state(&mut deps.storage, &state).unwrap();
+        deps.querier.base.update_balance(MOCK_CONTRACT_ADDR, coins(1_000, MOCK_DENOM));
+        // A pair quoting 2 governance tokens per underlying, whatever it was moved to
+        deps.querier.with_contract("pair", |msg| {
+            let PairQuery::Simulation { offer_asset } = from_binary(msg)?;
+            to_binary(&SimulationResponse {
+                return_amount: Uint128::from(offer_asset.amount.u128() * 2),
+                spread_amount: Uint128::zero(),
+                commission_amount: Uint128::zero(),
+            })
+        });
+        deps.querier.with_contract("gov", |_| to_binary(&cw20::BalanceResponse { balance: Uint128::zero() }));
+
+        let buyback = |min_receive: u128| HandleMsg::Buyback { amount: Uint128::from(100u128), min_receive: Uint128::from(min_receive) };
+        assert!(handle(&mut deps, mock_env("admin", &[]), buyback(0)).is_err());
+        assert!(handle(&mut deps, mock_env("someone", &[]), buyback(150)).is_err());
+
+        // max_spread around the quote tightens the caller's bound
+        handle(&mut deps, mock_env("admin", &[]), buyback(150)).unwrap();
+        assert_eq!(get_pending_buyback(&deps.storage).unwrap().unwrap().min_receive, Uint128::from(180u128));
+        set_pending_buyback(&mut deps.storage, None).unwrap();
+        handle(&mut deps, mock_env("admin", &[]), buyback(195)).unwrap();
+        assert_eq!(get_pending_buyback(&deps.storage).unwrap().unwrap().min_receive, Uint128::from(195u128));
+        assert_eq!(get_state(&deps.storage).unwrap().total_reserves, Uint128::from(300u128));
+
+        // The quote alone cannot satisfy a caller pricing off-chain
+        set_pending_buyback(&mut deps.storage, None).unwrap();
+        let err = handle(&mut deps, mock_env("admin", &[]), buyback(250)).unwrap_err();
+        assert!(err.to_string().contains("quotes less than min_receive"));
+    }
+}
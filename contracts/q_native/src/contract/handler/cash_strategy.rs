@@ -0,0 +1,187 @@
+use cosmwasm_std::{
+    log, Api, Coin, CosmosMsg, Env, Extern, HandleResponse, HumanAddr, Querier, StdError,
+    StdResult, Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::asset::AssetInfoRaw;
+use quasar_interfaces::strategy::StrategyHandleMsg;
+
+use crate::contract::handler::audit::audit;
+use crate::msg::CashStrategyMsg;
+use crate::state::{get_config, get_state, get_warm_cash, set_config, set_warm_cash, CashStrategy, Config, Role};
+
+use crate::contract::handler::exponential::{SCALE, truncate};
+use crate::contract::handler::funds::{query_underlying_balance, transfer_underlying};
+use crate::contract::handler::roles::assert_role;
+
+/// Validated cash strategy for a market lending `underlying`
+pub fn cash_strategy_from_msg<A: Api>(
+    api: &A,
+    underlying: &AssetInfoRaw,
+    cash_strategy: Option<CashStrategyMsg>,
+) -> StdResult<Option<CashStrategy>> {
+    let cash_strategy = match cash_strategy {
+        Some(cash_strategy) => cash_strategy,
+        None => return Ok(None),
+    };
+    if let AssetInfoRaw::Token { .. } = underlying {
+        return Err(StdError::generic_err("Cash strategies only support native underlyings"));
+    }
+    if cash_strategy.target_hot_ratio.u128() > SCALE {
+        return Err(StdError::generic_err(format!(
+            "Target hot ratio must be within [0, 1]: target_hot_ratio: {}",
+            cash_strategy.target_hot_ratio
+        )));
+    }
+    Ok(Some(CashStrategy {
+        contract: canonicalize_address(api, &cash_strategy.contract)?,
+        target_hot_ratio: cash_strategy.target_hot_ratio,
+    }))
+}
+
+/// Pays `amount` of cash to `recipient`, first withdrawing from the strategy
+/// whatever the market does not hold itself
+pub fn pay_out_cash<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    config: &Config,
+    recipient: &HumanAddr,
+    amount: Uint128,
+) -> StdResult<Vec<CosmosMsg>> {
+    split_pay_out_cash(deps, env, config, &[(recipient, amount)])
+}
+
+/// Pays each of `payouts` like pay_out_cash, withdrawing once for all of them
+pub fn split_pay_out_cash<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    config: &Config,
+    payouts: &[(&HumanAddr, Uint128)],
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages = vec![];
+    let amount = Uint128::from(payouts.iter().map(|(_, amount)| amount.u128()).sum::<u128>());
+    let held = query_underlying_balance(&deps.api, &deps.querier, &config.underlying, &env.contract.address)?;
+    let shortfall = amount.u128().saturating_sub(held.u128());
+    if shortfall > 0 {
+        let warm_cash = get_warm_cash(&deps.storage)?;
+        let cash_strategy = match &config.cash_strategy {
+            Some(cash_strategy) if warm_cash.u128() >= shortfall => cash_strategy,
+            _ => {
+                return Err(StdError::generic_err(format!(
+                    "The market holds insufficient cash: amount: {}, hot_cash: {}, warm_cash: {}",
+                    amount, held, warm_cash
+                )));
+            }
+        };
+        set_warm_cash(&mut deps.storage, Uint128::from(warm_cash.u128() - shortfall))?;
+        messages.push(
+            StrategyHandleMsg::Withdraw { amount: Uint128::from(shortfall) }
+                .into_cosmos_msg(deps.api.human_address(&cash_strategy.contract)?, vec![])?,
+        );
+    }
+    for (recipient, amount) in payouts {
+        messages.push(transfer_underlying(&deps.api, &config.underlying, &env.contract.address, recipient, *amount)?);
+    }
+    Ok(messages)
+}
+
+pub fn try_set_cash_strategy<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    cash_strategy: Option<CashStrategyMsg>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ReserveManager)?;
+    let mut config = get_config(&deps.storage)?;
+    let new_strategy = cash_strategy_from_msg(&deps.api, &config.underlying, cash_strategy.clone())?;
+
+    // Warm cash stays accounted to the strategy it was deployed to
+    let warm_cash = get_warm_cash(&deps.storage)?;
+    let old_contract = config.cash_strategy.as_ref().map(|strategy| &strategy.contract);
+    let new_contract = new_strategy.as_ref().map(|strategy| &strategy.contract);
+    if !warm_cash.is_zero() && old_contract != new_contract {
+        return Err(StdError::generic_err(format!(
+            "Warm cash must be withdrawn before the strategy changes: warm_cash: {}",
+            warm_cash
+        )));
+    }
+    let old_strategy = match config.cash_strategy.as_ref() {
+        Some(strategy) => Some(CashStrategyMsg {
+            contract: deps.api.human_address(&strategy.contract)?,
+            target_hot_ratio: strategy.target_hot_ratio,
+        }),
+        None => None,
+    };
+    config.cash_strategy = new_strategy;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "cash_strategy", &old_strategy, &cash_strategy)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_cash_strategy"),
+            log("sender", env.message.sender.as_str()),
+            log("strategy", match &config.cash_strategy {
+                Some(strategy) => deps.api.human_address(&strategy.contract)?.to_string(),
+                None => String::new(),
+            }),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_rebalance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let cash_strategy = match &config.cash_strategy {
+        Some(cash_strategy) => cash_strategy,
+        None => return Err(StdError::generic_err("Market has no cash strategy")),
+    };
+    let denom = match &config.underlying {
+        AssetInfoRaw::NativeToken { denom } => denom.clone(),
+        AssetInfoRaw::Token { .. } => {
+            return Err(StdError::generic_err("Cash strategies only support native underlyings"));
+        }
+    };
+    let strategy = deps.api.human_address(&cash_strategy.contract)?;
+
+    // Cash counts both tiers, only the split between them moves. Payouts covered
+    // by unskimmed surplus can leave warm cash above cash
+    let cash = get_state(&deps.storage)?.cash.u128();
+    let warm_cash = get_warm_cash(&deps.storage)?.u128();
+    let hot_cash = cash.saturating_sub(warm_cash);
+    let target_hot = truncate(cash * cash_strategy.target_hot_ratio.u128());
+
+    let mut messages = vec![];
+    let (deposited, withdrawn) = if hot_cash > target_hot {
+        let amount = Uint128::from(hot_cash - target_hot);
+        messages.push(StrategyHandleMsg::Deposit {}.into_cosmos_msg(strategy, vec![Coin { denom, amount }])?);
+        (amount.u128(), 0)
+    } else {
+        let amount = (target_hot - hot_cash).min(warm_cash);
+        if amount > 0 {
+            messages.push(StrategyHandleMsg::Withdraw { amount: Uint128::from(amount) }.into_cosmos_msg(strategy, vec![])?);
+        }
+        (0, amount)
+    };
+    let new_warm_cash = warm_cash + deposited - withdrawn;
+    set_warm_cash(&mut deps.storage, Uint128::from(new_warm_cash))?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "rebalance"),
+            log("sender", env.message.sender.as_str()),
+            log("deposited", deposited),
+            log("withdrawn", withdrawn),
+            log("hot_cash", cash.saturating_sub(new_warm_cash)),
+            log("warm_cash", new_warm_cash),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
@@ -1,38 +1,129 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
 use cosmwasm_std::{
-    log, Api, Env, Extern, HandleResponse,  Querier,
-     StdError, StdResult, Storage, Uint128, BankMsg, CosmosMsg, Coin
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, Querier,
+     StdError, StdResult, Storage, Uint128, LogAttribute
 };
 
-use std::convert::TryInto;
-
-use crate::state::{get_state, set_state, get_config, set_config, set_borrow_balance, get_borrow_balance, BorrowSnapshot};
-
-use crate::contract::handler::interest_model::{get_borrow_rate};
-use crate::contract::handler::exponential::truncate;
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::comptroller::MarketAction;
+
+use crate::msg::IntegratorFee;
+use crate::state::{get_balance, get_state, set_state, get_config, set_config, set_borrow_balance, get_borrow_balance, update_leaderboard, get_exchange_rate_ema, set_exchange_rate_ema, get_pending_redeem, set_pending_redeem, update_metrics, get_interest_rate_model, get_shadow_interest_rate_model, get_utilization_average, set_utilization_average, get_accrual_remainder, set_accrual_remainder, get_warm_cash, get_settlement, get_last_borrow, set_last_borrow, AccrualRemainder, BorrowSnapshot, Config, State, UtilizationAverage, ExchangeRateEma, PauseAction, PendingRedeem, TOP_BORROWERS_KEY, EXCHANGE_RATE_EMA_WINDOW};
+
+use crate::contract::handler::admin::assert_action_not_paused;
+use crate::contract::handler::backstop::clear_recovered_backstop_flag;
+use crate::contract::handler::balance_hook::balance_hook;
+use crate::contract::handler::cash_strategy::{pay_out_cash, split_pay_out_cash};
+use crate::contract::handler::integrator::{integrator_fee as integrator_fee_of, record_integrator_fee};
+use crate::contract::handler::health_alert::health_alert_log;
+use crate::contract::handler::utilization_alert::utilization_alert_log;
+use crate::contract::handler::insurance::{insurance_top_up, settle_premium};
+use crate::contract::handler::interest_deferral::settle_interest_deferral;
+use crate::contract::handler::reserves::advance_reserve_factor_ramp;
+use crate::contract::handler::settlement::distribute_repayment;
+use crate::contract::handler::interest_model::{get_borrow_rate_at, get_utilization_rate};
+use crate::contract::handler::comptroller::comptroller_hook;
+use crate::contract::handler::exponential::{mul_div, SCALE, split_fraction, to_decimal, truncate};
+use crate::contract::handler::funds::{query_underlying_balance, transfer_underlying};
+use crate::contract::handler::liquidation::assert_not_flagged;
+use crate::contract::handler::transfer_policy::assert_transfer_allowed;
 use crate::contract::handler::token::{mint_tokens, burn_tokens};
 
 pub fn try_repay_borrow<S: Storage, A: Api, Q: Querier>(
-    _deps: &mut Extern<S, A, Q>,
+    deps: &mut Extern<S, A, Q>,
     env: Env,
+    amount: Uint128,
 ) -> StdResult<HandleResponse> {
-    let res = HandleResponse {
-        messages: vec![],
+    let borrower = env.message.sender.clone();
+    repay_borrow(deps, env, &borrower, amount)
+}
+
+/// Repays the borrower's debt with `amount` of underlying the market already
+/// holds, refunding anything above the debt to the borrower
+pub fn repay_borrow<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    borrower: &HumanAddr,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let mut accrual_log = accrue_interest(deps, env.clone())?;
+
+    let sender_raw = canonicalize_address(&deps.api, borrower)?;
+    accrual_log.extend(settle_interest_deferral(&mut deps.storage, &sender_raw)?);
+    settle_premium(&mut deps.storage, &sender_raw)?;
+    let state = get_state(&deps.storage)?;
+    let account_borrow = match get_borrow_balance(&deps.storage, &sender_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    if account_borrow == 0 {
+        return Err(StdError::generic_err(format!(
+            "Nothing to repay: account: {}",
+            borrower
+        )));
+    }
+
+    // Anything sent above the debt goes back to the sender
+    let repay_amount = amount.u128().min(account_borrow);
+    let refund = amount.u128() - repay_amount;
+
+    let new_account_borrow = account_borrow - repay_amount;
+    let mut new_state = state.clone();
+    new_state.cash += Uint128::from(repay_amount);
+    new_state.total_borrows = Uint128::from(new_state.total_borrows.u128().saturating_sub(repay_amount));
+    set_state(&mut deps.storage, &new_state)?;
+    distribute_repayment(&mut deps.storage, repay_amount)?;
+    set_borrow_balance(&mut deps.storage, &sender_raw, Some(BorrowSnapshot {
+        principal: Uint128::from(new_account_borrow),
+        interest_index: new_state.borrow_index,
+    }))?;
+    update_leaderboard(&mut deps.storage, TOP_BORROWERS_KEY, &sender_raw, new_account_borrow)?;
+    clear_recovered_backstop_flag(&mut deps.storage, &sender_raw)?;
+
+    let mut messages = vec![];
+    if refund > 0 {
+        messages.push(transfer_underlying(
+            &deps.api,
+            &config.underlying,
+            &env.contract.address,
+            borrower,
+            Uint128::from(refund),
+        )?);
+    }
+    messages.extend(comptroller_hook(&deps.api, &config, MarketAction::RepayBorrow, borrower, repay_amount)?);
+
+    let mut res = HandleResponse {
+        messages,
         log: vec![
             log("action", "repay_borrow"),
-            log("sender", env.message.sender.as_str()),
+            log("sender", borrower.as_str()),
+            log("amount", repay_amount),
+            log("repay_amount", repay_amount),
+            log("refund", refund),
+            log("new_account_borrow", new_account_borrow),
         ],
         data: None,
     };
+    res.log.extend(market_log(&deps.storage)?);
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&sender_raw])?);
+    res.log.extend(accrual_log);
     Ok(res)
 }
 
 pub fn try_borrow<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    borrow_amount: Uint128
+    borrow_amount: Uint128,
+    integrator_fee: Option<IntegratorFee>,
 ) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Borrow)?;
+    let integrator = integrator_fee_of(deps, integrator_fee.as_ref(), borrow_amount)?;
 
-    accrue_interest(deps, env.clone())?;
+    let mut accrual_log = accrue_interest(deps, env.clone())?;
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    accrual_log.extend(settle_interest_deferral(&mut deps.storage, &sender_raw)?);
+    settle_premium(&mut deps.storage, &sender_raw)?;
 
     let current_block = env.block.height;
     let state = get_state(&deps.storage)?;
@@ -44,8 +135,6 @@ pub fn try_borrow<S: Storage, A: Api, Q: Querier>(
         );
     }
 
-    // TODO: get query from controller contract whether the sender is allowed to borrow
-
     // Check if the pool has enough balance to lend to the sender
     if state.cash < borrow_amount { 
         return Err(StdError::generic_err(format!(
@@ -55,11 +144,33 @@ pub fn try_borrow<S: Storage, A: Api, Q: Querier>(
         );
     }
 
-    // Get borrow balance of the sender
-    let sender_raw = deps.api.canonical_address(&env.message.sender)?;
     // get borrow balance
     let account_borrow = get_account_borrow(deps, env.clone())?;
     let new_account_borrow = account_borrow + borrow_amount.u128();
+
+    // Standalone markets only accept collateral supplied to themselves,
+    // otherwise the comptroller hook checks the debt across all entered markets
+    let config = get_config(&deps.storage)?;
+    if config.comptroller.is_none() {
+        let exchange_rate = get_exchange_rate(deps, env.clone())?;
+        let limit = borrow_limit(&config, get_balance(&deps.storage, &sender_raw)?, exchange_rate);
+        if new_account_borrow > limit {
+            return Err(StdError::generic_err(format!(
+                "Borrow exceeds collateral limit: account_borrow: {}, borrow_limit: {}",
+                 new_account_borrow, limit)
+                )
+            );
+        }
+        // Borrowing to the limit would be liquidatable at the next accrual
+        if let Some(min_health_factor) = config.min_borrow_health_factor {
+            if Uint256::from(limit) < Uint256::from(new_account_borrow) * min_health_factor {
+                return Err(StdError::generic_err(format!(
+                    "Borrow leaves the account below the minimum health factor: account_borrow: {}, borrow_limit: {}, min_borrow_health_factor: {}",
+                    new_account_borrow, limit, min_health_factor
+                )));
+            }
+        }
+    }
     
 
     // Set new cash amount for contract
@@ -74,37 +185,103 @@ pub fn try_borrow<S: Storage, A: Api, Q: Querier>(
         interest_index: new_state.borrow_index
     };
     set_borrow_balance(&mut deps.storage, &sender_raw, Some(new_borrow_balance))?;
+    update_leaderboard(&mut deps.storage, TOP_BORROWERS_KEY, &sender_raw, new_account_borrow)?;
+    update_metrics(&mut deps.storage, |m| m.borrows += 1)?;
+    if config.borrow_cooldown.is_some() {
+        set_last_borrow(&mut deps.storage, &sender_raw, current_block)?;
+    }
     
-    // Transfer native token to the user
-    // TODO: in this case it is hard coded to luna, include denom in contract config
-    let native_transfer: CosmosMsg = CosmosMsg::Bank(BankMsg::Send {
-        from_address: env.contract.address.clone(),
-        to_address: env.message.sender.clone(),
-        amount: vec![Coin {
-            denom: "uluna".to_string(),
-            amount: borrow_amount.clone(),
-        }],
-    });
-    
-    let res = HandleResponse {
-        messages: vec![native_transfer],
+    // Transfer the underlying to the user, less the integrator's fee
+    let mut messages = match &integrator {
+        Some((integrator_raw, fee)) if !fee.is_zero() => {
+            record_integrator_fee(&mut deps.storage, integrator_raw, *fee)?;
+            let integrator = deps.api.human_address(integrator_raw)?;
+            split_pay_out_cash(deps, &env, &config, &[
+                (&env.message.sender, (borrow_amount - *fee)?),
+                (&integrator, *fee),
+            ])?
+        }
+        _ => pay_out_cash(deps, &env, &config, &env.message.sender, borrow_amount)?,
+    };
+    messages.extend(comptroller_hook(&deps.api, &config, MarketAction::Borrow, &env.message.sender, borrow_amount.u128())?);
+
+    let mut res = HandleResponse {
+        messages,
         log: vec![
             log("action", "borrow"),
             log("sender", env.message.sender.as_str()),
-            log("new_account_borrow", new_account_borrow.clone()),
+            log("recipient", env.message.sender.as_str()),
+            log("amount", borrow_amount),
+            log("new_account_borrow", new_account_borrow),
             log("new_total_borrows", new_state.clone().total_borrows)
         ],
         data: None,
     };
+    if let (Some(integrator_fee), Some((_, fee))) = (integrator_fee, integrator) {
+        res.log.push(log("integrator", integrator_fee.integrator.as_str()));
+        res.log.push(log("integrator_fee_bps", integrator_fee.fee_bps));
+        res.log.push(log("integrator_fee", fee));
+    }
+    res.log.extend(market_log(&deps.storage)?);
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&sender_raw])?);
+    res.log.extend(accrual_log);
     Ok(res)
 }
 
+/// Fails while the account is within the borrow cooldown of its last borrow
+pub fn assert_borrow_cooled_down<S: Storage>(storage: &S, account: &CanonicalAddr, env: &Env) -> StdResult<()> {
+    let cooldown = match get_config(storage)?.borrow_cooldown {
+        Some(cooldown) => cooldown,
+        None => return Ok(()),
+    };
+    if let Some(last_borrow) = get_last_borrow(storage, account)? {
+        if env.block.height < last_borrow + cooldown {
+            return Err(StdError::generic_err(format!(
+                "Account borrowed too recently: last_borrow: {}, cooldown_ends: {}",
+                last_borrow,
+                last_borrow + cooldown
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Fails when the account's debt exceeds the borrow limit of the qTokens it holds now,
+/// checked after qTokens left the account
+/// Markets under a comptroller leave this to the comptroller hook, which sees every entered market
+pub fn assert_account_healthy<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<()> {
+    let config = get_config(storage)?;
+    if config.comptroller.is_some() {
+        return Ok(());
+    }
+    let account_borrow = match get_borrow_balance(storage, account) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &get_state(storage)?.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    if account_borrow == 0 {
+        return Ok(());
+    }
+    let exchange_rate = calculate_exchange_rate(&config, &get_state(storage)?)?;
+    let limit = borrow_limit(&config, get_balance(storage, account)?, exchange_rate);
+    if account_borrow > limit {
+        return Err(StdError::generic_err(format!(
+            "Account would be undercollateralized: account_borrow: {}, borrow_limit: {}",
+            account_borrow, limit
+        )));
+    }
+    Ok(())
+}
+
+/// Mints qTokens for the sent funds to the recipient, which may differ from the paying sender
 pub fn try_mint<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    recipient: &HumanAddr,
+    mint_amount: Uint128,
 ) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Mint)?;
 
-    accrue_interest(deps, env.clone())?;
+    let accrual_log = accrue_interest(deps, env.clone())?;
     
     let current_block = env.block.height;
     let state = get_state(&deps.storage)?;
@@ -116,16 +293,12 @@ pub fn try_mint<S: Storage, A: Api, Q: Querier>(
         );
     }
     
-
-    // TODO: get query from controller contract whether the sender is allowed to borrow
-
-    // Check native currency transfer
-    let mint_amount = env.message.sent_funds[0].amount.u128(); 
+    let mint_amount = mint_amount.u128();
 
     // Get exchange rate derived from borrow and reserve
     let exchange_rate = get_exchange_rate(deps, env.clone())?;
 
-    let token_mint_amount = truncate(mint_amount * exchange_rate);
+    let token_mint_amount = tokens_for_underlying(mint_amount, exchange_rate);
 
     // Set new config
     let mut new_config = get_config(&deps.storage)?;
@@ -138,32 +311,53 @@ pub fn try_mint<S: Storage, A: Api, Q: Querier>(
 
     set_config(&mut deps.storage, &new_config)?;
 
-    // Mint token to the sender
-    let recipient_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    // Mint token to the recipient
+    assert_transfer_allowed(deps, None, recipient, Uint128::from(token_mint_amount))?;
+    let recipient_address_raw = canonicalize_address(&deps.api, recipient)?;
     mint_tokens(
         &mut deps.storage,
         &recipient_address_raw,
         token_mint_amount,
     )?;
+    update_metrics(&mut deps.storage, |m| m.mints += 1)?;
 
-    let res = HandleResponse {
-        messages: vec![],
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&recipient_address_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &new_config, MarketAction::Mint, recipient, mint_amount)?);
+
+    let mut res = HandleResponse {
+        messages,
         log: vec![
             log("action", "mint"),
             log("sender", env.message.sender.as_str()),
-            log("minted_amount", token_mint_amount.clone())
+            log("recipient", recipient.as_str()),
+            log("amount", mint_amount),
+            log("minted_amount", token_mint_amount)
         ],
         data: None,
     };
+    res.log.extend(market_log(&deps.storage)?);
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&recipient_address_raw])?);
+    res.log.extend(accrual_log);
     Ok(res)
 }
 
+/// Redeems either `redeem_tokens_in` qTokens or the qTokens worth `redeem_amount_in` underlying,
+/// the other must be 0
 pub fn try_redeem<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    redeem_tokens_in: Uint128
+    redeem_tokens_in: Uint128,
+    redeem_amount_in: Uint128
 ) -> StdResult<HandleResponse> {
-    accrue_interest(deps, env.clone())?;
+    if redeem_tokens_in.is_zero() == redeem_amount_in.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "one of redeeming tokens or underlying must be 0: redeem_tokens_in: {}, redeem_amount_in: {}",
+             redeem_tokens_in, redeem_amount_in)
+            )
+        );
+    }
+
+    let accrual_log = accrue_interest(deps, env.clone())?;
 
     let current_block = env.block.height;
     let state = get_state(&deps.storage)?;
@@ -175,35 +369,56 @@ pub fn try_redeem<S: Storage, A: Api, Q: Querier>(
         );
     }
 
-    // TODO: get query from controller contract whether the sender is allowed to borrow
+    let redeemer_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    assert_not_flagged(&deps.storage, &redeemer_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &redeemer_raw, &env)?;
 
     // Get exchange rate derived from borrow and reserve
     let exchange_rate = get_exchange_rate(deps, env.clone())?;
 
-    let redeem_native_in = env.message.sent_funds[0].amount.u128();
-    if redeem_tokens_in.u128() != 0 && redeem_native_in != 0 {
-        return Err(StdError::generic_err(format!(
-            "one of redeeming tokens or asset must be 0: redeeming_cw20: {}, redeeming_native_currency: {}",
-             redeem_tokens_in.u128(), redeem_native_in)
-            )
-        );
-    }
     // Calculate redeem amount
-    let (redeem_native, redeem_tokens, new_state) = match redeem_tokens_in.u128() {
-        x if x > 0 => {
-            // Set new cash amount for contract
-            let mut new_state = state.clone();
-            let redeem_native = truncate(exchange_rate * 100_000_000 * redeem_tokens_in.u128());
-            new_state.cash = (new_state.cash - Uint128::from(redeem_native))?;
-            (redeem_native, redeem_tokens_in.u128(), new_state) 
+    let (redeem_native, redeem_tokens) = match redeem_tokens_in.u128() {
+        0 => {
+            // Burned qTokens round up so the redemption never pays more than they are worth
+            let redeem_native = redeem_amount_in.u128();
+            (redeem_native, tokens_for_underlying_ceil(redeem_native, exchange_rate))
         },
-        _ => {
-            // Set new cash amount for contract
-            let mut new_state = state.clone();
-            new_state.cash += Uint128::from(redeem_native_in);
-            (redeem_native_in, truncate(redeem_native_in / (100_000_000 * exchange_rate)), new_state)
-        }
+        redeem_tokens => (underlying_for_tokens(redeem_tokens, exchange_rate), redeem_tokens)
     };
+    // Set new cash amount for contract
+    let mut new_state = state.clone();
+    new_state.cash = (new_state.cash - Uint128::from(redeem_native))?;
+
+    // Large redemptions must have been announced at least redeem_announce_delay blocks earlier
+    let config = get_config(&deps.storage)?;
+    if let Some(threshold) = config.large_redeem_threshold {
+        if Uint128::from(redeem_native) > threshold {
+            match get_pending_redeem(&deps.storage, &redeemer_raw)? {
+                Some(pending) if current_block < pending.executable_at => {
+                    return Err(StdError::generic_err(format!(
+                        "Announced redemption is not executable yet: current_block: {}, executable_at: {}",
+                         current_block, pending.executable_at)
+                        )
+                    );
+                },
+                Some(pending) if Uint128::from(redeem_native) > pending.amount => {
+                    return Err(StdError::generic_err(format!(
+                        "Redemption exceeds announced amount: redeem_amount: {}, announced_amount: {}",
+                         redeem_native, pending.amount)
+                        )
+                    );
+                },
+                Some(_) => set_pending_redeem(&mut deps.storage, &redeemer_raw, None)?,
+                None => {
+                    return Err(StdError::generic_err(format!(
+                        "Redemption above threshold must be announced first: redeem_amount: {}, threshold: {}",
+                         redeem_native, threshold)
+                        )
+                    );
+                }
+            }
+        }
+    }
 
     // Set new state for cash
     set_state(&mut deps.storage, &new_state)?;
@@ -216,12 +431,14 @@ pub fn try_redeem<S: Storage, A: Api, Q: Querier>(
     
 
     // Burn token to the sender
-    let recipient_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let recipient_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
     burn_tokens(
         &mut deps.storage,
         &recipient_address_raw,
         redeem_tokens,
     )?;
+    assert_account_healthy(&deps.storage, &recipient_address_raw)?;
+    update_metrics(&mut deps.storage, |m| m.redeems += 1)?;
 
     // Check if the pool has enough balance
     if state.cash < Uint128::from(redeem_native) {
@@ -232,39 +449,163 @@ pub fn try_redeem<S: Storage, A: Api, Q: Querier>(
         );
     }
 
-    // Transfer native token to the user
-    // TODO: in this case it is hard coded to luna, include denom in contract config
-    let native_transfer: CosmosMsg = CosmosMsg::Bank(BankMsg::Send {
-        from_address: env.contract.address.clone(),
-        to_address: env.message.sender.clone(),
-        amount: vec![Coin {
-            denom: "uluna".to_string(),
-            amount: Uint128::from(redeem_native.clone()),
-        }],
-    });
+    // Transfer the underlying to the user
+    let mut messages = pay_out_cash(deps, &env, &config, &env.message.sender, Uint128::from(redeem_native))?;
+    messages.extend(balance_hook(&deps.storage, &deps.api, &[&recipient_address_raw])?);
+    messages.extend(comptroller_hook(&deps.api, &config, MarketAction::Redeem, &env.message.sender, redeem_tokens)?);
 
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "redeem"),
+            log("sender", env.message.sender.as_str()),
+            log("recipient", env.message.sender.as_str()),
+            log("amount", redeem_native),
+            log("redeem_tokens", redeem_tokens),
+            log("redeem_native", redeem_native)
+        ],
+        data: None,
+    };
+    res.log.extend(market_log(&deps.storage)?);
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&recipient_address_raw])?);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Writes off a dust borrow, reserves absorb it so the exchange rate does not move
+pub fn try_close_dust_borrow<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    let config = get_config(&deps.storage)?;
+    let threshold = match config.dust_borrow_threshold {
+        Some(threshold) => threshold,
+        None => return Err(StdError::generic_err("Dust borrows are not enabled in this market")),
+    };
+
+    let account_raw = canonicalize_address(&deps.api, account)?;
+    let mut new_state = get_state(&deps.storage)?;
+    let account_borrow = match get_borrow_balance(&deps.storage, &account_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &new_state.borrow_index, Some(threshold)),
+        None => 0,
+    };
+    if account_borrow == 0 || Uint128::from(account_borrow) > threshold {
+        return Err(StdError::generic_err(format!(
+            "Borrow is not dust: account_borrow: {}, dust_borrow_threshold: {}",
+             account_borrow, threshold)
+            )
+        );
+    }
+    if new_state.total_reserves < Uint128::from(account_borrow) {
+        return Err(StdError::generic_err(format!(
+            "Reserves cannot absorb the dust borrow: account_borrow: {}, total_reserves: {}",
+             account_borrow, new_state.total_reserves)
+            )
+        );
+    }
 
-    // TODO: write defense hook
+    new_state.total_borrows = Uint128::from(new_state.total_borrows.u128().saturating_sub(account_borrow));
+    new_state.total_reserves = (new_state.total_reserves - Uint128::from(account_borrow))?;
+    set_state(&mut deps.storage, &new_state)?;
+    set_borrow_balance(&mut deps.storage, &account_raw, None)?;
+    update_leaderboard(&mut deps.storage, TOP_BORROWERS_KEY, &account_raw, 0)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "close_dust_borrow"),
+            log("sender", env.message.sender.as_str()),
+            log("account", account.as_str()),
+            log("written_off", account_borrow),
+            log("new_total_reserves", new_state.total_reserves)
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&account_raw])?);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Moves underlying held above the tracked cash, e.g. direct bank sends, into reserves
+/// Cash and reserves grow together so donations cannot move the exchange rate
+pub fn try_skim<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let balance = query_underlying_balance(&deps.api, &deps.querier, &config.underlying, &env.contract.address)?;
+    let mut new_state = get_state(&deps.storage)?;
+    // Warm cash is part of cash but held by the strategy
+    let held = balance.u128() + get_warm_cash(&deps.storage)?.u128();
+    let excess = held.saturating_sub(new_state.cash.u128());
+
+    new_state.cash += Uint128::from(excess);
+    new_state.total_reserves += Uint128::from(excess);
+    set_state(&mut deps.storage, &new_state)?;
 
     let res = HandleResponse {
-        messages: vec![
-            native_transfer
+        messages: vec![],
+        log: vec![
+            log("action", "skim"),
+            log("sender", env.message.sender.as_str()),
+            log("skimmed_amount", excess),
+            log("new_total_reserves", new_state.total_reserves)
         ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_announce_redeem<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if config.large_redeem_threshold.is_none() {
+        return Err(StdError::generic_err("Large redemptions do not need to be announced in this market"));
+    }
+
+    // Announcing again replaces the previous announcement and restarts the delay
+    let executable_at = env.block.height + config.redeem_announce_delay;
+    let redeemer_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    set_pending_redeem(&mut deps.storage, &redeemer_raw, Some(PendingRedeem {
+        amount,
+        executable_at
+    }))?;
+
+    let res = HandleResponse {
+        messages: vec![],
         log: vec![
-            log("action", "redeem"),
+            log("action", "announce_redeem"),
             log("sender", env.message.sender.as_str()),
-            log("redeem_tokens", redeem_tokens.clone()),
-            log("redeem_native", redeem_native.clone())
+            log("amount", amount),
+            log("executable_at", executable_at)
         ],
         data: None,
     };
     Ok(res)
 }
 
-fn accrue_interest<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: Env) -> StdResult<()>  {
+/// Accrues interest up to the current block and returns the accrual event logs
+/// Logs carry deltas against the previous accrual so indexers can replay the index history exactly
+pub fn accrue_interest<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: Env) -> StdResult<Vec<LogAttribute>>  {
+    // Borrows stay frozen at the settlement index
+    if get_settlement(&deps.storage)?.is_some() {
+        return Ok(vec![]);
+    }
+    let current_block = env.block.height;
+    // Interest since the last accrual is split at the factor reached by this block
+    advance_reserve_factor_ramp(&mut deps.storage, current_block)?;
     let prior_state = get_state(&deps.storage)?;
+    let config = get_config(&deps.storage)?;
 
-    let borrow_rate = get_borrow_rate(&prior_state.cash, &prior_state.total_borrows, &prior_state.total_reserves);
+    let utilization_rate = get_utilization_rate(&prior_state.cash, &prior_state.total_borrows, &prior_state.total_reserves);
+    let average_utilization = update_utilization_average(deps, current_block, utilization_rate, config.utilization_window)?;
+    let borrow_rate = get_borrow_rate_at(&get_interest_rate_model(&deps.storage)?, average_utilization);
 
     if borrow_rate > prior_state.max_borrow_rate.u128() {
         return Err(StdError::generic_err(format!(
@@ -274,17 +615,14 @@ fn accrue_interest<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, e
         );
     }
 
-    let current_block = env.block.height;
-
-    let block_delta: u128 = (prior_state.block_number - current_block).try_into().unwrap();
-
-    // Calculate the interest accumulated into borrows and reserves and the new index:
-    let simple_interest_factor = borrow_rate * block_delta;
+    let (block_delta, clamped) = accrual_block_delta(&config, &prior_state, current_block);
+    let block_delta: u128 = block_delta.into();
 
-    let accumulated_interest = truncate(simple_interest_factor * prior_state.total_borrows.u128());
+    let accrual = compute_accrual(&prior_state, &get_accrual_remainder(&deps.storage)?, borrow_rate, block_delta);
+    let accumulated_interest = accrual.interest;
     let new_total_borrows = accumulated_interest + prior_state.total_borrows.u128();
-    let new_total_reserves = truncate(accumulated_interest * prior_state.reserve_factor.u128()) + prior_state.total_reserves.u128();
-    let new_borrow_index = truncate(simple_interest_factor * prior_state.borrow_index.u128()) + prior_state.borrow_index.u128();
+    let new_total_reserves = accrual.reserve_take + prior_state.total_reserves.u128();
+    let new_borrow_index = accrual.borrow_index;
 
     // Set new state
     let mut new_state = get_state(&deps.storage)?;
@@ -294,26 +632,201 @@ fn accrue_interest<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, e
     new_state.total_reserves = Uint128::from(new_total_reserves);
 
     set_state(&mut deps.storage, &new_state)?;
+    set_accrual_remainder(&mut deps.storage, &accrual.remainder)?;
+    update_metrics(&mut deps.storage, |m| {
+        m.cumulative_interest += Uint128::from(accumulated_interest);
+        m.cumulative_reserve_interest += Uint128::from(accrual.reserve_take);
+        if clamped {
+            m.clamped_accruals += 1;
+        }
+    })?;
+
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    update_exchange_rate_ema(deps, env, exchange_rate)?;
+
+    let mut accrual_log = vec![
+        log("accrual_block", current_block),
+        log("accrual_block_delta", block_delta),
+        log("accrual_clamped", clamped),
+        log("accrual_utilization", utilization_rate),
+        log("accrual_average_utilization", average_utilization),
+        log("accrual_borrow_rate", borrow_rate),
+        log("accrual_borrow_index_delta", new_borrow_index - prior_state.borrow_index.u128()),
+        log("accrual_interest", accumulated_interest),
+        log("accrual_reserve_take", new_total_reserves - prior_state.total_reserves.u128()),
+        log("accrual_exchange_rate", exchange_rate)
+    ];
+
+    if let Some(top_up) = insurance_top_up(&mut deps.storage, block_delta)? {
+        accrual_log.push(log("accrual_insurance_top_up", top_up));
+    }
+    accrual_log.extend(utilization_alert_log(&mut deps.storage, &new_state)?);
+
+    // The shadow model sees the same inputs as the live one, its interest is only reported
+    if let Some(shadow_model) = get_shadow_interest_rate_model(&deps.storage)? {
+        let shadow_borrow_rate = get_borrow_rate_at(&shadow_model, average_utilization);
+        let shadow_interest = mul_div(shadow_borrow_rate * block_delta, prior_state.total_borrows.u128(), SCALE);
+        accrual_log.push(log("accrual_shadow_borrow_rate", shadow_borrow_rate));
+        accrual_log.push(log("accrual_shadow_interest", shadow_interest));
+    }
+    Ok(accrual_log)
+}
 
+/// Interest, reserve share and new index of one accrual, with the fractions carried to the next
+pub struct Accrual {
+    pub interest: u128,
+    pub reserve_take: u128,
+    pub borrow_index: u128,
+    pub remainder: AccrualRemainder,
+}
 
-    Ok(())
+/// Accrues `block_delta` blocks at `borrow_rate` onto `state`
+/// Products are taken in 256 bits, and what truncation drops is returned in the remainder
+pub fn compute_accrual(state: &State, remainder: &AccrualRemainder, borrow_rate: u128, block_delta: u128) -> Accrual {
+    let simple_interest_factor = to_decimal(borrow_rate * block_delta);
+
+    let (interest, interest_remainder) = split_fraction(
+        Decimal256::from_uint256(Uint256::from(state.total_borrows.u128())) * simple_interest_factor + remainder.interest,
+    );
+    let (reserve_take, reserves_remainder) = split_fraction(
+        Decimal256::from_uint256(Uint256::from(interest)) * to_decimal(state.reserve_factor.u128()) + remainder.reserves,
+    );
+    let (index_delta, index_remainder) = split_fraction(
+        Decimal256::from_uint256(Uint256::from(state.borrow_index.u128())) * simple_interest_factor + remainder.borrow_index,
+    );
+
+    Accrual {
+        interest,
+        reserve_take,
+        borrow_index: state.borrow_index.u128() + index_delta,
+        remainder: AccrualRemainder {
+            interest: interest_remainder,
+            reserves: reserves_remainder,
+            borrow_index: index_remainder,
+        },
+    }
 }
 
-fn get_exchange_rate<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, _env: Env) -> StdResult<u128> {
+/// Blocks to accrue since the last accrual
+/// A gap above max_accrual_block_delta (chain halt, height jump on upgrade) only accrues up to the limit
+pub fn accrual_block_delta(config: &Config, state: &State, current_block: u64) -> (u64, bool) {
+    let raw_block_delta = current_block.saturating_sub(state.block_number);
+    match config.max_accrual_block_delta {
+        Some(max_delta) if raw_block_delta > max_delta => (max_delta, true),
+        _ => (raw_block_delta, false),
+    }
+}
+
+/// Folds the utilization held since the last accrual into the time-weighted average
+/// Deposits and withdrawals within the current block do not move the average
+fn update_utilization_average<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, current_block: u64, utilization_rate: u128, window: Option<u64>) -> StdResult<u128> {
+    let average_utilization = utilization_average_at(&deps.storage, current_block, utilization_rate, window)?;
+    if window.is_none() {
+        return Ok(average_utilization);
+    }
+
+    set_utilization_average(&mut deps.storage, &UtilizationAverage {
+        average_utilization: Uint128::from(average_utilization),
+        block_number: current_block
+    })?;
+    Ok(average_utilization)
+}
+
+/// Average utilization the next accrual at `current_block` would use, without storing it
+pub fn utilization_average_at<S: Storage>(storage: &S, current_block: u64, utilization_rate: u128, window: Option<u64>) -> StdResult<u128> {
+    let window = match window {
+        Some(window) => window,
+        None => return Ok(utilization_rate),
+    };
+    let average_utilization = match get_utilization_average(storage)? {
+        Some(prior) => {
+            let block_delta = current_block.saturating_sub(prior.block_number);
+            if block_delta >= window {
+                utilization_rate
+            } else {
+                let block_delta = block_delta as u128;
+                let window = window as u128;
+                (prior.average_utilization.u128() * (window - block_delta) + utilization_rate * block_delta) / window
+            }
+        },
+        None => utilization_rate
+    };
+    Ok(average_utilization)
+}
+
+/// Moves the display exchange rate towards the spot rate over EXCHANGE_RATE_EMA_WINDOW blocks
+/// Transactional math never reads the smoothed value
+fn update_exchange_rate_ema<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: Env, exchange_rate: u128) -> StdResult<()> {
+    let current_block = env.block.height;
+    let smoothed_exchange_rate = match get_exchange_rate_ema(&deps.storage)? {
+        Some(prior) => {
+            let block_delta = current_block.saturating_sub(prior.block_number);
+            if block_delta >= EXCHANGE_RATE_EMA_WINDOW {
+                exchange_rate
+            } else {
+                let window = EXCHANGE_RATE_EMA_WINDOW as u128;
+                let block_delta = block_delta as u128;
+                (prior.smoothed_exchange_rate.u128() * (window - block_delta) + exchange_rate * block_delta) / window
+            }
+        },
+        None => exchange_rate
+    };
+
+    set_exchange_rate_ema(&mut deps.storage, &ExchangeRateEma {
+        smoothed_exchange_rate: Uint128::from(smoothed_exchange_rate),
+        last_exchange_rate: Uint128::from(exchange_rate),
+        block_number: current_block
+    })
+}
+
+/// Exchange rate and borrow index once a handler applied its changes, logged by
+/// mint, redeem, borrow, repay and liquidate
+pub fn market_log<S: Storage>(storage: &S) -> StdResult<Vec<LogAttribute>> {
+    let config = get_config(storage)?;
+    let state = get_state(storage)?;
+    Ok(vec![
+        log("exchange_rate", calculate_exchange_rate(&config, &state)?),
+        log("borrow_index", state.borrow_index),
+    ])
+}
+
+pub fn get_exchange_rate<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, _env: Env) -> StdResult<u128> {
     let config = get_config(&deps.storage)?;
-    
+    let state = get_state(&deps.storage)?;
+    calculate_exchange_rate(&config, &state)
+}
+
+/// qTokens worth `underlying` at an exchange rate of underlying per qToken scaled by 10^8, rounded down
+pub fn tokens_for_underlying(underlying: u128, exchange_rate: u128) -> u128 {
+    mul_div(underlying, SCALE, exchange_rate)
+}
+
+/// tokens_for_underlying rounded up, for qTokens taken from an account in exchange for underlying
+pub fn tokens_for_underlying_ceil(underlying: u128, exchange_rate: u128) -> u128 {
+    let tokens = tokens_for_underlying(underlying, exchange_rate);
+    match underlying_for_tokens(tokens, exchange_rate) < underlying {
+        true => tokens + 1,
+        false => tokens,
+    }
+}
+
+/// Underlying `tokens` qTokens are worth at an exchange rate scaled by 10^8, rounded down
+pub fn underlying_for_tokens(tokens: u128, exchange_rate: u128) -> u128 {
+    mul_div(tokens, exchange_rate, SCALE)
+}
+
+/// Exchange rate for the given market config and state, usable from queries
+pub fn calculate_exchange_rate(config: &Config, prior_state: &State) -> StdResult<u128> {
     // if total supply is zero
     if config.total_supply == Uint128::from(0u128) {
         return Ok(config.initial_exchange_rate.u128());
     }
     // else calculate exchange rate
-    let prior_state = get_state(&deps.storage)?;
-
     let total_cash = prior_state.cash;
 
     let cash_plus_borrows_minus_reserves = (total_cash + prior_state.total_borrows - prior_state.total_reserves)?;
 
-    let exchange_rate = cash_plus_borrows_minus_reserves.u128() / config.total_supply.u128() * 100_000_000;
+    let exchange_rate = mul_div(cash_plus_borrows_minus_reserves.u128(), SCALE, config.total_supply.u128());
 
 
     Ok(exchange_rate)
@@ -321,7 +834,7 @@ fn get_exchange_rate<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>,
 
 
 fn get_account_borrow<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: Env) -> StdResult<u128> {
-    let sender_raw = deps.api.canonical_address(&env.message.sender)?;
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
     let snapshot = get_borrow_balance(&deps.storage, &sender_raw);
     
     let borrow_snapshot = match snapshot {
@@ -345,7 +858,120 @@ fn get_account_borrow<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>
     }
 
     let state = get_state(&deps.storage)?;
-    let principal_time_index = borrow_snapshot.principal.u128() * state.borrow_index.u128();
-    return Ok(principal_time_index / borrow_snapshot.interest_index.u128());
+    let config = get_config(&deps.storage)?;
+    Ok(current_borrow_balance(&borrow_snapshot, &state.borrow_index, config.dust_borrow_threshold))
+}
+
+/// Most underlying an account holding `tokens` qTokens may owe this market
+pub fn borrow_limit(config: &Config, tokens: u128, exchange_rate: u128) -> u128 {
+    truncate(underlying_for_tokens(tokens, exchange_rate) * config.collateral_factor.u128())
+}
+
+/// Debt of a borrow snapshot at the given borrow index
+/// Principal at or below the dust threshold no longer grows with the index
+pub fn current_borrow_balance(snapshot: &BorrowSnapshot, borrow_index: &Uint128, dust_threshold: Option<Uint128>) -> u128 {
+    if snapshot.principal.is_zero() {
+        return 0;
+    }
+    match dust_threshold {
+        Some(threshold) if snapshot.principal <= threshold => snapshot.principal.u128(),
+        _ => mul_div(snapshot.principal.u128(), borrow_index.u128(), snapshot.interest_index.u128()),
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockStorage, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, BankMsg, CosmosMsg};
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market, MOCK_DENOM};
+    use crate::msg::HandleMsg;
+
+    #[test]
+    fn utilization_average_weights_scaled_utilization_by_blocks_held() {
+        let mut storage = MockStorage::new();
+        // 40% averaged over a 100 block window
+        set_utilization_average(&mut storage, &UtilizationAverage {
+            average_utilization: Uint128::from(40_000_000u128),
+            block_number: 1_000,
+        }).unwrap();
+
+        // 25 blocks of 80% move the average a quarter of the way
+        assert_eq!(utilization_average_at(&storage, 1_025, 80_000_000, Some(100)).unwrap(), 50_000_000);
+        // No blocks held leaves the average alone
+        assert_eq!(utilization_average_at(&storage, 1_000, 80_000_000, Some(100)).unwrap(), 40_000_000);
+        // A full window held replaces it
+        assert_eq!(utilization_average_at(&storage, 1_100, 80_000_000, Some(100)).unwrap(), 80_000_000);
+        assert_eq!(utilization_average_at(&storage, 5_000, 80_000_000, Some(100)).unwrap(), 80_000_000);
+        // Without a window the spot utilization is used
+        assert_eq!(utilization_average_at(&storage, 1_025, 80_000_000, None).unwrap(), 80_000_000);
+    }
+
+    #[test]
+    fn utilization_average_starts_at_spot_and_keeps_full_precision() {
+        let mut storage = MockStorage::new();
+        assert_eq!(utilization_average_at(&storage, 1_000, 33_333_333, Some(3)).unwrap(), 33_333_333);
+
+        set_utilization_average(&mut storage, &UtilizationAverage {
+            average_utilization: Uint128::from(100_000_000u128),
+            block_number: 1_000,
+        }).unwrap();
+        // (1e8 * 2 + 0 * 1) / 3 truncates in the last of eight decimals only
+        assert_eq!(utilization_average_at(&storage, 1_001, 0, Some(3)).unwrap(), 66_666_666);
+        // Windows of many blocks at full utilization do not overflow
+        assert_eq!(utilization_average_at(&storage, 1_001, 100_000_000, Some(u64::MAX)).unwrap(), 100_000_000);
+    }
+
+    #[test]
+    fn exchange_rate_converts_both_ways_in_underlying_per_qtoken() {
+        // 0.02 underlying per qToken
+        let exchange_rate = 2_000_000;
+        assert_eq!(tokens_for_underlying(1_000, exchange_rate), 50_000);
+        assert_eq!(underlying_for_tokens(50_000, exchange_rate), 1_000);
+
+        // 1.5 underlying per qToken leaves remainders on the way back
+        let exchange_rate = 150_000_000;
+        assert_eq!(tokens_for_underlying(100, exchange_rate), 66);
+        assert_eq!(tokens_for_underlying_ceil(100, exchange_rate), 67);
+        assert_eq!(underlying_for_tokens(66, exchange_rate), 99);
+        assert_eq!(tokens_for_underlying_ceil(150, exchange_rate), 100);
+    }
+
+    #[test]
+    fn redeem_underlying_burns_rounded_up_tokens_and_pays_out_of_cash() {
+        // 1.5 underlying per qToken
+        let mut msg = mock_init_msg();
+        msg.initial_exchange_rate = Uint128::from(150_000_000u128);
+        let mut deps = mock_market(msg);
+        handle(&mut deps, mock_env("alice", &coins(150, MOCK_DENOM)), HandleMsg::Mint {}).unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(150, MOCK_DENOM));
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        assert_eq!(get_balance(&deps.storage, &alice).unwrap(), 100);
+
+        // The amount comes from the message, attached funds are refused
+        let redeem = HandleMsg::RedeemUnderlying { redeem_amount: Uint128::from(100u128) };
+        assert!(handle(&mut deps, mock_env("alice", &coins(100, MOCK_DENOM)), redeem.clone()).is_err());
+
+        let res = handle(&mut deps, mock_env("alice", &[]), redeem).unwrap();
+        assert_eq!(res.messages, vec![CosmosMsg::Bank(BankMsg::Send {
+            from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+            to_address: HumanAddr::from("alice"),
+            amount: coins(100, MOCK_DENOM),
+        })]);
+        // 100 underlying is 66.67 qTokens, 67 are burned
+        assert_eq!(get_balance(&deps.storage, &alice).unwrap(), 33);
+        assert_eq!(get_config(&deps.storage).unwrap().total_supply, Uint128::from(33u128));
+        assert_eq!(get_state(&deps.storage).unwrap().cash, Uint128::from(50u128));
+        // The qTokens left are worth no more than the cash left
+        assert_eq!(calculate_exchange_rate(&get_config(&deps.storage).unwrap(), &get_state(&deps.storage).unwrap()).unwrap(), 151_515_151);
+
+        // More than the account holds, and zero, are refused
+        let too_much = HandleMsg::RedeemUnderlying { redeem_amount: Uint128::from(51u128) };
+        assert!(handle(&mut deps, mock_env("alice", &[]), too_much).is_err());
+        let nothing = HandleMsg::Redeem { redeem_tokens_in: Uint128::zero() };
+        assert!(handle(&mut deps, mock_env("alice", &[]), nothing).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use cosmwasm_std::{
+    from_slice, log, to_vec, Api, Binary, Env, Extern, HandleResponse, LogAttribute, Querier, StdError, StdResult,
+    Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::state::{
+    bytes_to_u128, get_compaction, get_config, get_raw_entries, set_compaction, set_raw_entry, CompactionSection,
+    ALLOWANCE_PREFIX, BALANCE_PREFIX, DEFAULT_COMPACTION_BATCH, MAX_COMPACTION_BATCH,
+};
+
+/// Admin only, re-encodes the next batch of legacy balance and allowance entries
+pub fn try_compact_storage<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limit: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    let mut log_attrs = vec![log("action", "compact_storage"), log("sender", env.message.sender.as_str())];
+    log_attrs.extend(compact_storage(&mut deps.storage, limit)?);
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: log_attrs,
+        data: None,
+    })
+}
+
+/// Scans up to `limit` entries from where the last batch stopped and rewrites those
+/// still in the raw 16 byte big-endian layout as Bucket encoded amounts, so a large
+/// market can be compacted over as many calls as its gas limit needs
+pub fn compact_storage<S: Storage>(storage: &mut S, limit: Option<u32>) -> StdResult<Vec<LogAttribute>> {
+    let limit = limit.unwrap_or(DEFAULT_COMPACTION_BATCH).clamp(1, MAX_COMPACTION_BATCH) as usize;
+    let mut compaction = get_compaction(storage)?;
+    let mut scanned = 0;
+    let mut rewritten = 0;
+    while scanned < limit {
+        let prefix = match compaction.section {
+            CompactionSection::Balances => BALANCE_PREFIX,
+            CompactionSection::Allowances => ALLOWANCE_PREFIX,
+            CompactionSection::Done => break,
+        };
+        let batch = limit - scanned;
+        let entries = get_raw_entries(
+            storage,
+            prefix,
+            compaction.start_after.as_ref().map(|key| key.as_slice()),
+            batch,
+        );
+        for (key, value) in entries.iter() {
+            if from_slice::<Uint128>(value).is_ok() {
+                continue;
+            }
+            let amount = bytes_to_u128(value)?;
+            set_raw_entry(storage, prefix, key, &to_vec(&Uint128::from(amount))?);
+            rewritten += 1;
+        }
+        scanned += entries.len();
+
+        // A short batch exhausted the section
+        match entries.last() {
+            Some((key, _)) if entries.len() == batch => compaction.start_after = Some(Binary(key.clone())),
+            _ => {
+                compaction.section = match compaction.section {
+                    CompactionSection::Balances => CompactionSection::Allowances,
+                    _ => CompactionSection::Done,
+                };
+                compaction.start_after = None;
+            }
+        }
+    }
+    compaction.rewritten += rewritten;
+    set_compaction(storage, &compaction)?;
+
+    Ok(vec![
+        log("compaction_scanned", scanned),
+        log("compaction_rewritten", rewritten),
+        log("compaction_total_rewritten", compaction.rewritten),
+        log("compaction_done", compaction.section == CompactionSection::Done),
+    ])
+}
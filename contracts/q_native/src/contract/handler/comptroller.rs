@@ -0,0 +1,51 @@
+use cosmwasm_bignumber::Uint256;
+use cosmwasm_std::{to_binary, Api, CosmosMsg, Extern, HumanAddr, Querier, QueryRequest, StdResult, Storage, Uint128, WasmQuery};
+
+use quasar_interfaces::comptroller::{ComptrollerHookMsg, ComptrollerQueryMsg, LiquidationShortfallResponse, MarketAction};
+
+use crate::state::Config;
+
+/// Hook message for the comptroller to check the operation after it was applied,
+/// empty for a standalone market
+pub fn comptroller_hook<A: Api>(
+    api: &A,
+    config: &Config,
+    action: MarketAction,
+    account: &HumanAddr,
+    amount: u128,
+) -> StdResult<Vec<CosmosMsg>> {
+    match &config.comptroller {
+        Some(comptroller) => {
+            let hook = ComptrollerHookMsg::MarketHook {
+                action,
+                account: account.clone(),
+                amount: Uint128::from(amount),
+            };
+            Ok(vec![hook.into_cosmos_msg(api.human_address(comptroller)?)?])
+        }
+        None => Ok(vec![]),
+    }
+}
+
+/// Shortfall of the account across every market it entered at `block`, in the comptroller's
+/// quote, None for a standalone market. Collateral factor cuts still in their grace period
+/// do not count towards it
+pub fn comptroller_shortfall<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    config: &Config,
+    account: &HumanAddr,
+    block: u64,
+) -> StdResult<Option<Uint256>> {
+    let comptroller = match &config.comptroller {
+        Some(comptroller) => deps.api.human_address(comptroller)?,
+        None => return Ok(None),
+    };
+    let res: LiquidationShortfallResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: comptroller,
+        msg: to_binary(&ComptrollerQueryMsg::LiquidationShortfall {
+            address: account.clone(),
+            block,
+        })?,
+    }))?;
+    Ok(Some(res.shortfall))
+}
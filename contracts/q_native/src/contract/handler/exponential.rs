@@ -1,8 +1,39 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
+
 /// exponential math lib
 /// TODO: Generalize this for each cToken asset
-pub static scale: u128 = 100_000_000; // 10^8
+pub static SCALE: u128 = 100_000_000; // 10^8
+/// Decimals of values scaled by `SCALE`
+pub const SCALE_DECIMALS: u8 = 8;
 
 /// truncate a number according to given mantissa
 pub fn truncate(a: u128) -> u128 {
-    a / scale
-}
\ No newline at end of file
+    a / SCALE
+}
+
+/// a * b / c in 256 bits, truncated
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    Uint256::from(a).multiply_ratio(Uint256::from(b).0, Uint256::from(c).0).into()
+}
+
+/// A scaled value as a decimal, e.g. 5_000_000 is 0.05
+pub fn to_decimal(a: u128) -> Decimal256 {
+    Decimal256::from_ratio(Uint256::from(a).0, Uint256::from(SCALE).0)
+}
+
+/// Whole units and the fraction left over
+pub fn split_fraction(a: Decimal256) -> (u128, Decimal256) {
+    let whole = Uint256::one() * a;
+    (whole.into(), a - Decimal256::from_uint256(whole))
+}
+
+/// A value scaled by 10^decimals as a decimal string, e.g. 5_000_000 with 8 decimals is "0.05"
+pub fn humanize(a: u128, decimals: u32) -> String {
+    let unit = 10u128.pow(decimals);
+    let fraction = format!("{:0width$}", a % unit, width = decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+    match fraction.is_empty() {
+        true => (a / unit).to_string(),
+        false => format!("{}.{}", a / unit, fraction),
+    }
+}
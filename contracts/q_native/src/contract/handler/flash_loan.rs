@@ -0,0 +1,253 @@
+use cosmwasm_std::{
+    log, to_binary, Api, Binary, CosmosMsg, Env, Extern, HandleResponse, HumanAddr, Querier,
+    StdError, StdResult, Storage, Uint128, WasmMsg,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::msg::{FlashLoanReceiverMsg, HandleMsg};
+use crate::state::{
+    get_config, get_flash_loan, get_state, get_warm_cash, set_config, set_flash_loan, set_state, FlashLoan,
+    PauseAction, MAX_FLASH_LOAN_FEE, Role,
+};
+
+use crate::contract::handler::admin::assert_action_not_paused;
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::collateral::accrue_interest;
+use crate::contract::handler::exponential::{SCALE, truncate};
+use crate::contract::handler::funds::{query_underlying_balance, transfer_underlying};
+use crate::contract::handler::roles::assert_role;
+
+pub fn try_flash_loan<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+    msg: Option<Binary>,
+    recipient: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let fee_rate = match config.flash_loan_fee {
+        Some(fee_rate) => fee_rate.u128(),
+        None => return Err(StdError::generic_err("Flash loans are disabled in this market")),
+    };
+    assert_action_not_paused(&deps.storage, PauseAction::Borrow)?;
+    if amount.is_zero() {
+        return Err(StdError::generic_err("Flash loan amount must be positive"));
+    }
+
+    // Only hot cash is lent, warm cash would have to leave the strategy first
+    let state = get_state(&deps.storage)?;
+    let hot_cash = state.cash.u128().saturating_sub(get_warm_cash(&deps.storage)?.u128());
+    if amount.u128() > hot_cash {
+        return Err(StdError::generic_err(format!(
+            "The lending pool has insufficient hot cash: amount: {}, hot_cash: {}",
+            amount, hot_cash
+        )));
+    }
+
+    // Rounded up so a non-zero fee rate never lends for free
+    let fee = (amount.u128() * fee_rate).div_ceil(SCALE);
+    let balance_before = query_underlying_balance(&deps.api, &deps.querier, &config.underlying, &env.contract.address)?;
+    set_flash_loan(&mut deps.storage, Some(&FlashLoan {
+        recipient: canonicalize_address(&deps.api, recipient)?,
+        amount,
+        fee: Uint128::from(fee),
+        balance_before,
+    }))?;
+
+    // Cash is left untouched, the loan never shows up in rates or the exchange rate
+    let callback = FlashLoanReceiverMsg::FlashLoanCallback {
+        initiator: env.message.sender.clone(),
+        amount,
+        fee: Uint128::from(fee),
+        msg,
+    };
+    let finish = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.clone(),
+        msg: to_binary(&HandleMsg::FinishFlashLoan {})?,
+        send: vec![],
+    });
+
+    let res = HandleResponse {
+        messages: vec![
+            transfer_underlying(&deps.api, &config.underlying, &env.contract.address, recipient, amount)?,
+            callback.into_cosmos_msg(recipient.clone())?,
+            finish,
+        ],
+        log: vec![
+            log("action", "flash_loan"),
+            log("sender", env.message.sender.as_str()),
+            log("recipient", recipient.as_str()),
+            log("amount", amount),
+            log("fee", fee),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_finish_flash_loan<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    if env.message.sender != env.contract.address {
+        return Err(StdError::unauthorized());
+    }
+    let flash_loan = match get_flash_loan(&deps.storage)? {
+        Some(flash_loan) => flash_loan,
+        None => return Err(StdError::generic_err("No flash loan is outstanding")),
+    };
+
+    let config = get_config(&deps.storage)?;
+    let balance = query_underlying_balance(&deps.api, &deps.querier, &config.underlying, &env.contract.address)?;
+    let required = flash_loan.balance_before + flash_loan.fee;
+    if balance < required {
+        return Err(StdError::generic_err(format!(
+            "Flash loan was not repaid: balance: {}, required: {}",
+            balance, required
+        )));
+    }
+
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    // The reserve factor share of the fee goes to reserves, the rest to suppliers
+    let mut new_state = get_state(&deps.storage)?;
+    let reserve_fee = truncate(flash_loan.fee.u128() * new_state.reserve_factor.u128());
+    new_state.cash += flash_loan.fee;
+    new_state.total_reserves += Uint128::from(reserve_fee);
+    set_state(&mut deps.storage, &new_state)?;
+    set_flash_loan(&mut deps.storage, None)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "finish_flash_loan"),
+            log("recipient", deps.api.human_address(&flash_loan.recipient)?.as_str()),
+            log("amount", flash_loan.amount),
+            log("fee", flash_loan.fee),
+            log("reserve_fee", reserve_fee),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+pub fn try_set_flash_loan_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    flash_loan_fee: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let mut config = get_config(&deps.storage)?;
+    if let Some(fee) = flash_loan_fee {
+        if fee.u128() > MAX_FLASH_LOAN_FEE {
+            return Err(StdError::generic_err(format!(
+                "Flash loan fee exceeds maximum: flash_loan_fee: {}, max_flash_loan_fee: {}",
+                fee, MAX_FLASH_LOAN_FEE
+            )));
+        }
+    }
+    let old_fee = config.flash_loan_fee;
+    config.flash_loan_fee = flash_loan_fee;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "flash_loan_fee", &old_fee, &flash_loan_fee)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_flash_loan_fee"),
+            log("sender", env.message.sender.as_str()),
+            log("flash_loan_fee", flash_loan_fee.map(|fee| fee.to_string()).unwrap_or_default()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Fails while a flash loan is outstanding, so the loaned cash cannot be used to
+/// mint, repay or move funds in and out of the market before it is returned
+pub fn assert_no_flash_loan<S: Storage>(storage: &S) -> StdResult<()> {
+    if get_flash_loan(storage)?.is_some() {
+        return Err(StdError::generic_err("Not allowed while a flash loan is outstanding"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, BankMsg, Coin};
+
+    use crate::contract::handle;
+    use crate::contract::handler::collateral::calculate_exchange_rate;
+    use crate::contract::testing::{mock_init_msg, mock_market, MOCK_DENOM};
+
+    fn env_at(sender: &str, height: u64, funds: &[Coin]) -> Env {
+        let mut env = mock_env(sender, funds);
+        env.block.height = height;
+        env
+    }
+
+    fn flash_loan(amount: u128) -> HandleMsg {
+        HandleMsg::FlashLoan { amount: Uint128::from(amount), msg: None, recipient: HumanAddr::from("receiver") }
+    }
+
+    /// 10,000 of cash at a 0.3% flash loan fee and the default 10% reserve factor
+    fn lending_market() -> Extern<MockStorage, MockApi, MockQuerier> {
+        let mut msg = mock_init_msg();
+        msg.flash_loan_fee = Some(Uint128::from(300_000u128));
+        let mut deps = mock_market(msg);
+        handle(&mut deps, env_at("alice", 20_000, &coins(10_000, MOCK_DENOM)), HandleMsg::Mint {}).unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(10_000, MOCK_DENOM));
+        deps
+    }
+
+    #[test]
+    fn repaid_fee_is_split_between_reserves_and_suppliers() {
+        let mut deps = lending_market();
+        let res = handle(&mut deps, env_at("bot", 20_000, &[]), flash_loan(10_000)).unwrap();
+        assert!(res.log.contains(&log("fee", 30)));
+        assert_eq!(res.messages[0], CosmosMsg::Bank(BankMsg::Send {
+            from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+            to_address: HumanAddr::from("receiver"),
+            amount: coins(10_000, MOCK_DENOM),
+        }));
+        assert_eq!(
+            handle(&mut deps, env_at("bob", 20_000, &coins(1, MOCK_DENOM)), HandleMsg::Mint {}).unwrap_err(),
+            StdError::generic_err("Not allowed while a flash loan is outstanding")
+        );
+        assert_eq!(
+            handle(&mut deps, env_at("bot", 20_000, &[]), HandleMsg::FinishFlashLoan {}).unwrap_err(),
+            StdError::unauthorized()
+        );
+
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(10_029, MOCK_DENOM));
+        assert_eq!(
+            handle(&mut deps, env_at(MOCK_CONTRACT_ADDR, 20_000, &[]), HandleMsg::FinishFlashLoan {}).unwrap_err(),
+            StdError::generic_err("Flash loan was not repaid: balance: 10029, required: 10030")
+        );
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(10_030, MOCK_DENOM));
+        let res = handle(&mut deps, env_at(MOCK_CONTRACT_ADDR, 20_000, &[]), HandleMsg::FinishFlashLoan {}).unwrap();
+        assert!(res.log.contains(&log("reserve_fee", 3)));
+        let config = get_config(&deps.storage).unwrap();
+        let state = get_state(&deps.storage).unwrap();
+        assert_eq!((state.cash, state.total_reserves), (Uint128::from(10_030u128), Uint128::from(3u128)));
+        // Suppliers keep the remaining 27
+        assert_eq!(calculate_exchange_rate(&config, &state).unwrap(), 100_270_000);
+        assert_eq!(get_flash_loan(&deps.storage).unwrap(), None);
+    }
+
+    #[test]
+    fn loans_are_bounded_by_hot_cash_and_never_free() {
+        let mut deps = lending_market();
+        assert_eq!(
+            handle(&mut deps, env_at("bot", 20_000, &[]), flash_loan(10_001)).unwrap_err(),
+            StdError::generic_err("The lending pool has insufficient hot cash: amount: 10001, hot_cash: 10000")
+        );
+        let res = handle(&mut deps, env_at("bot", 20_000, &[]), flash_loan(1)).unwrap();
+        assert!(res.log.contains(&log("fee", 1)));
+    }
+}
@@ -0,0 +1,87 @@
+use cosmwasm_std::{
+    Api, BankMsg, Coin, CosmosMsg, Env, HumanAddr, Querier, StdError, StdResult, Uint128,
+};
+use cw20::{Cw20Contract, Cw20HandleMsg};
+
+use quasar_interfaces::asset::AssetInfoRaw;
+
+/// Amount of the market denom sent with the message
+/// Fails if nothing, a zero amount or any other denom is attached, and for CW20
+/// underlyings, which arrive through Receive instead
+pub fn must_pay(env: &Env, underlying: &AssetInfoRaw) -> StdResult<Uint128> {
+    let denom = match underlying {
+        AssetInfoRaw::NativeToken { denom } => denom.as_str(),
+        AssetInfoRaw::Token { .. } => {
+            return Err(StdError::generic_err(
+                "Underlying is a CW20 token: send it to the market with a Cw20HookMsg",
+            ));
+        }
+    };
+    if env.message.sent_funds.is_empty() {
+        return Err(StdError::generic_err(format!("No funds sent: expected: {}", denom)));
+    }
+    if let Some(coin) = env.message.sent_funds.iter().find(|coin| coin.denom != denom) {
+        return Err(StdError::generic_err(format!(
+            "Unsupported denom sent: denom: {}, expected: {}",
+            coin.denom, denom
+        )));
+    }
+
+    let amount: u128 = env.message.sent_funds.iter().map(|coin| coin.amount.u128()).sum();
+    if amount == 0 {
+        return Err(StdError::generic_err(format!("Sent amount must be positive: denom: {}", denom)));
+    }
+    Ok(Uint128::from(amount))
+}
+
+/// Fails if any funds are attached to a message that does not take them
+pub fn nonpayable(env: &Env) -> StdResult<()> {
+    match env.message.sent_funds.first() {
+        Some(coin) => Err(StdError::generic_err(format!(
+            "Message does not accept funds: denom: {}, amount: {}",
+            coin.denom, coin.amount
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Pays `amount` of the underlying from the market to `recipient`
+pub fn transfer_underlying<A: Api>(
+    api: &A,
+    underlying: &AssetInfoRaw,
+    market: &HumanAddr,
+    recipient: &HumanAddr,
+    amount: Uint128,
+) -> StdResult<CosmosMsg> {
+    match underlying {
+        AssetInfoRaw::NativeToken { denom } => Ok(CosmosMsg::Bank(BankMsg::Send {
+            from_address: market.clone(),
+            to_address: recipient.clone(),
+            amount: vec![Coin {
+                denom: denom.clone(),
+                amount,
+            }],
+        })),
+        AssetInfoRaw::Token { contract_addr } => Cw20Contract(api.human_address(contract_addr)?).call(
+            Cw20HandleMsg::Transfer {
+                recipient: recipient.clone(),
+                amount,
+            },
+        ),
+    }
+}
+
+/// Underlying held by `address`, bank balance or CW20 balance
+pub fn query_underlying_balance<A: Api, Q: Querier>(
+    api: &A,
+    querier: &Q,
+    underlying: &AssetInfoRaw,
+    address: &HumanAddr,
+) -> StdResult<Uint128> {
+    match underlying {
+        AssetInfoRaw::NativeToken { denom } => Ok(querier.query_balance(address.clone(), denom)?.amount),
+        AssetInfoRaw::Token { contract_addr } => {
+            Cw20Contract(api.human_address(contract_addr)?).balance(querier, address.clone())
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use cosmwasm_std::{
+    log, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, Querier, StdError, StdResult,
+    Storage,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::contract::digest::genesis_digest;
+use crate::contract::handler::audit::{audit, ROLE_ADMIN};
+use crate::contract::handler::collateral::current_borrow_balance;
+use crate::msg::GenesisChunk;
+use crate::state::{
+    get_config, get_genesis_import, get_state, set_allowance, set_allowance_expiration, set_balance,
+    set_borrow_balance, set_config, set_genesis_import, set_state, update_leaderboard, Config,
+    GenesisImport, TOP_BORROWERS_KEY, TOP_SUPPLIERS_KEY,
+};
+
+/// Only a market nobody has supplied to or borrowed from can take an import
+pub fn try_begin_genesis_import<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    assert_admin(deps, &env, &config)?;
+    let state = get_state(&deps.storage)?;
+    if !config.total_supply.is_zero() || !state.total_borrows.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Genesis can only be imported into an empty market: total_supply: {}, total_borrows: {}",
+            config.total_supply, state.total_borrows
+        )));
+    }
+    if get_genesis_import(&deps.storage)?.is_some() {
+        return Err(StdError::generic_err("A genesis import is already in progress"));
+    }
+    set_genesis_import(&mut deps.storage, Some(&GenesisImport {
+        digest: Binary::default(),
+        chunks: 0,
+    }))?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "genesis_import", &false, &true)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "begin_genesis_import"),
+            log("sender", env.message.sender.as_str()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+pub fn try_import_genesis<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    chunk: GenesisChunk,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    assert_admin(deps, &env, &config)?;
+    let mut genesis_import = match get_genesis_import(&deps.storage)? {
+        Some(genesis_import) => genesis_import,
+        None => return Err(StdError::generic_err("No genesis import is in progress")),
+    };
+    genesis_import.digest = genesis_digest(&genesis_import.digest, &chunk)?;
+    genesis_import.chunks += 1;
+    set_genesis_import(&mut deps.storage, Some(&genesis_import))?;
+
+    let (section, entries) = match chunk {
+        GenesisChunk::Market { config: imported, state } => {
+            // The market keeps its own address and admin, everything else is taken over
+            set_config(&mut deps.storage, &Config {
+                admin: config.admin,
+                contract_addr: config.contract_addr,
+                ..*imported
+            })?;
+            set_state(&mut deps.storage, &state)?;
+            ("market", 1)
+        }
+        GenesisChunk::Balances { balances } => {
+            for entry in balances.iter() {
+                let address = CanonicalAddr::from(entry.address.as_slice());
+                set_balance(&mut deps.storage, &address, entry.balance.u128())?;
+                update_leaderboard(&mut deps.storage, TOP_SUPPLIERS_KEY, &address, entry.balance.u128())?;
+            }
+            ("balances", balances.len())
+        }
+        GenesisChunk::Borrows { borrows } => {
+            let config = get_config(&deps.storage)?;
+            let borrow_index = get_state(&deps.storage)?.borrow_index;
+            for entry in borrows.iter() {
+                let address = CanonicalAddr::from(entry.address.as_slice());
+                let debt = current_borrow_balance(&entry.snapshot, &borrow_index, config.dust_borrow_threshold);
+                set_borrow_balance(&mut deps.storage, &address, Some(entry.snapshot.clone()))?;
+                update_leaderboard(&mut deps.storage, TOP_BORROWERS_KEY, &address, debt)?;
+            }
+            ("borrows", borrows.len())
+        }
+        GenesisChunk::Allowances { allowances } => {
+            for entry in allowances.iter() {
+                let owner = CanonicalAddr::from(entry.owner.as_slice());
+                let spender = CanonicalAddr::from(entry.spender.as_slice());
+                set_allowance(&mut deps.storage, &owner, &spender, entry.amount.u128())?;
+                set_allowance_expiration(&mut deps.storage, &owner, &spender, &entry.expires)?;
+            }
+            ("allowances", allowances.len())
+        }
+    };
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "import_genesis"),
+            log("section", section),
+            log("entries", entries),
+            log("chunks", genesis_import.chunks),
+            log("digest", genesis_import.digest.to_base64()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// `digest` is the digest ExportGenesis returned with the last chunk
+pub fn try_finish_genesis_import<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    digest: Binary,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    assert_admin(deps, &env, &config)?;
+    let genesis_import = match get_genesis_import(&deps.storage)? {
+        Some(genesis_import) => genesis_import,
+        None => return Err(StdError::generic_err("No genesis import is in progress")),
+    };
+    if genesis_import.digest != digest {
+        return Err(StdError::generic_err(format!(
+            "Imported chunks do not match the export: digest: {}, expected: {}",
+            genesis_import.digest.to_base64(),
+            digest.to_base64()
+        )));
+    }
+    set_genesis_import(&mut deps.storage, None)?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "genesis_import", &true, &false)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "finish_genesis_import"),
+            log("sender", env.message.sender.as_str()),
+            log("chunks", genesis_import.chunks),
+            log("digest", digest.to_base64()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Fails while a genesis import is in progress
+pub fn assert_no_genesis_import<S: Storage>(storage: &S) -> StdResult<()> {
+    if get_genesis_import(storage)?.is_some() {
+        return Err(StdError::generic_err("Market is closed while a genesis import is in progress"));
+    }
+    Ok(())
+}
+
+fn assert_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    config: &Config,
+) -> StdResult<()> {
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    Ok(())
+}
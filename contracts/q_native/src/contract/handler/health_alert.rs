@@ -0,0 +1,110 @@
+use cosmwasm_std::{
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, LogAttribute, Querier, StdError,
+    StdResult, Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::state::{
+    get_balance, get_borrow_balance, get_config, get_health_alerts, get_state, set_health_alerts,
+    HealthAlerts, MAX_HEALTH_ALERTS,
+};
+
+use crate::contract::handler::collateral::{borrow_limit, calculate_exchange_rate, current_borrow_balance};
+use crate::contract::handler::exponential::{mul_div, SCALE};
+
+/// Empty thresholds stop alerts for the sender
+pub fn try_set_health_alerts<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    thresholds: Vec<Uint128>,
+) -> StdResult<HandleResponse> {
+    if thresholds.len() > MAX_HEALTH_ALERTS {
+        return Err(StdError::generic_err(format!(
+            "Too many health alert thresholds: thresholds: {}, max_health_alerts: {}",
+            thresholds.len(), MAX_HEALTH_ALERTS
+        )));
+    }
+    if thresholds.iter().any(|threshold| threshold.is_zero()) {
+        return Err(StdError::generic_err("Health alert thresholds must be positive"));
+    }
+    let mut thresholds = thresholds;
+    thresholds.sort();
+    thresholds.dedup();
+
+    // Crossings are measured from the health factor at the time of setting
+    let account_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let health_factor = health_factor(&deps.storage, &account_raw)?;
+    let health_alerts = HealthAlerts {
+        thresholds,
+        last_health_factor: health_factor.map(Uint128::from),
+    };
+    match health_alerts.thresholds.is_empty() {
+        true => set_health_alerts(&mut deps.storage, &account_raw, None)?,
+        false => set_health_alerts(&mut deps.storage, &account_raw, Some(&health_alerts))?,
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_health_alerts"),
+            log("sender", env.message.sender.as_str()),
+            log("thresholds", health_alerts.thresholds.len()),
+            log("health_factor", health_factor.map(|factor| factor.to_string()).unwrap_or_default()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Borrow limit over debt in this market, scaled by 10^8, None without debt
+pub fn health_factor<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<Option<u128>> {
+    let config = get_config(storage)?;
+    let state = get_state(storage)?;
+    let debt = match get_borrow_balance(storage, account) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    if debt == 0 {
+        return Ok(None);
+    }
+    let exchange_rate = calculate_exchange_rate(&config, &state)?;
+    let limit = borrow_limit(&config, get_balance(storage, account)?, exchange_rate);
+    Ok(Some(mul_div(limit, SCALE, debt)))
+}
+
+/// health_alert events for every threshold the accounts' health factor crossed
+/// since they were last touched, with the balances as stored now
+pub fn health_alert_log<S: Storage, A: Api>(
+    storage: &mut S,
+    api: &A,
+    accounts: &[&CanonicalAddr],
+) -> StdResult<Vec<LogAttribute>> {
+    let mut alert_log = vec![];
+    for account in accounts {
+        let mut health_alerts = match get_health_alerts(storage, account)? {
+            Some(health_alerts) => health_alerts,
+            None => continue,
+        };
+        // No debt counts as an unbounded health factor
+        let previous = health_alerts.last_health_factor.map(|factor| factor.u128()).unwrap_or(u128::MAX);
+        let current_factor = health_factor(storage, account)?;
+        let current = current_factor.unwrap_or(u128::MAX);
+        for threshold in health_alerts.thresholds.iter().map(|threshold| threshold.u128()) {
+            let direction = if previous >= threshold && current < threshold {
+                "below"
+            } else if previous < threshold && current >= threshold {
+                "above"
+            } else {
+                continue;
+            };
+            alert_log.push(log("health_alert", api.human_address(account)?.as_str()));
+            alert_log.push(log("health_alert_threshold", threshold));
+            alert_log.push(log("health_alert_direction", direction));
+            alert_log.push(log("health_factor", current_factor.map(|factor| factor.to_string()).unwrap_or_default()));
+        }
+        health_alerts.last_health_factor = current_factor.map(Uint128::from);
+        set_health_alerts(storage, account, Some(&health_alerts))?;
+    }
+    Ok(alert_log)
+}
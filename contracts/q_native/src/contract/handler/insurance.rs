@@ -0,0 +1,417 @@
+use cosmwasm_bignumber::Decimal256;
+use cosmwasm_std::{
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult,
+    Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::oracle::query_price;
+
+use crate::state::{
+    get_borrow_balance, get_config, get_insurance_checkpoint, get_insurance_pool, get_insured_position, get_settlement,
+    get_state, set_borrow_balance, set_config, set_insurance_checkpoint, set_insurance_pool,
+    set_insured_position, set_state, BorrowSnapshot, Config, InsuranceConfig, InsuredPosition,
+    PriceCheckpoint, MAX_INSURANCE_PREMIUM_RATE, MAX_INSURANCE_TARGET_COVERAGE, Role,
+};
+
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::collateral::{accrue_interest, current_borrow_balance};
+use crate::contract::handler::exponential::{mul_div, SCALE, to_decimal, truncate};
+use crate::contract::handler::roles::assert_role;
+
+/// Insurance needs the oracle to tell volatile liquidations apart
+pub fn validate_insurance(config: &Config) -> StdResult<()> {
+    let insurance = match &config.insurance {
+        Some(insurance) => insurance,
+        None => return Ok(()),
+    };
+    if config.oracle.is_none() {
+        return Err(StdError::generic_err("Insurance requires a price oracle"));
+    }
+    if insurance.premium_rate.u128() > MAX_INSURANCE_PREMIUM_RATE {
+        return Err(StdError::generic_err(format!(
+            "Insurance premium rate exceeds maximum: premium_rate: {}, max_premium_rate: {}",
+            insurance.premium_rate, MAX_INSURANCE_PREMIUM_RATE
+        )));
+    }
+    if insurance.refund_share.u128() > SCALE {
+        return Err(StdError::generic_err(format!(
+            "Refund share must not exceed 1: refund_share: {}",
+            insurance.refund_share
+        )));
+    }
+    if insurance.volatility_threshold.is_zero() || insurance.window == 0 {
+        return Err(StdError::generic_err("Volatility threshold and window must be positive"));
+    }
+    if let Some(top_up) = &insurance.top_up {
+        if top_up.target_coverage.is_zero() || top_up.target_coverage.u128() > MAX_INSURANCE_TARGET_COVERAGE {
+            return Err(StdError::generic_err(format!(
+                "Target coverage out of bounds: target_coverage: {}, max_target_coverage: {}",
+                top_up.target_coverage, MAX_INSURANCE_TARGET_COVERAGE
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub fn try_set_insurance_config<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    insurance: Option<InsuranceConfig>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let mut config = get_config(&deps.storage)?;
+    let old_insurance = config.insurance.clone();
+    config.insurance = insurance;
+    validate_insurance(&config)?;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "insurance", &old_insurance, &config.insurance)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_insurance_config"),
+            log("sender", env.message.sender.as_str()),
+            log("enabled", config.insurance.is_some()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Opting out settles the premium owed so far, opting in starts at the current index
+pub fn try_set_insured<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    insured: bool,
+) -> StdResult<HandleResponse> {
+    if insured && get_config(&deps.storage)?.insurance.is_none() {
+        return Err(StdError::generic_err("Insurance is not enabled in this market"));
+    }
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    let account_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let premium = settle_premium(&mut deps.storage, &account_raw)?;
+    let borrow_index = get_state(&deps.storage)?.borrow_index;
+    match insured {
+        true => set_insured_position(&mut deps.storage, &account_raw, Some(&InsuredPosition { premium_index: borrow_index }))?,
+        false => set_insured_position(&mut deps.storage, &account_raw, None)?,
+    }
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_insured"),
+            log("sender", env.message.sender.as_str()),
+            log("insured", insured),
+            log("premium", premium),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+pub fn try_settle_premium<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let accrual_log = accrue_interest(deps, env.clone())?;
+    let account_raw = canonicalize_address(&deps.api, account)?;
+    if get_insured_position(&deps.storage, &account_raw)?.is_none() {
+        return Err(StdError::generic_err(format!("Account is not insured: account: {}", account)));
+    }
+    let premium = settle_premium(&mut deps.storage, &account_raw)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "settle_premium"),
+            log("sender", env.message.sender.as_str()),
+            log("account", account.as_str()),
+            log("premium", premium),
+            log("insurance_pool", get_insurance_pool(&deps.storage)?),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Refreshes the price checkpoint once it is a window old, so a liquidator
+/// cannot reset the reference right before liquidating
+pub fn try_checkpoint_price<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let insurance = match &config.insurance {
+        Some(insurance) => insurance,
+        None => return Err(StdError::generic_err("Insurance is not enabled in this market")),
+    };
+    if let Some(checkpoint) = get_insurance_checkpoint(&deps.storage)? {
+        let next_checkpoint = checkpoint.block_number + insurance.window;
+        if env.block.height < next_checkpoint {
+            return Err(StdError::generic_err(format!(
+                "Price checkpoint is still fresh: current_block: {}, next_checkpoint: {}",
+                env.block.height, next_checkpoint
+            )));
+        }
+    }
+    let price = oracle_price(deps, &config)?;
+    set_insurance_checkpoint(&mut deps.storage, &PriceCheckpoint {
+        price,
+        block_number: env.block.height,
+    })?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "checkpoint_price"),
+            log("sender", env.message.sender.as_str()),
+            log("price", price),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Premium an insured account owes at the stored borrow index, `premium_rate`
+/// of the interest its debt accrued since the last settlement
+pub fn pending_premium<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<u128> {
+    let position = match get_insured_position(storage, account)? {
+        Some(position) => position,
+        None => return Ok(0),
+    };
+    let config = get_config(storage)?;
+    let premium_rate = match &config.insurance {
+        Some(insurance) => insurance.premium_rate.u128(),
+        None => return Ok(0),
+    };
+    let borrow_index = get_state(storage)?.borrow_index;
+    let debt = match get_borrow_balance(storage, account) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    if debt == 0 {
+        return Ok(0);
+    }
+    let interest = debt - mul_div(debt, position.premium_index.u128(), borrow_index.u128());
+    Ok(truncate(interest * premium_rate))
+}
+
+/// Moves the pending premium into the insurance pool by adding it to the debt
+/// and to reserves alike, leaving the exchange rate unchanged
+/// Must run after accrual and before a handler reads the borrow snapshot
+pub fn settle_premium<S: Storage>(storage: &mut S, account: &CanonicalAddr) -> StdResult<u128> {
+    if get_settlement(storage)?.is_some() {
+        return Ok(0);
+    }
+    let mut position = match get_insured_position(storage, account)? {
+        Some(position) => position,
+        None => return Ok(0),
+    };
+    let premium = pending_premium(storage, account)?;
+    let config = get_config(storage)?;
+    let mut state = get_state(storage)?;
+    position.premium_index = state.borrow_index;
+    set_insured_position(storage, account, Some(&position))?;
+    if premium == 0 {
+        return Ok(0);
+    }
+
+    let debt = match get_borrow_balance(storage, account) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    set_borrow_balance(storage, account, Some(BorrowSnapshot {
+        principal: Uint128::from(debt + premium),
+        interest_index: state.borrow_index,
+    }))?;
+    state.total_borrows += Uint128::from(premium);
+    state.total_reserves += Uint128::from(premium);
+    set_state(storage, &state)?;
+    let pool = get_insurance_pool(storage)?;
+    set_insurance_pool(storage, pool + Uint128::from(premium))?;
+    Ok(premium)
+}
+
+/// Moves free reserves into the insurance pool after an accrual of `block_delta`
+/// blocks, None without a top-up policy
+/// Reserves stay in total_reserves, only the part ReduceReserves may withdraw shrinks
+pub fn insurance_top_up<S: Storage>(storage: &mut S, block_delta: u128) -> StdResult<Option<u128>> {
+    let top_up = match get_config(storage)?.insurance.and_then(|insurance| insurance.top_up) {
+        Some(top_up) => top_up,
+        None => return Ok(None),
+    };
+    let state = get_state(storage)?;
+    let pool = get_insurance_pool(storage)?.u128();
+    let target = truncate(state.total_borrows.u128() * top_up.target_coverage.u128());
+    let amount = target
+        .saturating_sub(pool)
+        .min(state.total_reserves.u128().saturating_sub(pool))
+        .min(top_up.max_top_up_per_block.u128() * block_delta);
+    if amount > 0 {
+        set_insurance_pool(storage, Uint128::from(pool + amount))?;
+    }
+    Ok(Some(amount))
+}
+
+/// Books the refund owed to an insured borrower for a liquidation penalty,
+/// zero unless the price moved by the volatility threshold within the window
+/// The caller pays the refund out of cash
+pub fn insurance_refund<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    borrower: &CanonicalAddr,
+    penalty: u128,
+) -> StdResult<u128> {
+    let config = get_config(&deps.storage)?;
+    let insurance = match &config.insurance {
+        Some(insurance) => insurance,
+        None => return Ok(0),
+    };
+    if get_insured_position(&deps.storage, borrower)?.is_none() {
+        return Ok(0);
+    }
+    let checkpoint = match get_insurance_checkpoint(&deps.storage)? {
+        Some(checkpoint) if env.block.height <= checkpoint.block_number + insurance.window => checkpoint,
+        _ => return Ok(0),
+    };
+    // A failing oracle forfeits the refund rather than blocking the liquidation
+    let price = match oracle_price(deps, &config) {
+        Ok(price) => price,
+        Err(_) => return Ok(0),
+    };
+    let movement = match price > checkpoint.price {
+        true => price - checkpoint.price,
+        false => checkpoint.price - price,
+    };
+    if movement < checkpoint.price * to_decimal(insurance.volatility_threshold.u128()) {
+        return Ok(0);
+    }
+
+    let pool = get_insurance_pool(&deps.storage)?;
+    let mut state = get_state(&deps.storage)?;
+    let refund = truncate(penalty * insurance.refund_share.u128())
+        .min(pool.u128())
+        .min(state.cash.u128());
+    if refund == 0 {
+        return Ok(0);
+    }
+    set_insurance_pool(&mut deps.storage, (pool - Uint128::from(refund))?)?;
+    state.total_reserves = (state.total_reserves - Uint128::from(refund))?;
+    state.cash = (state.cash - Uint128::from(refund))?;
+    set_state(&mut deps.storage, &state)?;
+    Ok(refund)
+}
+
+fn oracle_price<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, config: &Config) -> StdResult<Decimal256> {
+    let oracle = match &config.oracle {
+        Some(oracle) => oracle,
+        None => return Err(StdError::generic_err("No price oracle is configured for this market")),
+    };
+    let price = query_price(
+        &deps.querier,
+        deps.api.human_address(&oracle.contract)?,
+        &oracle.kind,
+        oracle.base.clone(),
+        oracle.quote.clone(),
+    )?;
+    Ok(price.rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
+    use cosmwasm_std::to_binary;
+
+    use quasar_interfaces::oracle::{OracleKind, TeFiPriceResponse};
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market, with_wasm_querier, WasmMockQuerier};
+    use crate::msg::{HandleMsg, OracleMsg};
+    use crate::state::State;
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    fn set_oracle_price(deps: &mut Extern<MockStorage, MockApi, WasmMockQuerier>, price: Decimal256) {
+        deps.querier.with_contract("oracle", move |_| {
+            to_binary(&TeFiPriceResponse { rate: price, last_updated_base: 0, last_updated_quote: 0 })
+        });
+    }
+
+    /// Alice is insured for half her penalty on a 10% move within 100 blocks of the checkpoint
+    /// at a price of 1, taken at 20,000, with 100 in the pool
+    fn insured_market() -> Extern<MockStorage, MockApi, WasmMockQuerier> {
+        let mut msg = mock_init_msg();
+        msg.oracle = Some(OracleMsg {
+            contract: HumanAddr::from("oracle"),
+            kind: OracleKind::TeFi,
+            base: "LUNA".to_string(),
+            quote: "USD".to_string(),
+        });
+        msg.insurance = Some(InsuranceConfig {
+            premium_rate: Uint128::from(10_000_000u128),
+            refund_share: Uint128::from(50_000_000u128),
+            volatility_threshold: Uint128::from(10_000_000u128),
+            window: 100,
+            top_up: None,
+        });
+        let mut deps = with_wasm_querier(mock_market(msg));
+        let state = get_state(&deps.storage).unwrap();
+        set_state(&mut deps.storage, &State {
+            cash: Uint128::from(1_000u128),
+            total_reserves: Uint128::from(200u128),
+            block_number: 20_000,
+            ..state
+        })
+        .unwrap();
+        set_insurance_pool(&mut deps.storage, Uint128::from(100u128)).unwrap();
+        set_oracle_price(&mut deps, Decimal256::one());
+        handle(&mut deps, env_at("alice", 20_000), HandleMsg::SetInsured { insured: true }).unwrap();
+        handle(&mut deps, env_at("keeper", 20_000), HandleMsg::CheckpointPrice {}).unwrap();
+        deps
+    }
+
+    fn refund(deps: &mut Extern<MockStorage, MockApi, WasmMockQuerier>, borrower: &str, height: u64) -> u128 {
+        let borrower = deps.api.canonical_address(&HumanAddr::from(borrower)).unwrap();
+        insurance_refund(deps, &env_at("liquidator", height), &borrower, 80).unwrap()
+    }
+
+    #[test]
+    fn refund_needs_a_move_beyond_the_threshold_within_the_window() {
+        let mut deps = insured_market();
+        set_oracle_price(&mut deps, Decimal256::percent(95));
+        assert_eq!(refund(&mut deps, "alice", 20_050), 0);
+        assert_eq!(
+            handle(&mut deps, env_at("keeper", 20_050), HandleMsg::CheckpointPrice {}).unwrap_err(),
+            StdError::generic_err("Price checkpoint is still fresh: current_block: 20050, next_checkpoint: 20100")
+        );
+
+        set_oracle_price(&mut deps, Decimal256::percent(90));
+        assert_eq!(refund(&mut deps, "bob", 20_050), 0);
+        assert_eq!(refund(&mut deps, "alice", 20_101), 0);
+        // Half of the 80 penalty, paid from the pool out of reserves and cash
+        assert_eq!(refund(&mut deps, "alice", 20_100), 40);
+        assert_eq!(get_insurance_pool(&deps.storage).unwrap(), Uint128::from(60u128));
+        let state = get_state(&deps.storage).unwrap();
+        assert_eq!((state.total_reserves, state.cash), (Uint128::from(160u128), Uint128::from(960u128)));
+    }
+
+    #[test]
+    fn refund_never_exceeds_the_pool() {
+        let mut deps = insured_market();
+        set_insurance_pool(&mut deps.storage, Uint128::from(25u128)).unwrap();
+        set_oracle_price(&mut deps, Decimal256::percent(120));
+        assert_eq!(refund(&mut deps, "alice", 20_010), 25);
+        assert_eq!(refund(&mut deps, "alice", 20_020), 0);
+    }
+}
@@ -0,0 +1,90 @@
+use cosmwasm_std::{log, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage, Uint128};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::msg::IntegratorFee;
+use crate::state::{get_integrator, set_integrator, Integrator, BPS_DENOMINATOR, MAX_INTEGRATOR_FEE_BPS, Role};
+
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::roles::assert_role;
+
+/// Admin or param_admin only, None deregisters `integrator` and forgets the fees it was paid
+pub fn try_set_integrator<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    integrator: HumanAddr,
+    max_fee_bps: Option<u16>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    if let Some(max_fee_bps) = max_fee_bps {
+        if max_fee_bps > MAX_INTEGRATOR_FEE_BPS {
+            return Err(StdError::generic_err(format!(
+                "Integrator fee cap exceeds maximum: max_fee_bps: {}, maximum: {}",
+                max_fee_bps, MAX_INTEGRATOR_FEE_BPS
+            )));
+        }
+    }
+    let integrator_raw = canonicalize_address(&deps.api, &integrator)?;
+    let old_integrator = get_integrator(&deps.storage, &integrator_raw)?;
+    let new_integrator = max_fee_bps.map(|max_fee_bps| Integrator {
+        max_fee_bps,
+        total_fees: old_integrator.as_ref().map(|old| old.total_fees).unwrap_or_default(),
+    });
+    set_integrator(&mut deps.storage, &integrator_raw, new_integrator.as_ref())?;
+    let parameter = format!("integrator.{}", integrator);
+    let old_max_fee_bps = old_integrator.map(|old| old.max_fee_bps);
+    let audit_log = audit(deps, &env, role, &parameter, &old_max_fee_bps, &max_fee_bps)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_integrator"),
+            log("sender", env.message.sender.as_str()),
+            log("integrator", integrator.as_str()),
+            log("max_fee_bps", max_fee_bps.map(|bps| bps.to_string()).unwrap_or_default()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Integrator and the part of `borrow_amount` it is paid, checked against its cap
+pub fn integrator_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    integrator_fee: Option<&IntegratorFee>,
+    borrow_amount: Uint128,
+) -> StdResult<Option<(CanonicalAddr, Uint128)>> {
+    let integrator_fee = match integrator_fee {
+        Some(integrator_fee) => integrator_fee,
+        None => return Ok(None),
+    };
+    let integrator_raw = canonicalize_address(&deps.api, &integrator_fee.integrator)?;
+    let integrator = match get_integrator(&deps.storage, &integrator_raw)? {
+        Some(integrator) => integrator,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Integrator is not registered: integrator: {}",
+                integrator_fee.integrator
+            )))
+        }
+    };
+    if integrator_fee.fee_bps > integrator.max_fee_bps {
+        return Err(StdError::generic_err(format!(
+            "Integrator fee exceeds its cap: fee_bps: {}, max_fee_bps: {}",
+            integrator_fee.fee_bps, integrator.max_fee_bps
+        )));
+    }
+    let fee = borrow_amount.multiply_ratio(integrator_fee.fee_bps as u128, BPS_DENOMINATOR);
+    Ok(Some((integrator_raw, fee)))
+}
+
+/// Adds `fee` to what the integrator was paid so far
+pub fn record_integrator_fee<S: Storage>(storage: &mut S, integrator: &CanonicalAddr, fee: Uint128) -> StdResult<()> {
+    let mut info = match get_integrator(storage, integrator)? {
+        Some(info) => info,
+        None => return Err(StdError::generic_err("Integrator is not registered")),
+    };
+    info.total_fees += fee;
+    set_integrator(storage, integrator, Some(&info))
+}
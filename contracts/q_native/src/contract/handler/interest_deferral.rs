@@ -0,0 +1,304 @@
+use cosmwasm_std::{
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, LogAttribute, Querier, StdError, StdResult,
+    Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::state::{
+    get_borrow_balance, get_config, get_interest_deferral, get_settlement, get_state, set_borrow_balance,
+    set_interest_deferral, set_state, BorrowSnapshot, InterestDeferral, Role, MAX_INTEREST_DEFERRAL_DURATION,
+};
+
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::collateral::{accrue_interest, current_borrow_balance};
+use crate::contract::handler::exponential::{mul_div, truncate, SCALE};
+use crate::contract::handler::roles::assert_role;
+
+/// Admin or param_admin only, lets `account` off `discount` of the interest it accrues
+/// until block `expires`, replacing any earlier grant once the rebate it owes is settled
+pub fn try_grant_interest_deferral<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: HumanAddr,
+    discount: Uint128,
+    expires: u64,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    if discount.is_zero() || discount.u128() > SCALE {
+        return Err(StdError::generic_err("Interest discount must be within (0, 1]"));
+    }
+    let current_block = env.block.height;
+    if expires <= current_block || expires - current_block > MAX_INTEREST_DEFERRAL_DURATION {
+        return Err(StdError::generic_err(format!(
+            "Interest deferral must expire within {} blocks: expires: {}",
+            MAX_INTEREST_DEFERRAL_DURATION, expires
+        )));
+    }
+    let mut accrual_log = accrue_interest(deps, env.clone())?;
+
+    let account_raw = canonicalize_address(&deps.api, &account)?;
+    accrual_log.extend(settle_interest_deferral(&mut deps.storage, &account_raw)?);
+    let old_deferral = get_interest_deferral(&deps.storage, &account_raw)?;
+    let state = get_state(&deps.storage)?;
+    let deferral = InterestDeferral {
+        discount,
+        expires,
+        index: state.borrow_index,
+        block: state.block_number,
+    };
+    set_interest_deferral(&mut deps.storage, &account_raw, Some(&deferral))?;
+    let parameter = format!("interest_deferral.{}", account);
+    let audit_log = audit(deps, &env, role, &parameter, &old_deferral, &Some(deferral))?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "grant_interest_deferral"),
+            log("sender", env.message.sender.as_str()),
+            log("account", account.as_str()),
+            log("discount", discount),
+            log("expires", expires),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Admin or param_admin only, ends the deferral of `account` after settling the rebate it owes
+pub fn try_revoke_interest_deferral<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: HumanAddr,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let mut accrual_log = accrue_interest(deps, env.clone())?;
+
+    let account_raw = canonicalize_address(&deps.api, &account)?;
+    accrual_log.extend(settle_interest_deferral(&mut deps.storage, &account_raw)?);
+    let old_deferral = get_interest_deferral(&deps.storage, &account_raw)?;
+    set_interest_deferral(&mut deps.storage, &account_raw, None)?;
+    let parameter = format!("interest_deferral.{}", account);
+    let audit_log = audit(deps, &env, role, &parameter, &old_deferral, &None::<InterestDeferral>)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "revoke_interest_deferral"),
+            log("sender", env.message.sender.as_str()),
+            log("account", account.as_str()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Pays out the rebate an account is owed, callable by anyone
+/// Settling at the expiry block keeps the rebate exact, see `pending_interest_rebate`
+pub fn try_settle_interest_deferral<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let mut accrual_log = accrue_interest(deps, env.clone())?;
+    let account_raw = canonicalize_address(&deps.api, account)?;
+    if get_interest_deferral(&deps.storage, &account_raw)?.is_none() {
+        return Err(StdError::generic_err(format!("Account has no interest deferral: account: {}", account)));
+    }
+    accrual_log.extend(settle_interest_deferral(&mut deps.storage, &account_raw)?);
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "settle_interest_deferral"),
+            log("sender", env.message.sender.as_str()),
+            log("account", account.as_str()),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Rebate an account is owed at the stored borrow index, `discount` of the interest its debt
+/// accrued since the last settlement and before expiry, at most the reserves that fund it
+/// A settlement spanning the expiry waives the share of the interest taken as accruing evenly
+/// over the blocks before it
+pub fn pending_interest_rebate<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<u128> {
+    let deferral = match get_interest_deferral(storage, account)? {
+        Some(deferral) => deferral,
+        None => return Ok(0),
+    };
+    let state = get_state(storage)?;
+    if state.block_number <= deferral.block || deferral.expires <= deferral.block {
+        return Ok(0);
+    }
+    let debt = match get_borrow_balance(storage, account) {
+        Some(snapshot) => {
+            current_borrow_balance(&snapshot, &state.borrow_index, get_config(storage)?.dust_borrow_threshold)
+        }
+        None => 0,
+    };
+    if debt == 0 {
+        return Ok(0);
+    }
+    let interest = debt - mul_div(debt, deferral.index.u128(), state.borrow_index.u128());
+    let waived_blocks = state.block_number.min(deferral.expires) - deferral.block;
+    let interest = mul_div(interest, waived_blocks as u128, (state.block_number - deferral.block) as u128);
+    Ok(truncate(interest * deferral.discount.u128()).min(state.total_reserves.u128()))
+}
+
+/// Takes the pending rebate off the debt and off reserves alike, leaving the exchange rate
+/// unchanged, and ends the deferral once it has expired
+/// Must run after accrual and before a handler reads the borrow snapshot
+pub fn settle_interest_deferral<S: Storage>(storage: &mut S, account: &CanonicalAddr) -> StdResult<Vec<LogAttribute>> {
+    if get_settlement(storage)?.is_some() {
+        return Ok(vec![]);
+    }
+    let mut deferral = match get_interest_deferral(storage, account)? {
+        Some(deferral) => deferral,
+        None => return Ok(vec![]),
+    };
+    let rebate = pending_interest_rebate(storage, account)?;
+    let mut state = get_state(storage)?;
+    let expired = state.block_number >= deferral.expires;
+    deferral.index = state.borrow_index;
+    deferral.block = state.block_number;
+    match expired {
+        true => set_interest_deferral(storage, account, None)?,
+        false => set_interest_deferral(storage, account, Some(&deferral))?,
+    }
+    let mut deferral_log = vec![];
+    if rebate > 0 {
+        let debt = match get_borrow_balance(storage, account) {
+            Some(snapshot) => {
+                current_borrow_balance(&snapshot, &state.borrow_index, get_config(storage)?.dust_borrow_threshold)
+            }
+            None => 0,
+        };
+        set_borrow_balance(storage, account, Some(BorrowSnapshot {
+            principal: Uint128::from(debt - rebate),
+            interest_index: state.borrow_index,
+        }))?;
+        state.total_borrows = Uint128::from(state.total_borrows.u128().saturating_sub(rebate));
+        state.total_reserves = Uint128::from(state.total_reserves.u128() - rebate);
+        set_state(storage, &state)?;
+        deferral_log.push(log("interest_rebate", rebate));
+    }
+    if expired {
+        deferral_log.push(log("interest_deferral_expired", true));
+    }
+    Ok(deferral_log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market};
+    use crate::msg::HandleMsg;
+    use crate::state::State;
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    fn grant(discount: u128, expires: u64) -> HandleMsg {
+        HandleMsg::GrantInterestDeferral { account: HumanAddr::from("maker"), discount: Uint128::from(discount), expires }
+    }
+
+    /// Moves the stored accrual to `block` at `borrow_index`, as if interest had accrued
+    fn accrued_to<S: Storage>(storage: &mut S, block: u64, borrow_index: u128, total_borrows: u128, total_reserves: u128) {
+        let state = get_state(storage).unwrap();
+        set_state(storage, &State {
+            block_number: block,
+            borrow_index: Uint128::from(borrow_index),
+            total_borrows: Uint128::from(total_borrows),
+            total_reserves: Uint128::from(total_reserves),
+            ..state
+        })
+        .unwrap();
+    }
+
+    fn debt<S: Storage>(storage: &S, account: &CanonicalAddr) -> BorrowSnapshot {
+        get_borrow_balance(storage, account).unwrap()
+    }
+
+    #[test]
+    fn rebate_covers_the_discounted_interest_until_expiry() {
+        let mut deps = mock_market(mock_init_msg());
+        let maker = deps.api.canonical_address(&HumanAddr::from("maker")).unwrap();
+        handle(&mut deps, env_at("admin", 20_000), grant(50_000_000, 20_100)).unwrap();
+        set_borrow_balance(&mut deps.storage, &maker, Some(BorrowSnapshot {
+            principal: Uint128::from(1_000u128),
+            interest_index: Uint128::from(100_000_000u128),
+        }))
+        .unwrap();
+
+        // 10% interest, half of it waived and refunded out of reserves
+        accrued_to(&mut deps.storage, 20_050, 110_000_000, 1_100, 100);
+        assert_eq!(pending_interest_rebate(&deps.storage, &maker).unwrap(), 50);
+        let settle = HandleMsg::SettleInterestDeferral { account: HumanAddr::from("maker") };
+        let res = handle(&mut deps, env_at("anyone", 20_050), settle.clone()).unwrap();
+        assert!(res.log.contains(&log("interest_rebate", 50)));
+        assert_eq!(debt(&deps.storage, &maker).principal, Uint128::from(1_050u128));
+        let state = get_state(&deps.storage).unwrap();
+        assert_eq!((state.total_borrows, state.total_reserves), (Uint128::from(1_050u128), Uint128::from(50u128)));
+
+        // Another 10% over 100 blocks of which only the first 50 are before expiry
+        accrued_to(&mut deps.storage, 20_150, 121_000_000, 1_155, 50);
+        let res = handle(&mut deps, env_at("anyone", 20_150), settle.clone()).unwrap();
+        assert!(res.log.contains(&log("interest_rebate", 26)));
+        assert!(res.log.contains(&log("interest_deferral_expired", true)));
+        assert_eq!(debt(&deps.storage, &maker).principal, Uint128::from(1_129u128));
+        assert_eq!(get_interest_deferral(&deps.storage, &maker).unwrap(), None);
+        assert_eq!(
+            handle(&mut deps, env_at("anyone", 20_151), settle).unwrap_err(),
+            StdError::generic_err("Account has no interest deferral: account: maker")
+        );
+    }
+
+    #[test]
+    fn rebate_is_capped_by_reserves() {
+        let mut deps = mock_market(mock_init_msg());
+        let maker = deps.api.canonical_address(&HumanAddr::from("maker")).unwrap();
+        handle(&mut deps, env_at("admin", 20_000), grant(100_000_000, 20_100)).unwrap();
+        set_borrow_balance(&mut deps.storage, &maker, Some(BorrowSnapshot {
+            principal: Uint128::from(1_000u128),
+            interest_index: Uint128::from(100_000_000u128),
+        }))
+        .unwrap();
+        accrued_to(&mut deps.storage, 20_050, 110_000_000, 1_100, 30);
+        assert_eq!(pending_interest_rebate(&deps.storage, &maker).unwrap(), 30);
+    }
+
+    #[test]
+    fn grants_are_bounded_and_revocable() {
+        let mut deps = mock_market(mock_init_msg());
+        assert_eq!(
+            handle(&mut deps, env_at("maker", 20_000), grant(50_000_000, 20_100)).unwrap_err(),
+            StdError::unauthorized()
+        );
+        assert_eq!(
+            handle(&mut deps, env_at("admin", 20_000), grant(100_000_001, 20_100)).unwrap_err(),
+            StdError::generic_err("Interest discount must be within (0, 1]")
+        );
+        assert!(handle(&mut deps, env_at("admin", 20_000), grant(50_000_000, 20_000)).is_err());
+        assert!(handle(&mut deps, env_at("admin", 20_000), grant(50_000_000, 20_001 + MAX_INTEREST_DEFERRAL_DURATION)).is_err());
+
+        let res = handle(&mut deps, env_at("admin", 20_000), grant(50_000_000, 20_100)).unwrap();
+        assert!(res.log.contains(&log("audit_parameter", "interest_deferral.maker")));
+        let revoke = HandleMsg::RevokeInterestDeferral { account: HumanAddr::from("maker") };
+        handle(&mut deps, env_at("admin", 20_001), revoke).unwrap();
+        let maker = deps.api.canonical_address(&HumanAddr::from("maker")).unwrap();
+        assert_eq!(get_interest_deferral(&deps.storage, &maker).unwrap(), None);
+    }
+}
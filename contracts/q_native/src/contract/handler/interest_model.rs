@@ -1,36 +1,264 @@
-// A mockup interest model 
+// Interest rate models selectable per market
 // TODO: make this in a separate contract and let someone manage this per each collaterized asset 
-use cosmwasm_std::{Uint128};
-// constant that needs to be managed
-pub static  multiplier_per_block: u128 = 23; // 0.000000237823 * 10^8
-pub static  base_rate_per_block: u128 = 0; 
-pub static  jump_multiplier_per_block: u128 = 51; // 0.000000518455 * 10^8;
-pub static kink: u128 =  80_000_000; // 0.8 * 10^8
-//pub static config_reserve_factor: u128 = 5_000_000; // 0.05 * 10^8
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{StdError, StdResult, Uint128};
 
+use crate::contract::handler::exponential::{mul_div, SCALE, truncate};
+use crate::msg::{RateConversionResponse, RateUnit};
+use crate::state::{InterestRateModel, InterestRateModelBounds, ParameterBound, SECONDS_PER_YEAR};
+
+/// Borrows over cash plus borrows less reserves, scaled by 10^8
 pub fn get_utilization_rate(cash: &Uint128, borrows: &Uint128, reserves: &Uint128) -> u128 {
-    if *borrows == Uint128::from(0u128) {
+    let supplied = (cash.u128() + borrows.u128()).saturating_sub(reserves.u128());
+    if borrows.is_zero() || supplied == 0 {
         return 0;
     }
-    borrows.u128()/((cash.u128() + borrows.u128()) - reserves.u128())
+    mul_div(borrows.u128(), SCALE, supplied)
+}
+
+
+pub fn get_borrow_rate(model: &InterestRateModel, cash: &Uint128, borrows: &Uint128, reserves: &Uint128) -> u128 {
+    get_borrow_rate_at(model, get_utilization_rate(cash, borrows, reserves))
+}
+
+/// Borrow rate per block of the model evaluated at the given utilization, never below the model's floor
+pub fn get_borrow_rate_at(model: &InterestRateModel, util: u128) -> u128 {
+    let curve_rate = match model {
+        InterestRateModel::Linear { base_rate_per_block, multiplier_per_block, .. } => {
+            truncate(util * multiplier_per_block.u128()) + base_rate_per_block.u128()
+        }
+        InterestRateModel::JumpRate { base_rate_per_block, multiplier_per_block, jump_multiplier_per_block, kink, .. } => {
+            let kink = kink.u128();
+            if util <= kink {
+                truncate(util * multiplier_per_block.u128()) + base_rate_per_block.u128()
+            } else {
+                let normal_rate = truncate(kink * multiplier_per_block.u128()) + base_rate_per_block.u128();
+                let excess_util = util - kink;
+                truncate(excess_util * jump_multiplier_per_block.u128()) + normal_rate
+            }
+        }
+    };
+    curve_rate.max(model.min_borrow_rate_per_block())
+}
+
+pub fn get_supply_rate(model: &InterestRateModel, cash: &Uint128, borrows: &Uint128, reserves: &Uint128, reserve_factor: &Uint128) -> u128 {
+    let one_minus_reserve_factor = SCALE - reserve_factor.u128();
+    let borrow_rate = get_borrow_rate(model, cash, borrows, reserves);
+    let rate_to_pool = truncate(borrow_rate * one_minus_reserve_factor);
+    truncate(get_utilization_rate(cash, borrows, reserves) * rate_to_pool)
+}
+
+/// Rejects models whose kink lies outside full utilization, or whose floor alone
+/// would exceed the market's max borrow rate and halt accrual
+pub fn validate_interest_rate_model(model: &InterestRateModel, max_borrow_rate: u128) -> StdResult<()> {
+    let min_borrow_rate = model.min_borrow_rate_per_block();
+    if min_borrow_rate > max_borrow_rate {
+        return Err(StdError::generic_err(format!(
+            "Min borrow rate exceeds max borrow rate: min_borrow_rate: {}, max_borrow_rate: {}",
+            min_borrow_rate, max_borrow_rate
+        )));
+    }
+    if let InterestRateModel::JumpRate { kink, .. } = model {
+        if kink.u128() > 100_000_000 {
+            return Err(StdError::generic_err(format!(
+                "Kink must not exceed 1: kink: {}",
+                kink
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects models with any parameter outside its governance approved bound
+pub fn validate_interest_rate_model_bounds(model: &InterestRateModel, bounds: &InterestRateModelBounds) -> StdResult<()> {
+    let (base_rate_per_block, multiplier_per_block) = match model {
+        InterestRateModel::JumpRate { base_rate_per_block, multiplier_per_block, jump_multiplier_per_block, kink, .. } => {
+            check_bound("jump_multiplier_per_block", *jump_multiplier_per_block, &bounds.jump_multiplier_per_block)?;
+            check_bound("kink", *kink, &bounds.kink)?;
+            (base_rate_per_block, multiplier_per_block)
+        }
+        InterestRateModel::Linear { base_rate_per_block, multiplier_per_block, .. } => (base_rate_per_block, multiplier_per_block),
+    };
+    check_bound("base_rate_per_block", *base_rate_per_block, &bounds.base_rate_per_block)?;
+    check_bound("multiplier_per_block", *multiplier_per_block, &bounds.multiplier_per_block)?;
+    check_bound(
+        "min_borrow_rate_per_block",
+        Uint128::from(model.min_borrow_rate_per_block()),
+        &bounds.min_borrow_rate_per_block,
+    )
+}
+
+fn check_bound(parameter: &str, value: Uint128, bound: &Option<ParameterBound>) -> StdResult<()> {
+    match bound {
+        Some(bound) if !bound.contains(value) => Err(StdError::generic_err(format!(
+            "Rate model parameter out of bounds: parameter: {}, value: {}, min: {}, max: {}",
+            parameter, value, bound.min, bound.max
+        ))),
+        _ => Ok(()),
+    }
 }
 
+/// Every bound must have its min at or below its max
+pub fn validate_parameter_bounds(bounds: &InterestRateModelBounds) -> StdResult<()> {
+    let named = [
+        ("base_rate_per_block", &bounds.base_rate_per_block),
+        ("multiplier_per_block", &bounds.multiplier_per_block),
+        ("jump_multiplier_per_block", &bounds.jump_multiplier_per_block),
+        ("kink", &bounds.kink),
+        ("min_borrow_rate_per_block", &bounds.min_borrow_rate_per_block),
+    ];
+    for (parameter, bound) in named.iter() {
+        if let Some(bound) = bound {
+            if bound.min > bound.max {
+                return Err(StdError::generic_err(format!(
+                    "Bound min exceeds max: parameter: {}, min: {}, max: {}",
+                    parameter, bound.min, bound.max
+                )));
+            }
+        }
+    }
+    Ok(())
+}
 
-pub fn get_borrow_rate(cash: &Uint128, borrows: &Uint128, reserves: &Uint128) -> u128 {
-    let util = get_utilization_rate(cash, borrows, reserves);
+pub fn validate_blocks_per_year(blocks_per_year: Option<u64>) -> StdResult<()> {
+    if blocks_per_year == Some(0) {
+        return Err(StdError::generic_err("Blocks per year must be positive"));
+    }
+    Ok(())
+}
 
-    if util <= kink {
-        return util * multiplier_per_block + base_rate_per_block;
-    } else {
-        let normal_rate = kink * multiplier_per_block + base_rate_per_block;
-        let excess_util = util - kink;
-        return excess_util * jump_multiplier_per_block + normal_rate;
+/// Expresses `rate` given in `unit` in every unit, together with the truncated
+/// per block value a model would store and the annual rate that value charges
+pub fn convert_rate(rate: Decimal256, unit: RateUnit, blocks_per_year: u64) -> RateConversionResponse {
+    let blocks = Decimal256::from_uint256(Uint256::from(blocks_per_year));
+    let seconds = Decimal256::from_uint256(Uint256::from(SECONDS_PER_YEAR));
+    let annual = match unit {
+        RateUnit::Annual => rate,
+        RateUnit::PerBlock => rate * blocks,
+        RateUnit::PerSecond => rate * seconds,
+    };
+    let per_block = annual / blocks;
+    let per_block_scaled: Uint128 = (Uint256::from(SCALE) * per_block).into();
+    RateConversionResponse {
+        blocks_per_year,
+        annual,
+        per_block,
+        per_second: annual / seconds,
+        per_block_scaled,
+        effective_annual: Decimal256::from_ratio(
+            (Uint256::from(per_block_scaled) * Uint256::from(blocks_per_year)).0,
+            Uint256::from(SCALE).0,
+        ),
     }
 }
 
-pub fn get_supply_rate(cash: &Uint128, borrows: &Uint128, reserves: &Uint128, reserve_factor: &Uint128) -> u128 {
-    let one_minus_reserve_factor = 1*10_i32.pow(8) as u128 - reserve_factor.u128();
-    let borrow_rate = get_borrow_rate(cash, borrows, reserves);
-    let rate_to_pool = borrow_rate * one_minus_reserve_factor;
-    get_utilization_rate(cash, borrows, reserves) * rate_to_pool
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jump_rate(min_borrow_rate_per_block: Option<u128>) -> InterestRateModel {
+        InterestRateModel::JumpRate {
+            base_rate_per_block: Uint128::zero(),
+            multiplier_per_block: Uint128::from(1_000u128),
+            jump_multiplier_per_block: Uint128::from(5_000u128),
+            kink: Uint128::from(80_000_000u128),
+            min_borrow_rate_per_block: min_borrow_rate_per_block.map(Uint128::from),
+        }
+    }
+
+    #[test]
+    fn min_borrow_rate_floors_the_curve_up_to_where_it_crosses() {
+        let model = jump_rate(Some(400));
+        assert_eq!(model.min_borrow_rate_per_block(), 400);
+        // Idle markets still charge the floor
+        assert_eq!(get_borrow_rate_at(&model, 0), 400);
+        assert_eq!(get_borrow_rate_at(&model, 39_900_000), 400);
+        // The curve reaches the floor at 40% and takes over above it
+        assert_eq!(get_borrow_rate_at(&model, 40_000_000), 400);
+        assert_eq!(get_borrow_rate_at(&model, 40_100_000), 401);
+        assert_eq!(get_borrow_rate_at(&model, 90_000_000), 1_300);
+    }
+
+    #[test]
+    fn min_borrow_rate_above_the_kink_rate_reaches_into_the_jump() {
+        let model = jump_rate(Some(1_000));
+        assert_eq!(get_borrow_rate_at(&model, 80_000_000), 1_000);
+        // 800 at the kink plus 5000 per unit of excess utilization crosses 1000 at 84%
+        assert_eq!(get_borrow_rate_at(&model, 84_000_000), 1_000);
+        assert_eq!(get_borrow_rate_at(&model, 84_020_000), 1_001);
+        assert_eq!(get_borrow_rate_at(&model, 100_000_000), 1_800);
+    }
+
+    #[test]
+    fn models_without_a_floor_follow_the_curve() {
+        let model = jump_rate(None);
+        assert_eq!(model.min_borrow_rate_per_block(), 0);
+        assert_eq!(get_borrow_rate_at(&model, 0), 0);
+        assert_eq!(get_borrow_rate_at(&model, 39_900_000), 399);
+
+        let linear = InterestRateModel::Linear {
+            base_rate_per_block: Uint128::from(100u128),
+            multiplier_per_block: Uint128::from(1_000u128),
+            min_borrow_rate_per_block: None,
+        };
+        assert_eq!(get_borrow_rate_at(&linear, 0), 100);
+        // A zero floor is the same as none
+        let linear = InterestRateModel::Linear {
+            base_rate_per_block: Uint128::from(100u128),
+            multiplier_per_block: Uint128::from(1_000u128),
+            min_borrow_rate_per_block: Some(Uint128::zero()),
+        };
+        assert_eq!(get_borrow_rate_at(&linear, 0), 100);
+    }
+
+    #[test]
+    fn min_borrow_rate_may_reach_but_not_exceed_the_max_borrow_rate() {
+        assert!(validate_interest_rate_model(&jump_rate(Some(5_000)), 5_000).is_ok());
+        assert_eq!(
+            validate_interest_rate_model(&jump_rate(Some(5_001)), 5_000).unwrap_err(),
+            StdError::generic_err("Min borrow rate exceeds max borrow rate: min_borrow_rate: 5001, max_borrow_rate: 5000")
+        );
+    }
+
+    fn bound(min: u128, max: u128) -> Option<ParameterBound> {
+        Some(ParameterBound { min: Uint128::from(min), max: Uint128::from(max) })
+    }
+
+    #[test]
+    fn bounds_check_every_parameter_the_model_has() {
+        let bounds = InterestRateModelBounds {
+            multiplier_per_block: bound(500, 1_000),
+            jump_multiplier_per_block: bound(0, 4_999),
+            ..InterestRateModelBounds::default()
+        };
+        assert_eq!(
+            validate_interest_rate_model_bounds(&jump_rate(None), &bounds).unwrap_err(),
+            StdError::generic_err(
+                "Rate model parameter out of bounds: parameter: jump_multiplier_per_block, value: 5000, min: 0, max: 4999"
+            )
+        );
+        // A linear model has no jump multiplier to check
+        let linear = InterestRateModel::Linear {
+            base_rate_per_block: Uint128::zero(),
+            multiplier_per_block: Uint128::from(1_000u128),
+            min_borrow_rate_per_block: None,
+        };
+        assert!(validate_interest_rate_model_bounds(&linear, &bounds).is_ok());
+
+        // A missing floor counts as zero
+        let bounds = InterestRateModelBounds { min_borrow_rate_per_block: bound(100, 400), ..InterestRateModelBounds::default() };
+        assert!(validate_interest_rate_model_bounds(&jump_rate(None), &bounds).is_err());
+        assert!(validate_interest_rate_model_bounds(&jump_rate(Some(400)), &bounds).is_ok());
+        assert!(validate_interest_rate_model_bounds(&jump_rate(Some(401)), &bounds).is_err());
+    }
+
+    #[test]
+    fn bounds_must_not_be_inverted() {
+        assert!(validate_parameter_bounds(&InterestRateModelBounds { kink: bound(80, 80), ..InterestRateModelBounds::default() }).is_ok());
+        assert_eq!(
+            validate_parameter_bounds(&InterestRateModelBounds { kink: bound(81, 80), ..InterestRateModelBounds::default() })
+                .unwrap_err(),
+            StdError::generic_err("Bound min exceeds max: parameter: kink, min: 81, max: 80")
+        );
+    }
+}
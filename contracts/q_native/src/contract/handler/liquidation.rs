@@ -0,0 +1,435 @@
+use cosmwasm_std::{
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult,
+    Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::comptroller::{ComptrollerHookMsg, MarketAction};
+
+use crate::state::{
+    get_balance, get_borrow_balance, get_config, get_shortfall_block, get_state, set_backstop_flag, set_borrow_balance,
+    set_config, set_shortfall_block, set_state, update_leaderboard, update_metrics, append_liquidation,
+    BorrowSnapshot, Config, LiquidationRecord, PauseAction, MAX_PROTOCOL_SEIZE_SHARE, TOP_BORROWERS_KEY, Role,
+};
+
+use crate::contract::handler::admin::assert_action_not_paused;
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::balance_hook::balance_hook;
+use crate::contract::handler::collateral::{
+    accrue_interest, borrow_limit, current_borrow_balance, get_exchange_rate, market_log, tokens_for_underlying,
+    underlying_for_tokens,
+};
+use crate::contract::handler::comptroller::{comptroller_hook, comptroller_shortfall};
+use crate::contract::handler::exponential::truncate;
+use crate::contract::handler::cash_strategy::pay_out_cash;
+use crate::contract::handler::health_alert::health_alert_log;
+use crate::contract::handler::transfer_policy::assert_transfer_allowed;
+use crate::contract::handler::insurance::{insurance_refund, settle_premium};
+use crate::contract::handler::interest_deferral::settle_interest_deferral;
+use crate::contract::handler::token::{burn_tokens, perform_transfer};
+use crate::contract::handler::roles::assert_role;
+
+/// Borrowers are underwater once their debt exceeds `collateral_factor` of the underlying
+/// value of their qTokens in this market, or across every entered market under a comptroller
+pub fn try_liquidate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    borrower: &HumanAddr,
+    repay_amount: Uint128,
+    sent: Uint128,
+    collateral_market: Option<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Liquidation)?;
+    if *borrower == env.message.sender {
+        return Err(StdError::generic_err("Borrowers cannot liquidate themselves"));
+    }
+
+    let config = get_config(&deps.storage)?;
+    if sent != repay_amount {
+        return Err(StdError::generic_err(format!(
+            "Sent funds must match the repay amount: repay_amount: {}, sent: {}",
+            repay_amount, sent
+        )));
+    }
+    // Collateral elsewhere is seized by its own market, which only the comptroller may ask
+    let collateral_market = collateral_market.filter(|market| *market != env.contract.address);
+    let comptroller = match (&collateral_market, &config.comptroller) {
+        (Some(_), Some(comptroller)) => Some(deps.api.human_address(comptroller)?),
+        (Some(market), None) => {
+            return Err(StdError::generic_err(format!(
+                "Collateral in another market needs a comptroller: collateral_market: {}",
+                market
+            )));
+        }
+        (None, _) => None,
+    };
+
+    let mut accrual_log = accrue_interest(deps, env.clone())?;
+
+    let borrower_raw = canonicalize_address(&deps.api, borrower)?;
+    let liquidator_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    accrual_log.extend(settle_interest_deferral(&mut deps.storage, &borrower_raw)?);
+    settle_premium(&mut deps.storage, &borrower_raw)?;
+    let state = get_state(&deps.storage)?;
+    let account_borrow = match get_borrow_balance(&deps.storage, &borrower_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    if exchange_rate == 0 {
+        return Err(StdError::generic_err("Exchange rate is zero, collateral cannot be valued"));
+    }
+    let collateral_tokens = get_balance(&deps.storage, &borrower_raw)?;
+    let limit = borrow_limit(&config, collateral_tokens, exchange_rate);
+    if let Some(error) = not_underwater_error(deps, &config, borrower, account_borrow, limit, env.block.height)? {
+        return Err(StdError::generic_err(error));
+    }
+    // Keeps the borrower from moving collateral away from later liquidations in this block
+    set_shortfall_block(&mut deps.storage, &borrower_raw, env.block.height)?;
+    // A liquidator acted, the backstop waits for a new delay
+    set_backstop_flag(&mut deps.storage, &borrower_raw, None)?;
+
+    let max_repay = max_repay(&config, account_borrow);
+    if repay_amount.u128() > max_repay {
+        return Err(StdError::generic_err(format!(
+            "Repay amount exceeds close factor: repay_amount: {}, max_repay: {}",
+            repay_amount, max_repay
+        )));
+    }
+
+    let (seize_tokens, protocol_seize_tokens, protocol_seize_amount, mut messages) = match (&collateral_market, comptroller) {
+        (Some(market), Some(comptroller)) => {
+            let seize = ComptrollerHookMsg::SeizeCollateral {
+                collateral_market: market.clone(),
+                borrower: borrower.clone(),
+                liquidator: env.message.sender.clone(),
+                repay_amount,
+            };
+            (0, 0, 0, vec![seize.into_cosmos_msg(comptroller)?])
+        }
+        _ => {
+            let (seize_tokens, protocol_seize_tokens, protocol_seize_amount) =
+                seize(deps, &config, borrower, &env.message.sender, repay_amount.u128(), exchange_rate)?;
+            let mut messages = balance_hook(&deps.storage, &deps.api, &[&borrower_raw, &liquidator_raw])?;
+            messages.extend(comptroller_hook(&deps.api, &config, MarketAction::Seize, borrower, seize_tokens)?);
+            (seize_tokens, protocol_seize_tokens, protocol_seize_amount, messages)
+        }
+    };
+    let liquidator_seize_tokens = seize_tokens - protocol_seize_tokens;
+
+    let new_account_borrow = account_borrow - repay_amount.u128();
+    let mut new_state = get_state(&deps.storage)?;
+    new_state.cash += repay_amount;
+    new_state.total_borrows = Uint128::from(new_state.total_borrows.u128().saturating_sub(repay_amount.u128()));
+    set_state(&mut deps.storage, &new_state)?;
+    set_borrow_balance(&mut deps.storage, &borrower_raw, Some(BorrowSnapshot {
+        principal: Uint128::from(new_account_borrow),
+        interest_index: new_state.borrow_index,
+    }))?;
+    update_leaderboard(&mut deps.storage, TOP_BORROWERS_KEY, &borrower_raw, new_account_borrow)?;
+    update_metrics(&mut deps.storage, |m| m.liquidations += 1)?;
+
+    // Insured borrowers get part of the incentive back when liquidated in a volatile market
+    let penalty = truncate(repay_amount.u128() * config.liquidation_incentive.u128());
+    let insurance_refund = insurance_refund(deps, &env, &borrower_raw, penalty)?;
+    if insurance_refund > 0 {
+        messages.extend(pay_out_cash(deps, &env, &config, borrower, Uint128::from(insurance_refund))?);
+    }
+    let alert_log = health_alert_log(&mut deps.storage, &deps.api, &[&borrower_raw, &liquidator_raw])?;
+    let record = append_liquidation(&mut deps.storage, LiquidationRecord {
+        id: 0,
+        liquidator: liquidator_raw,
+        borrower: borrower_raw,
+        repay_amount,
+        seize_tokens: Uint128::from(seize_tokens),
+        protocol_seize_tokens: Uint128::from(protocol_seize_tokens),
+        exchange_rate: Uint128::from(exchange_rate),
+        block_number: env.block.height,
+        collateral_market: match &collateral_market {
+            Some(market) => Some(canonicalize_address(&deps.api, market)?),
+            None => None,
+        },
+    })?;
+
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "liquidate"),
+            log("liquidation_id", record.id),
+            log("sender", env.message.sender.as_str()),
+            log("borrower", borrower.as_str()),
+            log("amount", repay_amount),
+            log("repay_amount", repay_amount),
+            log("seize_tokens", seize_tokens),
+            log("liquidator_seize_tokens", liquidator_seize_tokens),
+            log("protocol_seize_tokens", protocol_seize_tokens),
+            log("protocol_seize_amount", protocol_seize_amount),
+            log("new_account_borrow", new_account_borrow),
+            log("insurance_refund", insurance_refund),
+        ],
+        data: None,
+    };
+    if let Some(market) = &collateral_market {
+        res.log.push(log("collateral_market", market.as_str()));
+    }
+    res.log.extend(market_log(&deps.storage)?);
+    res.log.extend(alert_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Comptroller only, seizes for a liquidation another market repaid. The comptroller
+/// checked the borrower's shortfall and valued the repayment in this market's underlying
+pub fn try_seize_collateral<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    borrower: &HumanAddr,
+    liquidator: &HumanAddr,
+    repay_value: Uint128,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if config.comptroller != Some(sender_raw) {
+        return Err(StdError::unauthorized());
+    }
+    assert_action_not_paused(&deps.storage, PauseAction::Liquidation)?;
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    if exchange_rate == 0 {
+        return Err(StdError::generic_err("Exchange rate is zero, collateral cannot be valued"));
+    }
+    let borrower_raw = canonicalize_address(&deps.api, borrower)?;
+    let liquidator_raw = canonicalize_address(&deps.api, liquidator)?;
+    set_shortfall_block(&mut deps.storage, &borrower_raw, env.block.height)?;
+    let (seize_tokens, protocol_seize_tokens, protocol_seize_amount) =
+        seize(deps, &config, borrower, liquidator, repay_value.u128(), exchange_rate)?;
+
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&borrower_raw, &liquidator_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &config, MarketAction::Seize, borrower, seize_tokens)?);
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "seize_collateral"),
+            log("sender", env.message.sender.as_str()),
+            log("borrower", borrower.as_str()),
+            log("liquidator", liquidator.as_str()),
+            log("repay_value", repay_value),
+            log("seize_tokens", seize_tokens),
+            log("liquidator_seize_tokens", seize_tokens - protocol_seize_tokens),
+            log("protocol_seize_tokens", protocol_seize_tokens),
+            log("protocol_seize_amount", protocol_seize_amount),
+        ],
+        data: None,
+    };
+    res.log.extend(market_log(&deps.storage)?);
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&borrower_raw, &liquidator_raw])?);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Moves the qTokens seized for `repay_value` of the underlying from the borrower to the
+/// liquidator. The protocol's share is burned and its underlying value moved to reserves,
+/// which leaves the exchange rate unchanged.
+/// Returns (seize_tokens, protocol_seize_tokens, protocol_seize_amount)
+fn seize<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    config: &Config,
+    borrower: &HumanAddr,
+    liquidator: &HumanAddr,
+    repay_value: u128,
+    exchange_rate: u128,
+) -> StdResult<(u128, u128, u128)> {
+    let borrower_raw = &canonicalize_address(&deps.api, borrower)?;
+    let liquidator_raw = &canonicalize_address(&deps.api, liquidator)?;
+    let collateral_tokens = get_balance(&deps.storage, borrower_raw)?;
+    let (_, seize_tokens, protocol_seize_tokens) = seize_amounts(config, repay_value, exchange_rate);
+    if seize_tokens > collateral_tokens {
+        return Err(StdError::generic_err(format!(
+            "Borrower has insufficient collateral to seize: seize_tokens: {}, collateral_tokens: {}",
+            seize_tokens, collateral_tokens
+        )));
+    }
+    let protocol_seize_amount = underlying_for_tokens(protocol_seize_tokens, exchange_rate);
+    let liquidator_seize_tokens = seize_tokens - protocol_seize_tokens;
+    assert_transfer_allowed(deps, Some(borrower), liquidator, Uint128::from(liquidator_seize_tokens))?;
+    perform_transfer(&mut deps.storage, borrower_raw, liquidator_raw, liquidator_seize_tokens)?;
+    if protocol_seize_tokens > 0 {
+        burn_tokens(&mut deps.storage, borrower_raw, protocol_seize_tokens)?;
+        let mut new_config = get_config(&deps.storage)?;
+        new_config.total_supply = (new_config.total_supply - Uint128::from(protocol_seize_tokens))?;
+        set_config(&mut deps.storage, &new_config)?;
+
+        let mut new_state = get_state(&deps.storage)?;
+        new_state.total_reserves += Uint128::from(protocol_seize_amount);
+        set_state(&mut deps.storage, &new_state)?;
+    }
+    Ok((seize_tokens, protocol_seize_tokens, protocol_seize_amount))
+}
+
+/// Why the account may not be liquidated, None when it is underwater
+/// Under a comptroller debt here may be backed by collateral in other markets and
+/// vice versa, so the comptroller's shortfall decides
+pub fn not_underwater_error<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    config: &Config,
+    account: &HumanAddr,
+    account_borrow: u128,
+    limit: u128,
+    block: u64,
+) -> StdResult<Option<String>> {
+    Ok(match comptroller_shortfall(deps, config, account, block)? {
+        Some(shortfall) if shortfall.is_zero() => Some("Account is not underwater: shortfall: 0".to_string()),
+        Some(_) => None,
+        None if account_borrow <= limit => Some(format!(
+            "Account is not underwater: account_borrow: {}, borrow_limit: {}",
+            account_borrow, limit
+        )),
+        None => None,
+    })
+}
+
+/// Most of the borrow one liquidation may repay, the close factor's share rounded down
+pub fn max_repay(config: &Config, account_borrow: u128) -> u128 {
+    truncate(account_borrow * config.close_factor.u128())
+}
+
+/// Seizes the repaid value plus the incentive, in qTokens at `exchange_rate`,
+/// as (seize_value, seize_tokens, protocol_seize_tokens)
+pub fn seize_amounts(config: &Config, repay_amount: u128, exchange_rate: u128) -> (u128, u128, u128) {
+    let seize_value = truncate(repay_amount * (100_000_000 + config.liquidation_incentive.u128()));
+    let seize_tokens = tokens_for_underlying(seize_value, exchange_rate);
+    let protocol_seize_tokens = truncate(seize_tokens * config.protocol_seize_share.u128());
+    (seize_value, seize_tokens, protocol_seize_tokens)
+}
+
+/// Lets a liquidator lock an underwater account's qTokens in place ahead of the liquidation
+pub fn try_flag_shortfall<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Liquidation)?;
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    let config = get_config(&deps.storage)?;
+    let state = get_state(&deps.storage)?;
+    let account_raw = canonicalize_address(&deps.api, account)?;
+    let account_borrow = match get_borrow_balance(&deps.storage, &account_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    let limit = borrow_limit(&config, get_balance(&deps.storage, &account_raw)?, exchange_rate);
+    if let Some(error) = not_underwater_error(deps, &config, account, account_borrow, limit, env.block.height)? {
+        return Err(StdError::generic_err(error));
+    }
+    set_shortfall_block(&mut deps.storage, &account_raw, env.block.height)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "flag_shortfall"),
+            log("sender", env.message.sender.as_str()),
+            log("account", account.as_str()),
+            log("account_borrow", account_borrow),
+            log("borrow_limit", limit),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Rejects moving qTokens out of an account found underwater earlier in the current block
+pub fn assert_not_flagged<S: Storage>(storage: &S, account: &CanonicalAddr, env: &Env) -> StdResult<()> {
+    if get_shortfall_block(storage, account)? == Some(env.block.height) {
+        return Err(StdError::generic_err(format!(
+            "Account is flagged liquidatable in this block: block: {}",
+            env.block.height
+        )));
+    }
+    Ok(())
+}
+
+pub fn try_set_protocol_seize_share<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    protocol_seize_share: Uint128,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let mut config = get_config(&deps.storage)?;
+    if protocol_seize_share.u128() > MAX_PROTOCOL_SEIZE_SHARE {
+        return Err(StdError::generic_err(format!(
+            "Protocol seize share exceeds maximum: protocol_seize_share: {}, max_protocol_seize_share: {}",
+            protocol_seize_share, MAX_PROTOCOL_SEIZE_SHARE
+        )));
+    }
+    let old_share = config.protocol_seize_share;
+    config.protocol_seize_share = protocol_seize_share;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, role, "protocol_seize_share", &old_share, &protocol_seize_share)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_protocol_seize_share"),
+            log("sender", env.message.sender.as_str()),
+            log("protocol_seize_share", protocol_seize_share),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::testing::{mock_init_msg, mock_market};
+
+    fn mock_config(close_factor: u128, protocol_seize_share: u128) -> Config {
+        let mut msg = mock_init_msg();
+        msg.close_factor = Uint128::from(close_factor);
+        msg.protocol_seize_share = Uint128::from(protocol_seize_share);
+        get_config(&mock_market(msg).storage).unwrap()
+    }
+
+    #[test]
+    fn max_repay_is_the_close_factor_share_rounded_down() {
+        let config = mock_config(50_000_000, 0);
+        assert_eq!(max_repay(&config, 1_000), 500);
+        assert_eq!(max_repay(&config, 1_001), 500);
+        assert_eq!(max_repay(&config, 1), 0);
+        assert_eq!(max_repay(&config, 0), 0);
+        // A close factor of 1 repays the whole borrow
+        assert_eq!(max_repay(&mock_config(100_000_000, 0), 1_001), 1_001);
+    }
+
+    #[test]
+    fn seize_amounts_add_the_incentive_at_the_exchange_rate() {
+        // 8% incentive
+        let config = mock_config(50_000_000, 0);
+        assert_eq!(seize_amounts(&config, 1_000, 100_000_000), (1_080, 1_080, 0));
+        // Each qToken is worth 2 underlying
+        assert_eq!(seize_amounts(&config, 1_000, 200_000_000), (1_080, 540, 0));
+        // 1081.08 underlying truncates to 1081, worth 360.33 qTokens at 3 underlying each
+        assert_eq!(seize_amounts(&config, 1_001, 300_000_000), (1_081, 360, 0));
+        // Below 1 each seized underlying takes more qTokens
+        assert_eq!(seize_amounts(&config, 1_000, 50_000_000), (1_080, 2_160, 0));
+    }
+
+    #[test]
+    fn protocol_seize_share_rounds_down_in_the_liquidators_favor() {
+        // 2.8% of the seized qTokens go to reserves
+        let config = mock_config(50_000_000, 2_800_000);
+        assert_eq!(seize_amounts(&config, 1_000, 100_000_000), (1_080, 1_080, 30));
+        // 360 * 0.028 = 10.08
+        assert_eq!(seize_amounts(&config, 1_001, 300_000_000), (1_081, 360, 10));
+        // Too small a seizure leaves nothing for the protocol
+        assert_eq!(seize_amounts(&config, 10, 100_000_000), (10, 10, 0));
+        let (_, seize_tokens, protocol_seize_tokens) = seize_amounts(&config, 123_456_789, 123_456_789);
+        assert!(protocol_seize_tokens <= seize_tokens);
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cosmwasm_std::{log, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, Querier, StdError, StdResult, Storage, Uint128};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::contract::digest::snapshot_digest;
+use crate::state::{
+    get_balance, get_borrow_balance, get_config, get_liquidity_snapshot_count, get_pending_liquidity_snapshot,
+    get_preserved_balances, get_preserved_borrows, get_raw_entries, get_state, remove_preserved, set_liquidity_snapshot,
+    set_snapshot_entry, LiquiditySnapshot, SnapshotSection, BALANCE_PREFIX, BORROW_PREFIX,
+    DEFAULT_LIQUIDITY_SNAPSHOT_BATCH, MAX_LIQUIDITY_SNAPSHOT_BATCH,
+};
+
+use crate::contract::handler::collateral::{accrue_interest, current_borrow_balance};
+
+/// Admin only, freezes qToken balances and debts at this block into a new snapshot, or
+/// captures the next `limit` accounts of the snapshot in progress
+pub fn try_snapshot_liquidity<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limit: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    let mut snapshot = match get_pending_liquidity_snapshot(&deps.storage)? {
+        Some(snapshot) => snapshot,
+        None => {
+            // Debts are valued at the index of the snapshot block
+            accrue_interest(deps, env.clone())?;
+            LiquiditySnapshot {
+                id: get_liquidity_snapshot_count(&deps.storage)? + 1,
+                block: env.block.height,
+                borrow_index: get_state(&deps.storage)?.borrow_index,
+                section: SnapshotSection::Balances,
+                start_after: None,
+                entries: 0,
+                digest: Binary::default(),
+            }
+        }
+    };
+
+    let limit = limit.unwrap_or(DEFAULT_LIQUIDITY_SNAPSHOT_BATCH).clamp(1, MAX_LIQUIDITY_SNAPSHOT_BATCH) as usize;
+    let mut captured = 0;
+    while captured < limit && snapshot.section != SnapshotSection::Done {
+        let batch = limit - captured;
+        let (accounts, exhausted) = next_accounts(&deps.storage, &snapshot, batch, config.dust_borrow_threshold)?;
+        for (account, amount) in accounts.iter() {
+            remove_preserved(&mut deps.storage, snapshot.section, account.as_slice());
+            if amount.is_zero() {
+                continue;
+            }
+            let address = deps.api.human_address(account)?;
+            snapshot.digest = snapshot_digest(&snapshot.digest, snapshot.section, &address, *amount);
+            set_snapshot_entry(&mut deps.storage, snapshot.id, snapshot.section, account, *amount)?;
+            snapshot.entries += 1;
+        }
+        captured += accounts.len();
+
+        match accounts.last() {
+            Some((account, _)) if !exhausted => snapshot.start_after = Some(Binary(account.as_slice().to_vec())),
+            _ => {
+                snapshot.section = match snapshot.section {
+                    SnapshotSection::Balances => SnapshotSection::Borrows,
+                    _ => SnapshotSection::Done,
+                };
+                snapshot.start_after = None;
+            }
+        }
+    }
+    set_liquidity_snapshot(&mut deps.storage, &snapshot)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "snapshot_liquidity"),
+            log("sender", env.message.sender.as_str()),
+            log("snapshot_id", snapshot.id),
+            log("snapshot_block", snapshot.block),
+            log("snapshot_captured", captured),
+            log("snapshot_entries", snapshot.entries),
+            log("snapshot_done", snapshot.section == SnapshotSection::Done),
+            log("snapshot_digest", snapshot.digest.to_base64()),
+        ],
+        data: None,
+    })
+}
+
+/// Up to `limit` accounts of the section after the snapshot's cursor, each with its balance or
+/// debt at the snapshot block, and whether the section has no accounts left after them
+/// Accounts written since the snapshot block are read from their preserved value, which also
+/// covers borrows repaid in full and removed from storage
+fn next_accounts<S: Storage>(
+    storage: &S,
+    snapshot: &LiquiditySnapshot,
+    limit: usize,
+    dust_threshold: Option<Uint128>,
+) -> StdResult<(Vec<(CanonicalAddr, Uint128)>, bool)> {
+    let start_after = snapshot.start_after.as_ref().map(|key| key.as_slice());
+    let prefix = match snapshot.section {
+        SnapshotSection::Balances => BALANCE_PREFIX,
+        SnapshotSection::Borrows => BORROW_PREFIX,
+        SnapshotSection::Done => return Ok((vec![], true)),
+    };
+    let debt = |borrow: Option<_>| match borrow {
+        Some(borrow) => Uint128::from(current_borrow_balance(&borrow, &snapshot.borrow_index, dust_threshold)),
+        None => Uint128::zero(),
+    };
+    let live = get_raw_entries(storage, prefix, start_after, limit);
+    let preserved: BTreeMap<Vec<u8>, Uint128> = match snapshot.section {
+        SnapshotSection::Balances => get_preserved_balances(storage, start_after, limit)?.into_iter().collect(),
+        _ => get_preserved_borrows(storage, start_after, limit)?
+            .into_iter()
+            .map(|(key, borrow)| (key, debt(borrow)))
+            .collect(),
+    };
+
+    let keys: BTreeSet<Vec<u8>> = live.iter().map(|(key, _)| key.clone()).chain(preserved.keys().cloned()).collect();
+    let exhausted = live.len() < limit && preserved.len() < limit && keys.len() <= limit;
+    let accounts = keys
+        .into_iter()
+        .take(limit)
+        .map(|key| {
+            let account = CanonicalAddr::from(key.as_slice());
+            let amount = match preserved.get(&key) {
+                Some(amount) => *amount,
+                None => match snapshot.section {
+                    SnapshotSection::Balances => Uint128::from(get_balance(storage, &account)?),
+                    _ => debt(get_borrow_balance(storage, &account)),
+                },
+            };
+            Ok((account, amount))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok((accounts, exhausted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::{from_binary, HumanAddr};
+
+    use crate::contract::testing::{mock_init_msg, mock_market};
+    use crate::contract::{handle, query};
+    use crate::msg::{HandleMsg, LiquiditySnapshotEntriesResponse, LiquiditySnapshotResponse, QueryMsg};
+    use crate::state::{set_balance, set_borrow_balance, BorrowSnapshot};
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    fn snapshot_liquidity(limit: Option<u32>) -> HandleMsg {
+        HandleMsg::SnapshotLiquidity { limit }
+    }
+
+    fn entries<S: Storage, A: Api, Q: Querier>(
+        deps: &Extern<S, A, Q>,
+        section: SnapshotSection,
+    ) -> Vec<(String, u128)> {
+        let res: LiquiditySnapshotEntriesResponse = from_binary(
+            &query(deps, QueryMsg::LiquiditySnapshotEntries { id: 1, section, page: None }).unwrap(),
+        )
+        .unwrap();
+        res.entries.into_iter().map(|entry| (entry.address.to_string(), entry.amount.u128())).collect()
+    }
+
+    #[test]
+    fn transfers_during_capture_leave_the_snapshot_block_balances() {
+        let mut deps = mock_market(mock_init_msg());
+        for (account, balance) in &[("alice", 100u128), ("bob", 200), ("carol", 300)] {
+            let account_raw = deps.api.canonical_address(&HumanAddr::from(*account)).unwrap();
+            set_balance(&mut deps.storage, &account_raw, *balance).unwrap();
+        }
+
+        let res = handle(&mut deps, env_at("admin", 20_000), snapshot_liquidity(Some(1))).unwrap();
+        assert!(res.log.contains(&log("snapshot_done", false)));
+        // Every account moves funds before the capture reaches the rest
+        for account in &["alice", "bob", "carol"] {
+            let transfer = HandleMsg::Transfer { recipient: HumanAddr::from("dave"), amount: Uint128::from(50u128) };
+            handle(&mut deps, env_at(account, 20_001), transfer).unwrap();
+        }
+        assert_eq!(
+            handle(&mut deps, env_at("alice", 20_002), snapshot_liquidity(None)).unwrap_err(),
+            StdError::unauthorized()
+        );
+        handle(&mut deps, env_at("admin", 20_002), snapshot_liquidity(None)).unwrap();
+
+        let snapshot: Option<LiquiditySnapshotResponse> =
+            from_binary(&query(&deps, QueryMsg::LiquiditySnapshot { id: None }).unwrap()).unwrap();
+        let snapshot = snapshot.unwrap();
+        assert_eq!((snapshot.id, snapshot.block, snapshot.entries), (1, 20_000, 3));
+        assert_eq!(snapshot.section, SnapshotSection::Done);
+
+        let mut balances = entries(&deps, SnapshotSection::Balances);
+        // The digest chains the entries in export order
+        let digest = balances.iter().fold(Binary::default(), |prior, (address, amount)| {
+            snapshot_digest(&prior, SnapshotSection::Balances, &HumanAddr::from(address.as_str()), Uint128::from(*amount))
+        });
+        assert_eq!(snapshot.digest, digest);
+        balances.sort();
+        assert_eq!(balances, vec![("alice".to_string(), 100), ("bob".to_string(), 200), ("carol".to_string(), 300)]);
+        assert_eq!(get_preserved_balances(&deps.storage, None, 10).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn borrows_repaid_during_capture_are_exported_at_the_snapshot_debt() {
+        let mut deps = mock_market(mock_init_msg());
+        let borrow_index = get_state(&deps.storage).unwrap().borrow_index;
+        let borrowers: Vec<CanonicalAddr> = ["alice", "bob"]
+            .iter()
+            .map(|account| deps.api.canonical_address(&HumanAddr::from(*account)).unwrap())
+            .collect();
+        for borrower in borrowers.iter() {
+            let borrow = BorrowSnapshot { principal: Uint128::from(100u128), interest_index: borrow_index };
+            set_borrow_balance(&mut deps.storage, borrower, Some(borrow)).unwrap();
+        }
+
+        // With no balances the first batch goes straight to the borrows
+        handle(&mut deps, env_at("admin", 20_000), snapshot_liquidity(Some(1))).unwrap();
+        for borrower in borrowers.iter() {
+            set_borrow_balance(&mut deps.storage, borrower, None).unwrap();
+        }
+        handle(&mut deps, env_at("admin", 20_001), snapshot_liquidity(None)).unwrap();
+
+        let mut borrows = entries(&deps, SnapshotSection::Borrows);
+        borrows.sort();
+        assert_eq!(borrows, vec![("alice".to_string(), 100), ("bob".to_string(), 100)]);
+        assert_eq!(get_preserved_borrows(&deps.storage, None, 10).unwrap(), vec![]);
+
+        // The next call starts a fresh snapshot of the repaid market
+        let res = handle(&mut deps, env_at("admin", 20_002), snapshot_liquidity(None)).unwrap();
+        assert!(res.log.contains(&log("snapshot_id", 2)));
+        assert!(res.log.contains(&log("snapshot_entries", 0)));
+    }
+}
@@ -0,0 +1,88 @@
+/// Storage access metering for development builds
+/// Counts storage reads and writes made by a single handler call so accidental
+/// N+1 storage patterns show up in the response logs
+use std::cell::Cell;
+
+use cosmwasm_std::{
+    log, Api, Empty, Env, Extern, HandleResponse, Order, Querier, QuerierResult, ReadonlyStorage,
+    StdResult, Storage, KV,
+};
+
+use crate::msg::HandleMsg;
+
+/// Storage wrapper counting every read and write forwarded to the inner storage
+pub struct CountingStorage<'a, S: Storage> {
+    inner: &'a mut S,
+    reads: Cell<u64>,
+    writes: u64,
+}
+
+impl<'a, S: Storage> CountingStorage<'a, S> {
+    pub fn new(inner: &'a mut S) -> Self {
+        CountingStorage {
+            inner,
+            reads: Cell::new(0),
+            writes: 0,
+        }
+    }
+}
+
+impl<'a, S: Storage> ReadonlyStorage for CountingStorage<'a, S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.reads.set(self.reads.get() + 1);
+        self.inner.get(key)
+    }
+
+    fn range<'b>(
+        &'b self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = KV> + 'b> {
+        // every yielded entry counts as one read
+        let reads = &self.reads;
+        Box::new(
+            self.inner
+                .range(start, end, order)
+                .inspect(move |_| reads.set(reads.get() + 1)),
+        )
+    }
+}
+
+impl<'a, S: Storage> Storage for CountingStorage<'a, S> {
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.writes += 1;
+        self.inner.set(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.writes += 1;
+        self.inner.remove(key)
+    }
+}
+
+/// Querier forwarding to a borrowed querier
+pub struct QuerierRef<'a, Q: Querier>(&'a Q);
+
+impl<'a, Q: Querier> Querier for QuerierRef<'a, Q> {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        self.0.raw_query(bin_request)
+    }
+}
+
+/// Runs the handler against counting storage and appends the counts to the response logs
+pub fn handle_with_metrics<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: HandleMsg,
+) -> StdResult<HandleResponse<Empty>> {
+    let mut metered = Extern {
+        storage: CountingStorage::new(&mut deps.storage),
+        api: deps.api,
+        querier: QuerierRef(&deps.querier),
+    };
+    let mut res = super::dispatch(&mut metered, env, msg)?;
+    res.log.push(log("storage_reads", metered.storage.reads.get()));
+    res.log.push(log("storage_writes", metered.storage.writes));
+    Ok(res)
+}
@@ -1,11 +1,47 @@
-use cosmwasm_std::{Api, Empty, Env, Extern, HandleResponse, Querier, StdResult, Storage};
+use cosmwasm_std::{from_binary, Api, Empty, Env, Extern, HandleResponse, Querier, StdError, StdResult, Storage, Uint128};
+use cw20::Cw20ReceiveMsg;
 
-use crate::msg::HandleMsg;
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::asset::AssetInfoRaw;
 
-mod collateral;
+use crate::contract::digest::update_state_digest;
+use crate::msg::{Cw20HookMsg, HandleMsg};
+use crate::state::{get_config, get_global_pause};
+
+pub(crate) mod admin;
+pub(crate) mod audit;
+pub(crate) mod collateral;
+mod comptroller;
+pub(crate) mod liquidation;
+pub(crate) mod backstop;
+mod reserves;
 mod token;
-mod exponential;
-mod interest_model;
+pub(crate) mod transfer_policy;
+mod term_deposit;
+mod otc;
+mod self_repay;
+mod yield_redirect;
+mod yield_checkpoint;
+pub(crate) mod exponential;
+mod funds;
+mod integrator;
+mod balance_hook;
+pub(crate) mod compaction;
+pub(crate) mod buyback;
+pub(crate) mod cash_strategy;
+mod flash_loan;
+mod genesis;
+pub(crate) mod health_alert;
+pub(crate) mod utilization_alert;
+pub(crate) mod insurance;
+pub(crate) mod settlement;
+mod swap_repay;
+mod roles;
+mod liquidity_snapshot;
+pub(crate) mod interest_deferral;
+pub(crate) mod interest_model;
+#[cfg(all(feature = "storage-metrics", debug_assertions))]
+mod metrics;
 
 /// General handler for contract tx input
 /// tx inputs are defined HandleMsg enum in msg.rs file
@@ -14,8 +50,178 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse<Empty>> {
+    let block_number = env.block.height;
+
+    #[cfg(all(feature = "storage-metrics", debug_assertions))]
+    let res = metrics::handle_with_metrics(deps, env, msg)?;
+
+    #[cfg(not(all(feature = "storage-metrics", debug_assertions)))]
+    let res = dispatch(deps, env, msg)?;
+
+    update_state_digest(&mut deps.storage, block_number)?;
+    Ok(res)
+}
+
+fn dispatch<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: HandleMsg,
+) -> StdResult<HandleResponse<Empty>> {
+    // Payable handlers validate attached funds themselves
+    match msg {
+        HandleMsg::Mint {}
+        | HandleMsg::MintTo { .. }
+        | HandleMsg::RepayBorrow {}
+        | HandleMsg::RepayWithOtherAsset { .. }
+        | HandleMsg::Liquidate { .. }
+        | HandleMsg::BuyOtc { .. }
+        | HandleMsg::AddReserves {} => {}
+        _ => funds::nonpayable(&env)?,
+    }
+
+    // While the comptroller has the protocol paused only repayments and
+    // bookkeeping that moves no value go through
+    match msg {
+        HandleMsg::RepayBorrow {}
+        | HandleMsg::RepayWithOtherAsset { .. }
+        | HandleMsg::FinishRepayWithOtherAsset {}
+        | HandleMsg::SetSwapPair { .. }
+        | HandleMsg::SetIntegrator { .. }
+        | HandleMsg::SyncGlobalPause { .. }
+        | HandleMsg::UpdateInterestRateModel { .. }
+        | HandleMsg::SetShadowInterestRateModel { .. }
+        | HandleMsg::ProposeInterestRateModelBounds { .. }
+        | HandleMsg::ApplyInterestRateModelBounds {}
+        | HandleMsg::SetReserveFactor { .. }
+        | HandleMsg::SetProtocolSeizeShare { .. }
+        | HandleMsg::TransferOwnership { .. }
+        | HandleMsg::AcceptOwnership {}
+        | HandleMsg::SetGuardian { .. }
+        | HandleMsg::SetPaused { .. }
+        | HandleMsg::GrantRole { .. }
+        | HandleMsg::RevokeRole { .. }
+        | HandleMsg::SetOracle { .. }
+        | HandleMsg::SetBalanceHook { .. }
+        | HandleMsg::SetTransferPolicy { .. }
+        | HandleMsg::SetCashStrategy { .. }
+        | HandleMsg::SetFlashLoanFee { .. }
+        | HandleMsg::FinishFlashLoan {}
+        | HandleMsg::BeginGenesisImport {}
+        | HandleMsg::ImportGenesis { .. }
+        | HandleMsg::FinishGenesisImport { .. }
+        | HandleMsg::CompactStorage { .. }
+        | HandleMsg::SnapshotLiquidity { .. }
+        | HandleMsg::AddReserves {}
+        | HandleMsg::Approve { .. }
+        | HandleMsg::IncreaseAllowance { .. }
+        | HandleMsg::DecreaseAllowance { .. }
+        | HandleMsg::AnnounceRedeem { .. }
+        | HandleMsg::EnableSelfRepay {}
+        | HandleMsg::DisableSelfRepay {}
+        | HandleMsg::EnableYieldRedirect { .. }
+        | HandleMsg::DisableYieldRedirect {}
+        | HandleMsg::SetHealthAlerts { .. }
+        | HandleMsg::SetUtilizationAlerts { .. }
+        | HandleMsg::SetInsuranceConfig { .. }
+        | HandleMsg::SetInsured { .. }
+        | HandleMsg::SettlePremium { .. }
+        | HandleMsg::GrantInterestDeferral { .. }
+        | HandleMsg::RevokeInterestDeferral { .. }
+        | HandleMsg::SettleInterestDeferral { .. }
+        | HandleMsg::CheckpointPrice {}
+        | HandleMsg::Intent { .. }
+        | HandleMsg::SetBlocksPerYear { .. }
+        | HandleMsg::SetBorrowCooldown { .. }
+        | HandleMsg::SetMinBorrowHealthFactor { .. }
+        | HandleMsg::SetBackstop { .. }
+        | HandleMsg::SetBuyback { .. }
+        | HandleMsg::FinishBuyback {}
+        | HandleMsg::EnterSettlement {} => {}
+        // Checked against the hook message once it is decoded
+        HandleMsg::Receive(_) => {}
+        _ => assert_not_paused(&deps.storage)?,
+    }
+
+    // An imported market only opens once the whole export has been applied
+    match msg {
+        HandleMsg::BeginGenesisImport {}
+        | HandleMsg::ImportGenesis { .. }
+        | HandleMsg::FinishGenesisImport { .. } => {}
+        _ => genesis::assert_no_genesis_import(&deps.storage)?,
+    }
+
+    // A settled market only takes repayments, claims, term deposit unlocks and bookkeeping that moves no value
+    match msg {
+        HandleMsg::RepayBorrow {}
+        | HandleMsg::RepayWithOtherAsset { .. }
+        | HandleMsg::FinishRepayWithOtherAsset {}
+        | HandleMsg::ClaimSettlement {}
+        | HandleMsg::UnlockDeposit { .. }
+        | HandleMsg::CancelOtc { .. }
+        | HandleMsg::SetSwapPair { .. }
+        | HandleMsg::SetIntegrator { .. }
+        | HandleMsg::SyncGlobalPause { .. }
+        | HandleMsg::TransferOwnership { .. }
+        | HandleMsg::AcceptOwnership {}
+        | HandleMsg::SetGuardian { .. }
+        | HandleMsg::SetPaused { .. }
+        | HandleMsg::GrantRole { .. }
+        | HandleMsg::RevokeRole { .. }
+        | HandleMsg::SetBalanceHook { .. }
+        | HandleMsg::SetTransferPolicy { .. }
+        | HandleMsg::SetCashStrategy { .. }
+        | HandleMsg::Rebalance {}
+        | HandleMsg::Approve { .. }
+        | HandleMsg::IncreaseAllowance { .. }
+        | HandleMsg::DecreaseAllowance { .. }
+        | HandleMsg::SetHealthAlerts { .. }
+        | HandleMsg::SetUtilizationAlerts { .. }
+        | HandleMsg::SetBlocksPerYear { .. }
+        | HandleMsg::SetBorrowCooldown { .. }
+        | HandleMsg::SetMinBorrowHealthFactor { .. }
+        | HandleMsg::CompactStorage { .. }
+        | HandleMsg::SnapshotLiquidity { .. } => {}
+        // Checked against the hook message once it is decoded
+        HandleMsg::Receive(_) => {}
+        _ => settlement::assert_not_settled(&deps.storage)?,
+    }
+
+    // Loaned cash may only move again once FinishFlashLoan has checked repayment
+    match msg {
+        HandleMsg::Mint {}
+        | HandleMsg::MintTo { .. }
+        | HandleMsg::Redeem { .. }
+        | HandleMsg::RedeemUnderlying { .. }
+        | HandleMsg::Borrow { .. }
+        | HandleMsg::RepayBorrow {}
+        | HandleMsg::RepayWithOtherAsset { .. }
+        | HandleMsg::FinishRepayWithOtherAsset {}
+        | HandleMsg::Liquidate { .. }
+        | HandleMsg::SeizeCollateral { .. }
+        | HandleMsg::BackstopLiquidate { .. }
+        | HandleMsg::Skim {}
+        | HandleMsg::AddReserves {}
+        | HandleMsg::ReduceReserves { .. }
+        | HandleMsg::UnlockDeposit { .. }
+        | HandleMsg::FlashLoan { .. }
+        | HandleMsg::Rebalance {}
+        | HandleMsg::EnterSettlement {}
+        | HandleMsg::ClaimSettlement {}
+        | HandleMsg::Buyback { .. }
+        | HandleMsg::FinishBuyback {}
+        | HandleMsg::BuyOtc { .. }
+        | HandleMsg::Receive(_) => flash_loan::assert_no_flash_loan(&deps.storage)?,
+        _ => {}
+    }
+
     match msg {
         HandleMsg::Approve { spender, amount } => token::try_approve(deps, env, &spender, &amount),
+        HandleMsg::IncreaseAllowance { spender, amount, expires } => {
+            token::try_increase_allowance(deps, env, &spender, &amount, expires)
+        }
+        HandleMsg::DecreaseAllowance { spender, amount, expires } => {
+            token::try_decrease_allowance(deps, env, &spender, &amount, expires)
+        }
         HandleMsg::Transfer { recipient, amount } => {
             token::try_transfer(deps, env, &recipient, &amount)
         }
@@ -24,9 +230,357 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             recipient,
             amount,
         } => token::try_transfer_from(deps, env, &owner, &recipient, &amount),
-        HandleMsg::Mint {} => collateral::try_mint(deps, env),
-        HandleMsg::Redeem {redeem_tokens_in} => collateral::try_redeem(deps, env, redeem_tokens_in),
-        HandleMsg::RepayBorrow {} => collateral::try_repay_borrow(deps, env),
-        HandleMsg::Borrow{ borrow_amount } => collateral::try_borrow(deps, env, borrow_amount)
+        HandleMsg::Send { contract, amount, msg } => token::try_send(deps, env, &contract, &amount, msg),
+        HandleMsg::SendFrom { owner, contract, amount, msg } => {
+            token::try_send_from(deps, env, &owner, &contract, &amount, msg)
+        }
+        HandleMsg::Burn { amount } => token::try_burn(deps, env, &amount),
+        HandleMsg::BurnFrom { owner, amount } => token::try_burn_from(deps, env, &owner, &amount),
+        HandleMsg::Receive(cw20_msg) => receive_cw20(deps, env, cw20_msg),
+        HandleMsg::Mint {} => {
+            let amount = funds::must_pay(&env, &get_config(&deps.storage)?.underlying)?;
+            let recipient = env.message.sender.clone();
+            collateral::try_mint(deps, env, &recipient, amount)
+        }
+        HandleMsg::MintTo { recipient } => {
+            let amount = funds::must_pay(&env, &get_config(&deps.storage)?.underlying)?;
+            collateral::try_mint(deps, env, &recipient, amount)
+        }
+        HandleMsg::Redeem {redeem_tokens_in} => collateral::try_redeem(deps, env, redeem_tokens_in, Uint128::zero()),
+        HandleMsg::RedeemUnderlying {redeem_amount} => collateral::try_redeem(deps, env, Uint128::zero(), redeem_amount),
+        HandleMsg::UpdateInterestRateModel { model } => admin::try_update_interest_rate_model(deps, env, model),
+        HandleMsg::SetShadowInterestRateModel { model } => admin::try_set_shadow_interest_rate_model(deps, env, model),
+        HandleMsg::ProposeInterestRateModelBounds { bounds } => {
+            admin::try_propose_interest_rate_model_bounds(deps, env, bounds)
+        }
+        HandleMsg::ApplyInterestRateModelBounds {} => admin::try_apply_interest_rate_model_bounds(deps, env),
+        HandleMsg::SyncGlobalPause { paused, epoch } => admin::try_sync_global_pause(deps, env, paused, epoch),
+        HandleMsg::Skim {} => collateral::try_skim(deps, env),
+        HandleMsg::AddReserves {} => {
+            let amount = funds::must_pay(&env, &get_config(&deps.storage)?.underlying)?;
+            reserves::try_add_reserves(deps, env, amount)
+        }
+        HandleMsg::ReduceReserves { amount } => reserves::try_reduce_reserves(deps, env, amount),
+        HandleMsg::SetReserveFactor { reserve_factor, ramp_blocks } => {
+            reserves::try_set_reserve_factor(deps, env, reserve_factor, ramp_blocks)
+        }
+        HandleMsg::SetProtocolSeizeShare { protocol_seize_share } => {
+            liquidation::try_set_protocol_seize_share(deps, env, protocol_seize_share)
+        }
+        HandleMsg::TransferOwnership { new_owner } => admin::try_transfer_ownership(deps, env, &new_owner),
+        HandleMsg::AcceptOwnership {} => admin::try_accept_ownership(deps, env),
+        HandleMsg::SetGuardian { guardian } => admin::try_set_guardian(deps, env, guardian),
+        HandleMsg::SetPaused { action, paused } => admin::try_set_paused(deps, env, action, paused),
+        HandleMsg::GrantRole { role, account } => roles::try_grant_role(deps, env, role, account),
+        HandleMsg::RevokeRole { role, account } => roles::try_revoke_role(deps, env, role, account),
+        HandleMsg::SetOracle { oracle } => admin::try_set_oracle(deps, env, oracle),
+        HandleMsg::SetCashStrategy { cash_strategy } => cash_strategy::try_set_cash_strategy(deps, env, cash_strategy),
+        HandleMsg::Rebalance {} => cash_strategy::try_rebalance(deps, env),
+        HandleMsg::SetBalanceHook { balance_hook } => admin::try_set_balance_hook(deps, env, balance_hook),
+        HandleMsg::SetTransferPolicy { contract } => transfer_policy::try_set_transfer_policy(deps, env, contract),
+        HandleMsg::SetFlashLoanFee { flash_loan_fee } => flash_loan::try_set_flash_loan_fee(deps, env, flash_loan_fee),
+        HandleMsg::FlashLoan { amount, msg, recipient } => flash_loan::try_flash_loan(deps, env, amount, msg, &recipient),
+        HandleMsg::FinishFlashLoan {} => flash_loan::try_finish_flash_loan(deps, env),
+        HandleMsg::CompactStorage { limit } => compaction::try_compact_storage(deps, env, limit),
+        HandleMsg::SnapshotLiquidity { limit } => liquidity_snapshot::try_snapshot_liquidity(deps, env, limit),
+        HandleMsg::BeginGenesisImport {} => genesis::try_begin_genesis_import(deps, env),
+        HandleMsg::ImportGenesis { chunk } => genesis::try_import_genesis(deps, env, chunk),
+        HandleMsg::FinishGenesisImport { digest } => genesis::try_finish_genesis_import(deps, env, digest),
+        HandleMsg::AnnounceRedeem { amount } => collateral::try_announce_redeem(deps, env, amount),
+        HandleMsg::RepayBorrow {} => {
+            let amount = funds::must_pay(&env, &get_config(&deps.storage)?.underlying)?;
+            collateral::try_repay_borrow(deps, env, amount)
+        }
+        HandleMsg::RepayWithOtherAsset { offer_denom, max_offer } => {
+            swap_repay::try_repay_with_other_asset(deps, env, offer_denom, max_offer)
+        }
+        HandleMsg::FinishRepayWithOtherAsset {} => swap_repay::try_finish_repay_with_other_asset(deps, env),
+        HandleMsg::SetSwapPair { offer_denom, pair } => swap_repay::try_set_swap_pair(deps, env, offer_denom, pair),
+        HandleMsg::Liquidate { borrower, repay_amount, collateral_market } => {
+            let amount = funds::must_pay(&env, &get_config(&deps.storage)?.underlying)?;
+            liquidation::try_liquidate(deps, env, &borrower, repay_amount, amount, collateral_market)
+        }
+        HandleMsg::SeizeCollateral { borrower, liquidator, repay_value } => {
+            liquidation::try_seize_collateral(deps, env, &borrower, &liquidator, repay_value)
+        }
+        HandleMsg::FlagShortfall { account } => liquidation::try_flag_shortfall(deps, env, &account),
+        HandleMsg::BackstopLiquidate { borrower } => backstop::try_backstop_liquidate(deps, env, &borrower),
+        HandleMsg::SetBackstop { backstop } => backstop::try_set_backstop(deps, env, backstop),
+        HandleMsg::CloseDustBorrow { account } => collateral::try_close_dust_borrow(deps, env, &account),
+        HandleMsg::Borrow { borrow_amount, integrator_fee } => {
+            collateral::try_borrow(deps, env, borrow_amount, integrator_fee)
+        }
+        HandleMsg::SetIntegrator { integrator, max_fee_bps } => {
+            integrator::try_set_integrator(deps, env, integrator, max_fee_bps)
+        }
+        HandleMsg::LockDeposit { amount, term } => term_deposit::try_lock_deposit(deps, env, amount, term),
+        HandleMsg::UnlockDeposit { id } => term_deposit::try_unlock_deposit(deps, env, id),
+        HandleMsg::ListOtc { amount, price, expires } => otc::try_list_otc(deps, env, amount, price, expires),
+        HandleMsg::BuyOtc { id } => {
+            let amount = funds::must_pay(&env, &get_config(&deps.storage)?.underlying)?;
+            otc::try_buy_otc(deps, env, id, amount)
+        }
+        HandleMsg::CancelOtc { id } => otc::try_cancel_otc(deps, env, id),
+        HandleMsg::EnableSelfRepay {} => self_repay::try_enable_self_repay(deps, env),
+        HandleMsg::DisableSelfRepay {} => self_repay::try_disable_self_repay(deps, env),
+        HandleMsg::SettleSelfRepay { account } => self_repay::try_settle_self_repay(deps, env, &account),
+        HandleMsg::EnableYieldRedirect { beneficiary } => {
+            yield_redirect::try_enable_yield_redirect(deps, env, &beneficiary)
+        }
+        HandleMsg::DisableYieldRedirect {} => yield_redirect::try_disable_yield_redirect(deps, env),
+        HandleMsg::SettleYieldRedirect { account } => yield_redirect::try_settle_yield_redirect(deps, env, &account),
+        HandleMsg::SetHealthAlerts { thresholds } => health_alert::try_set_health_alerts(deps, env, thresholds),
+        HandleMsg::SetUtilizationAlerts { thresholds } => {
+            utilization_alert::try_set_utilization_alerts(deps, env, thresholds)
+        }
+        HandleMsg::SetInsuranceConfig { insurance } => insurance::try_set_insurance_config(deps, env, insurance),
+        HandleMsg::SetInsured { insured } => insurance::try_set_insured(deps, env, insured),
+        HandleMsg::SettlePremium { account } => insurance::try_settle_premium(deps, env, &account),
+        HandleMsg::CheckpointPrice {} => insurance::try_checkpoint_price(deps, env),
+        HandleMsg::GrantInterestDeferral { account, discount, expires } => {
+            interest_deferral::try_grant_interest_deferral(deps, env, account, discount, expires)
+        }
+        HandleMsg::RevokeInterestDeferral { account } => {
+            interest_deferral::try_revoke_interest_deferral(deps, env, account)
+        }
+        HandleMsg::SettleInterestDeferral { account } => {
+            interest_deferral::try_settle_interest_deferral(deps, env, &account)
+        }
+        HandleMsg::Intent { action, amount } => admin::try_record_intent(deps, env, action, amount),
+        HandleMsg::SetBuyback { buyback } => buyback::try_set_buyback(deps, env, buyback),
+        HandleMsg::Buyback { amount, min_receive } => buyback::try_buyback(deps, env, amount, min_receive),
+        HandleMsg::FinishBuyback {} => buyback::try_finish_buyback(deps, env),
+        HandleMsg::SetMinBorrowHealthFactor { min_borrow_health_factor } => {
+            admin::try_set_min_borrow_health_factor(deps, env, min_borrow_health_factor)
+        }
+        HandleMsg::SetBorrowCooldown { blocks } => admin::try_set_borrow_cooldown(deps, env, blocks),
+        HandleMsg::SetBlocksPerYear { blocks_per_year } => admin::try_set_blocks_per_year(deps, env, blocks_per_year),
+        HandleMsg::EnterSettlement {} => settlement::try_enter_settlement(deps, env),
+        HandleMsg::ClaimSettlement {} => settlement::try_claim_settlement(deps, env)
+    }
+}
+
+/// Runs a payable message for CW20 underlying sent by `cw20_msg.sender`
+fn receive_cw20<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    cw20_msg: Cw20ReceiveMsg,
+) -> StdResult<HandleResponse<Empty>> {
+    let token_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    match get_config(&deps.storage)?.underlying {
+        AssetInfoRaw::Token { contract_addr } if contract_addr == token_raw => {}
+        _ => {
+            return Err(StdError::generic_err(format!(
+                "Only the underlying token can be received: token: {}",
+                env.message.sender
+            )));
+        }
+    }
+    if cw20_msg.amount.is_zero() {
+        return Err(StdError::generic_err("Sent amount must be positive"));
+    }
+    let hook_msg: Cw20HookMsg = match cw20_msg.msg {
+        Some(msg) => from_binary(&msg)?,
+        None => return Err(StdError::generic_err("Receive requires a Cw20HookMsg")),
+    };
+    match hook_msg {
+        Cw20HookMsg::RepayBorrow {} | Cw20HookMsg::AddReserves {} => {}
+        _ => assert_not_paused(&deps.storage)?,
+    }
+    match hook_msg {
+        Cw20HookMsg::RepayBorrow {} => {}
+        _ => settlement::assert_not_settled(&deps.storage)?,
+    }
+
+    // Handlers see the token holder as the sender, as with native funds
+    let mut env = env;
+    env.message.sender = cw20_msg.sender;
+    let amount = cw20_msg.amount;
+    match hook_msg {
+        Cw20HookMsg::Mint {} => {
+            let recipient = env.message.sender.clone();
+            collateral::try_mint(deps, env, &recipient, amount)
+        }
+        Cw20HookMsg::MintTo { recipient } => collateral::try_mint(deps, env, &recipient, amount),
+        Cw20HookMsg::RepayBorrow {} => collateral::try_repay_borrow(deps, env, amount),
+        Cw20HookMsg::Liquidate { borrower, repay_amount, collateral_market } => {
+            liquidation::try_liquidate(deps, env, &borrower, repay_amount, amount, collateral_market)
+        }
+        Cw20HookMsg::AddReserves {} => reserves::try_add_reserves(deps, env, amount),
+        Cw20HookMsg::BuyOtc { id } => otc::try_buy_otc(deps, env, id, amount),
+    }
+}
+
+fn assert_not_paused<S: Storage>(storage: &S) -> StdResult<()> {
+    if get_global_pause(storage)?.paused {
+        return Err(StdError::generic_err("Market is paused by the comptroller"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, HumanAddr, Uint128};
+
+    use quasar_model::{Market, Params, RateModel};
+
+    use crate::contract::handler::exponential::{mul_div, to_decimal, SCALE};
+    use crate::contract::testing::{mock_init_msg, mock_market, snapshot_storage, Rng, MOCK_DENOM};
+    use crate::state::{get_accrual_remainder, get_balance, get_borrow_balance, get_state, InterestRateModel};
+
+    const ACCOUNTS: [&str; 4] = ["alice", "bob", "carol", "dave"];
+
+    #[derive(Clone, Copy, Debug)]
+    enum Action {
+        Mint(u128),
+        Redeem(u128),
+        RedeemUnderlying(u128),
+        Borrow(u128),
+        Repay(u128),
+    }
+
+    /// The same standalone market as a contract and as a model
+    /// Rates are steep enough for debt to outgrow collateral within a run
+    fn markets() -> (Extern<MockStorage, MockApi, MockQuerier>, Market) {
+        let mut msg = mock_init_msg();
+        msg.interest_rate_model = Some(InterestRateModel::JumpRate {
+            base_rate_per_block: Uint128::from(20u128),
+            multiplier_per_block: Uint128::from(2_000u128),
+            jump_multiplier_per_block: Uint128::from(15_000u128),
+            kink: Uint128::from(80_000_000u128),
+            min_borrow_rate_per_block: Some(Uint128::from(5u128)),
+        });
+        let params = Params {
+            initial_exchange_rate: msg.initial_exchange_rate.u128(),
+            reserve_factor: msg.reserve_factor.u128(),
+            collateral_factor: msg.collateral_factor.u128(),
+            max_borrow_rate: msg.max_borrow_rate.u128(),
+            rate_model: RateModel::JumpRate {
+                base_rate_per_block: 20,
+                multiplier_per_block: 2_000,
+                jump_multiplier_per_block: 15_000,
+                kink: 80_000_000,
+                min_borrow_rate_per_block: 5,
+            },
+        };
+        let model = Market::new(params, msg.borrow_index.u128(), mock_env("admin", &[]).block.height);
+        (mock_market(msg), model)
+    }
+
+    /// Mostly amounts around `near`, where the edge cases are
+    fn amount(rng: &mut Rng, near: u128) -> u128 {
+        match rng.below(4) {
+            0 => 0,
+            1 => near,
+            2 => near + 1,
+            _ => rng.next() as u128 % (near * 2 + 1),
+        }
+    }
+
+    fn random_action(rng: &mut Rng, model: &Market, account: &str) -> Action {
+        let exchange_rate = model.exchange_rate().unwrap_or(0);
+        let borrow_room = model
+            .borrow_limit(model.balance_of(account), exchange_rate)
+            .saturating_sub(model.borrow_balance_of(account));
+        let balance_value = mul_div(model.balance_of(account), exchange_rate, SCALE);
+        match rng.below(5) {
+            0 => Action::Mint(amount(rng, 1_000_000)),
+            1 => Action::Redeem(amount(rng, model.balance_of(account))),
+            2 => Action::RedeemUnderlying(amount(rng, balance_value)),
+            3 => Action::Borrow(amount(rng, borrow_room)),
+            _ => Action::Repay(amount(rng, model.borrow_balance_of(account))),
+        }
+    }
+
+    fn handle_action(deps: &mut Extern<MockStorage, MockApi, MockQuerier>, account: &str, action: Action, height: u64) -> StdResult<HandleResponse> {
+        let (msg, funds) = match action {
+            Action::Mint(amount) => (HandleMsg::Mint {}, amount),
+            Action::Redeem(tokens) => (HandleMsg::Redeem { redeem_tokens_in: Uint128::from(tokens) }, 0),
+            Action::RedeemUnderlying(amount) => (HandleMsg::RedeemUnderlying { redeem_amount: Uint128::from(amount) }, 0),
+            Action::Borrow(amount) => (HandleMsg::Borrow { borrow_amount: Uint128::from(amount), integrator_fee: None }, 0),
+            Action::Repay(amount) => (HandleMsg::RepayBorrow {}, amount),
+        };
+        let sent_funds = match funds {
+            0 => vec![],
+            funds => coins(funds, MOCK_DENOM),
+        };
+        let mut env = mock_env(account, &sent_funds);
+        env.block.height = height;
+        handle(deps, env, msg)
+    }
+
+    fn assert_matches_model(deps: &Extern<MockStorage, MockApi, MockQuerier>, model: &Market, context: &str) {
+        let state = get_state(&deps.storage).unwrap();
+        assert_eq!(state.block_number, model.block, "block: {}", context);
+        assert_eq!(state.cash.u128(), model.cash, "cash: {}", context);
+        assert_eq!(state.total_borrows.u128(), model.total_borrows, "total_borrows: {}", context);
+        assert_eq!(state.total_reserves.u128(), model.total_reserves, "total_reserves: {}", context);
+        assert_eq!(state.borrow_index.u128(), model.borrow_index, "borrow_index: {}", context);
+        assert_eq!(get_config(&deps.storage).unwrap().total_supply.u128(), model.total_supply, "total_supply: {}", context);
+
+        let remainder = get_accrual_remainder(&deps.storage).unwrap();
+        assert_eq!(remainder.interest, to_decimal(model.interest_remainder), "interest remainder: {}", context);
+        assert_eq!(remainder.reserves, to_decimal(model.reserves_remainder), "reserves remainder: {}", context);
+        assert_eq!(remainder.borrow_index, to_decimal(model.index_remainder), "index remainder: {}", context);
+
+        for account in ACCOUNTS.iter() {
+            let account_raw = deps.api.canonical_address(&HumanAddr::from(*account)).unwrap();
+            assert_eq!(get_balance(&deps.storage, &account_raw).unwrap(), model.balance_of(account), "balance of {}: {}", account, context);
+            let snapshot = get_borrow_balance(&deps.storage, &account_raw).map(|s| (s.principal.u128(), s.interest_index.u128()));
+            let expected = model.borrows.get(*account).map(|s| (s.principal, s.interest_index));
+            assert_eq!(snapshot, expected, "borrow of {}: {}", account, context);
+        }
+    }
+
+    #[test]
+    fn random_market_actions_match_the_model() {
+        // Successes and rejections seen per action, so a run that never reaches a branch fails
+        let mut outcomes = [[0u32; 2]; 5];
+
+        for seed in 1..=16u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let (mut deps, mut model) = markets();
+            let mut height = model.block;
+
+            for step in 0..200 {
+                height += rng.below(500) as u64;
+                let account = ACCOUNTS[rng.below(ACCOUNTS.len())];
+                let action = random_action(&mut rng, &model, account);
+
+                let expected = match action {
+                    Action::Mint(amount) => model.mint(account, amount, height).map(|_| ()),
+                    Action::Redeem(tokens) => model.redeem(account, tokens, height).map(|_| ()),
+                    Action::RedeemUnderlying(amount) => model.redeem_underlying(account, amount, height).map(|_| ()),
+                    Action::Borrow(amount) => model.borrow(account, amount, height),
+                    Action::Repay(amount) => model.repay(account, amount, height).map(|_| ()),
+                };
+                let before = snapshot_storage(&deps.storage);
+                let handled = handle_action(&mut deps, account, action, height);
+                if handled.is_err() {
+                    deps.storage = before;
+                }
+
+                let context = format!("seed {} step {}: {} {:?} at {}", seed, step, account, action, height);
+                assert_eq!(handled.is_ok(), expected.is_ok(), "{}: contract {:?}, model {:?}", context, handled.err(), expected);
+                assert_matches_model(&deps, &model, &context);
+
+                let kind = match action {
+                    Action::Mint(_) => 0,
+                    Action::Redeem(_) => 1,
+                    Action::RedeemUnderlying(_) => 2,
+                    Action::Borrow(_) => 3,
+                    Action::Repay(_) => 4,
+                };
+                outcomes[kind][expected.is_err() as usize] += 1;
+                // The bank balance follows cash, so payouts find the funds they need
+                deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(model.cash, MOCK_DENOM));
+            }
+        }
+
+        for (kind, [succeeded, rejected]) in outcomes.iter().enumerate() {
+            assert!(*succeeded > 0 && *rejected > 0, "action {}: {} succeeded, {} rejected", kind, succeeded, rejected);
+        }
     }
 }
@@ -0,0 +1,249 @@
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, Querier, StdError, StdResult, Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::comptroller::MarketAction;
+
+use crate::state::{
+    get_config, get_otc_offer, next_otc_offer_id, remove_otc_offer, set_otc_offer, OtcOffer, PauseAction,
+    MAX_OTC_DURATION,
+};
+
+use crate::contract::handler::admin::assert_action_not_paused;
+use crate::contract::handler::balance_hook::balance_hook;
+use crate::contract::handler::collateral::{assert_account_healthy, assert_borrow_cooled_down};
+use crate::contract::handler::comptroller::comptroller_hook;
+use crate::contract::handler::funds::transfer_underlying;
+use crate::contract::handler::health_alert::health_alert_log;
+use crate::contract::handler::liquidation::assert_not_flagged;
+use crate::contract::handler::token::perform_transfer;
+use crate::contract::handler::transfer_policy::assert_transfer_allowed;
+
+pub fn try_list_otc<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+    price: Uint128,
+    expires: u64,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    if amount.is_zero() || price.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "OTC amount and price must be positive: amount: {}, price: {}",
+            amount, price
+        )));
+    }
+    let max_expires = env.block.height + MAX_OTC_DURATION;
+    if expires <= env.block.height || expires > max_expires {
+        return Err(StdError::generic_err(format!(
+            "OTC expiry must be within the next thirty days: expires: {}, current_block: {}, max_expires: {}",
+            expires, env.block.height, max_expires
+        )));
+    }
+
+    // Listed qTokens are held by the market itself until bought or cancelled
+    let seller_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let contract_raw = canonicalize_address(&deps.api, &env.contract.address)?;
+    assert_not_flagged(&deps.storage, &seller_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &seller_raw, &env)?;
+    perform_transfer(&mut deps.storage, &seller_raw, &contract_raw, amount.u128())?;
+    // Escrowed qTokens no longer count as the seller's collateral
+    assert_account_healthy(&deps.storage, &seller_raw)?;
+
+    let offer = OtcOffer {
+        id: next_otc_offer_id(&mut deps.storage)?,
+        seller: seller_raw,
+        amount,
+        price,
+        expires,
+    };
+    set_otc_offer(&mut deps.storage, &offer)?;
+
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&offer.seller, &contract_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, &env.message.sender, amount.u128())?);
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "list_otc"),
+            log("sender", env.message.sender.as_str()),
+            log("offer_id", offer.id),
+            log("amount", amount),
+            log("price", price),
+            log("expires", expires),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&offer.seller])?);
+    Ok(res)
+}
+
+/// The payment goes straight on to the seller and never counts as market cash
+pub fn try_buy_otc<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+    sent: Uint128,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    let offer = load_otc_offer(&deps.storage, id)?;
+    if env.block.height >= offer.expires {
+        return Err(StdError::generic_err(format!(
+            "OTC offer has expired: offer_id: {}, expires: {}",
+            id, offer.expires
+        )));
+    }
+    let buyer_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if buyer_raw == offer.seller {
+        return Err(StdError::generic_err("Sellers cannot buy their own offer"));
+    }
+    if sent != offer.price {
+        return Err(StdError::generic_err(format!(
+            "Sent funds must match the offer price: price: {}, sent: {}",
+            offer.price, sent
+        )));
+    }
+
+    let seller = deps.api.human_address(&offer.seller)?;
+    let contract_raw = canonicalize_address(&deps.api, &env.contract.address)?;
+    assert_transfer_allowed(deps, Some(&seller), &env.message.sender, offer.amount)?;
+    perform_transfer(&mut deps.storage, &contract_raw, &buyer_raw, offer.amount.u128())?;
+    remove_otc_offer(&mut deps.storage, id);
+
+    let config = get_config(&deps.storage)?;
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&contract_raw, &buyer_raw])?;
+    messages.push(transfer_underlying(&deps.api, &config.underlying, &env.contract.address, &seller, offer.price)?);
+
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "buy_otc"),
+            log("sender", env.message.sender.as_str()),
+            log("offer_id", id),
+            log("seller", seller.as_str()),
+            log("amount", offer.amount),
+            log("price", offer.price),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&buyer_raw])?);
+    Ok(res)
+}
+
+pub fn try_cancel_otc<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let offer = load_otc_offer(&deps.storage, id)?;
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if sender_raw != offer.seller && env.block.height < offer.expires {
+        return Err(StdError::unauthorized());
+    }
+
+    let contract_raw = canonicalize_address(&deps.api, &env.contract.address)?;
+    perform_transfer(&mut deps.storage, &contract_raw, &offer.seller, offer.amount.u128())?;
+    remove_otc_offer(&mut deps.storage, id);
+
+    let mut res = HandleResponse {
+        messages: balance_hook(&deps.storage, &deps.api, &[&contract_raw, &offer.seller])?,
+        log: vec![
+            log("action", "cancel_otc"),
+            log("sender", env.message.sender.as_str()),
+            log("offer_id", id),
+            log("seller", deps.api.human_address(&offer.seller)?.as_str()),
+            log("amount", offer.amount),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&offer.seller])?);
+    Ok(res)
+}
+
+fn load_otc_offer<S: Storage>(storage: &S, id: u64) -> StdResult<OtcOffer> {
+    match get_otc_offer(storage, id)? {
+        Some(offer) => Ok(offer),
+        None => Err(StdError::generic_err(format!("OTC offer not found: offer_id: {}", id))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, BankMsg, Coin, CosmosMsg, HumanAddr};
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market, MOCK_DENOM};
+    use crate::msg::HandleMsg;
+    use crate::state::{get_balance, get_state};
+
+    fn env_at(sender: &str, height: u64, funds: &[Coin]) -> Env {
+        let mut env = mock_env(sender, funds);
+        env.block.height = height;
+        env
+    }
+
+    fn balance(deps: &Extern<MockStorage, MockApi, MockQuerier>, account: &str) -> u128 {
+        get_balance(&deps.storage, &deps.api.canonical_address(&HumanAddr::from(account)).unwrap()).unwrap()
+    }
+
+    /// Alice lists 500 of her 1,000 qTokens for 600 until block 20,100
+    fn listed_market() -> Extern<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_market(mock_init_msg());
+        handle(&mut deps, env_at("alice", 20_000, &coins(1_000, MOCK_DENOM)), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, env_at("alice", 20_000, &[]), HandleMsg::ListOtc {
+            amount: Uint128::from(500u128),
+            price: Uint128::from(600u128),
+            expires: 20_100,
+        })
+        .unwrap();
+        deps
+    }
+
+    #[test]
+    fn buyer_pays_the_seller_the_exact_price() {
+        let mut deps = listed_market();
+        assert_eq!(balance(&deps, "alice"), 500);
+        assert_eq!(
+            handle(&mut deps, env_at("bob", 20_050, &coins(599, MOCK_DENOM)), HandleMsg::BuyOtc { id: 1 }).unwrap_err(),
+            StdError::generic_err("Sent funds must match the offer price: price: 600, sent: 599")
+        );
+        assert_eq!(
+            handle(&mut deps, env_at("alice", 20_050, &coins(600, MOCK_DENOM)), HandleMsg::BuyOtc { id: 1 }).unwrap_err(),
+            StdError::generic_err("Sellers cannot buy their own offer")
+        );
+
+        let res = handle(&mut deps, env_at("bob", 20_050, &coins(600, MOCK_DENOM)), HandleMsg::BuyOtc { id: 1 }).unwrap();
+        assert!(res.messages.contains(&CosmosMsg::Bank(BankMsg::Send {
+            from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+            to_address: HumanAddr::from("alice"),
+            amount: coins(600, MOCK_DENOM),
+        })));
+        assert_eq!(balance(&deps, "bob"), 500);
+        assert_eq!(balance(&deps, MOCK_CONTRACT_ADDR), 0);
+        // The payment passes through, market cash is what Alice minted with
+        assert_eq!(get_state(&deps.storage).unwrap().cash, Uint128::from(1_000u128));
+    }
+
+    #[test]
+    fn anyone_returns_an_expired_offer_to_the_seller() {
+        let mut deps = listed_market();
+        assert_eq!(
+            handle(&mut deps, env_at("bob", 20_099, &[]), HandleMsg::CancelOtc { id: 1 }).unwrap_err(),
+            StdError::unauthorized()
+        );
+        assert_eq!(
+            handle(&mut deps, env_at("bob", 20_100, &coins(600, MOCK_DENOM)), HandleMsg::BuyOtc { id: 1 }).unwrap_err(),
+            StdError::generic_err("OTC offer has expired: offer_id: 1, expires: 20100")
+        );
+
+        handle(&mut deps, env_at("bob", 20_100, &[]), HandleMsg::CancelOtc { id: 1 }).unwrap();
+        assert_eq!(balance(&deps, "alice"), 1_000);
+        assert_eq!(balance(&deps, "bob"), 0);
+        assert_eq!(
+            handle(&mut deps, env_at("alice", 20_100, &[]), HandleMsg::CancelOtc { id: 1 }).unwrap_err(),
+            StdError::generic_err("OTC offer not found: offer_id: 1")
+        );
+    }
+}
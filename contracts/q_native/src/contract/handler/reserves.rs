@@ -0,0 +1,238 @@
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, Querier, StdError, StdResult, Storage, Uint128,
+};
+
+use crate::state::{
+    get_config, get_insurance_pool, get_reserve_factor_ramp, get_state, set_config, set_reserve_factor_ramp, set_state,
+    ParameterRamp, MAX_PARAMETER_RAMP_BLOCKS, MAX_RESERVE_FACTOR, Role,
+};
+
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::collateral::accrue_interest;
+use crate::contract::handler::cash_strategy::pay_out_cash;
+use crate::contract::handler::roles::assert_role;
+
+pub fn try_add_reserves<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    let mut new_state = get_state(&deps.storage)?;
+    new_state.cash += amount;
+    new_state.total_reserves += amount;
+    set_state(&mut deps.storage, &new_state)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "add_reserves"),
+            log("sender", env.message.sender.as_str()),
+            log("amount", amount),
+            log("new_total_reserves", new_state.total_reserves),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+pub fn try_reduce_reserves<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ReserveManager)?;
+    let config = get_config(&deps.storage)?;
+
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    // The insurance pool is held in reserves but only pays out refunds
+    let mut new_state = get_state(&deps.storage)?;
+    let available = (new_state.total_reserves - get_insurance_pool(&deps.storage)?).unwrap_or_default();
+    if amount > available {
+        return Err(StdError::generic_err(format!(
+            "Amount exceeds reserves: amount: {}, total_reserves: {}, available: {}",
+            amount, new_state.total_reserves, available
+        )));
+    }
+    if amount > new_state.cash {
+        return Err(StdError::generic_err(format!(
+            "The lending pool has insufficient cash: amount: {}, pool_reserve: {}",
+            amount, new_state.cash
+        )));
+    }
+    let old_reserves = new_state.total_reserves;
+    new_state.total_reserves = (new_state.total_reserves - amount)?;
+    new_state.cash = (new_state.cash - amount)?;
+    set_state(&mut deps.storage, &new_state)?;
+    let audit_log = audit(deps, &env, role, "total_reserves", &old_reserves, &new_state.total_reserves)?;
+
+    let treasury = deps.api.human_address(&config.treasury)?;
+    let mut res = HandleResponse {
+        messages: pay_out_cash(deps, &env, &config, &treasury, amount)?,
+        log: vec![
+            log("action", "reduce_reserves"),
+            log("sender", env.message.sender.as_str()),
+            log("treasury", treasury.as_str()),
+            log("amount", amount),
+            log("new_total_reserves", new_state.total_reserves),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+pub fn try_set_reserve_factor<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    reserve_factor: Uint128,
+    ramp_blocks: Option<u64>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    if reserve_factor.u128() > MAX_RESERVE_FACTOR {
+        return Err(StdError::generic_err(format!(
+            "Reserve factor exceeds maximum: reserve_factor: {}, max_reserve_factor: {}",
+            reserve_factor, MAX_RESERVE_FACTOR
+        )));
+    }
+    if let Some(blocks) = ramp_blocks {
+        if blocks > MAX_PARAMETER_RAMP_BLOCKS {
+            return Err(StdError::generic_err(format!(
+                "Ramp exceeds maximum: ramp_blocks: {}, maximum: {}",
+                blocks, MAX_PARAMETER_RAMP_BLOCKS
+            )));
+        }
+    }
+
+    // Settle interest owed under the previous factor before switching
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    // A new change replaces one still ramping, starting from the factor reached so far
+    let old_factor = get_state(&deps.storage)?.reserve_factor;
+    let ramp = match ramp_blocks {
+        Some(blocks) if blocks > 0 => Some(ParameterRamp {
+            from: old_factor,
+            to: reserve_factor,
+            start: env.block.height,
+            end: env.block.height + blocks,
+        }),
+        _ => {
+            set_reserve_factor(&mut deps.storage, reserve_factor)?;
+            None
+        }
+    };
+    set_reserve_factor_ramp(&mut deps.storage, ramp.as_ref())?;
+    let audit_log = audit(deps, &env, role, "reserve_factor", &old_factor, &reserve_factor)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_reserve_factor"),
+            log("sender", env.message.sender.as_str()),
+            log("reserve_factor", reserve_factor),
+            log("ramp_end", ramp.map(|ramp| ramp.end).unwrap_or(env.block.height)),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Moves the reserve factor along its ramp up to `height`, clearing the ramp once it is reached
+pub fn advance_reserve_factor_ramp<S: Storage>(storage: &mut S, height: u64) -> StdResult<()> {
+    let ramp = match get_reserve_factor_ramp(storage)? {
+        Some(ramp) => ramp,
+        None => return Ok(()),
+    };
+    set_reserve_factor(storage, ramp.value_at(height))?;
+    if height >= ramp.end {
+        set_reserve_factor_ramp(storage, None)?;
+    }
+    Ok(())
+}
+
+/// Accrual reads the factor from state, config mirrors it for the config query
+fn set_reserve_factor<S: Storage>(storage: &mut S, reserve_factor: Uint128) -> StdResult<()> {
+    let mut state = get_state(storage)?;
+    if state.reserve_factor == reserve_factor {
+        return Ok(());
+    }
+    state.reserve_factor = reserve_factor;
+    set_state(storage, &state)?;
+    let mut config = get_config(storage)?;
+    config.reserve_factor = reserve_factor;
+    set_config(storage, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market};
+    use crate::msg::HandleMsg;
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    fn set_reserve_factor(reserve_factor: u128, ramp_blocks: Option<u64>) -> HandleMsg {
+        HandleMsg::SetReserveFactor { reserve_factor: Uint128::from(reserve_factor), ramp_blocks }
+    }
+
+    #[test]
+    fn reserve_factor_ramps_linearly_as_interest_accrues() {
+        let mut deps = mock_market(mock_init_msg());
+        handle(&mut deps, env_at("admin", 20_000), set_reserve_factor(30_000_000, Some(200))).unwrap();
+        // The factor only moves once accrual reaches a later block
+        assert_eq!(get_state(&deps.storage).unwrap().reserve_factor, Uint128::from(10_000_000u128));
+
+        accrue_interest(&mut deps, env_at("anyone", 20_050)).unwrap();
+        assert_eq!(get_state(&deps.storage).unwrap().reserve_factor, Uint128::from(15_000_000u128));
+        assert_eq!(get_config(&deps.storage).unwrap().reserve_factor, Uint128::from(15_000_000u128));
+
+        accrue_interest(&mut deps, env_at("anyone", 20_300)).unwrap();
+        assert_eq!(get_state(&deps.storage).unwrap().reserve_factor, Uint128::from(30_000_000u128));
+        assert_eq!(get_reserve_factor_ramp(&deps.storage).unwrap(), None);
+    }
+
+    #[test]
+    fn a_new_change_ramps_from_the_factor_reached_so_far() {
+        let mut deps = mock_market(mock_init_msg());
+        handle(&mut deps, env_at("admin", 20_000), set_reserve_factor(30_000_000, Some(100))).unwrap();
+        handle(&mut deps, env_at("admin", 20_050), set_reserve_factor(0, Some(100))).unwrap();
+        assert_eq!(
+            get_reserve_factor_ramp(&deps.storage).unwrap(),
+            Some(ParameterRamp {
+                from: Uint128::from(20_000_000u128),
+                to: Uint128::zero(),
+                start: 20_050,
+                end: 20_150,
+            })
+        );
+        accrue_interest(&mut deps, env_at("anyone", 20_125)).unwrap();
+        assert_eq!(get_state(&deps.storage).unwrap().reserve_factor, Uint128::from(5_000_000u128));
+
+        // An instant change cancels the ramp
+        handle(&mut deps, env_at("admin", 20_130), set_reserve_factor(40_000_000, None)).unwrap();
+        assert_eq!(get_state(&deps.storage).unwrap().reserve_factor, Uint128::from(40_000_000u128));
+        assert_eq!(get_reserve_factor_ramp(&deps.storage).unwrap(), None);
+        assert_eq!(
+            handle(&mut deps, env_at("admin", 20_140), set_reserve_factor(0, Some(MAX_PARAMETER_RAMP_BLOCKS + 1)))
+                .unwrap_err(),
+            StdError::generic_err(format!(
+                "Ramp exceeds maximum: ramp_blocks: {}, maximum: {}",
+                MAX_PARAMETER_RAMP_BLOCKS + 1,
+                MAX_PARAMETER_RAMP_BLOCKS
+            ))
+        );
+    }
+}
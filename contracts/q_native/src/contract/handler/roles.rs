@@ -0,0 +1,174 @@
+use cosmwasm_std::{log, Api, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::state::{get_config, has_role, set_role, Role};
+
+use crate::contract::handler::audit::{audit, ROLE_ADMIN};
+
+/// Fails unless the sender is the admin or holds `role`
+/// Returns the role the sender acts in, as the audit log records it
+pub fn assert_role<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    role: Role,
+) -> StdResult<&'static str> {
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if sender_raw == get_config(&deps.storage)?.admin {
+        return Ok(ROLE_ADMIN);
+    }
+    if has_role(&deps.storage, role, &sender_raw)? {
+        return Ok(role.as_str());
+    }
+    Err(StdError::unauthorized())
+}
+
+/// Admin only
+pub fn try_grant_role<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    role: Role,
+    account: HumanAddr,
+) -> StdResult<HandleResponse> {
+    set_role_membership(deps, env, role, account, true)
+}
+
+/// Admin only, revoking a role the account does not hold is a no-op
+pub fn try_revoke_role<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    role: Role,
+    account: HumanAddr,
+) -> StdResult<HandleResponse> {
+    set_role_membership(deps, env, role, account, false)
+}
+
+fn set_role_membership<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    role: Role,
+    account: HumanAddr,
+    granted: bool,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    let account_raw = canonicalize_address(&deps.api, &account)?;
+    let was_granted = has_role(&deps.storage, role, &account_raw)?;
+    set_role(&mut deps.storage, role, &account_raw, granted)?;
+    let parameter = format!("role.{}.{}", role.as_str(), account);
+    let audit_log = audit(deps, &env, ROLE_ADMIN, &parameter, &was_granted, &granted)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", if granted { "grant_role" } else { "revoke_role" }),
+            log("sender", env.message.sender.as_str()),
+            log("role", role.as_str()),
+            log("account", account.as_str()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::{from_binary, Uint128};
+
+    use crate::contract::testing::{mock_init_msg, mock_market};
+    use crate::contract::{handle, query};
+    use quasar_interfaces::pagination::PageRequest;
+
+    use crate::msg::{HandleMsg, QueryMsg, RoleMembersResponse};
+    use crate::state::{get_pause_flags, get_state, PauseAction};
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    fn grant(role: Role, account: &str) -> HandleMsg {
+        HandleMsg::GrantRole { role, account: HumanAddr::from(account) }
+    }
+
+    fn set_reserve_factor(reserve_factor: u128) -> HandleMsg {
+        HandleMsg::SetReserveFactor { reserve_factor: Uint128::from(reserve_factor), ramp_blocks: None }
+    }
+
+    #[test]
+    fn granted_role_covers_its_entry_points_until_revoked() {
+        let mut deps = mock_market(mock_init_msg());
+        assert_eq!(
+            handle(&mut deps, env_at("risk", 20_000), set_reserve_factor(20_000_000)).unwrap_err(),
+            StdError::unauthorized()
+        );
+
+        handle(&mut deps, env_at("admin", 20_000), grant(Role::ParamAdmin, "risk")).unwrap();
+        let res = handle(&mut deps, env_at("risk", 20_001), set_reserve_factor(20_000_000)).unwrap();
+        assert!(res.log.contains(&log("audit_role", "param_admin")));
+        assert_eq!(get_state(&deps.storage).unwrap().reserve_factor, Uint128::from(20_000_000u128));
+        // The role covers parameters only, not reserves
+        assert_eq!(
+            handle(&mut deps, env_at("risk", 20_002), HandleMsg::ReduceReserves { amount: Uint128::from(1u128) })
+                .unwrap_err(),
+            StdError::unauthorized()
+        );
+
+        let revoke = HandleMsg::RevokeRole { role: Role::ParamAdmin, account: HumanAddr::from("risk") };
+        assert_eq!(handle(&mut deps, env_at("risk", 20_003), revoke.clone()).unwrap_err(), StdError::unauthorized());
+        handle(&mut deps, env_at("admin", 20_003), revoke).unwrap();
+        assert_eq!(
+            handle(&mut deps, env_at("risk", 20_004), set_reserve_factor(30_000_000)).unwrap_err(),
+            StdError::unauthorized()
+        );
+    }
+
+    #[test]
+    fn only_the_admin_grants_roles() {
+        let mut deps = mock_market(mock_init_msg());
+        handle(&mut deps, env_at("admin", 20_000), grant(Role::ParamAdmin, "risk")).unwrap();
+        assert_eq!(
+            handle(&mut deps, env_at("risk", 20_001), grant(Role::ParamAdmin, "friend")).unwrap_err(),
+            StdError::unauthorized()
+        );
+    }
+
+    #[test]
+    fn pause_guardian_pauses_but_never_unpauses() {
+        let mut deps = mock_market(mock_init_msg());
+        handle(&mut deps, env_at("admin", 20_000), grant(Role::PauseGuardian, "watcher")).unwrap();
+
+        let pause = |paused| HandleMsg::SetPaused { action: PauseAction::Borrow, paused };
+        let res = handle(&mut deps, env_at("watcher", 20_001), pause(true)).unwrap();
+        assert!(res.log.contains(&log("audit_role", "pause_guardian")));
+        assert!(get_pause_flags(&deps.storage).unwrap().is_paused(PauseAction::Borrow));
+        assert_eq!(handle(&mut deps, env_at("watcher", 20_002), pause(false)).unwrap_err(), StdError::unauthorized());
+    }
+
+    #[test]
+    fn role_members_are_paged() {
+        let mut deps = mock_market(mock_init_msg());
+        for account in &["alice", "bob", "carol"] {
+            handle(&mut deps, env_at("admin", 20_000), grant(Role::ReserveManager, account)).unwrap();
+        }
+        handle(&mut deps, env_at("admin", 20_000), grant(Role::OracleAdmin, "dave")).unwrap();
+
+        let members = |page| -> RoleMembersResponse {
+            from_binary(&query(&deps, QueryMsg::RoleMembers { role: Role::ReserveManager, page }).unwrap()).unwrap()
+        };
+        let first = members(Some(PageRequest { start_after: None, limit: Some(2) }));
+        assert_eq!(first.members.len(), 2);
+        assert!(first.page.next_key.is_some());
+        let second = members(Some(PageRequest { start_after: first.page.next_key.clone(), limit: Some(2) }));
+        assert_eq!(second.page.next_key, None);
+        let mut all: Vec<String> = [first.members, second.members].concat().into_iter().map(|member| member.to_string()).collect();
+        all.sort();
+        assert_eq!(all, vec!["alice", "bob", "carol"]);
+    }
+}
@@ -0,0 +1,225 @@
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage,
+    Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::state::{
+    get_balance, get_borrow_balance, get_config, get_self_repay, get_state, get_yield_redirect, set_borrow_balance,
+    set_config, set_self_repay, set_state, update_leaderboard, BorrowSnapshot, SelfRepay,
+    SELF_REPAY_EPOCH, TOP_BORROWERS_KEY,
+};
+
+use crate::contract::handler::balance_hook::balance_hook;
+use crate::contract::handler::collateral::{
+    accrue_interest, current_borrow_balance, get_exchange_rate, tokens_for_underlying_ceil, underlying_for_tokens,
+};
+use crate::contract::handler::health_alert::health_alert_log;
+use crate::contract::handler::token::burn_tokens;
+use crate::contract::handler::yield_checkpoint::book_yield;
+
+pub fn try_enable_self_repay<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    // Yield can only go one way, to the debt or to a beneficiary
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if get_yield_redirect(&deps.storage, &sender_raw)?.is_some() {
+        return Err(StdError::generic_err("Yield is redirected to a beneficiary, disable the redirect first"));
+    }
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    // Only yield earned after enabling is routed to the debt
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    set_self_repay(&mut deps.storage, &sender_raw, Some(SelfRepay {
+        exchange_rate: Uint128::from(exchange_rate),
+        last_settled: env.block.height,
+        accrued_yield: Uint128::zero(),
+    }))?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "enable_self_repay"),
+            log("sender", env.message.sender.as_str()),
+            log("exchange_rate", exchange_rate),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+pub fn try_disable_self_repay<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    set_self_repay(&mut deps.storage, &sender_raw, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "disable_self_repay"),
+            log("sender", env.message.sender.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_settle_self_repay<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    let account_raw = canonicalize_address(&deps.api, account)?;
+    let mut checkpoint = match get_self_repay(&deps.storage, &account_raw)? {
+        Some(checkpoint) => checkpoint,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Self-repay is not enabled: account: {}",
+                account
+            )));
+        }
+    };
+
+    let next_settlement = checkpoint.last_settled + SELF_REPAY_EPOCH;
+    if env.block.height < next_settlement {
+        return Err(StdError::generic_err(format!(
+            "Self-repay epoch has not ended: current_block: {}, next_settlement: {}",
+            env.block.height, next_settlement
+        )));
+    }
+
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    let balance = get_balance(&deps.storage, &account_raw)?;
+    let state = get_state(&deps.storage)?;
+
+    book_yield(&mut checkpoint.exchange_rate, &mut checkpoint.accrued_yield, balance, exchange_rate);
+    let yield_amount = checkpoint.accrued_yield.u128();
+    // Checkpointed first, so the burn below books no further yield
+    set_self_repay(&mut deps.storage, &account_raw, Some(SelfRepay {
+        exchange_rate: checkpoint.exchange_rate,
+        last_settled: env.block.height,
+        accrued_yield: Uint128::zero(),
+    }))?;
+
+    let config = get_config(&deps.storage)?;
+    let account_borrow = match get_borrow_balance(&deps.storage, &account_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+
+    // Round the burned qTokens up so the pool never pays more than the yield covers
+    let burn_amount = match yield_amount.min(account_borrow) {
+        0 => 0,
+        owed_yield => tokens_for_underlying_ceil(owed_yield, exchange_rate).min(balance),
+    };
+    // Yield booked on qTokens that have since left the account went with them,
+    // only what the burned qTokens are worth repays the debt
+    let repay_amount = yield_amount.min(account_borrow).min(underlying_for_tokens(burn_amount, exchange_rate));
+
+    if repay_amount > 0 {
+        // Redeemed underlying is repaid in the same step, so cash is unchanged
+        burn_tokens(&mut deps.storage, &account_raw, burn_amount)?;
+        let mut new_config = get_config(&deps.storage)?;
+        new_config.total_supply = (new_config.total_supply - Uint128::from(burn_amount))?;
+        set_config(&mut deps.storage, &new_config)?;
+
+        let mut new_state = state.clone();
+        new_state.total_borrows = (new_state.total_borrows - Uint128::from(repay_amount))?;
+        set_state(&mut deps.storage, &new_state)?;
+
+        let new_account_borrow = account_borrow - repay_amount;
+        set_borrow_balance(&mut deps.storage, &account_raw, Some(BorrowSnapshot {
+            principal: Uint128::from(new_account_borrow),
+            interest_index: new_state.borrow_index,
+        }))?;
+        update_leaderboard(&mut deps.storage, TOP_BORROWERS_KEY, &account_raw, new_account_borrow)?;
+    }
+
+    // Nothing was burned when no yield was settled
+    let messages = match burn_amount {
+        0 => vec![],
+        _ => balance_hook(&deps.storage, &deps.api, &[&account_raw])?,
+    };
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "settle_self_repay"),
+            log("sender", env.message.sender.as_str()),
+            log("account", account.as_str()),
+            log("yield_amount", yield_amount),
+            log("repay_amount", repay_amount),
+            log("burned_tokens", burn_amount),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&account_raw])?);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::coins;
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market, MOCK_DENOM};
+    use crate::msg::HandleMsg;
+
+    fn env_at(sender: &str, funds: u128, height: u64) -> Env {
+        let mut env = match funds {
+            0 => mock_env(sender, &[]),
+            funds => mock_env(sender, &coins(funds, MOCK_DENOM)),
+        };
+        env.block.height = height;
+        env
+    }
+
+    fn log_value(res: &HandleResponse, key: &str) -> String {
+        res.log.iter().find(|attr| attr.key == key).unwrap().value.clone()
+    }
+
+    #[test]
+    fn settlement_only_repays_what_the_remaining_qtokens_are_worth() {
+        // Collateral held in other markets lets the account move every qToken out
+        let mut msg = mock_init_msg();
+        msg.comptroller = Some(HumanAddr::from("comptroller"));
+        let mut deps = mock_market(msg);
+        let start = mock_env("admin", &[]).block.height;
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(2_000_000, MOCK_DENOM));
+
+        handle(&mut deps, env_at("bob", 1_000_000, start), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, env_at("alice", 1_000_000, start), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, env_at("alice", 0, start), HandleMsg::Borrow { borrow_amount: Uint128::from(500_000u128), integrator_fee: None }).unwrap();
+        handle(&mut deps, env_at("alice", 0, start), HandleMsg::EnableSelfRepay {}).unwrap();
+
+        // Interest raises the exchange rate, and the transfer books alice's yield before her balance leaves
+        let later = start + 10_000;
+        handle(&mut deps, env_at("bob", 1, later), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, env_at("alice", 0, later), HandleMsg::Transfer {
+            recipient: HumanAddr::from("bob"),
+            amount: Uint128::from(1_000_000u128),
+        }).unwrap();
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        assert!(!get_self_repay(&deps.storage, &alice).unwrap().unwrap().accrued_yield.is_zero());
+
+        let settle = HandleMsg::SettleSelfRepay { account: HumanAddr::from("alice") };
+        let res = handle(&mut deps, env_at("keeper", 0, start + SELF_REPAY_EPOCH), settle).unwrap();
+        assert_eq!(log_value(&res, "burned_tokens"), "0");
+        assert_eq!(log_value(&res, "repay_amount"), "0");
+
+        // The debt only grew, the pool covered none of it
+        let state = get_state(&deps.storage).unwrap();
+        let snapshot = get_borrow_balance(&deps.storage, &alice).unwrap();
+        assert!(current_borrow_balance(&snapshot, &state.borrow_index, None) > 500_000);
+        assert!(get_self_repay(&deps.storage, &alice).unwrap().unwrap().accrued_yield.is_zero());
+    }
+}
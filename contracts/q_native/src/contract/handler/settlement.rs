@@ -0,0 +1,239 @@
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{
+    log, Api, CanonicalAddr, Env, Extern, HandleResponse, Querier, StdError, StdResult, Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+
+use crate::state::{
+    get_balance, get_config, get_settlement, get_settlement_claimed, get_state, set_insurance_pool, set_settlement,
+    set_settlement_claimed, set_state, Settlement,
+};
+
+use crate::contract::handler::audit::{audit, ROLE_ADMIN};
+use crate::contract::handler::cash_strategy::pay_out_cash;
+use crate::contract::handler::collateral::accrue_interest;
+
+/// Admin only and final, freezes borrows at the current index and releases cash
+/// and reserves to suppliers pro rata to their qTokens
+pub fn try_enter_settlement<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    if get_settlement(&deps.storage)?.is_some() {
+        return Err(StdError::generic_err("Market is already settled"));
+    }
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    // Reserves are not withdrawable once settled, suppliers are repaid first
+    let mut new_state = get_state(&deps.storage)?;
+    let released_reserves = new_state.total_reserves;
+    new_state.total_reserves = Uint128::zero();
+    set_state(&mut deps.storage, &new_state)?;
+    set_insurance_pool(&mut deps.storage, Uint128::zero())?;
+
+    let claim_index = match config.total_supply.is_zero() {
+        true => Decimal256::zero(),
+        false => Decimal256::from_ratio(Uint256::from(new_state.cash).0, Uint256::from(config.total_supply).0),
+    };
+    let settlement = Settlement {
+        block_number: env.block.height,
+        borrow_index: new_state.borrow_index,
+        total_supply: config.total_supply,
+        claim_index,
+    };
+    set_settlement(&mut deps.storage, &settlement)?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "settlement", &None, &Some(settlement.clone()))?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "enter_settlement"),
+            log("sender", env.message.sender.as_str()),
+            log("borrow_index", settlement.borrow_index),
+            log("total_supply", settlement.total_supply),
+            log("cash", new_state.cash),
+            log("released_reserves", released_reserves),
+            log("claim_index", claim_index),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Pays the sender's share of cash and repayments received since its last claim
+pub fn try_claim_settlement<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    if get_settlement(&deps.storage)?.is_none() {
+        return Err(StdError::generic_err("Market is not settled"));
+    }
+    let claimant_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let (entitled, claimed) = settlement_claim(&deps.storage, &claimant_raw)?;
+
+    // Rounding of the claim index never pays out more than the market holds
+    let mut new_state = get_state(&deps.storage)?;
+    let amount = (entitled - claimed).min(new_state.cash.u128());
+    if amount == 0 {
+        return Err(StdError::generic_err(format!(
+            "Nothing to claim: entitled: {}, claimed: {}",
+            entitled, claimed
+        )));
+    }
+    new_state.cash = (new_state.cash - Uint128::from(amount))?;
+    set_state(&mut deps.storage, &new_state)?;
+    set_settlement_claimed(&mut deps.storage, &claimant_raw, Uint128::from(claimed + amount))?;
+
+    let config = get_config(&deps.storage)?;
+    let res = HandleResponse {
+        messages: pay_out_cash(deps, &env, &config, &env.message.sender, Uint128::from(amount))?,
+        log: vec![
+            log("action", "claim_settlement"),
+            log("sender", env.message.sender.as_str()),
+            log("amount", amount),
+            log("claimed", claimed + amount),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Underlying the account's qTokens entitle it to so far and what it already claimed
+pub fn settlement_claim<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<(u128, u128)> {
+    let settlement = match get_settlement(storage)? {
+        Some(settlement) => settlement,
+        None => return Ok((0, 0)),
+    };
+    let entitled: u128 = (Uint256::from(get_balance(storage, account)?) * settlement.claim_index).into();
+    let claimed = get_settlement_claimed(storage, account)?.u128();
+    Ok((entitled.max(claimed), claimed))
+}
+
+/// Shares a repayment received after settlement among all qTokens
+pub fn distribute_repayment<S: Storage>(storage: &mut S, amount: u128) -> StdResult<()> {
+    let mut settlement = match get_settlement(storage)? {
+        Some(settlement) => settlement,
+        None => return Ok(()),
+    };
+    if settlement.total_supply.is_zero() || amount == 0 {
+        return Ok(());
+    }
+    settlement.claim_index += Decimal256::from_ratio(Uint256::from(amount).0, Uint256::from(settlement.total_supply).0);
+    set_settlement(storage, &settlement)
+}
+
+/// Fails once the market is settled
+pub fn assert_not_settled<S: Storage>(storage: &S) -> StdResult<()> {
+    if get_settlement(storage)?.is_some() {
+        return Err(StdError::generic_err("Market is settled, only repayments and claims are accepted"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, Extern, HumanAddr};
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market, MOCK_DENOM};
+    use crate::msg::HandleMsg;
+    use crate::state::DepositTerm;
+
+    fn env_with(sender: &str, funds: u128) -> Env {
+        match funds {
+            0 => mock_env(sender, &[]),
+            funds => mock_env(sender, &coins(funds, MOCK_DENOM)),
+        }
+    }
+
+    fn claim(deps: &mut Extern<MockStorage, MockApi, MockQuerier>, account: &str) -> StdResult<u128> {
+        let res = handle(deps, env_with(account, 0), HandleMsg::ClaimSettlement {})?;
+        Ok(res.log.iter().find(|attr| attr.key == "amount").unwrap().value.parse().unwrap())
+    }
+
+    /// alice 1_000 and bob 3_000 qTokens, carol 2_000 qTokens and 1_000 borrowed, then settled
+    fn settled_market() -> Extern<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_market(mock_init_msg());
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(6_000, MOCK_DENOM));
+        handle(&mut deps, env_with("alice", 1_000), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, env_with("bob", 3_000), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, env_with("carol", 2_000), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, env_with("carol", 0), HandleMsg::Borrow { borrow_amount: Uint128::from(1_000u128), integrator_fee: None }).unwrap();
+        handle(&mut deps, env_with("admin", 0), HandleMsg::EnterSettlement {}).unwrap();
+        deps
+    }
+
+    #[test]
+    fn claim_index_shares_cash_pro_rata_once() {
+        let mut deps = settled_market();
+        // 5_000 cash over 6_000 qTokens
+        let settlement = get_settlement(&deps.storage).unwrap().unwrap();
+        assert_eq!(settlement.claim_index, Decimal256::from_ratio(5u64, 6u64));
+
+        assert_eq!(claim(&mut deps, "alice").unwrap(), 833);
+        // The index truncates in its last decimal, bob's 2_500 rounds down
+        assert_eq!(claim(&mut deps, "bob").unwrap(), 2_499);
+        assert!(claim(&mut deps, "alice").is_err());
+        assert!(claim(&mut deps, "dave").is_err());
+        assert_eq!(get_state(&deps.storage).unwrap().cash, Uint128::from(1_668u128));
+    }
+
+    #[test]
+    fn repayments_after_settlement_raise_every_claim() {
+        let mut deps = settled_market();
+        assert_eq!(claim(&mut deps, "alice").unwrap(), 833);
+
+        // Borrows are frozen at the settlement index, 600 repaid is 0.1 per qToken
+        handle(&mut deps, env_with("carol", 600), HandleMsg::RepayBorrow {}).unwrap();
+        assert_eq!(claim(&mut deps, "alice").unwrap(), 100);
+        assert_eq!(claim(&mut deps, "bob").unwrap(), 2_799);
+        assert_eq!(claim(&mut deps, "carol").unwrap(), 1_866);
+    }
+
+    #[test]
+    fn claims_never_exceed_cash() {
+        let mut deps = settled_market();
+        let mut state = get_state(&deps.storage).unwrap();
+        state.cash = Uint128::from(10u128);
+        set_state(&mut deps.storage, &state).unwrap();
+
+        // The shortfall stays claimable once cash comes back
+        assert_eq!(claim(&mut deps, "bob").unwrap(), 10);
+        assert!(claim(&mut deps, "alice").is_err());
+        handle(&mut deps, env_with("carol", 600), HandleMsg::RepayBorrow {}).unwrap();
+        assert_eq!(claim(&mut deps, "bob").unwrap(), 600);
+
+        // Truncation leaves dust, never a payout above what the market held
+        let mut deps = settled_market();
+        let paid: u128 = ["alice", "bob", "carol"].iter().map(|account| claim(&mut deps, account).unwrap()).sum();
+        assert!(paid <= 5_000 && 5_000 - paid < 3);
+    }
+
+    #[test]
+    fn term_deposits_unlock_without_penalty_and_claim_after_settlement() {
+        let mut msg = mock_init_msg();
+        msg.early_exit_penalty = Uint128::from(10_000_000u128);
+        let mut deps = mock_market(msg);
+        handle(&mut deps, env_with("alice", 1_000), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, env_with("alice", 0), HandleMsg::LockDeposit { amount: Uint128::from(1_000u128), term: DepositTerm::Days30 }).unwrap();
+        handle(&mut deps, env_with("admin", 0), HandleMsg::EnterSettlement {}).unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(1_000, MOCK_DENOM));
+
+        // The market's own address holds the locked shares and cannot claim for the owner
+        let res = handle(&mut deps, env_with("alice", 0), HandleMsg::UnlockDeposit { id: 1 }).unwrap();
+        let penalty = res.log.iter().find(|attr| attr.key == "penalty_tokens").unwrap();
+        assert_eq!(penalty.value, "0");
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        assert_eq!(get_balance(&deps.storage, &alice).unwrap(), 1_000);
+        assert_eq!(claim(&mut deps, "alice").unwrap(), 1_000);
+    }
+}
@@ -0,0 +1,196 @@
+use cosmwasm_std::{
+    log, to_binary, Api, BankMsg, Coin, CosmosMsg, Env, Extern, HandleResponse, HumanAddr, Querier,
+    StdError, StdResult, Storage, Uint128, WasmMsg,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::asset::{Asset, AssetInfo, AssetInfoRaw};
+use quasar_interfaces::dex::{query_reverse_simulation, query_simulation, PairHandleMsg};
+
+use crate::msg::HandleMsg;
+use crate::state::{
+    get_borrow_balance, get_config, get_state, get_swap_pair, get_swap_repay, set_swap_pair,
+    set_swap_repay, SwapRepay, Role,
+};
+
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::collateral::{accrue_interest, current_borrow_balance, repay_borrow};
+use crate::contract::handler::funds::{must_pay, query_underlying_balance};
+use crate::contract::handler::roles::assert_role;
+
+/// Swaps the attached `offer_denom` into the underlying and repays the sender's
+/// borrow with it, spending at most `max_offer` and refunding the rest
+/// The pair is quoted and swapped against in the same transaction, so the
+/// underlying received is checked against the quote in FinishRepayWithOtherAsset
+pub fn try_repay_with_other_asset<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    offer_denom: String,
+    max_offer: Uint128,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    if let AssetInfoRaw::NativeToken { denom } = &config.underlying {
+        if denom == &offer_denom {
+            return Err(StdError::generic_err("Offer denom is the underlying, repay it with RepayBorrow"));
+        }
+    }
+    let pair = match get_swap_pair(&deps.storage, &offer_denom)? {
+        Some(pair) => deps.api.human_address(&pair)?,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "No swap pair for the offer denom: offer_denom: {}",
+                offer_denom
+            )));
+        }
+    };
+    let offer_info = AssetInfoRaw::NativeToken { denom: offer_denom.clone() };
+    let sent = must_pay(&env, &offer_info)?;
+
+    let accrual_log = accrue_interest(deps, env.clone())?;
+    let borrower_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let state = get_state(&deps.storage)?;
+    let account_borrow = match get_borrow_balance(&deps.storage, &borrower_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    if account_borrow == 0 {
+        return Err(StdError::generic_err(format!(
+            "Nothing to repay: account: {}",
+            env.message.sender
+        )));
+    }
+
+    // Swap no more than the debt needs, the sender caps the price with max_offer
+    let needed = query_reverse_simulation(&deps.querier, pair.clone(), Asset {
+        info: config.underlying.to_normal(&deps.api)?,
+        amount: Uint128::from(account_borrow),
+    })?;
+    let offer_amount = needed.min(max_offer).min(sent);
+    if offer_amount.is_zero() {
+        return Err(StdError::generic_err("Offer amount must be positive"));
+    }
+    let offer_asset = Asset {
+        info: AssetInfo::NativeToken { denom: offer_denom.clone() },
+        amount: offer_amount,
+    };
+    let min_receive = query_simulation(&deps.querier, pair.clone(), offer_asset.clone())?;
+
+    let balance_before = query_underlying_balance(&deps.api, &deps.querier, &config.underlying, &env.contract.address)?;
+    set_swap_repay(&mut deps.storage, Some(&SwapRepay {
+        borrower: borrower_raw,
+        offer_denom: offer_denom.clone(),
+        offer_amount,
+        min_receive,
+        balance_before,
+    }))?;
+
+    let swap = PairHandleMsg::Swap {
+        offer_asset,
+        belief_price: None,
+        max_spread: None,
+        to: None,
+    };
+    let mut messages = vec![swap.into_cosmos_msg(pair, vec![Coin {
+        denom: offer_denom.clone(),
+        amount: offer_amount,
+    }])?];
+    let refund = (sent - offer_amount)?;
+    if !refund.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            from_address: env.contract.address.clone(),
+            to_address: env.message.sender.clone(),
+            amount: vec![Coin {
+                denom: offer_denom.clone(),
+                amount: refund,
+            }],
+        }));
+    }
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.clone(),
+        msg: to_binary(&HandleMsg::FinishRepayWithOtherAsset {})?,
+        send: vec![],
+    }));
+
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "repay_with_other_asset"),
+            log("sender", env.message.sender.as_str()),
+            log("offer_denom", offer_denom),
+            log("offer_amount", offer_amount),
+            log("min_receive", min_receive),
+            log("refund", refund),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+/// Market only, repays with the underlying the swap returned
+pub fn try_finish_repay_with_other_asset<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    if env.message.sender != env.contract.address {
+        return Err(StdError::unauthorized());
+    }
+    let swap_repay = match get_swap_repay(&deps.storage)? {
+        Some(swap_repay) => swap_repay,
+        None => return Err(StdError::generic_err("No swap repayment is pending")),
+    };
+    set_swap_repay(&mut deps.storage, None)?;
+
+    let config = get_config(&deps.storage)?;
+    let balance = query_underlying_balance(&deps.api, &deps.querier, &config.underlying, &env.contract.address)?;
+    let received = (balance - swap_repay.balance_before)?;
+    if received < swap_repay.min_receive {
+        return Err(StdError::generic_err(format!(
+            "Swap returned less than quoted: received: {}, min_receive: {}",
+            received, swap_repay.min_receive
+        )));
+    }
+
+    let borrower = deps.api.human_address(&swap_repay.borrower)?;
+    let mut res = repay_borrow(deps, env, &borrower, received)?;
+    res.log.extend(vec![
+        log("offer_denom", swap_repay.offer_denom),
+        log("offer_amount", swap_repay.offer_amount),
+        log("received", received),
+    ]);
+    Ok(res)
+}
+
+/// Admin or param_admin only, None stops swapping `offer_denom` for repayments
+pub fn try_set_swap_pair<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    offer_denom: String,
+    pair: Option<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    let pair_raw = match &pair {
+        Some(pair) => Some(canonicalize_address(&deps.api, pair)?),
+        None => None,
+    };
+    let old_pair = match get_swap_pair(&deps.storage, &offer_denom)? {
+        Some(old_pair) => Some(deps.api.human_address(&old_pair)?),
+        None => None,
+    };
+    set_swap_pair(&mut deps.storage, &offer_denom, pair_raw.as_ref())?;
+    let parameter = format!("swap_pair.{}", offer_denom);
+    let audit_log = audit(deps, &env, role, &parameter, &old_pair, &pair)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_swap_pair"),
+            log("sender", env.message.sender.as_str()),
+            log("offer_denom", offer_denom),
+            log("pair", pair.map(|pair| pair.to_string()).unwrap_or_default()),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
@@ -0,0 +1,227 @@
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, Querier, StdError, StdResult, Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::comptroller::MarketAction;
+
+use crate::state::{
+    get_config, get_settlement, get_state, get_tranche, next_tranche_id, remove_tranche, set_config, set_state,
+    set_tranche, DepositTerm, Tranche,
+};
+
+use crate::contract::handler::balance_hook::balance_hook;
+use crate::contract::handler::cash_strategy::pay_out_cash;
+use crate::contract::handler::collateral::{accrue_interest, assert_account_healthy, assert_borrow_cooled_down, get_exchange_rate, underlying_for_tokens};
+use crate::contract::handler::comptroller::comptroller_hook;
+use crate::contract::handler::exponential::truncate;
+use crate::contract::handler::health_alert::health_alert_log;
+use crate::contract::handler::liquidation::assert_not_flagged;
+use crate::contract::handler::token::{burn_tokens, perform_transfer};
+
+pub fn try_lock_deposit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+    term: DepositTerm,
+) -> StdResult<HandleResponse> {
+    if amount.is_zero() {
+        return Err(StdError::generic_err("Term deposit amount must be positive"));
+    }
+
+    // Locked qTokens are held by the market itself until unlocked
+    let owner_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let contract_raw = canonicalize_address(&deps.api, &env.contract.address)?;
+    assert_not_flagged(&deps.storage, &owner_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &owner_raw, &env)?;
+    perform_transfer(&mut deps.storage, &owner_raw, &contract_raw, amount.u128())?;
+    // Locked qTokens no longer count as the owner's collateral
+    assert_account_healthy(&deps.storage, &owner_raw)?;
+
+    let tranche = Tranche {
+        id: next_tranche_id(&mut deps.storage)?,
+        owner: owner_raw,
+        amount,
+        term,
+        start_block: env.block.height,
+        maturity: env.block.height + term.blocks(),
+    };
+    set_tranche(&mut deps.storage, &tranche)?;
+
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&tranche.owner, &contract_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, &env.message.sender, amount.u128())?);
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "lock_deposit"),
+            log("sender", env.message.sender.as_str()),
+            log("tranche_id", tranche.id),
+            log("amount", amount),
+            log("maturity", tranche.maturity),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&tranche.owner])?);
+    Ok(res)
+}
+
+pub fn try_unlock_deposit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let tranche = get_tranche(&deps.storage, id)?;
+    let owner_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if tranche.owner != owner_raw {
+        return Err(StdError::unauthorized());
+    }
+
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    let config = get_config(&deps.storage)?;
+    let contract_raw = canonicalize_address(&deps.api, &env.contract.address)?;
+    let matured = env.block.height >= tranche.maturity;
+
+    let (returned_tokens, penalty_tokens, bonus) = if get_settlement(&deps.storage)?.is_some() {
+        // A settled market only hands the shares back, they claim their settlement like any others
+        (tranche.amount.u128(), 0, 0)
+    } else if matured {
+        // Bonus is paid in underlying out of reserves and never exceeds them
+        let exchange_rate = get_exchange_rate(deps, env.clone())?;
+        let underlying_value = underlying_for_tokens(tranche.amount.u128(), exchange_rate);
+        let bonus = truncate(underlying_value * config.term_bonus_rates.rate(tranche.term).u128());
+        let state = get_state(&deps.storage)?;
+        let bonus = bonus.min(state.total_reserves.u128()).min(state.cash.u128());
+        (tranche.amount.u128(), 0, bonus)
+    } else {
+        // Burned penalty shares leave the underlying in the pool for the remaining suppliers
+        let penalty_tokens = truncate(tranche.amount.u128() * config.early_exit_penalty.u128());
+        (tranche.amount.u128() - penalty_tokens, penalty_tokens, 0)
+    };
+
+    perform_transfer(&mut deps.storage, &contract_raw, &owner_raw, returned_tokens)?;
+    if penalty_tokens > 0 {
+        burn_tokens(&mut deps.storage, &contract_raw, penalty_tokens)?;
+        let mut new_config = get_config(&deps.storage)?;
+        new_config.total_supply = (new_config.total_supply - Uint128::from(penalty_tokens))?;
+        set_config(&mut deps.storage, &new_config)?;
+    }
+    remove_tranche(&mut deps.storage, &tranche);
+
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&contract_raw, &owner_raw])?;
+    if bonus > 0 {
+        let mut new_state = get_state(&deps.storage)?;
+        new_state.total_reserves = (new_state.total_reserves - Uint128::from(bonus))?;
+        new_state.cash = (new_state.cash - Uint128::from(bonus))?;
+        set_state(&mut deps.storage, &new_state)?;
+
+        messages.extend(pay_out_cash(deps, &env, &config, &env.message.sender, Uint128::from(bonus))?);
+    }
+
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "unlock_deposit"),
+            log("sender", env.message.sender.as_str()),
+            log("tranche_id", id),
+            log("matured", matured),
+            log("returned_tokens", returned_tokens),
+            log("penalty_tokens", penalty_tokens),
+            log("bonus", bonus),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&owner_raw])?);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, BankMsg, CosmosMsg, HumanAddr};
+
+    use crate::contract::handle;
+    use crate::contract::handler::collateral::calculate_exchange_rate;
+    use crate::contract::testing::{mock_init_msg, mock_market, MOCK_DENOM};
+    use crate::msg::{HandleMsg, InitMsg};
+    use crate::state::{get_balance, set_balance, State, TermBonusRates, BLOCKS_PER_DAY};
+
+    fn env_at(sender: &str, height: u64) -> Env {
+        let mut env = mock_env(sender, &[]);
+        env.block.height = height;
+        env
+    }
+
+    /// Alice locks all 1,000 qTokens for 30 days at an exchange rate of 1, with 30 of reserves
+    fn locked_market(msg: InitMsg) -> Extern<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_market(msg);
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        let mut config = get_config(&deps.storage).unwrap();
+        config.total_supply = Uint128::from(1_000u128);
+        set_config(&mut deps.storage, &config).unwrap();
+        set_balance(&mut deps.storage, &alice, 1_000).unwrap();
+        let state = get_state(&deps.storage).unwrap();
+        set_state(&mut deps.storage, &State {
+            cash: Uint128::from(1_030u128),
+            block_number: 20_000,
+            total_reserves: Uint128::from(30u128),
+            ..state
+        })
+        .unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(1_030, MOCK_DENOM));
+        handle(&mut deps, env_at("alice", 20_000), HandleMsg::LockDeposit {
+            amount: Uint128::from(1_000u128),
+            term: DepositTerm::Days30,
+        })
+        .unwrap();
+        deps
+    }
+
+    #[test]
+    fn maturity_bonus_is_capped_by_reserves() {
+        // 5% of 1,000 is 50, more than the reserves hold
+        let mut msg = mock_init_msg();
+        msg.term_bonus_rates = TermBonusRates { days_30: Uint128::from(5_000_000u128), ..TermBonusRates::default() };
+        let mut deps = locked_market(msg);
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+        assert_eq!(get_balance(&deps.storage, &alice).unwrap(), 0);
+        assert_eq!(
+            handle(&mut deps, env_at("bob", 20_000 + 30 * BLOCKS_PER_DAY), HandleMsg::UnlockDeposit { id: 1 }).unwrap_err(),
+            StdError::unauthorized()
+        );
+
+        let res = handle(&mut deps, env_at("alice", 20_000 + 30 * BLOCKS_PER_DAY), HandleMsg::UnlockDeposit { id: 1 }).unwrap();
+        assert!(res.log.contains(&log("matured", true)));
+        assert!(res.log.contains(&log("bonus", 30)));
+        assert!(res.messages.contains(&CosmosMsg::Bank(BankMsg::Send {
+            from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+            to_address: HumanAddr::from("alice"),
+            amount: coins(30, MOCK_DENOM),
+        })));
+        assert_eq!(get_balance(&deps.storage, &alice).unwrap(), 1_000);
+        let state = get_state(&deps.storage).unwrap();
+        assert_eq!((state.total_reserves, state.cash), (Uint128::zero(), Uint128::from(1_000u128)));
+        assert!(get_tranche(&deps.storage, 1).is_err());
+    }
+
+    #[test]
+    fn early_exit_burns_the_penalty_for_the_remaining_suppliers() {
+        let mut msg = mock_init_msg();
+        msg.early_exit_penalty = Uint128::from(10_000_000u128);
+        let mut deps = locked_market(msg);
+        let alice = deps.api.canonical_address(&HumanAddr::from("alice")).unwrap();
+
+        let res = handle(&mut deps, env_at("alice", 20_100), HandleMsg::UnlockDeposit { id: 1 }).unwrap();
+        assert!(res.log.contains(&log("matured", false)));
+        assert!(res.log.contains(&log("penalty_tokens", 100)));
+        assert_eq!(get_balance(&deps.storage, &alice).unwrap(), 900);
+        let config = get_config(&deps.storage).unwrap();
+        let state = get_state(&deps.storage).unwrap();
+        assert_eq!(config.total_supply, Uint128::from(900u128));
+        // The underlying stays, so every remaining qToken is worth more
+        assert_eq!(state.cash, Uint128::from(1_030u128));
+        assert_eq!(calculate_exchange_rate(&config, &state).unwrap(), 111_111_111);
+    }
+}
@@ -1,14 +1,25 @@
 use cosmwasm_std::{
-    log, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, InitResponse, Querier,
-    ReadonlyStorage, StdError, StdResult, Storage, Uint128,
+    log, Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult,
+    Storage, Uint128,
 };
-use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+use cw20::{Cw20ReceiveMsg, Expiration};
 
 //use std::convert::TryInto;
 
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::comptroller::MarketAction;
+
+use crate::contract::handler::admin::assert_action_not_paused;
+use crate::contract::handler::balance_hook::balance_hook;
+use crate::contract::handler::collateral::{assert_account_healthy, assert_borrow_cooled_down};
+use crate::contract::handler::comptroller::comptroller_hook;
+use crate::contract::handler::health_alert::health_alert_log;
+use crate::contract::handler::liquidation::assert_not_flagged;
+use crate::contract::handler::transfer_policy::assert_transfer_allowed;
+use crate::contract::handler::yield_checkpoint::checkpoint_yield;
 use crate::state::{
-    bytes_to_u128, get_allowance, get_balance, set_allowance, to_u128, ALLOWANCE_PREFIX,
-    BALANCE_PREFIX,
+    get_allowance, get_allowance_expiration, get_balance, get_config, set_allowance, set_balance, set_config,
+    set_allowance_expiration, update_leaderboard, PauseAction, TOP_SUPPLIERS_KEY,
 };
 
 pub fn try_transfer<S: Storage, A: Api, Q: Querier>(
@@ -17,26 +28,35 @@ pub fn try_transfer<S: Storage, A: Api, Q: Querier>(
     recipient: &HumanAddr,
     amount: &Uint128,
 ) -> StdResult<HandleResponse> {
-    let sender_address_raw = deps.api.canonical_address(recipient)?;
-    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    let sender_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let recipient_address_raw = canonicalize_address(&deps.api, recipient)?;
     let amount_raw = amount.u128();
 
+    assert_not_flagged(&deps.storage, &sender_address_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &sender_address_raw, &env)?;
+    assert_transfer_allowed(deps, Some(&env.message.sender), recipient, *amount)?;
     perform_transfer(
         &mut deps.storage,
         &sender_address_raw,
         &recipient_address_raw,
         amount_raw,
     )?;
+    assert_account_healthy(&deps.storage, &sender_address_raw)?;
 
-    let res = HandleResponse {
-        messages: vec![],
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&sender_address_raw, &recipient_address_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, &env.message.sender, amount_raw)?);
+    let mut res = HandleResponse {
+        messages,
         log: vec![
             log("action", "transfer"),
             log("sender", env.message.sender.as_str()),
             log("recipient", recipient.as_str()),
+            log("amount", amount),
         ],
         data: None,
     };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&sender_address_raw, &recipient_address_raw])?);
     Ok(res)
 }
 
@@ -47,42 +67,190 @@ pub fn try_transfer_from<S: Storage, A: Api, Q: Querier>(
     recipient: &HumanAddr,
     amount: &Uint128,
 ) -> StdResult<HandleResponse> {
-    let spender_address_raw = deps.api.canonical_address(&env.message.sender)?;
-    let owner_address_raw = deps.api.canonical_address(owner)?;
-    let recipient_address_raw = deps.api.canonical_address(recipient)?;
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    let spender_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let owner_address_raw = canonicalize_address(&deps.api, owner)?;
+    let recipient_address_raw = canonicalize_address(&deps.api, recipient)?;
     let amount_raw = amount.u128();
 
-    let mut allowance = get_allowance(&deps.storage, &owner_address_raw, &spender_address_raw)?;
-    if allowance < amount_raw {
-        return Err(StdError::generic_err(format!(
-            "Insufficient allowance: allowance={}, required={}",
-            allowance, amount_raw
-        )));
-    }
-    allowance -= amount_raw;
-    set_allowance(
-        &mut deps.storage,
-        &owner_address_raw,
-        &spender_address_raw,
-        allowance,
-    )?;
+    assert_not_flagged(&deps.storage, &owner_address_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &owner_address_raw, &env)?;
+    assert_transfer_allowed(deps, Some(owner), recipient, *amount)?;
+    spend_allowance(&mut deps.storage, &env, &owner_address_raw, &spender_address_raw, amount_raw)?;
     perform_transfer(
         &mut deps.storage,
         &owner_address_raw,
         &recipient_address_raw,
         amount_raw,
     )?;
+    assert_account_healthy(&deps.storage, &owner_address_raw)?;
 
-    let res = HandleResponse {
-        messages: vec![],
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&owner_address_raw, &recipient_address_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, owner, amount_raw)?);
+    let mut res = HandleResponse {
+        messages,
         log: vec![
             log("action", "transfer_from"),
             log("spender", env.message.sender.as_str()),
             log("sender", owner.as_str()),
             log("recipient", recipient.as_str()),
+            log("amount", amount),
         ],
         data: None,
     };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&owner_address_raw, &recipient_address_raw])?);
+    Ok(res)
+}
+
+pub fn try_send<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract: &HumanAddr,
+    amount: &Uint128,
+    msg: Option<Binary>,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    let sender_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let contract_address_raw = canonicalize_address(&deps.api, contract)?;
+    assert_not_flagged(&deps.storage, &sender_address_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &sender_address_raw, &env)?;
+    assert_transfer_allowed(deps, Some(&env.message.sender), contract, *amount)?;
+    perform_transfer(
+        &mut deps.storage,
+        &sender_address_raw,
+        &contract_address_raw,
+        amount.u128(),
+    )?;
+    assert_account_healthy(&deps.storage, &sender_address_raw)?;
+
+    let receive = Cw20ReceiveMsg {
+        sender: env.message.sender.clone(),
+        amount: *amount,
+        msg,
+    };
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&sender_address_raw, &contract_address_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, &env.message.sender, amount.u128())?);
+    messages.push(receive.into_cosmos_msg(contract.clone())?);
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "send"),
+            log("sender", env.message.sender.as_str()),
+            log("recipient", contract.as_str()),
+            log("amount", amount),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&sender_address_raw, &contract_address_raw])?);
+    Ok(res)
+}
+
+pub fn try_send_from<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: &HumanAddr,
+    contract: &HumanAddr,
+    amount: &Uint128,
+    msg: Option<Binary>,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    let spender_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let owner_address_raw = canonicalize_address(&deps.api, owner)?;
+    let contract_address_raw = canonicalize_address(&deps.api, contract)?;
+
+    assert_not_flagged(&deps.storage, &owner_address_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &owner_address_raw, &env)?;
+    assert_transfer_allowed(deps, Some(owner), contract, *amount)?;
+    spend_allowance(&mut deps.storage, &env, &owner_address_raw, &spender_address_raw, amount.u128())?;
+    perform_transfer(
+        &mut deps.storage,
+        &owner_address_raw,
+        &contract_address_raw,
+        amount.u128(),
+    )?;
+    assert_account_healthy(&deps.storage, &owner_address_raw)?;
+
+    // The receiving contract sees the spender as sender, as in cw20-base
+    let receive = Cw20ReceiveMsg {
+        sender: env.message.sender.clone(),
+        amount: *amount,
+        msg,
+    };
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&owner_address_raw, &contract_address_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, owner, amount.u128())?);
+    messages.push(receive.into_cosmos_msg(contract.clone())?);
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "send_from"),
+            log("spender", env.message.sender.as_str()),
+            log("sender", owner.as_str()),
+            log("recipient", contract.as_str()),
+            log("amount", amount),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&owner_address_raw, &contract_address_raw])?);
+    Ok(res)
+}
+
+/// Burning without redeeming leaves the underlying to the remaining suppliers
+pub fn try_burn<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: &Uint128,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    let owner_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    assert_not_flagged(&deps.storage, &owner_address_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &owner_address_raw, &env)?;
+    burn_supply(&mut deps.storage, &owner_address_raw, amount.u128())?;
+    assert_account_healthy(&deps.storage, &owner_address_raw)?;
+
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&owner_address_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, &env.message.sender, amount.u128())?);
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "burn"),
+            log("from", env.message.sender.as_str()),
+            log("amount", amount),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&owner_address_raw])?);
+    Ok(res)
+}
+
+pub fn try_burn_from<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: &HumanAddr,
+    amount: &Uint128,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    let spender_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let owner_address_raw = canonicalize_address(&deps.api, owner)?;
+
+    assert_not_flagged(&deps.storage, &owner_address_raw, &env)?;
+    assert_borrow_cooled_down(&deps.storage, &owner_address_raw, &env)?;
+    spend_allowance(&mut deps.storage, &env, &owner_address_raw, &spender_address_raw, amount.u128())?;
+    burn_supply(&mut deps.storage, &owner_address_raw, amount.u128())?;
+    assert_account_healthy(&deps.storage, &owner_address_raw)?;
+
+    let mut messages = balance_hook(&deps.storage, &deps.api, &[&owner_address_raw])?;
+    messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, owner, amount.u128())?);
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "burn_from"),
+            log("spender", env.message.sender.as_str()),
+            log("from", owner.as_str()),
+            log("amount", amount),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&owner_address_raw])?);
     Ok(res)
 }
 
@@ -92,14 +260,27 @@ pub fn try_approve<S: Storage, A: Api, Q: Querier>(
     spender: &HumanAddr,
     amount: &Uint128,
 ) -> StdResult<HandleResponse> {
-    let owner_address_raw = deps.api.canonical_address(&env.message.sender)?;
-    let spender_address_raw = deps.api.canonical_address(spender)?;
+    let config = get_config(&deps.storage)?;
+    if !config.approve_enabled {
+        return Err(StdError::generic_err(
+            "Approve is disabled: use increase_allowance or decrease_allowance",
+        ));
+    }
+
+    let owner_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let spender_address_raw = canonicalize_address(&deps.api, spender)?;
     set_allowance(
         &mut deps.storage,
         &owner_address_raw,
         &spender_address_raw,
         amount.u128(),
     )?;
+    set_allowance_expiration(
+        &mut deps.storage,
+        &owner_address_raw,
+        &spender_address_raw,
+        &Expiration::Never {},
+    )?;
     let res = HandleResponse {
         messages: vec![],
         log: vec![
@@ -112,15 +293,130 @@ pub fn try_approve<S: Storage, A: Api, Q: Querier>(
     Ok(res)
 }
 
-fn perform_transfer<T: Storage>(
+pub fn try_increase_allowance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    spender: &HumanAddr,
+    amount: &Uint128,
+    expires: Option<Expiration>,
+) -> StdResult<HandleResponse> {
+    let owner_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let spender_address_raw = canonicalize_address(&deps.api, spender)?;
+    if owner_address_raw == spender_address_raw {
+        return Err(StdError::generic_err("Cannot set allowance to own account"));
+    }
+
+    let allowance = get_allowance(&deps.storage, &owner_address_raw, &spender_address_raw)?;
+    let new_allowance = allowance.checked_add(amount.u128()).ok_or_else(|| {
+        StdError::generic_err(format!(
+            "Allowance overflow: allowance={}, increase={}",
+            allowance, amount
+        ))
+    })?;
+    set_allowance(
+        &mut deps.storage,
+        &owner_address_raw,
+        &spender_address_raw,
+        new_allowance,
+    )?;
+    if let Some(expires) = expires {
+        set_allowance_expiration(&mut deps.storage, &owner_address_raw, &spender_address_raw, &expires)?;
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "increase_allowance"),
+            log("owner", env.message.sender.as_str()),
+            log("spender", spender.as_str()),
+            log("amount", amount),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_decrease_allowance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    spender: &HumanAddr,
+    amount: &Uint128,
+    expires: Option<Expiration>,
+) -> StdResult<HandleResponse> {
+    let owner_address_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    let spender_address_raw = canonicalize_address(&deps.api, spender)?;
+    if owner_address_raw == spender_address_raw {
+        return Err(StdError::generic_err("Cannot set allowance to own account"));
+    }
+
+    // Decreasing below zero clears the allowance instead of failing, so a spender
+    // using part of it right before this tx cannot make the decrease revert
+    let allowance = get_allowance(&deps.storage, &owner_address_raw, &spender_address_raw)?;
+    let new_allowance = allowance.saturating_sub(amount.u128());
+    set_allowance(
+        &mut deps.storage,
+        &owner_address_raw,
+        &spender_address_raw,
+        new_allowance,
+    )?;
+    if let Some(expires) = expires {
+        set_allowance_expiration(&mut deps.storage, &owner_address_raw, &spender_address_raw, &expires)?;
+    }
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "decrease_allowance"),
+            log("owner", env.message.sender.as_str()),
+            log("spender", spender.as_str()),
+            log("amount", amount),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+/// Deducts from an unexpired allowance of owner to spender
+fn spend_allowance<T: Storage>(
+    store: &mut T,
+    env: &Env,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
+    amount: u128,
+) -> StdResult<()> {
+    let expires = get_allowance_expiration(store, owner, spender)?;
+    if expires.is_expired(&env.block) {
+        return Err(StdError::generic_err(format!(
+            "Allowance is expired: {}",
+            expires
+        )));
+    }
+
+    let allowance = get_allowance(store, owner, spender)?;
+    if allowance < amount {
+        return Err(StdError::generic_err(format!(
+            "Insufficient allowance: allowance={}, required={}",
+            allowance, amount
+        )));
+    }
+    set_allowance(store, owner, spender, allowance - amount)
+}
+
+/// Burns tokens and removes them from the total supply
+fn burn_supply<T: Storage>(store: &mut T, owner: &CanonicalAddr, amount: u128) -> StdResult<()> {
+    burn_tokens(store, owner, amount)?;
+    let mut config = get_config(store)?;
+    config.total_supply = (config.total_supply - Uint128::from(amount))?;
+    set_config(store, &config)
+}
+
+pub fn perform_transfer<T: Storage>(
     store: &mut T,
     from: &CanonicalAddr,
     to: &CanonicalAddr,
     amount: u128,
 ) -> StdResult<()> {
-    let mut balances_store = PrefixedStorage::new(BALANCE_PREFIX, store);
-
-    let mut from_balance = to_u128(&balances_store, from.as_slice())?;
+    let mut from_balance = get_balance(store, from)?;
     if from_balance < amount {
         return Err(StdError::generic_err(format!(
             "Insufficient funds: sender={}, balance={}, required={}",
@@ -129,12 +425,16 @@ fn perform_transfer<T: Storage>(
             amount
         )));
     }
+    checkpoint_yield(store, from)?;
+    checkpoint_yield(store, to)?;
     from_balance -= amount;
-    balances_store.set(from.as_slice(), &from_balance.to_be_bytes());
+    set_balance(store, from, from_balance)?;
+
+    let to_balance = get_balance(store, to)? + amount;
+    set_balance(store, to, to_balance)?;
 
-    let mut to_balance = to_u128(&balances_store, to.as_slice())?;
-    to_balance += amount;
-    balances_store.set(to.as_slice(), &to_balance.to_be_bytes());
+    update_leaderboard(store, TOP_SUPPLIERS_KEY, from, from_balance)?;
+    update_leaderboard(store, TOP_SUPPLIERS_KEY, to, to_balance)?;
 
     Ok(())
 }
@@ -144,11 +444,11 @@ pub fn mint_tokens<T: Storage>(
     to: &CanonicalAddr,
     amount: u128,
 ) -> StdResult<()> {
-    let mut balances_store = PrefixedStorage::new(BALANCE_PREFIX, store);
+    checkpoint_yield(store, to)?;
+    let to_balance = get_balance(store, to)? + amount;
+    set_balance(store, to, to_balance)?;
 
-    let mut to_balance = to_u128(&balances_store, to.as_slice())?;
-    to_balance += amount;
-    balances_store.set(to.as_slice(), &to_balance.to_be_bytes());
+    update_leaderboard(store, TOP_SUPPLIERS_KEY, to, to_balance)?;
 
     Ok(())
 }
@@ -159,11 +459,235 @@ pub fn burn_tokens<T: Storage>(
     to: &CanonicalAddr,
     amount: u128,
 ) -> StdResult<()> {
-    let mut balances_store = PrefixedStorage::new(BALANCE_PREFIX, store);
-
-    let mut to_balance = to_u128(&balances_store, to.as_slice())?;
+    let mut to_balance = get_balance(store, to)?;
+    if to_balance < amount {
+        return Err(StdError::generic_err(format!(
+            "Insufficient funds: sender={}, balance={}, required={}",
+            HumanAddr::from(to.to_string()),
+            to_balance,
+            amount
+        )));
+    }
+    checkpoint_yield(store, to)?;
     to_balance -= amount;
-    balances_store.set(to.as_slice(), &to_balance.to_be_bytes());
+    set_balance(store, to, to_balance)?;
+
+    update_leaderboard(store, TOP_SUPPLIERS_KEY, to, to_balance)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_storage::PrefixedStorage;
+
+    use crate::contract::testing::{mock_init_msg, mock_market, snapshot_storage, Rng};
+    use crate::state::{ALLOWANCE_PREFIX, BALANCE_PREFIX};
+
+    const OWNER: &str = "owner";
+    const SPENDER: &str = "spender";
+    const RECIPIENT: &str = "recipient";
+
+    fn allowance<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> u128 {
+        let owner = deps.api.canonical_address(&HumanAddr::from(OWNER)).unwrap();
+        let spender = deps.api.canonical_address(&HumanAddr::from(SPENDER)).unwrap();
+        get_allowance(&deps.storage, &owner, &spender).unwrap()
+    }
+
+    fn spend<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, height: u64, amount: u128) -> StdResult<HandleResponse> {
+        let mut env = mock_env(SPENDER, &[]);
+        env.block.height = height;
+        try_transfer_from(deps, env, &HumanAddr::from(OWNER), &HumanAddr::from(RECIPIENT), &Uint128::from(amount))
+    }
+
+    fn funded_market() -> Extern<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_market(mock_init_msg());
+        let owner = deps.api.canonical_address(&HumanAddr::from(OWNER)).unwrap();
+        mint_tokens(&mut deps.storage, &owner, 1_000).unwrap();
+        deps
+    }
+
+    #[test]
+    fn decrease_allowance_saturates_at_zero() {
+        let mut deps = funded_market();
+        let spender = HumanAddr::from(SPENDER);
+        try_increase_allowance(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(100u128), None).unwrap();
+        try_decrease_allowance(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(40u128), None).unwrap();
+        assert_eq!(allowance(&deps), 60);
+
+        // A spender using part of the allowance first cannot make the decrease fail
+        spend(&mut deps, 12_345, 50).unwrap();
+        try_decrease_allowance(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(60u128), None).unwrap();
+        assert_eq!(allowance(&deps), 0);
+        try_decrease_allowance(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(u128::MAX), None).unwrap();
+        assert_eq!(allowance(&deps), 0);
+
+        assert_eq!(
+            spend(&mut deps, 12_345, 1).unwrap_err(),
+            StdError::generic_err("Insufficient allowance: allowance=0, required=1")
+        );
+    }
+
+    #[test]
+    fn allowance_expires_at_its_height() {
+        let mut deps = funded_market();
+        let spender = HumanAddr::from(SPENDER);
+        let expires = Some(Expiration::AtHeight(12_400));
+        try_increase_allowance(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(100u128), expires).unwrap();
+
+        spend(&mut deps, 12_399, 10).unwrap();
+        assert_eq!(
+            spend(&mut deps, 12_400, 10).unwrap_err(),
+            StdError::generic_err("Allowance is expired: expiration height: 12400")
+        );
+        assert_eq!(allowance(&deps), 90);
+
+        // Increasing without an expiration keeps the current one
+        try_increase_allowance(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(10u128), None).unwrap();
+        assert!(spend(&mut deps, 12_400, 10).is_err());
+
+        // A new expiration revives the allowance
+        let expires = Some(Expiration::Never {});
+        try_decrease_allowance(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(50u128), expires).unwrap();
+        spend(&mut deps, 20_000, 50).unwrap();
+        assert_eq!(allowance(&deps), 0);
+    }
+
+    #[test]
+    fn approve_replaces_the_allowance_and_never_expires() {
+        let mut deps = funded_market();
+        let spender = HumanAddr::from(SPENDER);
+        let expires = Some(Expiration::AtHeight(12_400));
+        try_increase_allowance(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(100u128), expires).unwrap();
+        try_approve(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(30u128)).unwrap();
+        assert_eq!(allowance(&deps), 30);
+        spend(&mut deps, 20_000, 30).unwrap();
+
+        let mut msg = mock_init_msg();
+        msg.approve_enabled = false;
+        let mut deps = mock_market(msg);
+        assert!(try_approve(&mut deps, mock_env(OWNER, &[]), &spender, &Uint128::from(30u128)).is_err());
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    enum Op {
+        Transfer,
+        TransferFrom,
+        Approve,
+        IncreaseAllowance,
+        DecreaseAllowance,
+    }
+
+    #[test]
+    fn random_transfers_and_allowances_conserve_supply() {
+        const ACCOUNTS: usize = 5;
+        const OPS: [Op; 5] = [Op::Transfer, Op::TransferFrom, Op::Approve, Op::IncreaseAllowance, Op::DecreaseAllowance];
+
+        for seed in 1..=32u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let mut deps = mock_market(mock_init_msg());
+            let names: Vec<HumanAddr> = (0..ACCOUNTS).map(|i| HumanAddr::from(format!("account{}", i))).collect();
+            let raws: Vec<CanonicalAddr> = names.iter().map(|name| deps.api.canonical_address(name).unwrap()).collect();
+
+            // Even accounts start in the raw 16 byte layout, odd ones in the Uint128 bucket layout
+            let mut balances = [0u128; ACCOUNTS];
+            let mut allowances = [[0u128; ACCOUNTS]; ACCOUNTS];
+            for i in 0..ACCOUNTS {
+                balances[i] = rng.next() as u128 % 10_000;
+                let spender = rng.below(ACCOUNTS);
+                allowances[i][spender] = rng.next() as u128 % 10_000;
+                if i % 2 == 0 {
+                    PrefixedStorage::new(BALANCE_PREFIX, &mut deps.storage)
+                        .set(raws[i].as_slice(), &balances[i].to_be_bytes());
+                    PrefixedStorage::multilevel(&[ALLOWANCE_PREFIX, raws[i].as_slice()], &mut deps.storage)
+                        .set(raws[spender].as_slice(), &allowances[i][spender].to_be_bytes());
+                } else {
+                    set_balance(&mut deps.storage, &raws[i], balances[i]).unwrap();
+                    set_allowance(&mut deps.storage, &raws[i], &raws[spender], allowances[i][spender]).unwrap();
+                }
+            }
+            let total_supply: u128 = balances.iter().sum();
+            let mut config = get_config(&deps.storage).unwrap();
+            config.total_supply = Uint128::from(total_supply);
+            set_config(&mut deps.storage, &config).unwrap();
+
+            for step in 0..200 {
+                let op = OPS[rng.below(OPS.len())];
+                let (a, b, c) = (rng.below(ACCOUNTS), rng.below(ACCOUNTS), rng.below(ACCOUNTS));
+                let amount = match op {
+                    Op::Transfer => rng.amount(balances[a]),
+                    Op::TransferFrom => rng.amount(balances[a].min(allowances[a][b])),
+                    _ => rng.amount(allowances[a][b]),
+                };
+                let uint = Uint128::from(amount);
+
+                // What the handler must do, None when it must fail
+                let expected = match op {
+                    Op::Transfer if balances[a] >= amount => {
+                        let mut next = (balances, allowances);
+                        next.0[a] -= amount;
+                        next.0[b] += amount;
+                        Some(next)
+                    }
+                    // `b` spends `a`'s allowance to pay `c`
+                    Op::TransferFrom if allowances[a][b] >= amount && balances[a] >= amount => {
+                        let mut next = (balances, allowances);
+                        next.1[a][b] -= amount;
+                        next.0[a] -= amount;
+                        next.0[c] += amount;
+                        Some(next)
+                    }
+                    Op::Approve => {
+                        let mut next = (balances, allowances);
+                        next.1[a][b] = amount;
+                        Some(next)
+                    }
+                    Op::IncreaseAllowance if a != b && allowances[a][b].checked_add(amount).is_some() => {
+                        let mut next = (balances, allowances);
+                        next.1[a][b] += amount;
+                        Some(next)
+                    }
+                    Op::DecreaseAllowance if a != b => {
+                        let mut next = (balances, allowances);
+                        next.1[a][b] = allowances[a][b].saturating_sub(amount);
+                        Some(next)
+                    }
+                    _ => None,
+                };
+
+                let before = snapshot_storage(&deps.storage);
+                let result = match op {
+                    Op::Transfer => try_transfer(&mut deps, mock_env(&names[a], &[]), &names[b], &uint),
+                    Op::TransferFrom => try_transfer_from(&mut deps, mock_env(&names[b], &[]), &names[a], &names[c], &uint),
+                    Op::Approve => try_approve(&mut deps, mock_env(&names[a], &[]), &names[b], &uint),
+                    Op::IncreaseAllowance => try_increase_allowance(&mut deps, mock_env(&names[a], &[]), &names[b], &uint, None),
+                    Op::DecreaseAllowance => try_decrease_allowance(&mut deps, mock_env(&names[a], &[]), &names[b], &uint, None),
+                };
+                let context = format!("seed {} step {}: {:?} {} {} {} {}", seed, step, op, a, b, c, amount);
+                match (result, expected) {
+                    (Ok(_), Some(next)) => {
+                        balances = next.0;
+                        allowances = next.1;
+                    }
+                    (Err(_), None) => deps.storage = before,
+                    (result, _) => panic!("{}: unexpected {:?}", context, result.map(|_| ())),
+                }
+
+                for i in 0..ACCOUNTS {
+                    assert_eq!(get_balance(&deps.storage, &raws[i]).unwrap(), balances[i], "{}: balance of {}", context, i);
+                    for j in 0..ACCOUNTS {
+                        assert_eq!(
+                            get_allowance(&deps.storage, &raws[i], &raws[j]).unwrap(),
+                            allowances[i][j],
+                            "{}: allowance of {} to {}", context, i, j
+                        );
+                    }
+                }
+                let sum: u128 = (0..ACCOUNTS).map(|i| get_balance(&deps.storage, &raws[i]).unwrap()).sum();
+                assert_eq!(sum, total_supply, "{}", context);
+                assert_eq!(get_config(&deps.storage).unwrap().total_supply.u128(), total_supply, "{}", context);
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use cosmwasm_std::{
+    log, to_binary, Api, Env, Extern, HandleResponse, HumanAddr, Querier, QueryRequest, StdError, StdResult,
+    Storage, Uint128, WasmQuery,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::market::{TransferPolicyQueryMsg, TransferPolicyResponse};
+
+use crate::msg::TransferPolicyMsg;
+use crate::state::{get_config, set_config, TransferPolicy};
+
+use crate::contract::handler::audit::{audit, ROLE_ADMIN};
+
+pub fn transfer_policy_msg<A: Api>(
+    api: &A,
+    transfer_policy: Option<&TransferPolicy>,
+) -> StdResult<Option<TransferPolicyMsg>> {
+    match transfer_policy {
+        Some(TransferPolicy::Enforced { contract }) => Ok(Some(TransferPolicyMsg::Enforced {
+            contract: api.human_address(contract)?,
+        })),
+        Some(TransferPolicy::Disabled) => Ok(Some(TransferPolicyMsg::Disabled)),
+        None => Ok(None),
+    }
+}
+
+/// Admin only, a disabled policy cannot be enforced again
+pub fn try_set_transfer_policy<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract: Option<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let mut config = get_config(&deps.storage)?;
+    if canonicalize_address(&deps.api, &env.message.sender)? != config.admin {
+        return Err(StdError::unauthorized());
+    }
+    let old_transfer_policy = transfer_policy_msg(&deps.api, config.transfer_policy.as_ref())?;
+    config.transfer_policy = match (&config.transfer_policy, &contract) {
+        (Some(TransferPolicy::Disabled), _) => {
+            return Err(StdError::generic_err("Transfer policy was disabled and cannot be enforced again"))
+        }
+        (None, None) => return Err(StdError::generic_err("No transfer policy is enforced")),
+        (_, Some(contract)) => Some(TransferPolicy::Enforced {
+            contract: canonicalize_address(&deps.api, contract)?,
+        }),
+        (Some(TransferPolicy::Enforced { .. }), None) => Some(TransferPolicy::Disabled),
+    };
+    let new_transfer_policy = transfer_policy_msg(&deps.api, config.transfer_policy.as_ref())?;
+    set_config(&mut deps.storage, &config)?;
+    let audit_log = audit(deps, &env, ROLE_ADMIN, "transfer_policy", &old_transfer_policy, &new_transfer_policy)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_transfer_policy"),
+            log("sender", env.message.sender.as_str()),
+            log("transfer_policy", contract.as_ref().map(|contract| contract.as_str()).unwrap_or("")),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// Fails unless the enforced transfer policy allows `amount` of qTokens reaching `to`,
+/// `from` is None for minted qTokens. Any qToken movement between accounts goes
+/// through here, a policy that fails to answer blocks the transfer
+pub fn assert_transfer_allowed<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    from: Option<&HumanAddr>,
+    to: &HumanAddr,
+    amount: Uint128,
+) -> StdResult<()> {
+    let contract = match get_config(&deps.storage)?.transfer_policy {
+        Some(TransferPolicy::Enforced { contract }) => deps.api.human_address(&contract)?,
+        _ => return Ok(()),
+    };
+    let res: TransferPolicyResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: contract,
+        msg: to_binary(&TransferPolicyQueryMsg::CheckTransfer {
+            from: from.cloned(),
+            to: to.clone(),
+            amount,
+        })?,
+    }))?;
+    if !res.allowed {
+        return Err(StdError::generic_err(format!(
+            "Transfer refused by the transfer policy: to: {}, reason: {}",
+            to,
+            res.reason.unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use cosmwasm_std::{log, Api, Env, Extern, HandleResponse, LogAttribute, Querier, StdError, StdResult, Storage, Uint128};
+
+use crate::state::{
+    get_state, get_utilization_alerts, set_utilization_alerts, State, UtilizationAlerts,
+    MAX_UTILIZATION_ALERTS, Role,
+};
+
+use crate::contract::handler::audit::audit;
+use crate::contract::handler::interest_model::get_utilization_rate;
+use crate::contract::handler::roles::assert_role;
+
+/// Admin or param_admin only, empty thresholds stop alerts
+pub fn try_set_utilization_alerts<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    thresholds: Vec<Uint128>,
+) -> StdResult<HandleResponse> {
+    let role = assert_role(deps, &env, Role::ParamAdmin)?;
+    if thresholds.len() > MAX_UTILIZATION_ALERTS {
+        return Err(StdError::generic_err(format!(
+            "Too many utilization alert thresholds: thresholds: {}, max_utilization_alerts: {}",
+            thresholds.len(), MAX_UTILIZATION_ALERTS
+        )));
+    }
+    if thresholds.iter().any(|threshold| threshold.is_zero() || threshold.u128() > 100_000_000) {
+        return Err(StdError::generic_err("Utilization alert thresholds must be within (0, 1]"));
+    }
+    let mut thresholds = thresholds;
+    thresholds.sort();
+    thresholds.dedup();
+
+    // Crossings are measured from the utilization at the time of setting
+    let old_thresholds = get_utilization_alerts(&deps.storage)?.map(|alerts| alerts.thresholds).unwrap_or_default();
+    let state = get_state(&deps.storage)?;
+    let utilization = get_utilization_rate(&state.cash, &state.total_borrows, &state.total_reserves);
+    let utilization_alerts = UtilizationAlerts {
+        thresholds: thresholds.clone(),
+        last_utilization: Uint128::from(utilization),
+    };
+    match thresholds.is_empty() {
+        true => set_utilization_alerts(&mut deps.storage, None)?,
+        false => set_utilization_alerts(&mut deps.storage, Some(&utilization_alerts))?,
+    }
+    let audit_log = audit(deps, &env, role, "utilization_alerts", &old_thresholds, &thresholds)?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_utilization_alerts"),
+            log("sender", env.message.sender.as_str()),
+            log("thresholds", thresholds.len()),
+            log("utilization", utilization),
+        ],
+        data: None,
+    };
+    res.log.extend(audit_log);
+    Ok(res)
+}
+
+/// utilization_threshold_crossed events for every threshold utilization crossed
+/// since the last accrual, at the state the accrual left
+pub fn utilization_alert_log<S: Storage>(storage: &mut S, state: &State) -> StdResult<Vec<LogAttribute>> {
+    let mut utilization_alerts = match get_utilization_alerts(storage)? {
+        Some(utilization_alerts) => utilization_alerts,
+        None => return Ok(vec![]),
+    };
+    let previous = utilization_alerts.last_utilization.u128();
+    let current = get_utilization_rate(&state.cash, &state.total_borrows, &state.total_reserves);
+    let mut alert_log = vec![];
+    for threshold in utilization_alerts.thresholds.iter().map(|threshold| threshold.u128()) {
+        let direction = if previous < threshold && current >= threshold {
+            "above"
+        } else if previous >= threshold && current < threshold {
+            "below"
+        } else {
+            continue;
+        };
+        alert_log.push(log("utilization_threshold_crossed", threshold));
+        alert_log.push(log("utilization_threshold_direction", direction));
+        alert_log.push(log("utilization", current));
+    }
+    if current != previous {
+        utilization_alerts.last_utilization = Uint128::from(current);
+        set_utilization_alerts(storage, Some(&utilization_alerts))?;
+    }
+    Ok(alert_log)
+}
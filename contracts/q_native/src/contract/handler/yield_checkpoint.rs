@@ -0,0 +1,37 @@
+use cosmwasm_std::{CanonicalAddr, StdResult, Storage, Uint128};
+
+use crate::state::{
+    get_balance, get_config, get_self_repay, get_state, get_yield_redirect, set_self_repay, set_yield_redirect,
+};
+
+use crate::contract::handler::collateral::{calculate_exchange_rate, underlying_for_tokens};
+
+/// Books the underlying value `balance` qTokens gained since the checkpoint's exchange rate
+/// and moves the checkpoint to `exchange_rate`
+pub fn book_yield(checkpoint_rate: &mut Uint128, accrued_yield: &mut Uint128, balance: u128, exchange_rate: u128) {
+    let rate_gain = exchange_rate.saturating_sub(checkpoint_rate.u128());
+    *accrued_yield += Uint128::from(underlying_for_tokens(balance, rate_gain));
+    *checkpoint_rate = Uint128::from(exchange_rate);
+}
+
+/// Books the yield of an opted-in account's balance before it changes, so qTokens only
+/// earn yield for the time they are held. Valued at the exchange rate of the last accrual
+pub fn checkpoint_yield<S: Storage>(storage: &mut S, account: &CanonicalAddr) -> StdResult<()> {
+    let self_repay = get_self_repay(storage, account)?;
+    let yield_redirect = get_yield_redirect(storage, account)?;
+    if self_repay.is_none() && yield_redirect.is_none() {
+        return Ok(());
+    }
+
+    let exchange_rate = calculate_exchange_rate(&get_config(storage)?, &get_state(storage)?)?;
+    let balance = get_balance(storage, account)?;
+    if let Some(mut self_repay) = self_repay {
+        book_yield(&mut self_repay.exchange_rate, &mut self_repay.accrued_yield, balance, exchange_rate);
+        set_self_repay(storage, account, Some(self_repay))?;
+    }
+    if let Some(mut yield_redirect) = yield_redirect {
+        book_yield(&mut yield_redirect.exchange_rate, &mut yield_redirect.accrued_yield, balance, exchange_rate);
+        set_yield_redirect(storage, account, Some(yield_redirect))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, HumanAddr, Querier, StdError, StdResult, Storage,
+    Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::comptroller::MarketAction;
+
+use crate::state::{
+    get_balance, get_config, get_self_repay, get_yield_redirect, set_yield_redirect, PauseAction, YieldRedirect,
+    YIELD_REDIRECT_EPOCH,
+};
+
+use crate::contract::handler::admin::assert_action_not_paused;
+use crate::contract::handler::balance_hook::balance_hook;
+use crate::contract::handler::collateral::{accrue_interest, assert_account_healthy, get_exchange_rate, tokens_for_underlying};
+use crate::contract::handler::comptroller::comptroller_hook;
+use crate::contract::handler::health_alert::health_alert_log;
+use crate::contract::handler::liquidation::assert_not_flagged;
+use crate::contract::handler::token::perform_transfer;
+use crate::contract::handler::transfer_policy::assert_transfer_allowed;
+use crate::contract::handler::yield_checkpoint::book_yield;
+
+pub fn try_enable_yield_redirect<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    beneficiary: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    if *beneficiary == env.message.sender {
+        return Err(StdError::generic_err("Beneficiary must differ from the sender"));
+    }
+    // Yield can only go one way, to the debt or to a beneficiary
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    if get_self_repay(&deps.storage, &sender_raw)?.is_some() {
+        return Err(StdError::generic_err("Yield repays the sender's debt, disable self-repay first"));
+    }
+    let beneficiary_raw = canonicalize_address(&deps.api, beneficiary)?;
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    // Only yield earned after enabling is donated, changing the beneficiary
+    // forfeits nothing to the new one
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    set_yield_redirect(&mut deps.storage, &sender_raw, Some(YieldRedirect {
+        beneficiary: beneficiary_raw,
+        exchange_rate: Uint128::from(exchange_rate),
+        last_settled: env.block.height,
+        accrued_yield: Uint128::zero(),
+    }))?;
+
+    let mut res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "enable_yield_redirect"),
+            log("sender", env.message.sender.as_str()),
+            log("beneficiary", beneficiary.as_str()),
+            log("exchange_rate", exchange_rate),
+        ],
+        data: None,
+    };
+    res.log.extend(accrual_log);
+    Ok(res)
+}
+
+pub fn try_disable_yield_redirect<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let sender_raw = canonicalize_address(&deps.api, &env.message.sender)?;
+    set_yield_redirect(&mut deps.storage, &sender_raw, None)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "disable_yield_redirect"),
+            log("sender", env.message.sender.as_str()),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_settle_yield_redirect<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    account: &HumanAddr,
+) -> StdResult<HandleResponse> {
+    assert_action_not_paused(&deps.storage, PauseAction::Transfer)?;
+    let account_raw = canonicalize_address(&deps.api, account)?;
+    let mut checkpoint = match get_yield_redirect(&deps.storage, &account_raw)? {
+        Some(checkpoint) => checkpoint,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "Yield redirect is not enabled: account: {}",
+                account
+            )));
+        }
+    };
+
+    let next_settlement = checkpoint.last_settled + YIELD_REDIRECT_EPOCH;
+    if env.block.height < next_settlement {
+        return Err(StdError::generic_err(format!(
+            "Yield redirect epoch has not ended: current_block: {}, next_settlement: {}",
+            env.block.height, next_settlement
+        )));
+    }
+    assert_not_flagged(&deps.storage, &account_raw, &env)?;
+
+    let accrual_log = accrue_interest(deps, env.clone())?;
+
+    let exchange_rate = get_exchange_rate(deps, env.clone())?;
+    let balance = get_balance(&deps.storage, &account_raw)?;
+
+    book_yield(&mut checkpoint.exchange_rate, &mut checkpoint.accrued_yield, balance, exchange_rate);
+    let yield_amount = checkpoint.accrued_yield.u128();
+    // Round the donated qTokens down so the account's principal is never touched
+    let donated_tokens = match exchange_rate {
+        0 => 0,
+        _ => tokens_for_underlying(yield_amount, exchange_rate).min(balance),
+    };
+
+    // Checkpointed first, so the transfer below books no further yield
+    set_yield_redirect(&mut deps.storage, &account_raw, Some(YieldRedirect {
+        beneficiary: checkpoint.beneficiary.clone(),
+        exchange_rate: checkpoint.exchange_rate,
+        last_settled: env.block.height,
+        accrued_yield: Uint128::zero(),
+    }))?;
+
+    let beneficiary = deps.api.human_address(&checkpoint.beneficiary)?;
+    if donated_tokens > 0 {
+        assert_transfer_allowed(deps, Some(account), &beneficiary, Uint128::from(donated_tokens))?;
+        perform_transfer(&mut deps.storage, &account_raw, &checkpoint.beneficiary, donated_tokens)?;
+        assert_account_healthy(&deps.storage, &account_raw)?;
+    }
+
+    // Nothing moved when no yield was settled
+    let messages = match donated_tokens {
+        0 => vec![],
+        _ => {
+            let mut messages = balance_hook(&deps.storage, &deps.api, &[&account_raw, &checkpoint.beneficiary])?;
+            messages.extend(comptroller_hook(&deps.api, &get_config(&deps.storage)?, MarketAction::Transfer, account, donated_tokens)?);
+            messages
+        }
+    };
+    let mut res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "settle_yield_redirect"),
+            log("sender", env.message.sender.as_str()),
+            log("account", account.as_str()),
+            log("beneficiary", beneficiary.as_str()),
+            log("yield_amount", yield_amount),
+            log("donated_tokens", donated_tokens),
+        ],
+        data: None,
+    };
+    res.log.extend(health_alert_log(&mut deps.storage, &deps.api, &[&account_raw, &checkpoint.beneficiary])?);
+    res.log.extend(accrual_log);
+    Ok(res)
+}
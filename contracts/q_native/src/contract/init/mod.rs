@@ -1,7 +1,16 @@
-use cosmwasm_std::{Api, Env, Extern, InitResponse, Querier, StdResult, Storage, Uint128};
+use cosmwasm_std::{Api, Env, Extern, InitResponse, Querier, StdError, StdResult, Storage, Uint128};
+
+use quasar_interfaces::address::canonicalize_address;
 
 use crate::msg::InitMsg;
-use crate::state::{config, Config, set_state, State};
+use crate::contract::handler::admin::{validate_borrow_cooldown, validate_min_borrow_health_factor};
+use crate::contract::handler::backstop::validate_backstop;
+use crate::contract::handler::buyback::buyback_from_msg;
+use crate::contract::handler::cash_strategy::cash_strategy_from_msg;
+use crate::contract::handler::insurance::validate_insurance;
+use crate::contract::handler::interest_model::{validate_blocks_per_year, validate_interest_rate_model};
+use crate::contract::{CONTRACT_NAME, CONTRACT_VERSION};
+use crate::state::{config, set_contract_version, Config, OracleConfig, TransferPolicy, set_interest_rate_model, set_state, State, MAX_FLASH_LOAN_FEE, MAX_PROTOCOL_SEIZE_SHARE, MAX_RESERVE_FACTOR};
 
 /// Contract instantiation tx
 /// tx inputs are specified in InitMsg in msg.rs file
@@ -10,19 +19,137 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
+    if msg.early_exit_penalty.u128() > 100_000_000 {
+        return Err(StdError::generic_err(format!(
+            "Early exit penalty must not exceed 1: early_exit_penalty: {}",
+            msg.early_exit_penalty
+        )));
+    }
+
+    // As in cw20-base, also keeps humanized query amounts within u128
+    if msg.decimals > 18 {
+        return Err(StdError::generic_err(format!(
+            "Decimals must not exceed 18: decimals: {}",
+            msg.decimals
+        )));
+    }
+
+    if msg.max_accrual_block_delta == Some(0) {
+        return Err(StdError::generic_err("Max accrual block delta must be positive"));
+    }
+
+    if msg.utilization_window == Some(0) {
+        return Err(StdError::generic_err("Utilization window must be positive"));
+    }
+
+    if msg.reserve_factor.u128() > MAX_RESERVE_FACTOR {
+        return Err(StdError::generic_err(format!(
+            "Reserve factor exceeds maximum: reserve_factor: {}, max_reserve_factor: {}",
+            msg.reserve_factor, MAX_RESERVE_FACTOR
+        )));
+    }
+
+    if msg.collateral_factor.u128() > 100_000_000 {
+        return Err(StdError::generic_err(format!(
+            "Collateral factor must not exceed 1: collateral_factor: {}",
+            msg.collateral_factor
+        )));
+    }
+    if msg.close_factor.is_zero() || msg.close_factor.u128() > 100_000_000 {
+        return Err(StdError::generic_err(format!(
+            "Close factor must be within (0, 1]: close_factor: {}",
+            msg.close_factor
+        )));
+    }
+
+    if msg.protocol_seize_share.u128() > MAX_PROTOCOL_SEIZE_SHARE {
+        return Err(StdError::generic_err(format!(
+            "Protocol seize share exceeds maximum: protocol_seize_share: {}, max_protocol_seize_share: {}",
+            msg.protocol_seize_share, MAX_PROTOCOL_SEIZE_SHARE
+        )));
+    }
+
+    if let Some(flash_loan_fee) = msg.flash_loan_fee {
+        if flash_loan_fee.u128() > MAX_FLASH_LOAN_FEE {
+            return Err(StdError::generic_err(format!(
+                "Flash loan fee exceeds maximum: flash_loan_fee: {}, max_flash_loan_fee: {}",
+                flash_loan_fee, MAX_FLASH_LOAN_FEE
+            )));
+        }
+    }
+
+    let interest_rate_model = msg.interest_rate_model.unwrap_or_default();
+    validate_interest_rate_model(&interest_rate_model, msg.max_borrow_rate.u128())?;
+
+    let admin = msg.admin.unwrap_or_else(|| env.message.sender.clone());
+    let underlying = msg.underlying.to_raw(&deps.api)?;
     let init_config = Config {
+        admin: canonicalize_address(&deps.api, &admin)?,
+        contract_addr: canonicalize_address(&deps.api, &env.contract.address)?,
         name: msg.name,
         total_supply: msg.total_supply,
         decimals: msg.decimals,
         symbol: msg.symbol,
-        denom: msg.denom,
+        underlying: underlying.clone(),
         initial_exchange_rate: msg.initial_exchange_rate,
         reserve_factor: msg.reserve_factor,
         max_borrow_rate: msg.max_borrow_rate,
         borrow_index: msg.borrow_index,
+        approve_enabled: msg.approve_enabled,
+        large_redeem_threshold: msg.large_redeem_threshold,
+        redeem_announce_delay: msg.redeem_announce_delay,
+        term_bonus_rates: msg.term_bonus_rates,
+        early_exit_penalty: msg.early_exit_penalty,
+        max_accrual_block_delta: msg.max_accrual_block_delta,
+        utilization_window: msg.utilization_window,
+        dust_borrow_threshold: msg.dust_borrow_threshold,
+        collateral_factor: msg.collateral_factor,
+        close_factor: msg.close_factor,
+        liquidation_incentive: msg.liquidation_incentive,
+        protocol_seize_share: msg.protocol_seize_share,
+        oracle: match msg.oracle {
+            Some(oracle) => Some(OracleConfig {
+                contract: canonicalize_address(&deps.api, &oracle.contract)?,
+                kind: oracle.kind,
+                base: oracle.base,
+                quote: oracle.quote,
+            }),
+            None => None,
+        },
+        comptroller: match msg.comptroller {
+            Some(comptroller) => Some(canonicalize_address(&deps.api, &comptroller)?),
+            None => None,
+        },
+        treasury: canonicalize_address(&deps.api, msg.treasury.as_ref().unwrap_or(&admin))?,
+        guardian: match msg.guardian {
+            Some(guardian) => Some(canonicalize_address(&deps.api, &guardian)?),
+            None => None,
+        },
+        flash_loan_fee: msg.flash_loan_fee,
+        cash_strategy: cash_strategy_from_msg(&deps.api, &underlying, msg.cash_strategy)?,
+        balance_hook: match msg.balance_hook {
+            Some(balance_hook) => Some(canonicalize_address(&deps.api, &balance_hook)?),
+            None => None,
+        },
+        insurance: msg.insurance,
+        blocks_per_year: msg.blocks_per_year,
+        buyback: buyback_from_msg(&deps.api, &underlying, msg.buyback)?,
+        borrow_cooldown: validate_borrow_cooldown(msg.borrow_cooldown)?,
+        min_borrow_health_factor: validate_min_borrow_health_factor(msg.min_borrow_health_factor)?,
+        backstop: validate_backstop(msg.backstop)?,
+        transfer_policy: match msg.transfer_policy {
+            Some(contract) => Some(TransferPolicy::Enforced {
+                contract: canonicalize_address(&deps.api, &contract)?,
+            }),
+            None => None,
+        },
     };
+    validate_insurance(&init_config)?;
+    validate_blocks_per_year(init_config.blocks_per_year)?;
 
     config(&mut deps.storage).save(&init_config)?;
+    set_contract_version(&mut deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    set_interest_rate_model(&mut deps.storage, &interest_rate_model)?;
 
     
     let init_state = State {
@@ -0,0 +1,383 @@
+use cosmwasm_std::{
+    log, Api, Env, Extern, MigrateResponse, Querier, StdError, StdResult, Storage, Uint128,
+};
+
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::asset::AssetInfoRaw;
+
+use crate::contract::handler::audit::audit_value;
+use crate::contract::handler::compaction::compact_storage;
+use crate::contract::{CONTRACT_NAME, CONTRACT_VERSION};
+use crate::msg::{LegacyConfigParams, MigrateMsg};
+use crate::state::{
+    append_migration, get_config, get_contract_version, load_stored_config, set_config, set_contract_version,
+    Config, ConfigChange, ConfigV0, MigrationRecord, StoredConfig, TermBonusRates,
+};
+
+/// Contract migration tx
+/// Upgrades stored layouts written by older versions, then records the new version
+pub fn migrate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: MigrateMsg,
+) -> StdResult<MigrateResponse> {
+    // Deployments without a stored version predate versioning altogether
+    let from_version = match get_contract_version(&deps.storage)? {
+        Some(stored) => {
+            if stored.contract != CONTRACT_NAME {
+                return Err(StdError::generic_err(format!(
+                    "Cannot migrate from a different contract: stored: {}, new: {}",
+                    stored.contract, CONTRACT_NAME
+                )));
+            }
+            if parse_version(&stored.version)? > parse_version(CONTRACT_VERSION)? {
+                return Err(StdError::generic_err(format!(
+                    "Cannot migrate to an older version: stored: {}, new: {}",
+                    stored.version, CONTRACT_VERSION
+                )));
+            }
+            stored.version
+        }
+        None => "0.0.0".to_string(),
+    };
+
+    let stored_config = load_stored_config(&deps.storage)?;
+    let old_values = stored_config_values(&stored_config)?;
+    let mut upgraded = vec![];
+    let mut upgraded_layouts = vec![];
+    if let StoredConfig::V0(legacy) = stored_config {
+        let params = match msg.legacy_config {
+            Some(params) => params,
+            None => {
+                return Err(StdError::generic_err(
+                    "Stored config predates contract versions, legacy_config is required",
+                ));
+            }
+        };
+        let config = upgrade_config_v0(deps, &env, legacy, params)?;
+        set_config(&mut deps.storage, &config)?;
+        upgraded.push(log("upgraded", "config_v0"));
+        upgraded_layouts.push("config_v0".to_string());
+    }
+
+    // Large markets finish the compaction through CompactStorage
+    if let Some(limit) = msg.compact_limit {
+        upgraded.extend(compact_storage(&mut deps.storage, Some(limit))?);
+    }
+
+    set_contract_version(&mut deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    let changes = config_changes(old_values, config_values(&get_config(&deps.storage)?)?);
+    let record = append_migration(&mut deps.storage, MigrationRecord {
+        id: 0,
+        from_version: from_version.clone(),
+        to_version: CONTRACT_VERSION.to_string(),
+        block_number: env.block.height,
+        upgraded: upgraded_layouts,
+        changes,
+    })?;
+
+    let mut log_attrs = vec![
+        log("action", "migrate"),
+        log("from_version", from_version),
+        log("to_version", CONTRACT_VERSION),
+        log("migration_id", record.id),
+        log("config_changes", record.changes.len()),
+    ];
+    log_attrs.extend(upgraded);
+    Ok(MigrateResponse {
+        messages: vec![],
+        log: log_attrs,
+        data: None,
+    })
+}
+
+/// Fields added after the first deployments start disabled unless they
+/// affect solvency, those are taken from the migrate message
+fn upgrade_config_v0<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    legacy: ConfigV0,
+    params: LegacyConfigParams,
+) -> StdResult<Config> {
+    if params.collateral_factor.u128() > 100_000_000 {
+        return Err(StdError::generic_err(format!(
+            "Collateral factor must not exceed 1: collateral_factor: {}",
+            params.collateral_factor
+        )));
+    }
+    if params.close_factor.is_zero() || params.close_factor.u128() > 100_000_000 {
+        return Err(StdError::generic_err(format!(
+            "Close factor must be within (0, 1]: close_factor: {}",
+            params.close_factor
+        )));
+    }
+
+    let admin = params.admin.unwrap_or_else(|| env.message.sender.clone());
+    Ok(Config {
+        admin: canonicalize_address(&deps.api, &admin)?,
+        contract_addr: canonicalize_address(&deps.api, &env.contract.address)?,
+        name: legacy.name,
+        total_supply: legacy.total_supply,
+        decimals: legacy.decimals,
+        symbol: legacy.symbol,
+        initial_exchange_rate: legacy.initial_exchange_rate,
+        reserve_factor: legacy.reserve_factor,
+        borrow_index: legacy.borrow_index,
+        max_borrow_rate: legacy.max_borrow_rate,
+        underlying: AssetInfoRaw::NativeToken { denom: legacy.denom },
+        // Approve was the only way to grant allowances in the first layout
+        approve_enabled: true,
+        large_redeem_threshold: None,
+        redeem_announce_delay: 0,
+        term_bonus_rates: TermBonusRates::default(),
+        early_exit_penalty: Uint128::from(0u128),
+        max_accrual_block_delta: None,
+        utilization_window: None,
+        dust_borrow_threshold: None,
+        collateral_factor: params.collateral_factor,
+        close_factor: params.close_factor,
+        liquidation_incentive: params.liquidation_incentive,
+        protocol_seize_share: Uint128::from(0u128),
+        oracle: None,
+        comptroller: None,
+        treasury: canonicalize_address(&deps.api, params.treasury.as_ref().unwrap_or(&admin))?,
+        guardian: None,
+        flash_loan_fee: None,
+        balance_hook: None,
+        cash_strategy: None,
+        insurance: None,
+        blocks_per_year: None,
+        buyback: None,
+        transfer_policy: None,
+        borrow_cooldown: None,
+        min_borrow_health_factor: None,
+        backstop: None,
+    })
+}
+
+/// Each field of a config layout next to its JSON encoding, the destructuring
+/// fails to compile when a field is added without being listed here
+macro_rules! field_values {
+    ($config:expr, $layout:ident { $($field:ident),* $(,)? }) => {{
+        let $layout { $($field),* } = $config;
+        vec![$((stringify!($field), audit_value($field)?)),*]
+    }};
+}
+
+fn config_values(config: &Config) -> StdResult<Vec<(&'static str, String)>> {
+    Ok(field_values!(config, Config {
+        admin,
+        contract_addr,
+        name,
+        total_supply,
+        decimals,
+        symbol,
+        initial_exchange_rate,
+        reserve_factor,
+        borrow_index,
+        max_borrow_rate,
+        underlying,
+        approve_enabled,
+        large_redeem_threshold,
+        redeem_announce_delay,
+        term_bonus_rates,
+        early_exit_penalty,
+        max_accrual_block_delta,
+        utilization_window,
+        dust_borrow_threshold,
+        collateral_factor,
+        close_factor,
+        liquidation_incentive,
+        protocol_seize_share,
+        oracle,
+        comptroller,
+        treasury,
+        guardian,
+        flash_loan_fee,
+        balance_hook,
+        cash_strategy,
+        insurance,
+        blocks_per_year,
+        buyback,
+        transfer_policy,
+        borrow_cooldown,
+        min_borrow_health_factor,
+        backstop,
+    }))
+}
+
+fn stored_config_values(stored: &StoredConfig) -> StdResult<Vec<(&'static str, String)>> {
+    match stored {
+        StoredConfig::Current(config) => config_values(config),
+        StoredConfig::V0(legacy) => Ok(field_values!(legacy, ConfigV0 {
+            name,
+            total_supply,
+            decimals,
+            symbol,
+            initial_exchange_rate,
+            reserve_factor,
+            borrow_index,
+            max_borrow_rate,
+            denom,
+        })),
+    }
+}
+
+/// Fields whose encoding differs, in the new layout's order followed by removed fields
+fn config_changes(old_values: Vec<(&'static str, String)>, new_values: Vec<(&'static str, String)>) -> Vec<ConfigChange> {
+    let mut changes = vec![];
+    for (parameter, new_value) in new_values.iter() {
+        let old_value = old_values.iter().find(|(old, _)| old == parameter).map(|(_, value)| value.clone());
+        if old_value.as_ref() != Some(new_value) {
+            changes.push(ConfigChange {
+                parameter: parameter.to_string(),
+                old_value,
+                new_value: Some(new_value.clone()),
+            });
+        }
+    }
+    for (parameter, old_value) in old_values.into_iter() {
+        if !new_values.iter().any(|(new, _)| *new == parameter) {
+            changes.push(ConfigChange {
+                parameter: parameter.to_string(),
+                old_value: Some(old_value),
+                new_value: None,
+            });
+        }
+    }
+    changes
+}
+
+/// Numeric components of a `major.minor.patch` version, compared in order
+fn parse_version(version: &str) -> StdResult<Vec<u64>> {
+    version
+        .split('.')
+        .map(|part| {
+            part.parse::<u64>().map_err(|_| {
+                StdError::generic_err(format!("Invalid contract version: version: {}", version))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::HumanAddr;
+    use cosmwasm_storage::Singleton;
+
+    use crate::state::{get_migrations, CONFIG_PREFIX};
+
+    fn legacy_config() -> ConfigV0 {
+        ConfigV0 {
+            name: "Quasar Luna".to_string(),
+            total_supply: Uint128::from(5_000_000u128),
+            decimals: 6,
+            symbol: "qLUNA".to_string(),
+            initial_exchange_rate: Uint128::from(2_000_000u128),
+            reserve_factor: Uint128::from(10_000_000u128),
+            borrow_index: Uint128::from(103_000_000u128),
+            max_borrow_rate: Uint128::from(5_000u128),
+            denom: "uluna".to_string(),
+        }
+    }
+
+    fn legacy_params() -> LegacyConfigParams {
+        LegacyConfigParams {
+            admin: Some(HumanAddr::from("admin")),
+            treasury: None,
+            collateral_factor: Uint128::from(75_000_000u128),
+            close_factor: Uint128::from(50_000_000u128),
+            liquidation_incentive: Uint128::from(108_000_000u128),
+        }
+    }
+
+    #[test]
+    fn migrate_upgrades_every_field_of_a_v0_config() {
+        let mut deps = mock_dependencies(20, &[]);
+        Singleton::new(&mut deps.storage, CONFIG_PREFIX).save(&legacy_config()).unwrap();
+
+        let res = migrate(&mut deps, mock_env("migrator", &[]), MigrateMsg {
+            legacy_config: Some(legacy_params()),
+            compact_limit: None,
+        }).unwrap();
+        assert!(res.log.contains(&log("upgraded", "config_v0")));
+
+        let admin = deps.api.canonical_address(&HumanAddr::from("admin")).unwrap();
+        assert_eq!(get_config(&deps.storage).unwrap(), Config {
+            admin: admin.clone(),
+            contract_addr: deps.api.canonical_address(&HumanAddr::from(MOCK_CONTRACT_ADDR)).unwrap(),
+            name: "Quasar Luna".to_string(),
+            total_supply: Uint128::from(5_000_000u128),
+            decimals: 6,
+            symbol: "qLUNA".to_string(),
+            initial_exchange_rate: Uint128::from(2_000_000u128),
+            reserve_factor: Uint128::from(10_000_000u128),
+            borrow_index: Uint128::from(103_000_000u128),
+            max_borrow_rate: Uint128::from(5_000u128),
+            underlying: AssetInfoRaw::NativeToken { denom: "uluna".to_string() },
+            approve_enabled: true,
+            large_redeem_threshold: None,
+            redeem_announce_delay: 0,
+            term_bonus_rates: TermBonusRates::default(),
+            early_exit_penalty: Uint128::zero(),
+            max_accrual_block_delta: None,
+            utilization_window: None,
+            dust_borrow_threshold: None,
+            collateral_factor: Uint128::from(75_000_000u128),
+            close_factor: Uint128::from(50_000_000u128),
+            liquidation_incentive: Uint128::from(108_000_000u128),
+            protocol_seize_share: Uint128::zero(),
+            oracle: None,
+            comptroller: None,
+            // Defaults to the admin
+            treasury: admin,
+            guardian: None,
+            flash_loan_fee: None,
+            balance_hook: None,
+            cash_strategy: None,
+            insurance: None,
+            blocks_per_year: None,
+            buyback: None,
+            transfer_policy: None,
+            borrow_cooldown: None,
+            min_borrow_health_factor: None,
+            backstop: None,
+        });
+
+        let version = get_contract_version(&deps.storage).unwrap().unwrap();
+        assert_eq!((version.contract.as_str(), version.version.as_str()), (CONTRACT_NAME, CONTRACT_VERSION));
+
+        let migrations = get_migrations(&deps.storage, None, 10).unwrap();
+        assert_eq!(migrations.len(), 1);
+        assert_eq!(migrations[0].from_version, "0.0.0");
+        assert_eq!(migrations[0].upgraded, vec!["config_v0".to_string()]);
+        let changed = |parameter: &str| migrations[0].changes.iter().find(|c| c.parameter == parameter).cloned();
+        // Carried over fields are unchanged, the denom moved into the underlying
+        assert_eq!(changed("total_supply"), None);
+        assert_eq!(changed("borrow_index"), None);
+        assert_eq!(changed("denom").unwrap().new_value, None);
+        assert_eq!(changed("underlying").unwrap().old_value, None);
+    }
+
+    #[test]
+    fn migrate_requires_legacy_params_for_a_v0_config() {
+        let mut deps = mock_dependencies(20, &[]);
+        Singleton::new(&mut deps.storage, CONFIG_PREFIX).save(&legacy_config()).unwrap();
+
+        let err = migrate(&mut deps, mock_env("migrator", &[]), MigrateMsg {
+            legacy_config: None,
+            compact_limit: None,
+        }).unwrap_err();
+        assert_eq!(err, StdError::generic_err("Stored config predates contract versions, legacy_config is required"));
+
+        let mut params = legacy_params();
+        params.close_factor = Uint128::zero();
+        assert!(migrate(&mut deps, mock_env("migrator", &[]), MigrateMsg {
+            legacy_config: Some(params),
+            compact_limit: None,
+        }).is_err());
+        assert_eq!(get_contract_version(&deps.storage).unwrap(), None);
+    }
+}
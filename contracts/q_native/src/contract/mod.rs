@@ -1,9 +1,19 @@
+pub mod digest;
 pub mod handler;
 pub mod init;
+pub mod migrate;
 pub mod querier;
+#[cfg(test)]
+pub(crate) mod testing;
+
+/// Contract name and version reported to integrators
+pub const CONTRACT_NAME: &str = env!("CARGO_PKG_NAME");
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub use handler::handle;
 
 pub use init::init;
 
+pub use migrate::migrate;
+
 pub use querier::query;
@@ -1,7 +1,55 @@
-use cosmwasm_std::{to_binary, Api, Binary, Env, Extern, Querier, StdResult, Storage, Uint128};
+use std::cmp::Reverse;
 
-use crate::msg::{ConfigResponse, QueryMsg, BalanceResponse, AllowanceResponse};
-use crate::state::{get_allowance, get_balance, get_config};
+use cosmwasm_std::{to_binary, Api, Binary, CanonicalAddr, Coin, Decimal, Extern, HumanAddr, Querier, StdError, StdResult, Storage, Uint128};
+
+use cw20::{Cw20HandleMsg, AllAccountsResponse, AllAllowancesResponse, AllowanceInfo, MinterResponse, TokenInfoResponse};
+use cosmwasm_bignumber::Uint256;
+use quasar_interfaces::address::canonicalize_address;
+use quasar_interfaces::asset::AssetInfoRaw;
+use quasar_interfaces::market::{AccountSnapshotResponse, MarketTotalsResponse};
+use quasar_interfaces::oracle::query_price;
+use quasar_interfaces::pagination::{paginate, PageRequest, DEFAULT_LIMIT, MAX_LIMIT};
+
+use crate::contract::handler::exponential::{humanize, truncate, SCALE_DECIMALS};
+use crate::contract::handler::collateral::{
+    accrual_block_delta, borrow_limit, calculate_exchange_rate, compute_accrual, current_borrow_balance,
+    underlying_for_tokens, utilization_average_at,
+};
+use crate::contract::handler::buyback::buyback_msg;
+use crate::contract::handler::transfer_policy::transfer_policy_msg;
+use crate::contract::handler::health_alert::health_factor;
+use crate::contract::handler::insurance::pending_premium;
+use crate::contract::handler::interest_deferral::pending_interest_rebate;
+use crate::contract::handler::settlement::settlement_claim;
+use crate::contract::handler::liquidation::{max_repay, not_underwater_error, seize_amounts};
+use crate::contract::handler::interest_model::{convert_rate, get_borrow_rate, get_borrow_rate_at, get_supply_rate, get_utilization_rate};
+use crate::contract::digest::{account_digest, genesis_digest};
+use crate::contract::{CONTRACT_NAME, CONTRACT_VERSION};
+use crate::msg::{
+    ConfigResponse, QueryMsg, BalanceResponse, AllowanceResponse, LeaderboardEntryResponse,
+    LeaderboardResponse, SmoothedExchangeRateResponse, CapabilitiesResponse, FeaturesResponse,
+    HandleMsg, PayloadAction, SigningPayloadResponse, PendingRedeemResponse, MetricKind,
+    InterestSinceInceptionResponse, MetricResponse, MetricsResponse, ReserveFactorPreviewResponse, BorrowIndexResponse,
+    TrancheResponse, TranchesResponse, SelfRepayResponse, SimulateRedeemResponse,
+    SpenderAllowanceResponse, SpenderAllowancesResponse, StateDigestResponse, AccountDigestResponse,
+    BorrowRateResponse, SupplyRateResponse, LiquidationResponse, LiquidationsResponse,
+    AccountLiquidityResponse, OracleMsg, GlobalPauseResponse, AllBorrowersResponse, BorrowerResponse,
+    ReservesResponse, AccrualStateResponse, Cw20HookMsg, RolesResponse, PauseStatusResponse,
+    CashStrategyMsg, CashTiersResponse, HealthAlertsResponse, ExportGenesisResponse, GenesisAllowance,
+    GenesisBalance, GenesisBorrow, GenesisChunk, GenesisCursor, GenesisImportResponse, GenesisSection,
+    HumanizedAccountResponse, HumanizedMarketResponse, ScaledValue, InsurancePoolResponse,
+    InsuredPositionResponse, AccountSnapshotEntry, AccountSnapshotsResponse, SettlementClaimResponse,
+    SettlementResponse, AuditLogResponse, AuditRecordResponse, BuybackEpochResponse,
+    IntegratorResponse, PreviewLiquidationResponse, YieldRedirectResponse, StorageCompactionResponse,
+    UtilizationAlertsResponse, OtcOfferResponse, OtcOffersResponse, MigrationResponse,
+    MigrationHistoryResponse, ConfigDiffResponse, InterestRateModelBoundsResponse, RoleMembersResponse,
+    AccountRolesResponse, LiquiditySnapshotResponse, LiquiditySnapshotEntriesResponse, SnapshotEntryResponse,
+    InterestDeferralResponse, BackstopFlagResponse,
+};
+use crate::state::{
+    get_allowance, get_allowance_expiration, get_balance, get_borrow_balance, get_config, get_global_pause, get_exchange_rate_ema, get_leaderboard, get_metrics, get_pending_redeem, get_state,
+    get_owner_tranche_ids, get_tranche, get_otc_offer, get_otc_offers, get_self_repay, get_yield_redirect, get_health_alerts, get_utilization_alerts, get_insurance_checkpoint, get_insurance_pool, get_insured_position, get_settlement, get_buyback_epoch, get_integrator, get_swap_pair, get_genesis_import, get_compaction, get_raw_entries, split_allowance_key, decode_u128, get_spender_allowance_owners, get_state_digest, get_interest_rate_model, get_shadow_interest_rate_model, get_interest_rate_model_bounds, get_pending_interest_rate_model_bounds, get_reserve_factor_ramp, get_accounts, get_borrowers, get_owner_allowances, get_liquidation, get_liquidation_ids, get_audit_count, get_audit_records, get_migration_count, get_migrations, get_utilization_average, get_accrual_remainder, get_pause_flags, get_pending_admin, get_role_members, get_liquidity_snapshot, get_liquidity_snapshot_count, get_interest_deferral, get_backstop_flag, get_snapshot_entries, get_warm_cash, has_role, CashStrategy, Config, ConfigChange, OtcOffer, PauseAction, Role, SnapshotSection, State, Tranche, ALLOWANCE_PREFIX, BALANCE_PREFIX, BORROW_PREFIX, MAX_ACCOUNT_SNAPSHOTS, MAX_RESERVE_FACTOR, SELF_REPAY_EPOCH, TOP_BORROWERS_KEY, TOP_SUPPLIERS_KEY, YIELD_REDIRECT_EPOCH,
+};
 
 pub fn query<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
@@ -11,19 +59,93 @@ pub fn query<S: Storage, A: Api, Q: Querier>(
         QueryMsg::Config {} => {
             let config = get_config(&deps.storage)?;
             let out = to_binary(&ConfigResponse {
+                admin: deps.api.human_address(&config.admin)?,
                 name: config.name,
-                total_supply: Uint128::from(config.total_supply),
+                total_supply: config.total_supply,
                 decimals: config.decimals,
                 symbol: config.symbol,
-                denom: config.denom,
-                intital_exchange_rate: Uint128::from(config.initial_exchange_rate),
-                reserve_factor: Uint128::from(config.reserve_factor),
-                borrow_index: Uint128::from(config.borrow_index)
+                underlying: config.underlying.to_normal(&deps.api)?,
+                intital_exchange_rate: config.initial_exchange_rate,
+                reserve_factor: config.reserve_factor,
+                borrow_index: config.borrow_index,
+                approve_enabled: config.approve_enabled,
+                large_redeem_threshold: config.large_redeem_threshold,
+                redeem_announce_delay: config.redeem_announce_delay,
+                term_bonus_rates: config.term_bonus_rates,
+                early_exit_penalty: config.early_exit_penalty,
+                max_accrual_block_delta: config.max_accrual_block_delta,
+                utilization_window: config.utilization_window,
+                dust_borrow_threshold: config.dust_borrow_threshold,
+                collateral_factor: config.collateral_factor,
+                close_factor: config.close_factor,
+                liquidation_incentive: config.liquidation_incentive,
+                protocol_seize_share: config.protocol_seize_share,
+                oracle: match config.oracle {
+                    Some(oracle) => Some(OracleMsg {
+                        contract: deps.api.human_address(&oracle.contract)?,
+                        kind: oracle.kind,
+                        base: oracle.base,
+                        quote: oracle.quote,
+                    }),
+                    None => None,
+                },
+                comptroller: match config.comptroller {
+                    Some(comptroller) => Some(deps.api.human_address(&comptroller)?),
+                    None => None,
+                },
+                treasury: deps.api.human_address(&config.treasury)?,
+                guardian: match config.guardian {
+                    Some(guardian) => Some(deps.api.human_address(&guardian)?),
+                    None => None,
+                },
+                flash_loan_fee: config.flash_loan_fee,
+                balance_hook: match config.balance_hook {
+                    Some(balance_hook) => Some(deps.api.human_address(&balance_hook)?),
+                    None => None,
+                },
+                cash_strategy: cash_strategy_msg(deps, config.cash_strategy)?,
+                insurance: config.insurance,
+                blocks_per_year: config.blocks_per_year,
+                buyback: buyback_msg(&deps.api, config.buyback.as_ref())?,
+                transfer_policy: transfer_policy_msg(&deps.api, config.transfer_policy.as_ref())?,
+                borrow_cooldown: config.borrow_cooldown,
+                min_borrow_health_factor: config.min_borrow_health_factor,
+                backstop: config.backstop,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::TokenInfo {} => {
+            let config = get_config(&deps.storage)?;
+            let out = to_binary(&TokenInfoResponse {
+                name: config.name,
+                symbol: config.symbol,
+                decimals: config.decimals,
+                total_supply: config.total_supply,
             })?;
             Ok(out)
         }
+        QueryMsg::Minter {} => {
+            let config = get_config(&deps.storage)?;
+            let out = to_binary(&Some(MinterResponse {
+                minter: deps.api.human_address(&config.contract_addr)?,
+                cap: None,
+            }))?;
+            Ok(out)
+        }
+        QueryMsg::AllAllowances { owner, start_after, limit } => {
+            let out = to_binary(&query_all_allowances(deps, owner, start_after, limit)?)?;
+            Ok(out)
+        }
+        QueryMsg::AllAccounts { start_after, limit } => {
+            let out = to_binary(&query_all_accounts(deps, start_after, limit)?)?;
+            Ok(out)
+        }
+        QueryMsg::AllBorrowers { start_after, limit } => {
+            let out = to_binary(&query_all_borrowers(deps, start_after, limit)?)?;
+            Ok(out)
+        }
         QueryMsg::Balance { address } => {
-            let address_key = deps.api.canonical_address(&address)?;
+            let address_key = canonicalize_address(&deps.api, &address)?;
             let balance = get_balance(&deps.storage, &address_key)?;
             let out = to_binary(&BalanceResponse {
                 balance: Uint128::from(balance),
@@ -31,13 +153,1460 @@ pub fn query<S: Storage, A: Api, Q: Querier>(
             Ok(out)
         }
         QueryMsg::Allowance { owner, spender } => {
-            let owner_key = deps.api.canonical_address(&owner)?;
-            let spender_key = deps.api.canonical_address(&spender)?;
+            let owner_key = canonicalize_address(&deps.api, &owner)?;
+            let spender_key = canonicalize_address(&deps.api, &spender)?;
             let allowance = get_allowance(&deps.storage, &owner_key, &spender_key)?;
+            let expires = get_allowance_expiration(&deps.storage, &owner_key, &spender_key)?;
             let out = to_binary(&AllowanceResponse {
                 allowance: Uint128::from(allowance),
+                expires,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::TopSuppliers { page } => {
+            let config = get_config(&deps.storage)?;
+            let balance_of = |account: &CanonicalAddr| get_balance(&deps.storage, account);
+            let out = to_binary(&query_leaderboard(deps, TOP_SUPPLIERS_KEY, config.total_supply, page.unwrap_or_default(), balance_of)?)?;
+            Ok(out)
+        }
+        QueryMsg::TopBorrowers { page } => {
+            let config = get_config(&deps.storage)?;
+            let state = get_state(&deps.storage)?;
+            // Stored amounts are principal as of the borrower's last action, debts grow with the index since
+            let debt_of = |account: &CanonicalAddr| {
+                Ok(get_borrow_balance(&deps.storage, account).map_or(0, |snapshot| {
+                    current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold)
+                }))
+            };
+            let out = to_binary(&query_leaderboard(deps, TOP_BORROWERS_KEY, state.total_borrows, page.unwrap_or_default(), debt_of)?)?;
+            Ok(out)
+        }
+        QueryMsg::SmoothedExchangeRate {} => {
+            // Before the first accrual the market still quotes its initial exchange rate
+            let out = match get_exchange_rate_ema(&deps.storage)? {
+                Some(ema) => to_binary(&SmoothedExchangeRateResponse {
+                    smoothed_exchange_rate: ema.smoothed_exchange_rate,
+                    last_exchange_rate: ema.last_exchange_rate,
+                    block_number: ema.block_number,
+                })?,
+                None => {
+                    let state = get_state(&deps.storage)?;
+                    to_binary(&SmoothedExchangeRateResponse {
+                        smoothed_exchange_rate: state.exchange_rate,
+                        last_exchange_rate: state.exchange_rate,
+                        block_number: state.block_number,
+                    })?
+                }
+            };
+            Ok(out)
+        }
+        QueryMsg::Capabilities {} => {
+            let config = get_config(&deps.storage)?;
+            let comptroller = match config.comptroller {
+                Some(comptroller) => Some(deps.api.human_address(&comptroller)?),
+                None => None,
+            };
+            let oracle = match config.oracle {
+                Some(oracle) => Some(deps.api.human_address(&oracle.contract)?),
+                None => None,
+            };
+            let out = to_binary(&CapabilitiesResponse {
+                contract: CONTRACT_NAME.to_string(),
+                version: CONTRACT_VERSION.to_string(),
+                features: FeaturesResponse {
+                    cw20: true,
+                    flash_loans: config.flash_loan_fee.is_some(),
+                    rewards: false,
+                    stable_borrows: false,
+                },
+                comptroller,
+                oracle,
+                distributor: None,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::SigningPayload { action } => {
+            let out = to_binary(&query_signing_payload(deps, action)?)?;
+            Ok(out)
+        }
+        QueryMsg::PendingRedeem { address } => {
+            let address_key = canonicalize_address(&deps.api, &address)?;
+            let pending = get_pending_redeem(&deps.storage, &address_key)?;
+            let out = to_binary(&pending.map(|p| PendingRedeemResponse {
+                amount: p.amount,
+                executable_at: p.executable_at,
+            }))?;
+            Ok(out)
+        }
+        QueryMsg::Metrics {} => {
+            let out = to_binary(&query_metrics(deps)?)?;
+            Ok(out)
+        }
+        QueryMsg::InterestSinceInception {} => {
+            let metrics = get_metrics(&deps.storage)?;
+            let out = to_binary(&InterestSinceInceptionResponse {
+                borrower_interest: metrics.cumulative_interest,
+                supplier_interest: (metrics.cumulative_interest - metrics.cumulative_reserve_interest)?,
+                reserve_interest: metrics.cumulative_reserve_interest,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::BorrowIndex {} => {
+            let state = get_state(&deps.storage)?;
+            let out = to_binary(&BorrowIndexResponse {
+                borrow_index: state.borrow_index,
+                block_number: state.block_number,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::Roles {} => {
+            let config = get_config(&deps.storage)?;
+            let out = to_binary(&RolesResponse {
+                owner: deps.api.human_address(&config.admin)?,
+                pending_owner: match get_pending_admin(&deps.storage)? {
+                    Some(pending_admin) => Some(deps.api.human_address(&pending_admin)?),
+                    None => None,
+                },
+                guardian: match config.guardian {
+                    Some(guardian) => Some(deps.api.human_address(&guardian)?),
+                    None => None,
+                },
+            })?;
+            Ok(out)
+        }
+        QueryMsg::RoleMembers { role, page } => {
+            let out = to_binary(&query_role_members(deps, role, page.unwrap_or_default())?)?;
+            Ok(out)
+        }
+        QueryMsg::AccountRoles { address } => {
+            let address_raw = canonicalize_address(&deps.api, &address)?;
+            let mut roles = vec![];
+            for role in Role::ALL.iter() {
+                if has_role(&deps.storage, *role, &address_raw)? {
+                    roles.push(*role);
+                }
+            }
+            let out = to_binary(&AccountRolesResponse { roles })?;
+            Ok(out)
+        }
+        QueryMsg::PauseStatus {} => {
+            let flags = get_pause_flags(&deps.storage)?;
+            let out = to_binary(&PauseStatusResponse {
+                mint: flags.mint,
+                borrow: flags.borrow,
+                transfer: flags.transfer,
+                liquidation: flags.liquidation,
+                global: get_global_pause(&deps.storage)?.paused,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::AccrualState { block_height } => {
+            let out = to_binary(&query_accrual_state(deps, block_height)?)?;
+            Ok(out)
+        }
+        QueryMsg::InterestRateModel {} => {
+            let out = to_binary(&get_interest_rate_model(&deps.storage)?)?;
+            Ok(out)
+        }
+        QueryMsg::ShadowInterestRateModel {} => {
+            let out = to_binary(&get_shadow_interest_rate_model(&deps.storage)?)?;
+            Ok(out)
+        }
+        QueryMsg::InterestRateModelBounds {} => {
+            let out = to_binary(&InterestRateModelBoundsResponse {
+                bounds: get_interest_rate_model_bounds(&deps.storage)?,
+                pending: get_pending_interest_rate_model_bounds(&deps.storage)?,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::BorrowRate {} => {
+            let state = get_state(&deps.storage)?;
+            let model = get_interest_rate_model(&deps.storage)?;
+            let out = to_binary(&BorrowRateResponse {
+                borrow_rate: Uint128::from(get_borrow_rate_at(&model, rate_model_utilization(deps, &state)?)),
+            })?;
+            Ok(out)
+        }
+        QueryMsg::SupplyRate {} => {
+            let state = get_state(&deps.storage)?;
+            let model = get_interest_rate_model(&deps.storage)?;
+            let out = to_binary(&SupplyRateResponse {
+                supply_rate: Uint128::from(get_supply_rate(&model, &state.cash, &state.total_borrows, &state.total_reserves, &state.reserve_factor)),
+            })?;
+            Ok(out)
+        }
+        QueryMsg::ReserveFactorRamp {} => {
+            let out = to_binary(&get_reserve_factor_ramp(&deps.storage)?)?;
+            Ok(out)
+        }
+        QueryMsg::ReserveFactorPreview { proposed_reserve_factor, horizon_blocks } => {
+            let out = to_binary(&query_reserve_factor_preview(deps, proposed_reserve_factor, horizon_blocks)?)?;
+            Ok(out)
+        }
+        QueryMsg::Tranche { id } => {
+            let tranche = get_tranche(&deps.storage, id)?;
+            let out = to_binary(&tranche_response(deps, tranche)?)?;
+            Ok(out)
+        }
+        QueryMsg::Tranches { owner, page } => {
+            let out = to_binary(&query_tranches(deps, owner, page.unwrap_or_default())?)?;
+            Ok(out)
+        }
+        QueryMsg::OtcOffer { id } => {
+            let out = to_binary(&match get_otc_offer(&deps.storage, id)? {
+                Some(offer) => Some(otc_offer_response(deps, offer)?),
+                None => None,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::OtcOffers { page } => {
+            let out = to_binary(&query_otc_offers(deps, page.unwrap_or_default())?)?;
+            Ok(out)
+        }
+        QueryMsg::SelfRepay { address } => {
+            let address_key = canonicalize_address(&deps.api, &address)?;
+            let self_repay = get_self_repay(&deps.storage, &address_key)?;
+            let out = to_binary(&self_repay.map(|s| SelfRepayResponse {
+                exchange_rate: s.exchange_rate,
+                last_settled: s.last_settled,
+                accrued_yield: s.accrued_yield,
+                next_settlement: s.last_settled + SELF_REPAY_EPOCH,
+            }))?;
+            Ok(out)
+        }
+        QueryMsg::YieldRedirect { address } => {
+            let address_key = canonicalize_address(&deps.api, &address)?;
+            let out = to_binary(&match get_yield_redirect(&deps.storage, &address_key)? {
+                Some(r) => Some(YieldRedirectResponse {
+                    beneficiary: deps.api.human_address(&r.beneficiary)?,
+                    exchange_rate: r.exchange_rate,
+                    last_settled: r.last_settled,
+                    accrued_yield: r.accrued_yield,
+                    next_settlement: r.last_settled + YIELD_REDIRECT_EPOCH,
+                }),
+                None => None,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::HealthAlerts { address } => {
+            let address_key = canonicalize_address(&deps.api, &address)?;
+            let health_alerts = get_health_alerts(&deps.storage, &address_key)?;
+            let out = to_binary(&HealthAlertsResponse {
+                thresholds: health_alerts.as_ref().map(|h| h.thresholds.clone()).unwrap_or_default(),
+                health_factor: health_factor(&deps.storage, &address_key)?.map(Uint128::from),
+                last_health_factor: health_alerts.and_then(|h| h.last_health_factor),
+            })?;
+            Ok(out)
+        }
+        QueryMsg::UtilizationAlerts {} => {
+            let utilization_alerts = get_utilization_alerts(&deps.storage)?;
+            let state = get_state(&deps.storage)?;
+            let out = to_binary(&UtilizationAlertsResponse {
+                thresholds: utilization_alerts.as_ref().map(|u| u.thresholds.clone()).unwrap_or_default(),
+                utilization: Uint128::from(get_utilization_rate(&state.cash, &state.total_borrows, &state.total_reserves)),
+                last_utilization: utilization_alerts.map(|u| u.last_utilization),
+            })?;
+            Ok(out)
+        }
+        QueryMsg::InsurancePool {} => {
+            let checkpoint = get_insurance_checkpoint(&deps.storage)?;
+            let out = to_binary(&InsurancePoolResponse {
+                balance: get_insurance_pool(&deps.storage)?,
+                checkpoint_price: checkpoint.as_ref().map(|c| c.price),
+                checkpoint_block: checkpoint.map(|c| c.block_number),
+            })?;
+            Ok(out)
+        }
+        QueryMsg::InsuredPosition { address } => {
+            let address_key = canonicalize_address(&deps.api, &address)?;
+            let position = match get_insured_position(&deps.storage, &address_key)? {
+                Some(position) => Some(InsuredPositionResponse {
+                    premium_index: position.premium_index,
+                    pending_premium: Uint128::from(pending_premium(&deps.storage, &address_key)?),
+                }),
+                None => None,
+            };
+            let out = to_binary(&position)?;
+            Ok(out)
+        }
+        QueryMsg::InterestDeferral { address } => {
+            let address_key = canonicalize_address(&deps.api, &address)?;
+            let deferral = match get_interest_deferral(&deps.storage, &address_key)? {
+                Some(deferral) => Some(InterestDeferralResponse {
+                    discount: deferral.discount,
+                    expires: deferral.expires,
+                    index: deferral.index,
+                    pending_rebate: Uint128::from(pending_interest_rebate(&deps.storage, &address_key)?),
+                }),
+                None => None,
+            };
+            let out = to_binary(&deferral)?;
+            Ok(out)
+        }
+        QueryMsg::BackstopFlag { address } => {
+            let address_key = canonicalize_address(&deps.api, &address)?;
+            let delay = get_config(&deps.storage)?.backstop.map(|backstop| backstop.delay);
+            let flag = get_backstop_flag(&deps.storage, &address_key)?.map(|flagged_at| BackstopFlagResponse {
+                flagged_at,
+                eligible_at: delay.map(|delay| flagged_at + delay),
+            });
+            let out = to_binary(&flag)?;
+            Ok(out)
+        }
+        QueryMsg::Settlement {} => {
+            let settlement = get_settlement(&deps.storage)?.map(|settlement| SettlementResponse {
+                block_number: settlement.block_number,
+                borrow_index: settlement.borrow_index,
+                total_supply: settlement.total_supply,
+                claim_index: settlement.claim_index,
+            });
+            let out = to_binary(&settlement)?;
+            Ok(out)
+        }
+        QueryMsg::SettlementClaim { address } => {
+            let (entitled, claimed) = settlement_claim(&deps.storage, &canonicalize_address(&deps.api, &address)?)?;
+            let out = to_binary(&SettlementClaimResponse {
+                claimable: Uint128::from(entitled - claimed),
+                claimed: Uint128::from(claimed),
+            })?;
+            Ok(out)
+        }
+        QueryMsg::BuybackEpoch {} => {
+            let config = get_config(&deps.storage)?;
+            let epoch = get_buyback_epoch(&deps.storage)?;
+            let (end, remaining) = match config.buyback {
+                Some(buyback) => (
+                    epoch.start + buyback.epoch_blocks,
+                    (buyback.max_per_epoch - epoch.spent).unwrap_or_default(),
+                ),
+                None => (epoch.start, Uint128::zero()),
+            };
+            let out = to_binary(&BuybackEpochResponse {
+                start: epoch.start,
+                end,
+                spent: epoch.spent,
+                remaining,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::Integrator { address } => {
+            let integrator = get_integrator(&deps.storage, &canonicalize_address(&deps.api, &address)?)?;
+            let out = to_binary(&integrator.map(|integrator| IntegratorResponse {
+                max_fee_bps: integrator.max_fee_bps,
+                total_fees: integrator.total_fees,
+            }))?;
+            Ok(out)
+        }
+        QueryMsg::SwapPair { offer_denom } => {
+            let pair = match get_swap_pair(&deps.storage, &offer_denom)? {
+                Some(pair) => Some(deps.api.human_address(&pair)?),
+                None => None,
+            };
+            let out = to_binary(&pair)?;
+            Ok(out)
+        }
+        QueryMsg::ExportGenesis { cursor, prior_digest, limit } => {
+            let out = to_binary(&query_export_genesis(deps, cursor, prior_digest.unwrap_or_default(), limit)?)?;
+            Ok(out)
+        }
+        QueryMsg::GenesisImport {} => {
+            let genesis_import = get_genesis_import(&deps.storage)?;
+            let out = to_binary(&genesis_import.map(|g| GenesisImportResponse {
+                digest: g.digest,
+                chunks: g.chunks,
+            }))?;
+            Ok(out)
+        }
+        QueryMsg::StorageCompaction {} => {
+            let compaction = get_compaction(&deps.storage)?;
+            let out = to_binary(&StorageCompactionResponse {
+                section: compaction.section,
+                start_after: compaction.start_after,
+                rewritten: compaction.rewritten,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::LiquiditySnapshot { id } => {
+            let id = match id {
+                Some(id) => id,
+                None => get_liquidity_snapshot_count(&deps.storage)?,
+            };
+            let snapshot = get_liquidity_snapshot(&deps.storage, id)?;
+            let out = to_binary(&snapshot.map(|snapshot| LiquiditySnapshotResponse {
+                id: snapshot.id,
+                block: snapshot.block,
+                borrow_index: snapshot.borrow_index,
+                section: snapshot.section,
+                entries: snapshot.entries,
+                digest: snapshot.digest,
+            }))?;
+            Ok(out)
+        }
+        QueryMsg::LiquiditySnapshotEntries { id, section, page } => {
+            let out = to_binary(&query_liquidity_snapshot_entries(deps, id, section, page.unwrap_or_default())?)?;
+            Ok(out)
+        }
+        QueryMsg::HumanizedMarket {} => {
+            let out = to_binary(&query_humanized_market(deps)?)?;
+            Ok(out)
+        }
+        QueryMsg::HumanizedAccount { address } => {
+            let out = to_binary(&query_humanized_account(deps, address)?)?;
+            Ok(out)
+        }
+        QueryMsg::SpenderAllowances { spender, page } => {
+            let out = to_binary(&query_spender_allowances(deps, spender, page.unwrap_or_default())?)?;
+            Ok(out)
+        }
+        QueryMsg::StateDigest {} => {
+            let digest = get_state_digest(&deps.storage)?;
+            let out = to_binary(&StateDigestResponse {
+                digest: digest.digest,
+                sequence: digest.sequence,
+                block_number: digest.block_number,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::AccountDigest { address } => {
+            let address_key = canonicalize_address(&deps.api, &address)?;
+            let out = to_binary(&AccountDigestResponse {
+                digest: account_digest(&deps.storage, &address_key)?,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::Liquidations { borrower, page } => {
+            let out = to_binary(&query_liquidations(deps, borrower, page.unwrap_or_default())?)?;
+            Ok(out)
+        }
+        QueryMsg::ConvertRate { rate, unit } => {
+            let config = get_config(&deps.storage)?;
+            let out = to_binary(&convert_rate(rate, unit, config.blocks_per_year()))?;
+            Ok(out)
+        }
+        QueryMsg::AuditLog { page } => {
+            let out = to_binary(&query_audit_log(deps, page.unwrap_or_default())?)?;
+            Ok(out)
+        }
+        QueryMsg::MigrationHistory { page } => {
+            let out = to_binary(&query_migration_history(deps, page.unwrap_or_default())?)?;
+            Ok(out)
+        }
+        QueryMsg::ConfigDiff { from_version, to_version } => {
+            let out = to_binary(&query_config_diff(deps, from_version, to_version)?)?;
+            Ok(out)
+        }
+        QueryMsg::SimulateRedeem { address, amount } => {
+            let out = to_binary(&query_simulate_redeem(deps, address, amount)?)?;
+            Ok(out)
+        }
+        QueryMsg::PreviewLiquidation { borrower, repay_amount } => {
+            let out = to_binary(&query_preview_liquidation(deps, borrower, repay_amount)?)?;
+            Ok(out)
+        }
+        QueryMsg::AccountLiquidity { address } => {
+            let out = to_binary(&query_account_liquidity(deps, address)?)?;
+            Ok(out)
+        }
+        QueryMsg::Reserves {} => {
+            let config = get_config(&deps.storage)?;
+            let state = get_state(&deps.storage)?;
+            let out = to_binary(&ReservesResponse {
+                total_reserves: state.total_reserves,
+                reserve_factor: state.reserve_factor,
+                treasury: deps.api.human_address(&config.treasury)?,
             })?;
             Ok(out)
         }
+        QueryMsg::CashTiers {} => {
+            let config = get_config(&deps.storage)?;
+            let state = get_state(&deps.storage)?;
+            let warm_cash = get_warm_cash(&deps.storage)?;
+            let out = to_binary(&CashTiersResponse {
+                cash: state.cash,
+                hot_cash: Uint128::from(state.cash.u128().saturating_sub(warm_cash.u128())),
+                warm_cash,
+                cash_strategy: cash_strategy_msg(deps, config.cash_strategy)?,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::GlobalPause {} => {
+            let pause = get_global_pause(&deps.storage)?;
+            let out = to_binary(&GlobalPauseResponse {
+                paused: pause.paused,
+                epoch: pause.epoch,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::MarketTotals {} => {
+            let state = get_state(&deps.storage)?;
+            let out = to_binary(&MarketTotalsResponse {
+                cash: state.cash,
+                total_borrows: state.total_borrows,
+                total_reserves: state.total_reserves,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::AccountSnapshot { address } => {
+            let config = get_config(&deps.storage)?;
+            let state = get_state(&deps.storage)?;
+            let exchange_rate = calculate_exchange_rate(&config, &state)?;
+            let out = to_binary(&account_snapshot(deps, &config, &state, exchange_rate, &address)?)?;
+            Ok(out)
+        }
+        QueryMsg::AccountSnapshots { addresses } => {
+            if addresses.len() > MAX_ACCOUNT_SNAPSHOTS {
+                return Err(StdError::generic_err(format!(
+                    "Too many accounts: addresses: {}, max_account_snapshots: {}",
+                    addresses.len(), MAX_ACCOUNT_SNAPSHOTS
+                )));
+            }
+            let config = get_config(&deps.storage)?;
+            let state = get_state(&deps.storage)?;
+            let exchange_rate = calculate_exchange_rate(&config, &state)?;
+            let snapshots = addresses
+                .into_iter()
+                .map(|address| {
+                    let snapshot = account_snapshot(deps, &config, &state, exchange_rate, &address)?;
+                    Ok(AccountSnapshotEntry { address, snapshot })
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            let out = to_binary(&AccountSnapshotsResponse { snapshots })?;
+            Ok(out)
+        }
+    }
+}
+
+fn account_snapshot<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    config: &Config,
+    state: &State,
+    exchange_rate: u128,
+    address: &HumanAddr,
+) -> StdResult<AccountSnapshotResponse> {
+    let address_raw = canonicalize_address(&deps.api, address)?;
+    let borrow_balance = match get_borrow_balance(&deps.storage, &address_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    Ok(AccountSnapshotResponse {
+        balance: Uint128::from(get_balance(&deps.storage, &address_raw)?),
+        borrow_balance: Uint128::from(borrow_balance),
+        exchange_rate: Uint128::from(exchange_rate),
+        total_borrows: state.total_borrows,
+    })
+}
+
+/// Values collateral and debt at the stored borrow index, so interest since
+/// the last accrual is not included
+fn query_accrual_state<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    block_height: u64,
+) -> StdResult<AccrualStateResponse> {
+    let config = get_config(&deps.storage)?;
+    let state = get_state(&deps.storage)?;
+
+    let utilization_rate = get_utilization_rate(&state.cash, &state.total_borrows, &state.total_reserves);
+    let average_utilization = utilization_average_at(&deps.storage, block_height, utilization_rate, config.utilization_window)?;
+    let borrow_rate = get_borrow_rate_at(&get_interest_rate_model(&deps.storage)?, average_utilization);
+    let (pending_blocks, _) = accrual_block_delta(&config, &state, block_height);
+    let accrual = compute_accrual(&state, &get_accrual_remainder(&deps.storage)?, borrow_rate, pending_blocks.into());
+
+    let mut accrued_state = state.clone();
+    accrued_state.borrow_index = Uint128::from(accrual.borrow_index);
+    accrued_state.total_borrows += Uint128::from(accrual.interest);
+    accrued_state.total_reserves += Uint128::from(accrual.reserve_take);
+
+    Ok(AccrualStateResponse {
+        last_accrual_block: state.block_number,
+        pending_blocks,
+        borrow_rate: Uint128::from(borrow_rate),
+        pending_interest: Uint128::from(accrual.interest),
+        pending_reserves: Uint128::from(accrual.reserve_take),
+        borrow_index: accrued_state.borrow_index,
+        total_borrows: accrued_state.total_borrows,
+        total_reserves: accrued_state.total_reserves,
+        exchange_rate: Uint128::from(calculate_exchange_rate(&config, &accrued_state)?),
+    })
+}
+
+fn query_account_liquidity<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<AccountLiquidityResponse> {
+    let config = get_config(&deps.storage)?;
+    let oracle = match &config.oracle {
+        Some(oracle) => oracle,
+        None => return Err(StdError::generic_err("No price oracle is configured for this market")),
+    };
+    let price = query_price(
+        &deps.querier,
+        deps.api.human_address(&oracle.contract)?,
+        &oracle.kind,
+        oracle.base.clone(),
+        oracle.quote.clone(),
+    )?;
+
+    let address_raw = canonicalize_address(&deps.api, &address)?;
+    let state = get_state(&deps.storage)?;
+    let exchange_rate = calculate_exchange_rate(&config, &state)?;
+    let limit = borrow_limit(&config, get_balance(&deps.storage, &address_raw)?, exchange_rate);
+    let account_borrow = match get_borrow_balance(&deps.storage, &address_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+
+    let limit_value = Uint256::from(limit) * price.rate;
+    let borrow_value = Uint256::from(account_borrow) * price.rate;
+    let (liquidity, shortfall) = if limit_value >= borrow_value {
+        (limit_value - borrow_value, Uint256::zero())
+    } else {
+        (Uint256::zero(), borrow_value - limit_value)
+    };
+
+    Ok(AccountLiquidityResponse {
+        quote: oracle.quote.clone(),
+        price: price.rate,
+        liquidity,
+        shortfall,
+    })
+}
+
+fn query_liquidations<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    borrower: Option<HumanAddr>,
+    page: PageRequest,
+) -> StdResult<LiquidationsResponse> {
+    let borrower_raw = match borrower {
+        Some(borrower) => Some(canonicalize_address(&deps.api, &borrower)?),
+        None => None,
+    };
+    let start_after = match page.start_after.as_ref() {
+        Some(id) => Some(id.parse::<u64>().map_err(|_| {
+            StdError::generic_err(format!("Invalid liquidation id: start_after: {}", id))
+        })?),
+        None => None,
+    };
+
+    let liquidations = get_liquidation_ids(&deps.storage, borrower_raw.as_ref(), start_after, page.limit() + 1)?
+        .into_iter()
+        .map(|id| {
+            let record = get_liquidation(&deps.storage, id)?;
+            Ok((id.to_string(), LiquidationResponse {
+                id,
+                liquidator: deps.api.human_address(&record.liquidator)?,
+                borrower: deps.api.human_address(&record.borrower)?,
+                repay_amount: record.repay_amount,
+                seize_tokens: record.seize_tokens,
+                protocol_seize_tokens: record.protocol_seize_tokens,
+                exchange_rate: record.exchange_rate,
+                block_number: record.block_number,
+                collateral_market: match &record.collateral_market {
+                    Some(market) => Some(deps.api.human_address(market)?),
+                    None => None,
+                },
+            }))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let (liquidations, page) = paginate(liquidations.into_iter(), page.limit(), None);
+    Ok(LiquidationsResponse { liquidations, page })
+}
+
+fn query_audit_log<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    page: PageRequest,
+) -> StdResult<AuditLogResponse> {
+    let start_after = match page.start_after.as_ref() {
+        Some(id) => Some(id.parse::<u64>().map_err(|_| {
+            StdError::generic_err(format!("Invalid audit id: start_after: {}", id))
+        })?),
+        None => None,
+    };
+
+    let records = get_audit_records(&deps.storage, start_after, page.limit() + 1)?
+        .into_iter()
+        .map(|record| {
+            Ok((record.id.to_string(), AuditRecordResponse {
+                id: record.id,
+                actor: deps.api.human_address(&record.actor)?,
+                role: record.role,
+                parameter: record.parameter,
+                old_value: record.old_value,
+                new_value: record.new_value,
+                block_number: record.block_number,
+            }))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let total = get_audit_count(&deps.storage)?;
+    let (records, page) = paginate(records.into_iter(), page.limit(), Some(total));
+    Ok(AuditLogResponse { records, page })
+}
+
+fn query_migration_history<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    page: PageRequest,
+) -> StdResult<MigrationHistoryResponse> {
+    let start_after = match page.start_after.as_ref() {
+        Some(id) => Some(id.parse::<u64>().map_err(|_| {
+            StdError::generic_err(format!("Invalid migration id: start_after: {}", id))
+        })?),
+        None => None,
+    };
+
+    let migrations = get_migrations(&deps.storage, start_after, page.limit() + 1)?
+        .into_iter()
+        .map(|record| {
+            (record.id.to_string(), MigrationResponse {
+                id: record.id,
+                from_version: record.from_version,
+                to_version: record.to_version,
+                block_number: record.block_number,
+                upgraded: record.upgraded,
+                changes: record.changes,
+            })
+        });
+    let total = get_migration_count(&deps.storage)?;
+    let (migrations, page) = paginate(migrations, page.limit(), Some(total));
+    Ok(MigrationHistoryResponse { migrations, page })
+}
+
+/// Folds the changes of every migration from the first one leaving `from_version`
+/// up to the last one reaching `to_version`
+fn query_config_diff<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    from_version: Option<String>,
+    to_version: Option<String>,
+) -> StdResult<ConfigDiffResponse> {
+    let migrations = get_migrations(&deps.storage, None, get_migration_count(&deps.storage)? as usize)?;
+    let first = match from_version.as_ref() {
+        Some(version) => migrations.iter().position(|record| record.from_version == *version),
+        None if !migrations.is_empty() => Some(0),
+        None => None,
+    };
+    let first = match first {
+        Some(first) => first,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "No migration from the version is recorded: from_version: {}",
+                from_version.unwrap_or_default()
+            )));
+        }
+    };
+    let last = match to_version.as_ref() {
+        Some(version) => migrations.iter().rposition(|record| record.to_version == *version),
+        None => Some(migrations.len() - 1),
+    };
+    let last = match last {
+        Some(last) if last >= first => last,
+        _ => {
+            return Err(StdError::generic_err(format!(
+                "No migration to the version is recorded after the from version: from_version: {}, to_version: {}",
+                migrations[first].from_version,
+                to_version.unwrap_or_default()
+            )));
+        }
+    };
+
+    let mut changes: Vec<ConfigChange> = vec![];
+    for change in migrations[first..=last].iter().flat_map(|record| record.changes.iter()) {
+        match changes.iter_mut().find(|net| net.parameter == change.parameter) {
+            Some(net) => net.new_value = change.new_value.clone(),
+            None => changes.push(change.clone()),
+        }
+    }
+    changes.retain(|change| change.old_value != change.new_value);
+
+    Ok(ConfigDiffResponse {
+        from_version: migrations[first].from_version.clone(),
+        to_version: migrations[last].to_version.clone(),
+        migrations: migrations[first..=last].iter().map(|record| record.id).collect(),
+        changes,
+    })
+}
+
+fn query_all_allowances<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    owner: HumanAddr,
+    start_after: Option<HumanAddr>,
+    limit: Option<u32>,
+) -> StdResult<AllAllowancesResponse> {
+    let owner_raw = canonicalize_address(&deps.api, &owner)?;
+    let start_after = match start_after {
+        Some(spender) => Some(canonicalize_address(&deps.api, &spender)?),
+        None => None,
+    };
+    let limit = PageRequest { start_after: None, limit }.limit();
+
+    let allowances = get_owner_allowances(&deps.storage, &owner_raw, start_after.as_ref(), limit)?
+        .into_iter()
+        .map(|(spender_raw, allowance)| {
+            Ok(AllowanceInfo {
+                spender: deps.api.human_address(&spender_raw)?,
+                allowance: Uint128::from(allowance),
+                expires: get_allowance_expiration(&deps.storage, &owner_raw, &spender_raw)?,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AllAllowancesResponse { allowances })
+}
+
+fn query_all_accounts<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    start_after: Option<HumanAddr>,
+    limit: Option<u32>,
+) -> StdResult<AllAccountsResponse> {
+    let start_after = match start_after {
+        Some(account) => Some(canonicalize_address(&deps.api, &account)?),
+        None => None,
+    };
+    let limit = PageRequest { start_after: None, limit }.limit();
+
+    let accounts = get_accounts(&deps.storage, start_after.as_ref(), limit)?
+        .iter()
+        .map(|account| deps.api.human_address(account))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AllAccountsResponse { accounts })
+}
+
+fn query_all_borrowers<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    start_after: Option<HumanAddr>,
+    limit: Option<u32>,
+) -> StdResult<AllBorrowersResponse> {
+    let start_after = match start_after {
+        Some(borrower) => Some(canonicalize_address(&deps.api, &borrower)?),
+        None => None,
+    };
+    let limit = PageRequest { start_after: None, limit }.limit();
+    let config = get_config(&deps.storage)?;
+    let state = get_state(&deps.storage)?;
+
+    let borrowers = get_borrowers(&deps.storage, start_after.as_ref(), limit)?
+        .into_iter()
+        .map(|(borrower_raw, snapshot)| {
+            Ok(BorrowerResponse {
+                address: deps.api.human_address(&borrower_raw)?,
+                borrow_balance: Uint128::from(current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold)),
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AllBorrowersResponse { borrowers })
+}
+
+fn query_humanized_market<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<HumanizedMarketResponse> {
+    let config = get_config(&deps.storage)?;
+    let state = get_state(&deps.storage)?;
+    let model = get_interest_rate_model(&deps.storage)?;
+    let rate = |raw: u128| scaled_value(raw, SCALE_DECIMALS);
+    let amount = |raw: Uint128| scaled_value(raw.u128(), config.decimals);
+    Ok(HumanizedMarketResponse {
+        exchange_rate: rate(calculate_exchange_rate(&config, &state)?),
+        borrow_rate: rate(get_borrow_rate_at(&model, rate_model_utilization(deps, &state)?)),
+        supply_rate: rate(get_supply_rate(&model, &state.cash, &state.total_borrows, &state.total_reserves, &state.reserve_factor)),
+        utilization_rate: rate(get_utilization_rate(&state.cash, &state.total_borrows, &state.total_reserves)),
+        collateral_factor: rate(config.collateral_factor.u128()),
+        reserve_factor: rate(state.reserve_factor.u128()),
+        total_supply: amount(config.total_supply),
+        cash: amount(state.cash),
+        total_borrows: amount(state.total_borrows),
+        total_reserves: amount(state.total_reserves),
+    })
+}
+
+fn query_humanized_account<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<HumanizedAccountResponse> {
+    let config = get_config(&deps.storage)?;
+    let state = get_state(&deps.storage)?;
+    let address_raw = canonicalize_address(&deps.api, &address)?;
+    let balance = get_balance(&deps.storage, &address_raw)?;
+    let exchange_rate = calculate_exchange_rate(&config, &state)?;
+    let borrow_balance = match get_borrow_balance(&deps.storage, &address_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    Ok(HumanizedAccountResponse {
+        balance: scaled_value(balance, config.decimals),
+        underlying_balance: scaled_value(underlying_for_tokens(balance, exchange_rate), config.decimals),
+        borrow_balance: scaled_value(borrow_balance, config.decimals),
+        borrow_limit: scaled_value(borrow_limit(&config, balance, exchange_rate), config.decimals),
+    })
+}
+
+fn scaled_value(raw: u128, decimals: u8) -> ScaledValue {
+    ScaledValue {
+        raw: Uint128::from(raw),
+        humanized: humanize(raw, decimals as u32),
+        decimals,
+    }
+}
+
+/// One chunk of the genesis export in storage key order, a section moves on to
+/// the next once a chunk comes back short
+fn query_export_genesis<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    cursor: Option<GenesisCursor>,
+    prior_digest: Binary,
+    limit: Option<u32>,
+) -> StdResult<ExportGenesisResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+    let cursor = cursor.unwrap_or(GenesisCursor {
+        section: GenesisSection::Market,
+        start_after: None,
+    });
+    let start_after = cursor.start_after.as_ref().map(|key| key.as_slice());
+
+    let (chunk, entries) = match cursor.section {
+        GenesisSection::Market => {
+            let chunk = GenesisChunk::Market {
+                config: Box::new(get_config(&deps.storage)?),
+                state: get_state(&deps.storage)?,
+            };
+            (chunk, vec![])
+        }
+        GenesisSection::Balances => {
+            let entries = get_raw_entries(&deps.storage, BALANCE_PREFIX, start_after, limit);
+            let balances = entries
+                .iter()
+                .map(|(key, value)| Ok(GenesisBalance {
+                    address: Binary::from(key.as_slice()),
+                    balance: Uint128::from(decode_u128(value)?),
+                }))
+                .collect::<StdResult<Vec<_>>>()?;
+            (GenesisChunk::Balances { balances }, entries)
+        }
+        GenesisSection::Borrows => {
+            let entries = get_raw_entries(&deps.storage, BORROW_PREFIX, start_after, limit);
+            let borrows = entries
+                .iter()
+                .map(|(key, _)| match get_borrow_balance(&deps.storage, &CanonicalAddr::from(key.as_slice())) {
+                    Some(snapshot) => Ok(GenesisBorrow {
+                        address: Binary::from(key.as_slice()),
+                        snapshot,
+                    }),
+                    None => Err(StdError::generic_err("Corrupted borrow snapshot found")),
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            (GenesisChunk::Borrows { borrows }, entries)
+        }
+        GenesisSection::Allowances => {
+            let entries = get_raw_entries(&deps.storage, ALLOWANCE_PREFIX, start_after, limit);
+            let allowances = entries
+                .iter()
+                .map(|(key, value)| {
+                    let (owner, spender) = split_allowance_key(key)?;
+                    Ok(GenesisAllowance {
+                        expires: get_allowance_expiration(&deps.storage, &owner, &spender)?,
+                        owner: Binary::from(owner.as_slice()),
+                        spender: Binary::from(spender.as_slice()),
+                        amount: Uint128::from(decode_u128(value)?),
+                    })
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            (GenesisChunk::Allowances { allowances }, entries)
+        }
+    };
+
+    let section_start = |section| Some(GenesisCursor {
+        section,
+        start_after: None,
+    });
+    let next = match cursor.section {
+        GenesisSection::Market => section_start(GenesisSection::Balances),
+        section if entries.len() == limit => Some(GenesisCursor {
+            section,
+            start_after: entries.last().map(|(key, _)| Binary::from(key.as_slice())),
+        }),
+        GenesisSection::Balances => section_start(GenesisSection::Borrows),
+        GenesisSection::Borrows => section_start(GenesisSection::Allowances),
+        GenesisSection::Allowances => None,
+    };
+    Ok(ExportGenesisResponse {
+        digest: genesis_digest(&prior_digest, &chunk)?,
+        chunk,
+        next,
+    })
+}
+
+fn query_spender_allowances<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    spender: HumanAddr,
+    page: PageRequest,
+) -> StdResult<SpenderAllowancesResponse> {
+    let spender_raw = canonicalize_address(&deps.api, &spender)?;
+    let start_after = match page.start_after.as_ref() {
+        Some(owner) => Some(canonicalize_address(&deps.api, &HumanAddr::from(owner.as_str()))?),
+        None => None,
+    };
+
+    // One owner past the page tells whether another page follows
+    let allowances = get_spender_allowance_owners(&deps.storage, &spender_raw, start_after.as_ref(), page.limit() + 1)?
+        .into_iter()
+        .map(|owner_raw| {
+            let owner = deps.api.human_address(&owner_raw)?;
+            let allowance = get_allowance(&deps.storage, &owner_raw, &spender_raw)?;
+            let expires = get_allowance_expiration(&deps.storage, &owner_raw, &spender_raw)?;
+            Ok((owner.to_string(), SpenderAllowanceResponse {
+                owner,
+                allowance: Uint128::from(allowance),
+                expires,
+            }))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let (allowances, page) = paginate(allowances.into_iter(), page.limit(), None);
+    Ok(SpenderAllowancesResponse { allowances, page })
+}
+
+fn query_liquidity_snapshot_entries<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    id: u64,
+    section: SnapshotSection,
+    page: PageRequest,
+) -> StdResult<LiquiditySnapshotEntriesResponse> {
+    if get_liquidity_snapshot(&deps.storage, id)?.is_none() {
+        return Err(StdError::generic_err(format!("No liquidity snapshot: id: {}", id)));
+    }
+    let start_after = match page.start_after.as_ref() {
+        Some(address) => Some(canonicalize_address(&deps.api, &HumanAddr::from(address.as_str()))?),
+        None => None,
+    };
+
+    // One entry past the page tells whether another page follows
+    let entries = get_snapshot_entries(&deps.storage, id, section, start_after.as_ref(), page.limit() + 1)?
+        .into_iter()
+        .map(|(account, amount)| {
+            let address = deps.api.human_address(&account)?;
+            Ok((address.to_string(), SnapshotEntryResponse { address, amount }))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let (entries, page) = paginate(entries.into_iter(), page.limit(), None);
+    Ok(LiquiditySnapshotEntriesResponse { entries, page })
+}
+
+fn query_role_members<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    role: Role,
+    page: PageRequest,
+) -> StdResult<RoleMembersResponse> {
+    let start_after = match page.start_after.as_ref() {
+        Some(member) => Some(canonicalize_address(&deps.api, &HumanAddr::from(member.as_str()))?),
+        None => None,
+    };
+
+    // One member past the page tells whether another page follows
+    let members = get_role_members(&deps.storage, role, start_after.as_ref(), page.limit() + 1)?
+        .into_iter()
+        .map(|member_raw| {
+            let member = deps.api.human_address(&member_raw)?;
+            Ok((member.to_string(), member))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let (members, page) = paginate(members.into_iter(), page.limit(), None);
+    Ok(RoleMembersResponse { members, page })
+}
+
+/// Mirrors the checks of try_redeem, except interest is not accrued and the
+/// announcement delay is left to the caller since queries have no block height
+fn query_simulate_redeem<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    amount: Uint128,
+) -> StdResult<SimulateRedeemResponse> {
+    let config = get_config(&deps.storage)?;
+    let state = get_state(&deps.storage)?;
+    let address_key = canonicalize_address(&deps.api, &address)?;
+
+    let model = get_interest_rate_model(&deps.storage)?;
+    let exchange_rate = calculate_exchange_rate(&config, &state)?;
+    let underlying_out = underlying_for_tokens(amount.u128(), exchange_rate);
+    let balance = get_balance(&deps.storage, &address_key)?;
+    let pending = get_pending_redeem(&deps.storage, &address_key)?;
+    // Standalone markets keep the redeemer's debt within the limit of the qTokens it keeps
+    let account_borrow = match (&config.comptroller, get_borrow_balance(&deps.storage, &address_key)) {
+        (None, Some(snapshot)) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        _ => 0,
+    };
+    let limit_after = borrow_limit(&config, balance.saturating_sub(amount.u128()), exchange_rate);
+
+    let error = if balance < amount.u128() {
+        Some(format!("Insufficient funds: balance={}, required={}", balance, amount))
+    } else if account_borrow > limit_after {
+        Some(format!(
+            "Account would be undercollateralized: account_borrow: {}, borrow_limit: {}",
+            account_borrow, limit_after
+        ))
+    } else if state.cash.u128() < underlying_out {
+        Some(format!(
+            "The lending pool has insufficient cash: redeem_amount: {}, pool_reserve: {}",
+            underlying_out, state.cash
+        ))
+    } else {
+        match (config.large_redeem_threshold, pending.as_ref()) {
+            (Some(threshold), None) if underlying_out > threshold.u128() => Some(format!(
+                "Redemption above threshold must be announced first: redeem_amount: {}, threshold: {}",
+                underlying_out, threshold
+            )),
+            (Some(threshold), Some(p)) if underlying_out > threshold.u128() && underlying_out > p.amount.u128() => Some(format!(
+                "Redemption exceeds announced amount: redeem_amount: {}, announced_amount: {}",
+                underlying_out, p.amount
+            )),
+            _ => None,
+        }
+    };
+
+    // Rates after the redemption, or at the current state when it would fail
+    let cash = match error {
+        Some(_) => state.cash,
+        None => Uint128::from(state.cash.u128() - underlying_out),
+    };
+    Ok(SimulateRedeemResponse {
+        success: error.is_none(),
+        error,
+        underlying_out: Uint128::from(underlying_out),
+        utilization_rate: Uint128::from(get_utilization_rate(&cash, &state.total_borrows, &state.total_reserves)),
+        supply_rate: Uint128::from(get_supply_rate(&model, &cash, &state.total_borrows, &state.total_reserves, &state.reserve_factor)),
+        executable_at: pending.map(|p| p.executable_at),
+    })
+}
+
+/// Mirrors the checks of try_liquidate, except interest is not accrued and the
+/// transfer policy is left to the caller since queries have no liquidator
+fn query_preview_liquidation<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    borrower: HumanAddr,
+    repay_amount: Uint128,
+) -> StdResult<PreviewLiquidationResponse> {
+    let config = get_config(&deps.storage)?;
+    let state = get_state(&deps.storage)?;
+    let borrower_raw = canonicalize_address(&deps.api, &borrower)?;
+
+    let account_borrow = match get_borrow_balance(&deps.storage, &borrower_raw) {
+        Some(snapshot) => current_borrow_balance(&snapshot, &state.borrow_index, config.dust_borrow_threshold),
+        None => 0,
+    };
+    let exchange_rate = calculate_exchange_rate(&config, &state)?;
+    let collateral_tokens = get_balance(&deps.storage, &borrower_raw)?;
+    let limit = borrow_limit(&config, collateral_tokens, exchange_rate);
+    let max_repay = max_repay(&config, account_borrow);
+    let (seize_value, seize_tokens, protocol_seize_tokens) = match exchange_rate {
+        0 => (0, 0, 0),
+        _ => seize_amounts(&config, repay_amount.u128(), exchange_rate),
+    };
+
+    // Queries have no block, the last accrual stands in for it
+    let not_underwater = not_underwater_error(deps, &config, &borrower, account_borrow, limit, state.block_number)?;
+
+    let error = if get_pause_flags(&deps.storage)?.is_paused(PauseAction::Liquidation) {
+        Some(format!("Action is paused: action: {}", PauseAction::Liquidation.as_str()))
+    } else if exchange_rate == 0 {
+        Some("Exchange rate is zero, collateral cannot be valued".to_string())
+    } else if not_underwater.is_some() {
+        not_underwater
+    } else if repay_amount.u128() > max_repay {
+        Some(format!(
+            "Repay amount exceeds close factor: repay_amount: {}, max_repay: {}",
+            repay_amount, max_repay
+        ))
+    } else if seize_tokens > collateral_tokens {
+        Some(format!(
+            "Borrower has insufficient collateral to seize: seize_tokens: {}, collateral_tokens: {}",
+            seize_tokens, collateral_tokens
+        ))
+    } else {
+        None
+    };
+
+    Ok(PreviewLiquidationResponse {
+        success: error.is_none(),
+        error,
+        account_borrow: Uint128::from(account_borrow),
+        borrow_limit: Uint128::from(limit),
+        max_repay: Uint128::from(max_repay),
+        collateral_tokens: Uint128::from(collateral_tokens),
+        seize_tokens: Uint128::from(seize_tokens),
+        liquidator_seize_tokens: Uint128::from(seize_tokens - protocol_seize_tokens),
+        protocol_seize_tokens: Uint128::from(protocol_seize_tokens),
+        seize_value: Uint128::from(seize_value),
+        incentive_value: Uint128::from(seize_value.saturating_sub(repay_amount.u128())),
+    })
+}
+
+fn query_tranches<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    owner: HumanAddr,
+    page: PageRequest,
+) -> StdResult<TranchesResponse> {
+    let owner_raw = canonicalize_address(&deps.api, &owner)?;
+    let start_after = match page.start_after.as_ref() {
+        Some(id) => Some(id.parse::<u64>().map_err(|_| {
+            StdError::generic_err(format!("Invalid tranche id: start_after: {}", id))
+        })?),
+        None => None,
+    };
+
+    // Only load one tranche past the page to learn whether another page follows
+    let tranches = get_owner_tranche_ids(&deps.storage, &owner_raw, start_after)?
+        .into_iter()
+        .take(page.limit() + 1)
+        .map(|id| {
+            let tranche = tranche_response(deps, get_tranche(&deps.storage, id)?)?;
+            Ok((id.to_string(), tranche))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let (tranches, page) = paginate(tranches.into_iter(), page.limit(), None);
+    Ok(TranchesResponse { tranches, page })
+}
+
+fn query_otc_offers<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    page: PageRequest,
+) -> StdResult<OtcOffersResponse> {
+    let start_after = match page.start_after.as_ref() {
+        Some(id) => Some(id.parse::<u64>().map_err(|_| {
+            StdError::generic_err(format!("Invalid offer id: start_after: {}", id))
+        })?),
+        None => None,
+    };
+
+    // Only load one offer past the page to learn whether another page follows
+    let offers = get_otc_offers(&deps.storage, start_after, page.limit() + 1)?
+        .into_iter()
+        .map(|offer| Ok((offer.id.to_string(), otc_offer_response(deps, offer)?)))
+        .collect::<StdResult<Vec<_>>>()?;
+    let (offers, page) = paginate(offers.into_iter(), page.limit(), None);
+    Ok(OtcOffersResponse { offers, page })
+}
+
+fn otc_offer_response<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, offer: OtcOffer) -> StdResult<OtcOfferResponse> {
+    Ok(OtcOfferResponse {
+        id: offer.id,
+        seller: deps.api.human_address(&offer.seller)?,
+        amount: offer.amount,
+        price: offer.price,
+        expires: offer.expires,
+    })
+}
+
+fn cash_strategy_msg<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    cash_strategy: Option<CashStrategy>,
+) -> StdResult<Option<CashStrategyMsg>> {
+    match cash_strategy {
+        Some(cash_strategy) => Ok(Some(CashStrategyMsg {
+            contract: deps.api.human_address(&cash_strategy.contract)?,
+            target_hot_ratio: cash_strategy.target_hot_ratio,
+        })),
+        None => Ok(None),
+    }
+}
+
+fn tranche_response<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, tranche: Tranche) -> StdResult<TrancheResponse> {
+    Ok(TrancheResponse {
+        id: tranche.id,
+        owner: deps.api.human_address(&tranche.owner)?,
+        amount: tranche.amount,
+        term: tranche.term,
+        start_block: tranche.start_block,
+        maturity: tranche.maturity,
+    })
+}
+
+/// Utilization the next accrual feeds the rate model, the stored average when one is configured
+fn rate_model_utilization<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, state: &State) -> StdResult<u128> {
+    let spot = get_utilization_rate(&state.cash, &state.total_borrows, &state.total_reserves);
+    let config = get_config(&deps.storage)?;
+    Ok(match (config.utilization_window, get_utilization_average(&deps.storage)?) {
+        (Some(_), Some(average)) => average.average_utilization.u128(),
+        _ => spot,
+    })
+}
+
+fn query_reserve_factor_preview<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    proposed_reserve_factor: Uint128,
+    horizon_blocks: u64,
+) -> StdResult<ReserveFactorPreviewResponse> {
+    if proposed_reserve_factor.u128() > MAX_RESERVE_FACTOR {
+        return Err(StdError::generic_err(format!(
+            "Reserve factor exceeds maximum: proposed_reserve_factor: {}, max_reserve_factor: {}",
+            proposed_reserve_factor, MAX_RESERVE_FACTOR
+        )));
+    }
+
+    let state = get_state(&deps.storage)?;
+    let model = get_interest_rate_model(&deps.storage)?;
+    let borrow_rate = get_borrow_rate(&model, &state.cash, &state.total_borrows, &state.total_reserves);
+
+    // Same simple interest accrual as accrue_interest, held at current utilization
+    let simple_interest_factor = borrow_rate * horizon_blocks as u128;
+    let projected_interest = truncate(simple_interest_factor * state.total_borrows.u128());
+    let current_reserve_growth = truncate(projected_interest * state.reserve_factor.u128());
+    let proposed_reserve_growth = truncate(projected_interest * proposed_reserve_factor.u128());
+
+    Ok(ReserveFactorPreviewResponse {
+        horizon_blocks,
+        borrow_rate: Uint128::from(borrow_rate),
+        projected_interest: Uint128::from(projected_interest),
+        current_reserve_factor: state.reserve_factor,
+        current_supply_rate: Uint128::from(get_supply_rate(&model, &state.cash, &state.total_borrows, &state.total_reserves, &state.reserve_factor)),
+        current_reserve_growth: Uint128::from(current_reserve_growth),
+        proposed_reserve_factor,
+        proposed_supply_rate: Uint128::from(get_supply_rate(&model, &state.cash, &state.total_borrows, &state.total_reserves, &proposed_reserve_factor)),
+        proposed_reserve_growth: Uint128::from(proposed_reserve_growth),
+    })
+}
+
+fn query_metrics<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<MetricsResponse> {
+    let metrics = get_metrics(&deps.storage)?;
+    let config = get_config(&deps.storage)?;
+    let state = get_state(&deps.storage)?;
+
+    let metric = |name: &str, kind: MetricKind, value: Uint128| MetricResponse {
+        name: name.to_string(),
+        kind,
+        value,
+    };
+    Ok(MetricsResponse {
+        metrics: vec![
+            metric("mints_total", MetricKind::Counter, Uint128::from(metrics.mints)),
+            metric("redeems_total", MetricKind::Counter, Uint128::from(metrics.redeems)),
+            metric("borrows_total", MetricKind::Counter, Uint128::from(metrics.borrows)),
+            metric("interest_accrued_total", MetricKind::Counter, metrics.cumulative_interest),
+            metric("reserve_interest_total", MetricKind::Counter, metrics.cumulative_reserve_interest),
+            metric("clamped_accruals_total", MetricKind::Counter, Uint128::from(metrics.clamped_accruals)),
+            metric("liquidations_total", MetricKind::Counter, Uint128::from(metrics.liquidations)),
+            metric("cash", MetricKind::Gauge, state.cash),
+            metric("total_borrows", MetricKind::Gauge, state.total_borrows),
+            metric("total_reserves", MetricKind::Gauge, state.total_reserves),
+            metric("total_supply", MetricKind::Gauge, config.total_supply),
+        ],
+    })
+}
+
+fn query_signing_payload<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    action: PayloadAction,
+) -> StdResult<SigningPayloadResponse> {
+    let config = get_config(&deps.storage)?;
+    let market = deps.api.human_address(&config.contract_addr)?;
+
+    let (msg, payment) = match action {
+        PayloadAction::Mint { amount } => (HandleMsg::Mint {}, Some((Cw20HookMsg::Mint {}, amount))),
+        PayloadAction::Redeem { redeem_tokens_in } => (HandleMsg::Redeem { redeem_tokens_in }, None),
+        PayloadAction::RedeemUnderlying { redeem_amount } => (HandleMsg::RedeemUnderlying { redeem_amount }, None),
+        PayloadAction::Borrow { borrow_amount, integrator_fee } => {
+            (HandleMsg::Borrow { borrow_amount, integrator_fee }, None)
+        }
+        PayloadAction::RepayBorrow { amount } => (HandleMsg::RepayBorrow {}, Some((Cw20HookMsg::RepayBorrow {}, amount))),
+    };
+
+    // CW20 payments go through the token's Send, which calls back into the market
+    let (contract, msg, funds) = match (payment, &config.underlying) {
+        (Some((_, amount)), AssetInfoRaw::NativeToken { denom }) => {
+            (market, to_binary(&msg)?, vec![Coin { denom: denom.clone(), amount }])
+        }
+        (Some((hook_msg, amount)), AssetInfoRaw::Token { contract_addr }) => {
+            let send = Cw20HandleMsg::Send {
+                contract: market,
+                amount,
+                msg: Some(to_binary(&hook_msg)?),
+            };
+            (deps.api.human_address(contract_addr)?, to_binary(&send)?, vec![])
+        }
+        (None, _) => (market, to_binary(&msg)?, vec![]),
+    };
+
+    let json = String::from_utf8(msg.as_slice().to_vec())
+        .map_err(|_| StdError::generic_err("Execute payload is not valid UTF-8"))?;
+    Ok(SigningPayloadResponse { contract, msg, json, funds })
+}
+
+/// Leaderboard entries revalued with `amount_of` and ranked again
+/// The board only changes when an account acts, so an account that left it is
+/// missing until it acts again and the ranking beyond the listed accounts is approximate
+fn query_leaderboard<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    key: &[u8],
+    total: Uint128,
+    page: PageRequest,
+    amount_of: impl Fn(&CanonicalAddr) -> StdResult<u128>,
+) -> StdResult<LeaderboardResponse> {
+    let mut board = get_leaderboard(&deps.storage, key)?
+        .into_iter()
+        .map(|entry| Ok((amount_of(&entry.address)?, entry.address)))
+        .collect::<StdResult<Vec<_>>>()?;
+    board.retain(|(amount, _)| *amount > 0);
+    board.sort_by_key(|(amount, _)| Reverse(*amount));
+    let board_size = board.len() as u64;
+    let entries = board
+        .into_iter()
+        .map(|(amount, account)| {
+            let amount = Uint128::from(amount);
+            let share = if total.is_zero() {
+                Decimal::zero()
+            } else {
+                Decimal::from_ratio(amount, total).min(Decimal::one())
+            };
+            let address = deps.api.human_address(&account)?;
+            Ok((address.to_string(), LeaderboardEntryResponse {
+                address,
+                amount,
+                share,
+            }))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    // Entries are ordered by rank, so start_after resumes after that account's position
+    let start = match page.start_after.as_ref() {
+        Some(start_after) => entries
+            .iter()
+            .position(|(address, _)| address == start_after)
+            .map_or(entries.len(), |index| index + 1),
+        None => 0,
+    };
+    let (entries, page) = paginate(entries.into_iter().skip(start), page.limit(), Some(board_size));
+    Ok(LeaderboardResponse { total, entries, page })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, from_binary};
+
+    use crate::contract::handle;
+    use crate::contract::testing::{mock_init_msg, mock_market, MOCK_DENOM};
+    use crate::msg::HandleMsg;
+    use crate::state::InterestRateModel;
+
+    #[test]
+    fn top_borrowers_are_valued_at_the_current_borrow_index() {
+        let mut msg = mock_init_msg();
+        msg.interest_rate_model = Some(InterestRateModel::Linear {
+            base_rate_per_block: Uint128::from(1_000u128),
+            multiplier_per_block: Uint128::zero(),
+            min_borrow_rate_per_block: None,
+        });
+        let mut deps = mock_market(msg);
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(20_000, MOCK_DENOM));
+        let start = mock_env("admin", &[]).block.height;
+        let borrow = |amount: u128| HandleMsg::Borrow { borrow_amount: Uint128::from(amount), integrator_fee: None };
+        handle(&mut deps, mock_env("alice", &coins(10_000, MOCK_DENOM)), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, mock_env("bob", &coins(10_000, MOCK_DENOM)), HandleMsg::Mint {}).unwrap();
+        handle(&mut deps, mock_env("alice", &[]), borrow(1_000)).unwrap();
+        handle(&mut deps, mock_env("bob", &[]), borrow(2_000)).unwrap();
+
+        // 1_000 blocks at 0.00001 per block add 1% to every debt, neither borrower acted
+        let mut env = mock_env("carol", &coins(1, MOCK_DENOM));
+        env.block.height = start + 1_000;
+        handle(&mut deps, env, HandleMsg::Mint {}).unwrap();
+
+        let res: LeaderboardResponse = from_binary(&query(&deps, QueryMsg::TopBorrowers { page: None }).unwrap()).unwrap();
+        let ranked: Vec<(HumanAddr, Uint128)> = res.entries.into_iter().map(|entry| (entry.address, entry.amount)).collect();
+        assert_eq!(ranked, vec![
+            (HumanAddr::from("bob"), Uint128::from(2_020u128)),
+            (HumanAddr::from("alice"), Uint128::from(1_010u128)),
+        ]);
+        assert_eq!(res.total, Uint128::from(3_030u128));
     }
 }
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
+use cosmwasm_std::{
+    from_slice, Binary, Empty, Extern, HumanAddr, Order, Querier, QuerierResult, QueryRequest, ReadonlyStorage,
+    StdResult, Storage, SystemError, Uint128, WasmQuery,
+};
+
+use quasar_interfaces::asset::AssetInfo;
+
+use crate::contract::init;
+use crate::msg::InitMsg;
+use crate::state::TermBonusRates;
+
+pub const MOCK_DENOM: &str = "uluna";
+
+/// Standalone native market at an exchange rate of 1 with every optional feature disabled
+pub fn mock_init_msg() -> InitMsg {
+    InitMsg {
+        admin: None,
+        name: "Quasar Luna".to_string(),
+        total_supply: Uint128::zero(),
+        decimals: 6,
+        symbol: "qLUNA".to_string(),
+        initial_exchange_rate: Uint128::from(100_000_000u128),
+        reserve_factor: Uint128::from(10_000_000u128),
+        borrow_index: Uint128::from(100_000_000u128),
+        max_borrow_rate: Uint128::from(5_000u128),
+        underlying: AssetInfo::NativeToken { denom: MOCK_DENOM.to_string() },
+        approve_enabled: true,
+        large_redeem_threshold: None,
+        redeem_announce_delay: 0,
+        term_bonus_rates: TermBonusRates::default(),
+        early_exit_penalty: Uint128::zero(),
+        max_accrual_block_delta: None,
+        utilization_window: None,
+        dust_borrow_threshold: None,
+        collateral_factor: Uint128::from(75_000_000u128),
+        close_factor: Uint128::from(50_000_000u128),
+        liquidation_incentive: Uint128::from(8_000_000u128),
+        protocol_seize_share: Uint128::zero(),
+        interest_rate_model: None,
+        oracle: None,
+        comptroller: None,
+        treasury: None,
+        guardian: None,
+        flash_loan_fee: None,
+        balance_hook: None,
+        cash_strategy: None,
+        insurance: None,
+        blocks_per_year: None,
+        buyback: None,
+        transfer_policy: None,
+        borrow_cooldown: None,
+        min_borrow_health_factor: None,
+        backstop: None,
+    }
+}
+
+/// Market instantiated by `admin` from `msg`
+pub fn mock_market(msg: InitMsg) -> Extern<MockStorage, MockApi, MockQuerier> {
+    let mut deps = mock_dependencies(20, &[]);
+    init(&mut deps, mock_env("admin", &[]), msg).unwrap();
+    deps
+}
+
+/// Answers a smart query sent to one mocked contract
+pub type ContractHandler = Box<dyn Fn(&Binary) -> StdResult<Binary>>;
+
+/// MockQuerier that also answers smart queries to the mocked contracts
+/// Other contracts are missing, as with MockQuerier
+pub struct WasmMockQuerier {
+    pub base: MockQuerier,
+    pub contracts: HashMap<HumanAddr, ContractHandler>,
+}
+
+impl WasmMockQuerier {
+    pub fn with_contract(&mut self, address: &str, handler: impl Fn(&Binary) -> StdResult<Binary> + 'static) {
+        self.contracts.insert(HumanAddr::from(address), Box::new(handler));
+    }
+}
+
+impl Querier for WasmMockQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let request: QueryRequest<Empty> = match from_slice(bin_request) {
+            Ok(request) => request,
+            Err(e) => {
+                return Err(SystemError::InvalidRequest {
+                    error: format!("Parsing query request: {}", e),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        match &request {
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => match self.contracts.get(contract_addr) {
+                Some(handler) => Ok(handler(msg)),
+                None => Err(SystemError::NoSuchContract { addr: contract_addr.clone() }),
+            },
+            _ => self.base.handle_query(&request),
+        }
+    }
+}
+
+/// The mock market with a querier that can mock other contracts
+pub fn with_wasm_querier(deps: Extern<MockStorage, MockApi, MockQuerier>) -> Extern<MockStorage, MockApi, WasmMockQuerier> {
+    Extern {
+        storage: deps.storage,
+        api: deps.api,
+        querier: WasmMockQuerier { base: deps.querier, contracts: HashMap::new() },
+    }
+}
+
+/// xorshift64, deterministic so failing sequences replay from their seed
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Mostly amounts around `near`, where the edge cases are
+    pub fn amount(&mut self, near: u128) -> u128 {
+        match self.below(6) {
+            0 => 0,
+            1 => near,
+            2 => near.saturating_add(1),
+            3 => self.next() as u128 % near.saturating_mul(2).saturating_add(1),
+            4 => u128::MAX,
+            _ => self.next() as u128 % 1_000,
+        }
+    }
+}
+
+/// Copy of `storage` to restore after a failed message
+/// Failed messages revert on chain, handlers are not expected to leave storage untouched on error
+pub fn snapshot_storage(storage: &MockStorage) -> MockStorage {
+    let mut copy = MockStorage::new();
+    for (key, value) in storage.range(None, None, Order::Ascending) {
+        copy.set(&key, &value);
+    }
+    copy
+}
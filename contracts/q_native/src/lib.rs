@@ -3,6 +3,6 @@ pub mod msg;
 pub mod state;
 
 #[cfg(target_arch = "wasm32")]
-cosmwasm_std::create_entry_points!(contract);
+cosmwasm_std::create_entry_points_with_migration!(contract);
 
 
@@ -1,10 +1,22 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{HumanAddr, Uint128};
+use cosmwasm_std::{to_binary, Binary, Coin, CosmosMsg, Decimal, HumanAddr, StdResult, Uint128, WasmMsg};
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cw20::{Cw20ReceiveMsg, Expiration};
+use quasar_interfaces::asset::AssetInfo;
+use quasar_interfaces::market::AccountSnapshotResponse;
+use quasar_interfaces::oracle::OracleKind;
+use quasar_interfaces::pagination::{PageRequest, PageResponse};
+
+use crate::state::{
+    BackstopConfig, BorrowSnapshot, CompactionSection, Config, ConfigChange, DepositTerm, InsuranceConfig, InterestRateModel, InterestRateModelBounds, PauseAction, PendingInterestRateModelBounds, Role, SnapshotSection, State, TermBonusRates,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InitMsg {
+    /// Defaults to the instantiating address
+    pub admin: Option<HumanAddr>,
     pub name: String,
     pub total_supply: Uint128,
     pub decimals: u8,
@@ -13,24 +25,424 @@ pub struct InitMsg {
     pub reserve_factor: Uint128,
     pub borrow_index: Uint128,
     pub max_borrow_rate: Uint128,
-    pub denom: String
+    /// CW20 underlyings are deposited through the token's Send with a Cw20HookMsg
+    pub underlying: AssetInfo,
+    pub approve_enabled: bool,
+    pub large_redeem_threshold: Option<Uint128>,
+    pub redeem_announce_delay: u64,
+    pub term_bonus_rates: TermBonusRates,
+    pub early_exit_penalty: Uint128,
+    pub max_accrual_block_delta: Option<u64>,
+    pub utilization_window: Option<u64>,
+    pub dust_borrow_threshold: Option<Uint128>,
+    pub collateral_factor: Uint128,
+    pub close_factor: Uint128,
+    pub liquidation_incentive: Uint128,
+    pub protocol_seize_share: Uint128,
+    /// Defaults to the jump rate curve markets used before models became configurable
+    pub interest_rate_model: Option<InterestRateModel>,
+    pub oracle: Option<OracleMsg>,
+    /// Comptroller consulted after every mint, redeem, borrow and repay
+    pub comptroller: Option<HumanAddr>,
+    /// Defaults to the admin
+    pub treasury: Option<HumanAddr>,
+    pub guardian: Option<HumanAddr>,
+    pub flash_loan_fee: Option<Uint128>,
+    pub balance_hook: Option<HumanAddr>,
+    pub cash_strategy: Option<CashStrategyMsg>,
+    pub insurance: Option<InsuranceConfig>,
+    pub blocks_per_year: Option<u64>,
+    pub buyback: Option<BuybackMsg>,
+    pub transfer_policy: Option<HumanAddr>,
+    pub borrow_cooldown: Option<u64>,
+    pub min_borrow_health_factor: Option<Decimal256>,
+    pub backstop: Option<BackstopConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OracleMsg {
+    pub contract: HumanAddr,
+    pub kind: OracleKind,
+    /// Symbol the oracle prices the underlying under
+    pub base: String,
+    /// Denom account liquidity is reported in
+    pub quote: String,
+}
+
+/// Only native underlyings can buy back the CW20 governance `token`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuybackMsg {
+    pub pair: HumanAddr,
+    pub token: HumanAddr,
+    pub max_per_epoch: Uint128,
+    pub epoch_blocks: u64,
+    pub max_spread: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferPolicyMsg {
+    Enforced { contract: HumanAddr },
+    Disabled,
+}
+
+/// Fee paid to a registered integrator out of the borrowed amount
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IntegratorFee {
+    pub integrator: HumanAddr,
+    pub fee_bps: u16,
+}
+
+/// Only native underlyings can deploy cash to a strategy
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CashStrategyMsg {
+    pub contract: HumanAddr,
+    /// Share of cash kept in the market, scaled by 10^8
+    pub target_hot_ratio: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// Required when the stored config predates contract versions
+    pub legacy_config: Option<LegacyConfigParams>,
+    /// Re-encode this many legacy balance and allowance entries as part of the migration,
+    /// CompactStorage picks up where it stops
+    pub compact_limit: Option<u32>,
+}
+
+/// Values for config fields added since the first deployments, fields not listed
+/// here start out disabled
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyConfigParams {
+    /// Defaults to the migrating address
+    pub admin: Option<HumanAddr>,
+    /// Defaults to the admin
+    pub treasury: Option<HumanAddr>,
+    pub collateral_factor: Uint128,
+    pub close_factor: Uint128,
+    pub liquidation_incentive: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
     Mint {},
+    /// Mint for the sent funds and credit the qTokens to `recipient`
+    MintTo {
+        recipient: HumanAddr,
+    },
     Redeem {
         redeem_tokens_in: Uint128
     },
+    /// Burn the qTokens worth `redeem_amount` underlying, rounded up, and pay that amount out
+    RedeemUnderlying {
+        redeem_amount: Uint128
+    },
+    /// Admin or param_admin only, interest up to the current block accrues at the previous model
+    UpdateInterestRateModel {
+        model: InterestRateModel,
+    },
+    /// Admin or param_admin only, register a candidate model reported in accrual events but never applied
+    SetShadowInterestRateModel {
+        model: Option<InterestRateModel>,
+    },
+    /// Admin only, `bounds` replace the rate model bounds once the timelock has passed, None withdraws the proposal
+    ProposeInterestRateModelBounds {
+        bounds: Option<InterestRateModelBounds>,
+    },
+    /// Admin only, once the proposed bounds are past their eta
+    ApplyInterestRateModelBounds {},
+    /// Comptroller only, cache its protocol-wide pause
+    SyncGlobalPause {
+        paused: bool,
+        epoch: u64,
+    },
+    /// Move underlying held above tracked cash into reserves
+    Skim {},
+    /// Add the sent funds to reserves
+    AddReserves {},
+    /// Admin or reserve_manager only, send `amount` of reserves to the treasury
+    ReduceReserves {
+        amount: Uint128,
+    },
+    /// Admin or param_admin only, interest up to the current block accrues at the previous factor
+    /// With `ramp_blocks` the factor moves linearly to `reserve_factor` over that many blocks,
+    /// each accrual splitting interest at the factor reached by its block
+    SetReserveFactor {
+        reserve_factor: Uint128,
+        ramp_blocks: Option<u64>,
+    },
+    /// Admin or param_admin only, applies to liquidations after this one
+    SetProtocolSeizeShare {
+        protocol_seize_share: Uint128,
+    },
+    /// Admin only, `new_owner` becomes admin once it accepts
+    TransferOwnership {
+        new_owner: HumanAddr,
+    },
+    /// Pending owner only
+    AcceptOwnership {},
+    /// Admin only, None removes the guardian
+    SetGuardian {
+        guardian: Option<HumanAddr>,
+    },
+    /// Admin pauses or unpauses, the guardian and pause_guardian holders can only pause
+    SetPaused {
+        action: PauseAction,
+        paused: bool,
+    },
+    /// Admin only, `account` may then send the messages `role` covers
+    GrantRole {
+        role: Role,
+        account: HumanAddr,
+    },
+    /// Admin only
+    RevokeRole {
+        role: Role,
+        account: HumanAddr,
+    },
+    /// Admin or oracle_admin only, None leaves the market without a price feed
+    SetOracle {
+        oracle: Option<OracleMsg>,
+    },
+    /// Admin or reserve_manager only, the strategy contract can only change or be removed once no warm cash is left
+    SetCashStrategy {
+        cash_strategy: Option<CashStrategyMsg>,
+    },
+    /// Move cash between the market and the strategy towards the target hot ratio, callable by anyone
+    Rebalance {},
+    /// Admin only, enforces or replaces the transfer policy, None disables it for good
+    SetTransferPolicy {
+        contract: Option<HumanAddr>,
+    },
+    /// Admin only, None stops BalanceChanged notifications
+    SetBalanceHook {
+        balance_hook: Option<HumanAddr>,
+    },
+    /// Admin or param_admin only, None disables flash loans
+    SetFlashLoanFee {
+        flash_loan_fee: Option<Uint128>,
+    },
+    /// Send `amount` of cash to `recipient`, call it with a FlashLoanReceiverMsg and
+    /// require the amount plus the fee back by the end of the transaction
+    FlashLoan {
+        amount: Uint128,
+        msg: Option<Binary>,
+        recipient: HumanAddr,
+    },
+    /// Market only, checks repayment of the outstanding flash loan
+    FinishFlashLoan {},
+    /// Admin only, re-encodes the next `limit` balance and allowance entries still
+    /// in the raw 16 byte layout, resuming where the last batch stopped
+    CompactStorage {
+        limit: Option<u32>,
+    },
+    /// Admin only, freezes qToken balances and debts at this block into a new snapshot,
+    /// or captures the next `limit` accounts of the snapshot in progress
+    SnapshotLiquidity {
+        limit: Option<u32>,
+    },
+    /// Admin only, opens a fresh market for ImportGenesis and closes it to every other message
+    BeginGenesisImport {},
+    /// Admin only, applies the next chunk of an ExportGenesis run
+    ImportGenesis {
+        chunk: GenesisChunk,
+    },
+    /// Admin only, reopens the market once the imported chunks hash to `digest`
+    FinishGenesisImport {
+        digest: Binary,
+    },
+    /// Announce a redemption above `large_redeem_threshold`, in underlying
+    AnnounceRedeem {
+        amount: Uint128
+    },
+    /// `integrator_fee` is paid out of `borrow_amount`, the sender owes all of it
     Borrow {
-        borrow_amount: Uint128
+        borrow_amount: Uint128,
+        integrator_fee: Option<IntegratorFee>,
     },
     RepayBorrow {},
+    /// Swap the attached `offer_denom` into the underlying through its swap pair and
+    /// repay the sender's borrow, swapping at most `max_offer` and refunding the rest
+    RepayWithOtherAsset {
+        offer_denom: String,
+        max_offer: Uint128,
+    },
+    /// Market only, repays with what the RepayWithOtherAsset swap returned
+    FinishRepayWithOtherAsset {},
+    /// Admin or param_admin only, None stops accepting `offer_denom` in RepayWithOtherAsset
+    SetSwapPair {
+        offer_denom: String,
+        pair: Option<HumanAddr>,
+    },
+    /// Repay part of an underwater borrow with the sent funds and seize the borrower's qTokens,
+    /// in this market or, through the comptroller, in `collateral_market`
+    Liquidate {
+        borrower: HumanAddr,
+        repay_amount: Uint128,
+        collateral_market: Option<HumanAddr>,
+    },
+    /// Comptroller only, seize qTokens worth `repay_value` of the underlying plus the
+    /// liquidation incentive for a liquidation repaid in another market
+    SeizeCollateral {
+        borrower: HumanAddr,
+        liquidator: HumanAddr,
+        repay_value: Uint128,
+    },
+    /// Flag an underwater account so it cannot move qTokens out for the rest of the block
+    FlagShortfall {
+        account: HumanAddr,
+    },
+    /// Callable by anyone, standalone markets only. The first call flags a borrower below the
+    /// backstop health factor, a call `delay` blocks later repays the debt out of reserves and
+    /// seizes the borrower's qTokens to the treasury unless a liquidator acted in between
+    BackstopLiquidate {
+        borrower: HumanAddr,
+    },
+    /// Admin or param_admin only, None disables backstop liquidations
+    SetBackstop {
+        backstop: Option<BackstopConfig>,
+    },
+    /// Write off a borrow at or below `dust_borrow_threshold` against reserves
+    CloseDustBorrow {
+        account: HumanAddr,
+    },
+    /// Lock qTokens for a fixed term in exchange for a bonus at maturity
+    LockDeposit {
+        amount: Uint128,
+        term: DepositTerm,
+    },
+    /// Withdraw a term deposit, paying `early_exit_penalty` before maturity
+    UnlockDeposit {
+        id: u64,
+    },
+    /// Escrow `amount` qTokens for sale at `price` in the underlying until block `expires`
+    ListOtc {
+        amount: Uint128,
+        price: Uint128,
+        expires: u64,
+    },
+    /// Buy a listed offer whole, paying its price in the native underlying to the seller
+    BuyOtc {
+        id: u64,
+    },
+    /// Return an offer's qTokens to the seller, by the seller or by anyone once expired
+    CancelOtc {
+        id: u64,
+    },
+    /// Route the yield of the sender's qTokens to repaying the sender's borrow
+    EnableSelfRepay {},
+    DisableSelfRepay {},
+    /// Settle accrued yield of an account against its debt, callable by anyone once per epoch
+    SettleSelfRepay {
+        account: HumanAddr,
+    },
+    /// Donate the yield of the sender's qTokens to `beneficiary`, the principal stays with the sender
+    EnableYieldRedirect {
+        beneficiary: HumanAddr,
+    },
+    DisableYieldRedirect {},
+    /// Transfer the qTokens an account's yield bought to its beneficiary, callable by anyone once per epoch
+    SettleYieldRedirect {
+        account: HumanAddr,
+    },
+    /// Emit health_alert events whenever the sender's health factor crosses one of
+    /// the thresholds, scaled by 10^8, empty stops alerts
+    SetHealthAlerts {
+        thresholds: Vec<Uint128>,
+    },
+    /// Admin or param_admin only, emit utilization_threshold_crossed events whenever an accrual finds
+    /// utilization crossed one of the thresholds, scaled by 10^8, empty stops alerts
+    SetUtilizationAlerts {
+        thresholds: Vec<Uint128>,
+    },
+    /// Admin or param_admin only, None stops new borrowers from opting into insurance
+    SetInsuranceConfig {
+        insurance: Option<InsuranceConfig>,
+    },
+    /// Opt the sender's borrow into paying the insurance premium, or out of it
+    SetInsured {
+        insured: bool,
+    },
+    /// Move the premium an insured account owes into the insurance pool, callable by anyone
+    SettlePremium {
+        account: HumanAddr,
+    },
+    /// Admin or param_admin only, lets `account` off `discount` of its borrow interest, scaled by
+    /// 10^8, until block `expires`, refunded out of reserves whenever the deferral is settled
+    GrantInterestDeferral {
+        account: HumanAddr,
+        discount: Uint128,
+        expires: u64,
+    },
+    /// Admin or param_admin only, settles the rebate owed so far and ends the deferral
+    RevokeInterestDeferral {
+        account: HumanAddr,
+    },
+    /// Refund the interest rebate an account is owed, callable by anyone
+    SettleInterestDeferral {
+        account: HumanAddr,
+    },
+    /// Record the oracle price volatile liquidations are measured against,
+    /// callable by anyone once the last checkpoint is a window old
+    CheckpointPrice {},
+    /// Only accepted while the action is paused, logs what the sender wants to do
+    /// once the market reopens without changing any state
+    Intent {
+        action: IntentAction,
+        amount: Uint128,
+    },
+    /// Admin or param_admin only, registers `integrator` for fees up to `max_fee_bps`, None deregisters it
+    SetIntegrator {
+        integrator: HumanAddr,
+        max_fee_bps: Option<u16>,
+    },
+    /// Admin or reserve_manager only, None disables buybacks
+    SetBuyback {
+        buyback: Option<BuybackMsg>,
+    },
+    /// Admin or reserve_manager only, swaps `amount` of reserves for the governance token and burns it
+    /// `min_receive` is priced off-chain, the pair's own quote can be moved within the transaction
+    Buyback {
+        amount: Uint128,
+        min_receive: Uint128,
+    },
+    /// Burns what the buyback swap returned, only callable by the market itself
+    FinishBuyback {},
+    /// Admin or param_admin only, borrow limit over debt a borrow must leave the borrower at in a
+    /// standalone market, None only keeps borrows within the collateral limit. Markets with a
+    /// comptroller use its minimum instead
+    SetMinBorrowHealthFactor {
+        min_borrow_health_factor: Option<Decimal256>,
+    },
+    /// Admin or param_admin only, blocks a borrower must wait to redeem or move qTokens, None removes the cooldown
+    SetBorrowCooldown {
+        blocks: Option<u64>,
+    },
+    /// Admin or param_admin only, None falls back to DEFAULT_BLOCKS_PER_YEAR
+    SetBlocksPerYear {
+        blocks_per_year: Option<u64>,
+    },
+    /// Admin only and irreversible, stops accrual and every action but repayments
+    /// and claims, and releases cash and reserves to qToken holders
+    EnterSettlement {},
+    /// Pay the sender its share of cash and of repayments since its last claim
+    ClaimSettlement {},
+    /// Deprecated, only accepted while `approve_enabled` is set in config
     Approve {
         spender: HumanAddr,
         amount: Uint128,
     },
+    IncreaseAllowance {
+        spender: HumanAddr,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    DecreaseAllowance {
+        spender: HumanAddr,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
     Transfer {
         recipient: HumanAddr,
         amount: Uint128,
@@ -40,12 +452,50 @@ pub enum HandleMsg {
         recipient: HumanAddr,
         amount: Uint128,
     },
+    /// Underlying deposited by a CW20 underlying token, `msg` holds a Cw20HookMsg
+    Receive(Cw20ReceiveMsg),
+    /// Transfer to a contract and call its `receive` hook with a Cw20ReceiveMsg
+    Send {
+        contract: HumanAddr,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    SendFrom {
+        owner: HumanAddr,
+        contract: HumanAddr,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    Burn {
+        amount: Uint128,
+    },
+    BurnFrom {
+        owner: HumanAddr,
+        amount: Uint128,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
+    /// CW20 queries, answered with the cw20 response types
+    TokenInfo {},
+    Minter {},
+    AllAllowances {
+        owner: HumanAddr,
+        start_after: Option<HumanAddr>,
+        limit: Option<u32>,
+    },
+    AllAccounts {
+        start_after: Option<HumanAddr>,
+        limit: Option<u32>,
+    },
+    /// Accounts with an outstanding borrow in address order, for liquidation bots
+    AllBorrowers {
+        start_after: Option<HumanAddr>,
+        limit: Option<u32>,
+    },
     Balance {
         address: HumanAddr,
     },
@@ -53,10 +503,223 @@ pub enum QueryMsg {
         owner: HumanAddr,
         spender: HumanAddr,
     },
+    /// Largest suppliers at their current balance, approximate: the list is kept on
+    /// actions, so an account that dropped off is only ranked again once it acts
+    TopSuppliers {
+        page: Option<PageRequest>,
+    },
+    /// Largest borrowers at their debt at the current borrow index, approximate like TopSuppliers
+    TopBorrowers {
+        page: Option<PageRequest>,
+    },
+    SmoothedExchangeRate {},
+    Capabilities {},
+    SigningPayload {
+        action: PayloadAction,
+    },
+    PendingRedeem {
+        address: HumanAddr,
+    },
+    Metrics {},
+    /// Interest borrowers paid since the market was created and how it was split
+    InterestSinceInception {},
+    BorrowIndex {},
+    Roles {},
+    /// Accounts granted `role`, the admin holds every role without being listed
+    RoleMembers {
+        role: Role,
+        page: Option<PageRequest>,
+    },
+    /// Roles granted to `address`
+    AccountRoles {
+        address: HumanAddr,
+    },
+    PauseStatus {},
+    /// Last accrual and what accruing at `block_height` would add
+    AccrualState {
+        block_height: u64,
+    },
+    InterestRateModel {},
+    ShadowInterestRateModel {},
+    InterestRateModelBounds {},
+    BorrowRate {},
+    SupplyRate {},
+    /// Reserve factor change in progress, None when the factor is not moving
+    ReserveFactorRamp {},
+    /// Simulate a reserve factor change at current utilization
+    ReserveFactorPreview {
+        proposed_reserve_factor: Uint128,
+        horizon_blocks: u64,
+    },
+    Tranche {
+        id: u64,
+    },
+    Tranches {
+        owner: HumanAddr,
+        page: Option<PageRequest>,
+    },
+    /// None once the offer is bought or cancelled
+    OtcOffer {
+        id: u64,
+    },
+    /// Offers not yet bought or cancelled, expired ones included
+    OtcOffers {
+        page: Option<PageRequest>,
+    },
+    SelfRepay {
+        address: HumanAddr,
+    },
+    YieldRedirect {
+        address: HumanAddr,
+    },
+    HealthAlerts {
+        address: HumanAddr,
+    },
+    UtilizationAlerts {},
+    InsurancePool {},
+    /// None if the account is not insured
+    InsuredPosition {
+        address: HumanAddr,
+    },
+    /// None if the account has no interest deferral
+    InterestDeferral {
+        address: HumanAddr,
+    },
+    /// None until BackstopLiquidate flagged the account
+    BackstopFlag {
+        address: HumanAddr,
+    },
+    /// None until the market is settled
+    Settlement {},
+    SettlementClaim {
+        address: HumanAddr,
+    },
+    /// Reserves spent on buybacks in the current epoch
+    BuybackEpoch {},
+    /// Fee cap and fees paid of an integrator, None if not registered
+    Integrator {
+        address: HumanAddr,
+    },
+    /// Pair RepayWithOtherAsset swaps `offer_denom` through, None if not accepted
+    SwapPair {
+        offer_denom: String,
+    },
+    /// Chunk of the market state at `cursor`, None starts with the market chunk
+    /// `prior_digest` is the digest of the previous chunk, so the last chunk's
+    /// digest commits to the whole export
+    ExportGenesis {
+        cursor: Option<GenesisCursor>,
+        prior_digest: Option<Binary>,
+        limit: Option<u32>,
+    },
+    GenesisImport {},
+    /// Progress re-encoding legacy balance and allowance entries
+    StorageCompaction {},
+    /// Snapshot `id`, the latest when omitted, None before the first
+    LiquiditySnapshot {
+        id: Option<u64>,
+    },
+    /// Balances or debts captured by snapshot `id` in export order, the order its digest chains them in
+    LiquiditySnapshotEntries {
+        id: u64,
+        section: SnapshotSection,
+        page: Option<PageRequest>,
+    },
+    /// Market figures both as stored and as decimal strings, for clients that do
+    /// not want to reimplement the 10^8 and token decimal scaling
+    HumanizedMarket {},
+    /// `rate` expressed in every unit, using the configured blocks per year
+    ConvertRate {
+        rate: Decimal256,
+        unit: RateUnit,
+    },
+    HumanizedAccount {
+        address: HumanAddr,
+    },
+    /// Owners that granted an allowance to the spender
+    SpenderAllowances {
+        spender: HumanAddr,
+        page: Option<PageRequest>,
+    },
+    /// Hash chain over market state for cross-checking RPC nodes
+    StateDigest {},
+    AccountDigest {
+        address: HumanAddr,
+    },
+    /// Liquidation log in execution order, optionally of one borrower
+    Liquidations {
+        borrower: Option<HumanAddr>,
+        page: Option<PageRequest>,
+    },
+    /// Admin, guardian and comptroller changes in execution order
+    AuditLog {
+        page: Option<PageRequest>,
+    },
+    /// Migrations applied to the market in order, with the config fields each changed
+    MigrationHistory {
+        page: Option<PageRequest>,
+    },
+    /// Config fields changed between two recorded versions, the oldest and latest when omitted
+    ConfigDiff {
+        from_version: Option<String>,
+        to_version: Option<String>,
+    },
+    /// Check a redemption of `amount` qTokens against the current market state
+    SimulateRedeem {
+        address: HumanAddr,
+        amount: Uint128,
+    },
+    /// Check a liquidation of `borrower` repaying `repay_amount` against the current market state
+    PreviewLiquidation {
+        borrower: HumanAddr,
+        repay_amount: Uint128,
+    },
+    /// Borrow capacity left, or debt over the limit, valued at the oracle price
+    AccountLiquidity {
+        address: HumanAddr,
+    },
+    Reserves {},
+    /// Cash held by the market and cash deployed to the strategy
+    CashTiers {},
+    /// Protocol-wide pause as last cached from the comptroller
+    GlobalPause {},
+    /// Cash, borrows and reserves as read by the comptroller
+    MarketTotals {},
+    /// Position of an account as read by the comptroller
+    AccountSnapshot {
+        address: HumanAddr,
+    },
+    /// AccountSnapshot for up to MAX_ACCOUNT_SNAPSHOTS accounts, in the order given
+    AccountSnapshots {
+        addresses: Vec<HumanAddr>,
+    },
+}
+
+/// Market actions a wallet can request an execute payload for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadAction {
+    Mint {
+        amount: Uint128,
+    },
+    Redeem {
+        redeem_tokens_in: Uint128,
+    },
+    RedeemUnderlying {
+        redeem_amount: Uint128,
+    },
+    Borrow {
+        borrow_amount: Uint128,
+        integrator_fee: Option<IntegratorFee>,
+    },
+    RepayBorrow {
+        amount: Uint128,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct ConfigResponse {
+    pub admin: HumanAddr,
     pub name: String,
     pub total_supply: Uint128,
     pub decimals: u8,
@@ -64,7 +727,41 @@ pub struct ConfigResponse {
     pub intital_exchange_rate: Uint128,
     pub reserve_factor: Uint128,
     pub borrow_index: Uint128,
-    pub denom: String
+    pub underlying: AssetInfo,
+    pub approve_enabled: bool,
+    pub large_redeem_threshold: Option<Uint128>,
+    pub redeem_announce_delay: u64,
+    pub term_bonus_rates: TermBonusRates,
+    pub early_exit_penalty: Uint128,
+    pub max_accrual_block_delta: Option<u64>,
+    pub utilization_window: Option<u64>,
+    pub dust_borrow_threshold: Option<Uint128>,
+    pub collateral_factor: Uint128,
+    pub close_factor: Uint128,
+    pub liquidation_incentive: Uint128,
+    pub protocol_seize_share: Uint128,
+    pub oracle: Option<OracleMsg>,
+    pub comptroller: Option<HumanAddr>,
+    pub treasury: HumanAddr,
+    pub guardian: Option<HumanAddr>,
+    pub flash_loan_fee: Option<Uint128>,
+    pub balance_hook: Option<HumanAddr>,
+    pub cash_strategy: Option<CashStrategyMsg>,
+    pub insurance: Option<InsuranceConfig>,
+    pub blocks_per_year: Option<u64>,
+    pub buyback: Option<BuybackMsg>,
+    pub transfer_policy: Option<TransferPolicyMsg>,
+    pub borrow_cooldown: Option<u64>,
+    pub min_borrow_health_factor: Option<Decimal256>,
+    pub backstop: Option<BackstopConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct CashTiersResponse {
+    pub cash: Uint128,
+    pub hot_cash: Uint128,
+    pub warm_cash: Uint128,
+    pub cash_strategy: Option<CashStrategyMsg>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
@@ -75,4 +772,683 @@ pub struct BalanceResponse {
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct AllowanceResponse {
     pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct LeaderboardEntryResponse {
+    pub address: HumanAddr,
+    pub amount: Uint128,
+    pub share: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct LeaderboardResponse {
+    pub total: Uint128,
+    pub entries: Vec<LeaderboardEntryResponse>,
+    pub page: PageResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SmoothedExchangeRateResponse {
+    pub smoothed_exchange_rate: Uint128,
+    pub last_exchange_rate: Uint128,
+    pub block_number: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct FeaturesResponse {
+    pub cw20: bool,
+    pub flash_loans: bool,
+    pub rewards: bool,
+    pub stable_borrows: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct CapabilitiesResponse {
+    pub contract: String,
+    pub version: String,
+    pub features: FeaturesResponse,
+    pub comptroller: Option<HumanAddr>,
+    pub oracle: Option<HumanAddr>,
+    pub distributor: Option<HumanAddr>,
+}
+
+/// Payable messages of a CW20 underlying market, sent as the `msg` of the token's Send
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    Mint {},
+    MintTo { recipient: HumanAddr },
+    RepayBorrow {},
+    Liquidate { borrower: HumanAddr, repay_amount: Uint128, collateral_market: Option<HumanAddr> },
+    AddReserves {},
+    BuyOtc { id: u64 },
+}
+
+/// Callback executed on the recipient of a flash loan, which must send `amount + fee`
+/// of the underlying back to the market with a plain transfer before it returns
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashLoanReceiverMsg {
+    FlashLoanCallback {
+        initiator: HumanAddr,
+        amount: Uint128,
+        fee: Uint128,
+        msg: Option<Binary>,
+    },
+}
+
+impl FlashLoanReceiverMsg {
+    pub fn into_cosmos_msg(self, recipient: HumanAddr) -> StdResult<CosmosMsg> {
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: recipient,
+            msg: to_binary(&self)?,
+            send: vec![],
+        }))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SigningPayloadResponse {
+    /// Contract the execute message is sent to, the underlying token when paying in CW20
+    pub contract: HumanAddr,
+    /// Execute message exactly as it must be included in the transaction
+    pub msg: Binary,
+    /// The same message as a JSON string for display on the signing device
+    pub json: String,
+    /// Funds that must be attached to the execute message
+    pub funds: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PendingRedeemResponse {
+    pub amount: Uint128,
+    pub executable_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MetricResponse {
+    pub name: String,
+    pub kind: MetricKind,
+    pub value: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MetricsResponse {
+    pub metrics: Vec<MetricResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct InterestSinceInceptionResponse {
+    pub borrower_interest: Uint128,
+    pub supplier_interest: Uint128,
+    pub reserve_interest: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ReserveFactorPreviewResponse {
+    pub horizon_blocks: u64,
+    pub borrow_rate: Uint128,
+    pub projected_interest: Uint128,
+    pub current_reserve_factor: Uint128,
+    pub current_supply_rate: Uint128,
+    pub current_reserve_growth: Uint128,
+    pub proposed_reserve_factor: Uint128,
+    pub proposed_supply_rate: Uint128,
+    pub proposed_reserve_growth: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BorrowIndexResponse {
+    pub borrow_index: Uint128,
+    pub block_number: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AccrualStateResponse {
+    pub last_accrual_block: u64,
+    /// Blocks an accrual at the requested height would cover, after clamping
+    pub pending_blocks: u64,
+    pub borrow_rate: Uint128,
+    pub pending_interest: Uint128,
+    pub pending_reserves: Uint128,
+    /// Values right after an accrual at the requested height
+    pub borrow_index: Uint128,
+    pub total_borrows: Uint128,
+    pub total_reserves: Uint128,
+    pub exchange_rate: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct TrancheResponse {
+    pub id: u64,
+    pub owner: HumanAddr,
+    pub amount: Uint128,
+    pub term: DepositTerm,
+    pub start_block: u64,
+    pub maturity: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct TranchesResponse {
+    pub tranches: Vec<TrancheResponse>,
+    pub page: PageResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct OtcOfferResponse {
+    pub id: u64,
+    pub seller: HumanAddr,
+    pub amount: Uint128,
+    pub price: Uint128,
+    pub expires: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct OtcOffersResponse {
+    pub offers: Vec<OtcOfferResponse>,
+    pub page: PageResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SelfRepayResponse {
+    pub exchange_rate: Uint128,
+    pub last_settled: u64,
+    pub accrued_yield: Uint128,
+    pub next_settlement: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct YieldRedirectResponse {
+    pub beneficiary: HumanAddr,
+    pub exchange_rate: Uint128,
+    pub last_settled: u64,
+    pub accrued_yield: Uint128,
+    pub next_settlement: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IntentAction {
+    Mint,
+    Redeem,
+    Borrow,
+    RepayBorrow,
+    Transfer,
+    Liquidate,
+}
+
+impl IntentAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntentAction::Mint => "mint",
+            IntentAction::Redeem => "redeem",
+            IntentAction::Borrow => "borrow",
+            IntentAction::RepayBorrow => "repay_borrow",
+            IntentAction::Transfer => "transfer",
+            IntentAction::Liquidate => "liquidate",
+        }
+    }
+
+    /// Market-local pause the action is subject to, None if only the global pause stops it
+    pub fn pause_action(&self) -> Option<PauseAction> {
+        match self {
+            IntentAction::Mint => Some(PauseAction::Mint),
+            IntentAction::Borrow => Some(PauseAction::Borrow),
+            IntentAction::Transfer => Some(PauseAction::Transfer),
+            IntentAction::Liquidate => Some(PauseAction::Liquidation),
+            IntentAction::Redeem | IntentAction::RepayBorrow => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RateUnit {
+    Annual,
+    PerBlock,
+    PerSecond,
+}
+
+/// Rates are plain fractions, e.g. 0.05 for 5%
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct RateConversionResponse {
+    pub blocks_per_year: u64,
+    pub annual: Decimal256,
+    pub per_block: Decimal256,
+    pub per_second: Decimal256,
+    /// Per block rate as interest rate models take it, scaled by 10^8 and truncated
+    pub per_block_scaled: Uint128,
+    /// Annual rate `per_block_scaled` actually charges
+    pub effective_annual: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AccountSnapshotsResponse {
+    pub snapshots: Vec<AccountSnapshotEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AccountSnapshotEntry {
+    pub address: HumanAddr,
+    pub snapshot: AccountSnapshotResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct InsurancePoolResponse {
+    /// Part of total_reserves set aside for refunds
+    pub balance: Uint128,
+    pub checkpoint_price: Option<Decimal256>,
+    pub checkpoint_block: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BackstopFlagResponse {
+    pub flagged_at: u64,
+    /// First block the backstop may liquidate the account at, None while backstop liquidations are disabled
+    pub eligible_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct InterestDeferralResponse {
+    pub discount: Uint128,
+    pub expires: u64,
+    /// Borrow index the deferral was last settled at
+    pub index: Uint128,
+    /// Rebate owed since then
+    pub pending_rebate: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct InsuredPositionResponse {
+    /// Borrow index the premium was last settled at
+    pub premium_index: Uint128,
+    /// Premium owed since then
+    pub pending_premium: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct IntegratorResponse {
+    pub max_fee_bps: u16,
+    pub total_fees: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BuybackEpochResponse {
+    pub start: u64,
+    /// First block of the next epoch, which starts with nothing spent
+    pub end: u64,
+    pub spent: Uint128,
+    /// Left to spend until `end`, zero while buybacks are disabled
+    pub remaining: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SettlementResponse {
+    pub block_number: u64,
+    /// Index outstanding borrows are frozen at
+    pub borrow_index: Uint128,
+    /// qToken supply claims are shared among
+    pub total_supply: Uint128,
+    /// Underlying claimable per qToken since settlement
+    pub claim_index: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SettlementClaimResponse {
+    /// Underlying the account may claim now
+    pub claimable: Uint128,
+    pub claimed: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct HealthAlertsResponse {
+    pub thresholds: Vec<Uint128>,
+    /// Current health factor in this market, None without debt
+    pub health_factor: Option<Uint128>,
+    /// Health factor crossings are measured from
+    pub last_health_factor: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct UtilizationAlertsResponse {
+    pub thresholds: Vec<Uint128>,
+    /// Current utilization, scaled by 10^8
+    pub utilization: Uint128,
+    /// Utilization crossings are measured from, None without thresholds
+    pub last_utilization: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SimulateRedeemResponse {
+    pub success: bool,
+    /// Reason the redemption would fail, None on success
+    pub error: Option<String>,
+    pub underlying_out: Uint128,
+    pub utilization_rate: Uint128,
+    pub supply_rate: Uint128,
+    /// Block from which an announced large redemption becomes executable
+    pub executable_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SpenderAllowanceResponse {
+    pub owner: HumanAddr,
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SpenderAllowancesResponse {
+    pub allowances: Vec<SpenderAllowanceResponse>,
+    pub page: PageResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct StateDigestResponse {
+    pub digest: Binary,
+    pub sequence: u64,
+    pub block_number: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AccountDigestResponse {
+    pub digest: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BorrowRateResponse {
+    pub borrow_rate: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SupplyRateResponse {
+    pub supply_rate: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct LiquidationResponse {
+    pub id: u64,
+    pub liquidator: HumanAddr,
+    pub borrower: HumanAddr,
+    pub repay_amount: Uint128,
+    pub seize_tokens: Uint128,
+    pub protocol_seize_tokens: Uint128,
+    pub exchange_rate: Uint128,
+    pub block_number: u64,
+    pub collateral_market: Option<HumanAddr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct LiquidationsResponse {
+    pub liquidations: Vec<LiquidationResponse>,
+    pub page: PageResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AuditRecordResponse {
+    pub id: u64,
+    pub actor: HumanAddr,
+    pub role: String,
+    pub parameter: String,
+    /// JSON encoded
+    pub old_value: String,
+    pub new_value: String,
+    /// Block the change took effect at
+    pub block_number: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AuditLogResponse {
+    pub records: Vec<AuditRecordResponse>,
+    pub page: PageResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MigrationResponse {
+    pub id: u64,
+    pub from_version: String,
+    pub to_version: String,
+    pub block_number: u64,
+    pub upgraded: Vec<String>,
+    pub changes: Vec<ConfigChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MigrationHistoryResponse {
+    pub migrations: Vec<MigrationResponse>,
+    pub page: PageResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ConfigDiffResponse {
+    pub from_version: String,
+    pub to_version: String,
+    /// Migrations the diff spans
+    pub migrations: Vec<u64>,
+    /// Net changes, fields changed and later restored are left out
+    pub changes: Vec<ConfigChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PreviewLiquidationResponse {
+    pub success: bool,
+    /// Reason the liquidation would fail, None on success
+    pub error: Option<String>,
+    pub account_borrow: Uint128,
+    pub borrow_limit: Uint128,
+    pub max_repay: Uint128,
+    pub collateral_tokens: Uint128,
+    pub seize_tokens: Uint128,
+    pub liquidator_seize_tokens: Uint128,
+    pub protocol_seize_tokens: Uint128,
+    /// Underlying value of the seized qTokens
+    pub seize_value: Uint128,
+    /// Part of the seize value above the repay amount
+    pub incentive_value: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AccountLiquidityResponse {
+    /// Quote denom the values below are expressed in
+    pub quote: String,
+    pub price: Decimal256,
+    pub liquidity: Uint256,
+    pub shortfall: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct GlobalPauseResponse {
+    pub paused: bool,
+    pub epoch: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BorrowerResponse {
+    pub address: HumanAddr,
+    /// Debt at the last accrual
+    pub borrow_balance: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AllBorrowersResponse {
+    pub borrowers: Vec<BorrowerResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ReservesResponse {
+    pub total_reserves: Uint128,
+    pub reserve_factor: Uint128,
+    pub treasury: HumanAddr,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct RolesResponse {
+    pub owner: HumanAddr,
+    pub pending_owner: Option<HumanAddr>,
+    pub guardian: Option<HumanAddr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct RoleMembersResponse {
+    pub members: Vec<HumanAddr>,
+    pub page: PageResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AccountRolesResponse {
+    pub roles: Vec<Role>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct InterestRateModelBoundsResponse {
+    pub bounds: InterestRateModelBounds,
+    pub pending: Option<PendingInterestRateModelBounds>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PauseStatusResponse {
+    pub mint: bool,
+    pub borrow: bool,
+    pub transfer: bool,
+    pub liquidation: bool,
+    /// Pause pushed by the comptroller, blocks everything but repayments and bookkeeping
+    pub global: bool,
+}
+
+/// Sections of a genesis export, in export order
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GenesisSection {
+    Market,
+    Balances,
+    Borrows,
+    Allowances,
+}
+
+/// Position of the next chunk, `start_after` is the last raw storage key exported
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GenesisCursor {
+    pub section: GenesisSection,
+    pub start_after: Option<Binary>,
+}
+
+/// Market state as stored, addresses stay canonical so chunks import unchanged
+/// under another bech32 prefix
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GenesisChunk {
+    Market { config: Box<Config>, state: State },
+    Balances { balances: Vec<GenesisBalance> },
+    Borrows { borrows: Vec<GenesisBorrow> },
+    Allowances { allowances: Vec<GenesisAllowance> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GenesisBalance {
+    pub address: Binary,
+    pub balance: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GenesisBorrow {
+    pub address: Binary,
+    pub snapshot: BorrowSnapshot,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GenesisAllowance {
+    pub owner: Binary,
+    pub spender: Binary,
+    pub amount: Uint128,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ExportGenesisResponse {
+    pub chunk: GenesisChunk,
+    /// sha256(prior digest || chunk)
+    pub digest: Binary,
+    /// None after the last chunk
+    pub next: Option<GenesisCursor>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct GenesisImportResponse {
+    pub digest: Binary,
+    pub chunks: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StorageCompactionResponse {
+    pub section: CompactionSection,
+    pub start_after: Option<Binary>,
+    pub rewritten: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquiditySnapshotResponse {
+    pub id: u64,
+    pub block: u64,
+    pub borrow_index: Uint128,
+    /// Section still being captured, Done once the snapshot is final
+    pub section: SnapshotSection,
+    pub entries: u64,
+    /// sha256 chain over (section, address, amount) of every entry, final once the snapshot is done
+    pub digest: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SnapshotEntryResponse {
+    pub address: HumanAddr,
+    /// qToken balance, or debt at the snapshot's borrow index
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquiditySnapshotEntriesResponse {
+    pub entries: Vec<SnapshotEntryResponse>,
+    pub page: PageResponse,
+}
+
+/// A stored integer next to its decimal reading
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ScaledValue {
+    pub raw: Uint128,
+    /// `raw` divided by 10^decimals, without trailing zeros
+    pub humanized: String,
+    pub decimals: u8,
+}
+
+/// Rates and ratios have 8 decimals, amounts the market's token decimals
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct HumanizedMarketResponse {
+    pub exchange_rate: ScaledValue,
+    pub borrow_rate: ScaledValue,
+    pub supply_rate: ScaledValue,
+    pub utilization_rate: ScaledValue,
+    pub collateral_factor: ScaledValue,
+    pub reserve_factor: ScaledValue,
+    pub total_supply: ScaledValue,
+    pub cash: ScaledValue,
+    pub total_borrows: ScaledValue,
+    pub total_reserves: ScaledValue,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct HumanizedAccountResponse {
+    /// qTokens
+    pub balance: ScaledValue,
+    /// Underlying the qTokens are worth at the stored exchange rate
+    pub underlying_balance: ScaledValue,
+    pub borrow_balance: ScaledValue,
+    pub borrow_limit: ScaledValue,
 }
@@ -1,19 +1,154 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, StdError, StdResult, Storage, Uint128, ReadonlyStorage};
-use cosmwasm_storage::{singleton, Bucket, ReadonlyBucket, ReadonlySingleton, Singleton, ReadonlyPrefixedStorage, PrefixedStorage};
+use cosmwasm_bignumber::Decimal256;
+use cosmwasm_std::{from_slice, Binary, CanonicalAddr, Decimal, Order, StdError, StdResult, Storage, Uint128, ReadonlyStorage};
+use cosmwasm_storage::{singleton, Bucket, PrefixedStorage, ReadonlyBucket, ReadonlySingleton, Singleton, ReadonlyPrefixedStorage};
+use std::cmp::Reverse;
 use std::convert::TryInto;
 
+use cw20::Expiration;
+use quasar_interfaces::asset::AssetInfoRaw;
+use quasar_interfaces::oracle::OracleKind;
+use quasar_interfaces::pagination::calc_range_start;
+
 pub static CONFIG_PREFIX: &[u8] = b"config";
+/// Same key as cw2 so tooling reading cw2 contract info works unchanged
+pub static CONTRACT_INFO_KEY: &[u8] = b"contract_info";
 pub static BALANCE_PREFIX: &[u8] = b"balances";
 pub static ALLOWANCE_PREFIX: &[u8] = b"allowance";
+pub static ALLOWANCE_EXPIRATION_PREFIX: &[u8] = b"allowance_expiration";
+pub static SPENDER_ALLOWANCE_PREFIX: &[u8] = b"spender_allowance";
 pub static STATE_PREFIX: &[u8] = b"state";
 pub static BORROW_PREFIX: &[u8] = b"borrow";
+pub static TOP_SUPPLIERS_KEY: &[u8] = b"top_suppliers";
+pub static TOP_BORROWERS_KEY: &[u8] = b"top_borrowers";
+pub static EXCHANGE_RATE_EMA_KEY: &[u8] = b"exchange_rate_ema";
+pub static UTILIZATION_AVERAGE_KEY: &[u8] = b"utilization_average";
+pub static ACCRUAL_REMAINDER_KEY: &[u8] = b"accrual_remainder";
+pub static STATE_DIGEST_KEY: &[u8] = b"state_digest";
+pub static INTEREST_RATE_MODEL_KEY: &[u8] = b"interest_rate_model";
+pub static SHADOW_INTEREST_RATE_MODEL_KEY: &[u8] = b"shadow_interest_rate_model";
+pub static INTEREST_RATE_MODEL_BOUNDS_KEY: &[u8] = b"interest_rate_model_bounds";
+pub static PENDING_INTEREST_RATE_MODEL_BOUNDS_KEY: &[u8] = b"pending_interest_rate_model_bounds";
+pub static RESERVE_FACTOR_RAMP_KEY: &[u8] = b"reserve_factor_ramp";
+pub static ROLE_PREFIX: &[u8] = b"role";
+pub static GLOBAL_PAUSE_KEY: &[u8] = b"global_pause";
+pub static PENDING_REDEEM_PREFIX: &[u8] = b"pending_redeem";
+pub static METRICS_KEY: &[u8] = b"metrics";
+pub static OTC_OFFER_COUNT_KEY: &[u8] = b"otc_offer_count";
+pub static OTC_OFFER_PREFIX: &[u8] = b"otc_offer";
+pub static TRANCHE_COUNT_KEY: &[u8] = b"tranche_count";
+pub static TRANCHE_PREFIX: &[u8] = b"tranche";
+pub static OWNER_TRANCHE_PREFIX: &[u8] = b"owner_tranche";
+pub static SELF_REPAY_PREFIX: &[u8] = b"self_repay";
+pub static YIELD_REDIRECT_PREFIX: &[u8] = b"yield_redirect";
+pub static LIQUIDATION_COUNT_KEY: &[u8] = b"liquidation_count";
+pub static LIQUIDATION_PREFIX: &[u8] = b"liquidation";
+pub static BORROWER_LIQUIDATION_PREFIX: &[u8] = b"borrower_liquidation";
+pub static AUDIT_COUNT_KEY: &[u8] = b"audit_count";
+pub static AUDIT_PREFIX: &[u8] = b"audit";
+pub static MIGRATION_COUNT_KEY: &[u8] = b"migration_count";
+pub static MIGRATION_PREFIX: &[u8] = b"migration";
+pub static SHORTFALL_FLAG_PREFIX: &[u8] = b"shortfall_flag";
+pub static PAUSE_FLAGS_KEY: &[u8] = b"pause_flags";
+pub static PENDING_ADMIN_KEY: &[u8] = b"pending_admin";
+pub static FLASH_LOAN_KEY: &[u8] = b"flash_loan";
+pub static WARM_CASH_KEY: &[u8] = b"warm_cash";
+pub static HEALTH_ALERTS_PREFIX: &[u8] = b"health_alerts";
+pub static UTILIZATION_ALERTS_KEY: &[u8] = b"utilization_alerts";
+pub static SWAP_PAIR_PREFIX: &[u8] = b"swap_pair";
+pub static SWAP_REPAY_KEY: &[u8] = b"swap_repay";
+pub static GENESIS_IMPORT_KEY: &[u8] = b"genesis_import";
+pub static COMPACTION_KEY: &[u8] = b"compaction";
+pub static INSURANCE_POOL_KEY: &[u8] = b"insurance_pool";
+pub static INSURANCE_CHECKPOINT_KEY: &[u8] = b"insurance_checkpoint";
+pub static INSURED_PREFIX: &[u8] = b"insured";
+pub static SETTLEMENT_KEY: &[u8] = b"settlement";
+pub static SETTLEMENT_CLAIMED_PREFIX: &[u8] = b"settlement_claimed";
+pub static BUYBACK_EPOCH_KEY: &[u8] = b"buyback_epoch";
+pub static PENDING_BUYBACK_KEY: &[u8] = b"pending_buyback";
+pub static INTEGRATOR_PREFIX: &[u8] = b"integrator";
+pub static LAST_BORROW_PREFIX: &[u8] = b"last_borrow";
+pub static LIQUIDITY_SNAPSHOT_KEY: &[u8] = b"liquidity_snapshot";
+pub static INTEREST_DEFERRAL_PREFIX: &[u8] = b"interest_deferral";
+pub static LIQUIDITY_SNAPSHOT_COUNT_KEY: &[u8] = b"liquidity_snapshot_count";
+pub static LIQUIDITY_SNAPSHOT_PREFIX: &[u8] = b"liquidity_snapshots";
+pub static LIQUIDITY_SNAPSHOT_ENTRY_PREFIX: &[u8] = b"liquidity_snapshot_entry";
+pub static LIQUIDITY_SNAPSHOT_PRESERVED_PREFIX: &[u8] = b"liquidity_snapshot_preserved";
+pub static BACKSTOP_FLAG_PREFIX: &[u8] = b"backstop_flag";
+
+/// Number of accounts tracked on each leaderboard
+pub const LEADERBOARD_SIZE: usize = 20;
+
+/// Blocks per day assumed when converting deposit terms into blocks, ~6s block time
+pub const BLOCKS_PER_DAY: u64 = 14_400;
+
+/// Blocks per year assumed when converting rates unless `blocks_per_year` is configured
+pub const DEFAULT_BLOCKS_PER_YEAR: u64 = BLOCKS_PER_DAY * 365;
+/// Seconds in a 365 day year
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+
+/// Minimum blocks between two self-repay settlements of the same account
+pub const SELF_REPAY_EPOCH: u64 = BLOCKS_PER_DAY;
+
+/// Entries a storage compaction batch scans unless the caller asks for fewer
+pub const DEFAULT_COMPACTION_BATCH: u32 = 100;
+pub const MAX_COMPACTION_BATCH: u32 = 500;
+
+/// Accounts a liquidity snapshot batch captures unless the caller asks for fewer
+pub const DEFAULT_LIQUIDITY_SNAPSHOT_BATCH: u32 = 100;
+pub const MAX_LIQUIDITY_SNAPSHOT_BATCH: u32 = 500;
+
+/// Longest an OTC offer may stay listed, thirty days
+pub const MAX_OTC_DURATION: u64 = 30 * BLOCKS_PER_DAY;
+
+/// Longest an interest deferral may be granted for, ninety days
+pub const MAX_INTEREST_DEFERRAL_DURATION: u64 = 90 * BLOCKS_PER_DAY;
+
+/// Minimum blocks between two yield redirect settlements of the same account
+pub const YIELD_REDIRECT_EPOCH: u64 = BLOCKS_PER_DAY;
+
+/// Blocks a proposed change of the rate model bounds waits before it can be applied, seven days
+pub const INTEREST_RATE_MODEL_BOUNDS_TIMELOCK: u64 = 7 * BLOCKS_PER_DAY;
+
+/// Longest a parameter change may be ramped over, thirty days
+pub const MAX_PARAMETER_RAMP_BLOCKS: u64 = 30 * BLOCKS_PER_DAY;
+
+/// Number of blocks the smoothed exchange rate takes to fully catch up with the spot rate
+pub const EXCHANGE_RATE_EMA_WINDOW: u64 = 100;
+
+/// Largest reserve factor the admin may set, scaled by 10^8
+pub const MAX_RESERVE_FACTOR: u128 = 100_000_000;
+/// At most half of seized collateral may go to reserves, scaled by 10^8
+pub const MAX_PROTOCOL_SEIZE_SHARE: u128 = 50_000_000;
+/// Largest flash loan fee the admin may set, scaled by 10^8
+pub const MAX_FLASH_LOAN_FEE: u128 = 1_000_000;
+/// Largest share of interest charged as insurance premium, scaled by 10^8
+pub const MAX_INSURANCE_PREMIUM_RATE: u128 = 10_000_000;
+/// Largest share of total borrows the insurance pool may be topped up to, 20%
+pub const MAX_INSURANCE_TARGET_COVERAGE: u128 = 20_000_000;
+/// Most health factor thresholds a single account may watch
+pub const MAX_HEALTH_ALERTS: usize = 5;
+/// Most utilization thresholds a market may alert on
+pub const MAX_UTILIZATION_ALERTS: usize = 5;
+/// Highest minimum borrow health factor the admin may set
+pub const MAX_MIN_BORROW_HEALTH_FACTOR: u64 = 2;
+/// Longest borrow cooldown the admin may set, one day
+pub const MAX_BORROW_COOLDOWN: u64 = BLOCKS_PER_DAY;
+/// Accounts one AccountSnapshots query may read
+pub const MAX_ACCOUNT_SNAPSHOTS: usize = 50;
+/// Largest integrator fee any integrator may be registered for, 1% of a borrow
+pub const MAX_INTEGRATOR_FEE_BPS: u16 = 100;
+/// Basis points in a whole
+pub const BPS_DENOMINATOR: u128 = 10_000;
 
 /// Config struct
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
+    pub admin: CanonicalAddr,
+    /// The market itself, the only minter of its qTokens
+    pub contract_addr: CanonicalAddr,
     pub name: String,
     pub total_supply: Uint128,
     pub decimals: u8,
@@ -22,7 +157,544 @@ pub struct Config {
     pub reserve_factor: Uint128,
     pub borrow_index: Uint128,
     pub max_borrow_rate: Uint128,
-    pub denom: String,
+    /// Native denom or CW20 token the market lends out
+    pub underlying: AssetInfoRaw,
+    /// Whether the deprecated Approve handler may still overwrite allowances
+    pub approve_enabled: bool,
+    /// Redemptions paying out more underlying than this must be announced first
+    pub large_redeem_threshold: Option<Uint128>,
+    /// Blocks between announcing a large redemption and executing it
+    pub redeem_announce_delay: u64,
+    /// Bonus paid from reserves on matured term deposits, per term
+    pub term_bonus_rates: TermBonusRates,
+    /// Share of locked qTokens burned when a term deposit is withdrawn early
+    pub early_exit_penalty: Uint128,
+    /// Largest block gap a single accrual charges interest for, None accrues any gap in full
+    pub max_accrual_block_delta: Option<u64>,
+    /// Blocks the utilization fed to the rate model is averaged over, None uses spot utilization
+    pub utilization_window: Option<u64>,
+    /// Debt at or below this amount stops accruing interest and can be written off by anyone
+    pub dust_borrow_threshold: Option<Uint128>,
+    /// Share of a supplier's underlying value that counts towards its borrow limit
+    pub collateral_factor: Uint128,
+    /// Largest share of a borrow one liquidation may repay
+    pub close_factor: Uint128,
+    /// Extra collateral seized on top of the repaid value, e.g. 0.08 * 10^8
+    pub liquidation_incentive: Uint128,
+    /// Share of seized qTokens burned into reserves instead of going to the liquidator
+    pub protocol_seize_share: Uint128,
+    /// Price feed used to value accounts in the quote denom
+    pub oracle: Option<OracleConfig>,
+    /// Registry checking positions across markets, None for a standalone market
+    pub comptroller: Option<CanonicalAddr>,
+    /// Receives reserves withdrawn with ReduceReserves
+    pub treasury: CanonicalAddr,
+    /// May pause single actions during an incident, only the admin can unpause
+    pub guardian: Option<CanonicalAddr>,
+    /// Fee on flash loaned cash, split with suppliers by the reserve factor, None disables flash loans
+    pub flash_loan_fee: Option<Uint128>,
+    /// Notified with BalanceHookMsg after every qToken balance change, e.g. a rewards module
+    pub balance_hook: Option<CanonicalAddr>,
+    /// Fast-exit strategy part of cash is deployed to, None keeps all cash in the market
+    pub cash_strategy: Option<CashStrategy>,
+    /// Liquidation penalty insurance borrowers may opt into, None disables it
+    pub insurance: Option<InsuranceConfig>,
+    /// Used to convert rates between annual and per block, None assumes DEFAULT_BLOCKS_PER_YEAR
+    pub blocks_per_year: Option<u64>,
+    /// Buying back and burning the governance token with reserves, None disables it
+    pub buyback: Option<BuybackConfig>,
+    /// Contract that must allow qTokens reaching an account, None if never enforced
+    pub transfer_policy: Option<TransferPolicy>,
+    /// Blocks after a borrow before the borrower may redeem or move qTokens, None has no cooldown
+    pub borrow_cooldown: Option<u64>,
+    /// Borrow limit over debt a borrow must leave a standalone market's borrower at, e.g. 1.1
+    /// None only requires the borrow to stay within the collateral limit
+    pub min_borrow_health_factor: Option<Decimal256>,
+    /// Reserve-funded liquidation of accounts no liquidator took on, None disables it
+    pub backstop: Option<BackstopConfig>,
+}
+
+impl Config {
+    pub fn blocks_per_year(&self) -> u64 {
+        self.blocks_per_year.unwrap_or(DEFAULT_BLOCKS_PER_YEAR)
+    }
+}
+
+/// Once disabled the policy cannot be enforced again, so the market stays permissionless
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferPolicy {
+    Enforced { contract: CanonicalAddr },
+    Disabled,
+}
+
+/// Reserves are swapped for the CW20 `token` through `pair` and the tokens bought burned
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuybackConfig {
+    pub pair: CanonicalAddr,
+    pub token: CanonicalAddr,
+    /// Most underlying spent per epoch
+    pub max_per_epoch: Uint128,
+    pub epoch_blocks: u64,
+    /// Largest shortfall of the swap against the pair's quote
+    pub max_spread: Decimal,
+}
+
+/// Insured borrowers pay `premium_rate` of their interest into the insurance pool,
+/// which refunds `refund_share` of their liquidation penalty when the oracle price
+/// moved by `volatility_threshold` or more within `window` blocks, all scaled by 10^8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InsuranceConfig {
+    pub premium_rate: Uint128,
+    pub refund_share: Uint128,
+    pub volatility_threshold: Uint128,
+    pub window: u64,
+    /// Tops the pool up from free reserves at each accrual, None leaves it to premiums
+    pub top_up: Option<InsuranceTopUp>,
+}
+
+/// Accounts whose health factor stays below `max_health_factor`, scaled by 10^8, for `delay`
+/// blocks after being flagged may have their debt repaid out of reserves, with the collateral
+/// going to the treasury
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BackstopConfig {
+    pub delay: u64,
+    pub max_health_factor: Uint128,
+}
+
+/// Moves up to `max_top_up_per_block` of reserves into the insurance pool per
+/// accrued block while the pool covers less than `target_coverage` of total borrows,
+/// scaled by 10^8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InsuranceTopUp {
+    pub target_coverage: Uint128,
+    pub max_top_up_per_block: Uint128,
+}
+
+/// Cash is split into hot cash held by the market and warm cash deployed to the
+/// strategy, Rebalance moves it towards `target_hot_ratio` of cash being hot
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CashStrategy {
+    pub contract: CanonicalAddr,
+    /// Scaled by 10^8
+    pub target_hot_ratio: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OracleConfig {
+    pub contract: CanonicalAddr,
+    pub kind: OracleKind,
+    /// Symbol the oracle prices the underlying under, e.g. "LUNA"
+    pub base: String,
+    /// Denom liquidity and shortfall are reported in, e.g. "USD"
+    pub quote: String,
+}
+
+/// Borrow rate curve of the market, all values scaled by 10^8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InterestRateModel {
+    JumpRate {
+        base_rate_per_block: Uint128,
+        multiplier_per_block: Uint128,
+        jump_multiplier_per_block: Uint128,
+        kink: Uint128,
+        /// Lowest rate charged at any utilization, so idle markets still earn reserves
+        min_borrow_rate_per_block: Option<Uint128>,
+    },
+    Linear {
+        base_rate_per_block: Uint128,
+        multiplier_per_block: Uint128,
+        min_borrow_rate_per_block: Option<Uint128>,
+    },
+}
+
+impl InterestRateModel {
+    pub fn min_borrow_rate_per_block(&self) -> u128 {
+        let floor = match self {
+            InterestRateModel::JumpRate { min_borrow_rate_per_block, .. } => min_borrow_rate_per_block,
+            InterestRateModel::Linear { min_borrow_rate_per_block, .. } => min_borrow_rate_per_block,
+        };
+        floor.map(|floor| floor.u128()).unwrap_or(0)
+    }
+}
+
+impl Default for InterestRateModel {
+    /// The jump rate curve markets used before models became configurable
+    fn default() -> Self {
+        InterestRateModel::JumpRate {
+            base_rate_per_block: Uint128::zero(),
+            multiplier_per_block: Uint128::from(23u128), // 0.000000237823 * 10^8
+            jump_multiplier_per_block: Uint128::from(51u128), // 0.000000518455 * 10^8
+            kink: Uint128::from(80_000_000u128), // 0.8 * 10^8
+            min_borrow_rate_per_block: None,
+        }
+    }
+}
+
+/// Inclusive range a rate model parameter must stay within, scaled by 10^8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ParameterBound {
+    pub min: Uint128,
+    pub max: Uint128,
+}
+
+impl ParameterBound {
+    pub fn contains(&self, value: Uint128) -> bool {
+        self.min <= value && value <= self.max
+    }
+}
+
+/// Governance approved range of each rate model parameter, None leaves a parameter unbounded
+/// Parameters a model does not have are not checked, a missing floor counts as zero
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct InterestRateModelBounds {
+    pub base_rate_per_block: Option<ParameterBound>,
+    pub multiplier_per_block: Option<ParameterBound>,
+    pub jump_multiplier_per_block: Option<ParameterBound>,
+    pub kink: Option<ParameterBound>,
+    pub min_borrow_rate_per_block: Option<ParameterBound>,
+}
+
+/// Bounds change waiting out INTEREST_RATE_MODEL_BOUNDS_TIMELOCK
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingInterestRateModelBounds {
+    pub bounds: InterestRateModelBounds,
+    /// First block the bounds can be applied at
+    pub eta: u64,
+}
+
+/// Parameter moving linearly from `from` at block `start` to `to` at block `end`, scaled by 10^8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ParameterRamp {
+    pub from: Uint128,
+    pub to: Uint128,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ParameterRamp {
+    /// Value reached at `height`, truncated towards `from`
+    pub fn value_at(&self, height: u64) -> Uint128 {
+        if height >= self.end {
+            return self.to;
+        }
+        let elapsed = u128::from(height.saturating_sub(self.start));
+        let duration = u128::from(self.end - self.start);
+        let (from, to) = (self.from.u128(), self.to.u128());
+        Uint128::from(if to >= from {
+            from + (to - from) * elapsed / duration
+        } else {
+            from - (from - to) * elapsed / duration
+        })
+    }
+}
+
+/// Bonus on the underlying value of a matured term deposit, scaled by 10^8
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct TermBonusRates {
+    pub days_30: Uint128,
+    pub days_90: Uint128,
+    pub days_180: Uint128,
+}
+
+impl TermBonusRates {
+    pub fn rate(&self, term: DepositTerm) -> Uint128 {
+        match term {
+            DepositTerm::Days30 => self.days_30,
+            DepositTerm::Days90 => self.days_90,
+            DepositTerm::Days180 => self.days_180,
+        }
+    }
+}
+
+/// Lock period of a term deposit
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositTerm {
+    Days30,
+    Days90,
+    Days180,
+}
+
+impl DepositTerm {
+    pub fn blocks(&self) -> u64 {
+        match self {
+            DepositTerm::Days30 => 30 * BLOCKS_PER_DAY,
+            DepositTerm::Days90 => 90 * BLOCKS_PER_DAY,
+            DepositTerm::Days180 => 180 * BLOCKS_PER_DAY,
+        }
+    }
+}
+
+/// Exchange rate checkpoint of an account routing its supply yield to its debt
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SelfRepay {
+    pub exchange_rate: Uint128,
+    pub last_settled: u64,
+    /// Yield booked at balance changes since the last settlement, in the underlying
+    pub accrued_yield: Uint128,
+}
+
+/// Exchange rate checkpoint of an account donating its supply yield to a beneficiary
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct YieldRedirect {
+    pub beneficiary: CanonicalAddr,
+    pub exchange_rate: Uint128,
+    pub last_settled: u64,
+    /// Yield booked at balance changes since the last settlement, in the underlying
+    pub accrued_yield: Uint128,
+}
+
+/// Executed liquidation, appended to the liquidation log
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationRecord {
+    pub id: u64,
+    pub liquidator: CanonicalAddr,
+    pub borrower: CanonicalAddr,
+    pub repay_amount: Uint128,
+    pub seize_tokens: Uint128,
+    /// Part of seize_tokens burned into reserves
+    pub protocol_seize_tokens: Uint128,
+    /// Exchange rate the seized qTokens were valued at
+    pub exchange_rate: Uint128,
+    pub block_number: u64,
+    /// Market the qTokens were seized in when not this one, the seize amounts are then zero
+    pub collateral_market: Option<CanonicalAddr>,
+}
+
+/// Privileged change, appended to the audit log
+/// Values are the JSON encoding of the parameter before and after the change
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AuditRecord {
+    pub id: u64,
+    pub actor: CanonicalAddr,
+    /// admin, guardian or comptroller
+    pub role: String,
+    pub parameter: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub block_number: u64,
+}
+
+/// Config field a migration changed, JSON encoded as stored, None on the side
+/// whose layout has no such field
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigChange {
+    pub parameter: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+/// Migration applied to the market, appended to the migration history
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrationRecord {
+    pub id: u64,
+    pub from_version: String,
+    pub to_version: String,
+    pub block_number: u64,
+    /// Stored layouts the migration upgraded, e.g. config_v0
+    pub upgraded: Vec<String>,
+    pub changes: Vec<ConfigChange>,
+}
+
+/// Flash loan sent out in the current transaction and not yet checked for repayment
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FlashLoan {
+    pub recipient: CanonicalAddr,
+    pub amount: Uint128,
+    pub fee: Uint128,
+    /// Underlying held by the market before the loan was paid out
+    pub balance_before: Uint128,
+}
+
+/// Market wound down at `block_number`, borrows stay frozen at `borrow_index`
+/// and qToken balances at `total_supply`
+/// `claim_index` is the underlying each qToken may claim so far, growing with repayments
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Settlement {
+    pub block_number: u64,
+    pub borrow_index: Uint128,
+    pub total_supply: Uint128,
+    pub claim_index: Decimal256,
+}
+
+/// Borrow index up to which an insured borrower paid its premium
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InsuredPosition {
+    pub premium_index: Uint128,
+}
+
+/// Share of its borrow interest an account is let off until `expires`, paid back out of reserves
+/// whenever the deferral is settled
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InterestDeferral {
+    /// Scaled by 10^8
+    pub discount: Uint128,
+    pub expires: u64,
+    /// Borrow index and accrual block the deferral was last settled at
+    pub index: Uint128,
+    pub block: u64,
+}
+
+/// Oracle price volatility of liquidations is measured against
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceCheckpoint {
+    pub price: Decimal256,
+    pub block_number: u64,
+}
+
+/// Genesis import in progress, market handlers stay closed until it finishes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GenesisImport {
+    /// Hash chain over the chunks imported so far, empty before the first
+    pub digest: Binary,
+    pub chunks: u64,
+}
+
+/// Storage section a compaction works through, in order
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CompactionSection {
+    Balances,
+    Allowances,
+    Done,
+}
+
+/// Progress re-encoding balance and allowance entries still in the raw 16 byte layout
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Compaction {
+    pub section: CompactionSection,
+    /// Last raw key of the section already scanned
+    pub start_after: Option<Binary>,
+    pub rewritten: u64,
+}
+
+/// Section a liquidity snapshot captures, in order
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotSection {
+    Balances,
+    Borrows,
+    Done,
+}
+
+impl SnapshotSection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SnapshotSection::Balances => "balances",
+            SnapshotSection::Borrows => "borrows",
+            SnapshotSection::Done => "done",
+        }
+    }
+}
+
+/// qToken balances and debts frozen at `block`, captured over as many calls as the market needs
+/// Until the capture reaches an account, a write to it first preserves its value at `block`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquiditySnapshot {
+    pub id: u64,
+    pub block: u64,
+    /// Borrow index debts are valued at
+    pub borrow_index: Uint128,
+    pub section: SnapshotSection,
+    /// Last account key of the section already captured
+    pub start_after: Option<Binary>,
+    /// Accounts with a balance or debt captured so far
+    pub entries: u64,
+    /// Hash chain over the captured entries in export order, empty before the first
+    pub digest: Binary,
+}
+
+impl LiquiditySnapshot {
+    /// Whether `account` in `section` is still to be captured
+    pub fn is_pending(&self, section: SnapshotSection, account: &[u8]) -> bool {
+        match (self.section, section) {
+            (SnapshotSection::Balances, SnapshotSection::Borrows) => true,
+            (current, section) if current == section => match self.start_after.as_ref() {
+                Some(start_after) => account > start_after.as_slice(),
+                None => true,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Repayment waiting for its swap into the underlying to settle
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SwapRepay {
+    pub borrower: CanonicalAddr,
+    pub offer_denom: String,
+    pub offer_amount: Uint128,
+    /// Underlying the pair quoted for the offer
+    pub min_receive: Uint128,
+    /// Underlying held by the market before the swap
+    pub balance_before: Uint128,
+}
+
+/// Underlying spent on buybacks in the epoch starting at `start`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct BuybackEpoch {
+    pub start: u64,
+    pub spent: Uint128,
+}
+
+/// Buyback swap sent out in the current transaction and not yet burned
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingBuyback {
+    pub amount: Uint128,
+    /// Governance tokens the pair quoted, less the max spread
+    pub min_receive: Uint128,
+    /// Governance tokens held by the market before the swap
+    pub balance_before: Uint128,
+}
+
+/// Integrator registered to take a fee from borrows it routes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Integrator {
+    /// Largest fee a borrow may pay the integrator, in basis points
+    pub max_fee_bps: u16,
+    /// Underlying paid to the integrator so far
+    pub total_fees: Uint128,
+}
+
+/// Health factor levels an account is alerted about, scaled by 10^8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HealthAlerts {
+    pub thresholds: Vec<Uint128>,
+    /// Health factor when the account was last touched, None without debt
+    pub last_health_factor: Option<Uint128>,
+}
+
+/// Market utilization levels accruals alert on, scaled by 10^8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UtilizationAlerts {
+    pub thresholds: Vec<Uint128>,
+    /// Utilization at the last accrual
+    pub last_utilization: Uint128,
+}
+
+/// qTokens locked for a fixed term
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Tranche {
+    pub id: u64,
+    pub owner: CanonicalAddr,
+    pub amount: Uint128,
+    pub term: DepositTerm,
+    pub start_block: u64,
+    pub maturity: u64,
+}
+
+/// qTokens the market holds in escrow for sale at a fixed price in the underlying
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OtcOffer {
+    pub id: u64,
+    pub seller: CanonicalAddr,
+    pub amount: Uint128,
+    /// Underlying the buyer pays for the whole amount
+    pub price: Uint128,
+    /// First block the offer can no longer be bought
+    pub expires: u64,
 }
 
 /// State struct
@@ -44,8 +716,205 @@ pub struct BorrowSnapshot {
     pub interest_index: Uint128
 }
 
+/// Config layout of deployments instantiated before contract versions were stored
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigV0 {
+    pub name: String,
+    pub total_supply: Uint128,
+    pub decimals: u8,
+    pub symbol: String,
+    pub initial_exchange_rate: Uint128,
+    pub reserve_factor: Uint128,
+    pub borrow_index: Uint128,
+    pub max_borrow_rate: Uint128,
+    pub denom: String,
+}
+
+/// Config as found in storage, used by migrations to pick the upgrade path
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoredConfig {
+    Current(Box<Config>),
+    V0(ConfigV0),
+}
+
+/// Contract name and version, in the cw2 layout
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractVersion {
+    pub contract: String,
+    pub version: String,
+}
+
+/// Versioned storage envelope for BorrowSnapshot
+/// Layout changes are added as new variants, and entries written before the
+/// envelope existed are read as V1
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionedBorrowSnapshot {
+    V1(BorrowSnapshot),
+}
+
+impl VersionedBorrowSnapshot {
+    /// Upgrade the stored layout to the current BorrowSnapshot
+    pub fn into_latest(self) -> BorrowSnapshot {
+        match self {
+            VersionedBorrowSnapshot::V1(snapshot) => snapshot,
+        }
+    }
+}
+
+impl From<BorrowSnapshot> for VersionedBorrowSnapshot {
+    fn from(snapshot: BorrowSnapshot) -> Self {
+        VersionedBorrowSnapshot::V1(snapshot)
+    }
+}
+
+/// Monotonic counters maintained by handlers for off-chain monitoring
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Metrics {
+    pub mints: u64,
+    pub redeems: u64,
+    pub borrows: u64,
+    pub cumulative_interest: Uint128,
+    /// Part of cumulative_interest taken into reserves, the rest went to suppliers
+    pub cumulative_reserve_interest: Uint128,
+    pub clamped_accruals: u64,
+    pub liquidations: u64
+}
+
+/// Announced large redemption waiting for its delay to pass
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingRedeem {
+    pub amount: Uint128,
+    pub executable_at: u64
+}
+
+/// Smoothed exchange rate used for display only
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExchangeRateEma {
+    pub smoothed_exchange_rate: Uint128,
+    pub last_exchange_rate: Uint128,
+    pub block_number: u64
+}
+
+/// Time-weighted average utilization fed to the rate model
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UtilizationAverage {
+    pub average_utilization: Uint128,
+    pub block_number: u64
+}
+
+/// Fractions below one unit dropped by the last accrual, added back in the next one
+/// so small per-block interest is not lost to truncation
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct AccrualRemainder {
+    pub interest: Decimal256,
+    pub reserves: Decimal256,
+    pub borrow_index: Decimal256,
+}
+
+/// Hash chain over market state, extended after every handled message
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct StateDigest {
+    pub digest: Binary,
+    pub sequence: u64,
+    pub block_number: u64
+}
+
+/// Last protocol-wide pause pushed by the comptroller
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct GlobalPause {
+    pub paused: bool,
+    pub epoch: u64,
+}
+
+/// Actions the admin or guardian can pause one by one
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseAction {
+    Mint,
+    Borrow,
+    /// qToken transfers, sends and burns
+    Transfer,
+    Liquidation,
+}
+
+/// Authority the admin can grant to other accounts, each covering a group of admin entry points
+/// The admin itself holds every role
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Rate model, reserve factor, fees and other risk parameters
+    ParamAdmin,
+    /// May pause actions like the guardian, never unpause
+    PauseGuardian,
+    /// Price oracle of the market
+    OracleAdmin,
+    /// Reserve withdrawals, buybacks and the cash strategy
+    ReserveManager,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::ParamAdmin, Role::PauseGuardian, Role::OracleAdmin, Role::ReserveManager];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::ParamAdmin => "param_admin",
+            Role::PauseGuardian => "pause_guardian",
+            Role::OracleAdmin => "oracle_admin",
+            Role::ReserveManager => "reserve_manager",
+        }
+    }
+}
+
+/// Market-local pauses, independent of the comptroller's global pause
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PauseFlags {
+    pub mint: bool,
+    pub borrow: bool,
+    pub transfer: bool,
+    pub liquidation: bool,
+}
+
+impl PauseAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PauseAction::Mint => "mint",
+            PauseAction::Borrow => "borrow",
+            PauseAction::Transfer => "transfer",
+            PauseAction::Liquidation => "liquidation",
+        }
+    }
+}
+
+impl PauseFlags {
+    pub fn is_paused(&self, action: PauseAction) -> bool {
+        match action {
+            PauseAction::Mint => self.mint,
+            PauseAction::Borrow => self.borrow,
+            PauseAction::Transfer => self.transfer,
+            PauseAction::Liquidation => self.liquidation,
+        }
+    }
+
+    pub fn set(&mut self, action: PauseAction, paused: bool) {
+        match action {
+            PauseAction::Mint => self.mint = paused,
+            PauseAction::Borrow => self.borrow = paused,
+            PauseAction::Transfer => self.transfer = paused,
+            PauseAction::Liquidation => self.liquidation = paused,
+        }
+    }
+}
+
+/// Account position tracked on a leaderboard
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LeaderboardEntry {
+    pub address: CanonicalAddr,
+    pub amount: Uint128
+}
+
 /// Config singleton initialization
-pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, Config> {
+pub fn config<S: Storage>(storage: &mut S) -> Singleton<'_, S, Config> {
     singleton(storage, CONFIG_PREFIX)
 }
 
@@ -59,6 +928,27 @@ pub fn set_config<S: Storage>(storage: &mut S, config: &Config) -> StdResult<()>
     Singleton::new(storage, CONFIG_PREFIX).save(config)
 }
 
+/// Load config in whichever layout it was written, the current one is tried first
+/// since legacy layouts would also accept it with the extra fields ignored
+pub fn load_stored_config<S: Storage>(storage: &S) -> StdResult<StoredConfig> {
+    match ReadonlySingleton::<S, Config>::new(storage, CONFIG_PREFIX).load() {
+        Ok(config) => Ok(StoredConfig::Current(Box::new(config))),
+        Err(_) => Ok(StoredConfig::V0(ReadonlySingleton::new(storage, CONFIG_PREFIX).load()?)),
+    }
+}
+
+/// Get stored contract version, None for deployments that predate it
+pub fn get_contract_version<S: Storage>(storage: &S) -> StdResult<Option<ContractVersion>> {
+    ReadonlySingleton::new(storage, CONTRACT_INFO_KEY).may_load()
+}
+
+pub fn set_contract_version<S: Storage>(storage: &mut S, contract: &str, version: &str) -> StdResult<()> {
+    Singleton::new(storage, CONTRACT_INFO_KEY).save(&ContractVersion {
+        contract: contract.to_string(),
+        version: version.to_string(),
+    })
+}
+
 /// Get exchange rate
 pub fn get_state<S: Storage>(storage: &S) -> StdResult<State> {
     ReadonlySingleton::new(storage, STATE_PREFIX).load()
@@ -69,60 +959,617 @@ pub fn set_state<S: Storage>(storage: &mut S, state: &State) -> StdResult<()> {
     Singleton::new(storage, STATE_PREFIX).save(state)
 }
 
-/// Get balance from address
-pub fn get_balance<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<u128> {
-    let balance_store = ReadonlyPrefixedStorage::new(BALANCE_PREFIX, store);
-    to_u128(&balance_store, owner.as_slice())
+/// Get metrics counters
+pub fn get_metrics<S: Storage>(storage: &S) -> StdResult<Metrics> {
+    Ok(ReadonlySingleton::new(storage, METRICS_KEY).may_load()?.unwrap_or_default())
 }
 
-// Reads 16 byte storage value into u128
-// Returns zero if key does not exist. Returns 0 if data found that is not 16 bytes
-pub fn to_u128<S: ReadonlyStorage>(store: &S, key: &[u8]) -> StdResult<u128> {
-    let result = store.get(key);
-    match result {
-        Some(data) => bytes_to_u128(&data),
-        None => Ok(0u128),
-    }
+/// Apply an update to the metrics counters
+pub fn update_metrics<S: Storage, F: FnOnce(&mut Metrics)>(storage: &mut S, update: F) -> StdResult<()> {
+    let mut metrics = get_metrics(storage)?;
+    update(&mut metrics);
+    Singleton::new(storage, METRICS_KEY).save(&metrics)
 }
 
-// Converts 16 bytes value into u128
-// Errors if data found that is not 16 bytes
-pub fn bytes_to_u128(data: &[u8]) -> StdResult<u128> {
-    match data[0..16].try_into() {
-        Ok(bytes) => Ok(u128::from_be_bytes(bytes)),
-        Err(_) => Err(StdError::generic_err(
-            "Corrupted data found. 16 byte expected.",
-        )),
-    }
+/// Get smoothed exchange rate
+pub fn get_exchange_rate_ema<S: Storage>(storage: &S) -> StdResult<Option<ExchangeRateEma>> {
+    ReadonlySingleton::new(storage, EXCHANGE_RATE_EMA_KEY).may_load()
 }
 
-/// Get allowance from address
-pub fn get_allowance<S: Storage>(
-    store: &S,
-    owner: &CanonicalAddr,
-    spender: &CanonicalAddr,
-) -> StdResult<u128> {
-    let allowances_store = ReadonlyPrefixedStorage::new(ALLOWANCE_PREFIX, store);
-    let owner_store = ReadonlyPrefixedStorage::new(owner.as_slice(), &allowances_store);
-    to_u128(&owner_store, spender.as_slice())
+/// Set smoothed exchange rate
+pub fn set_exchange_rate_ema<S: Storage>(storage: &mut S, ema: &ExchangeRateEma) -> StdResult<()> {
+    Singleton::new(storage, EXCHANGE_RATE_EMA_KEY).save(ema)
 }
 
-/// Set allowance from address
-pub fn set_allowance<S: Storage>(
-    store: &mut S,
-    owner: &CanonicalAddr,
-    spender: &CanonicalAddr,
+/// Time-weighted utilization average as of the last accrual, none before the first one with a window
+pub fn get_utilization_average<S: Storage>(storage: &S) -> StdResult<Option<UtilizationAverage>> {
+    ReadonlySingleton::new(storage, UTILIZATION_AVERAGE_KEY).may_load()
+}
+
+pub fn set_utilization_average<S: Storage>(storage: &mut S, average: &UtilizationAverage) -> StdResult<()> {
+    Singleton::new(storage, UTILIZATION_AVERAGE_KEY).save(average)
+}
+
+/// Part of `cash` deployed to the cash strategy, zero without one
+pub fn get_warm_cash<S: Storage>(storage: &S) -> StdResult<Uint128> {
+    Ok(ReadonlySingleton::new(storage, WARM_CASH_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_warm_cash<S: Storage>(storage: &mut S, warm_cash: Uint128) -> StdResult<()> {
+    Singleton::new(storage, WARM_CASH_KEY).save(&warm_cash)
+}
+
+/// Zero before the first accrual that left a remainder
+pub fn get_accrual_remainder<S: Storage>(storage: &S) -> StdResult<AccrualRemainder> {
+    Ok(ReadonlySingleton::new(storage, ACCRUAL_REMAINDER_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_accrual_remainder<S: Storage>(storage: &mut S, remainder: &AccrualRemainder) -> StdResult<()> {
+    Singleton::new(storage, ACCRUAL_REMAINDER_KEY).save(remainder)
+}
+
+/// Deployments from before the model was stored keep the default curve
+pub fn get_interest_rate_model<S: Storage>(storage: &S) -> StdResult<InterestRateModel> {
+    Ok(ReadonlySingleton::new(storage, INTEREST_RATE_MODEL_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_interest_rate_model<S: Storage>(storage: &mut S, model: &InterestRateModel) -> StdResult<()> {
+    Singleton::new(storage, INTEREST_RATE_MODEL_KEY).save(model)
+}
+
+/// Get candidate model evaluated alongside the live one, None if not registered
+pub fn get_shadow_interest_rate_model<S: Storage>(storage: &S) -> StdResult<Option<InterestRateModel>> {
+    ReadonlySingleton::new(storage, SHADOW_INTEREST_RATE_MODEL_KEY).may_load()
+}
+
+pub fn set_shadow_interest_rate_model<S: Storage>(storage: &mut S, model: Option<InterestRateModel>) -> StdResult<()> {
+    match model {
+        Some(model) => Singleton::new(storage, SHADOW_INTEREST_RATE_MODEL_KEY).save(&model),
+        None => {
+            singleton::<S, InterestRateModel>(storage, SHADOW_INTEREST_RATE_MODEL_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+/// Get the rate model bounds, unbounded until governance applies some
+pub fn get_interest_rate_model_bounds<S: Storage>(storage: &S) -> StdResult<InterestRateModelBounds> {
+    Ok(ReadonlySingleton::new(storage, INTEREST_RATE_MODEL_BOUNDS_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_interest_rate_model_bounds<S: Storage>(storage: &mut S, bounds: &InterestRateModelBounds) -> StdResult<()> {
+    Singleton::new(storage, INTEREST_RATE_MODEL_BOUNDS_KEY).save(bounds)
+}
+
+pub fn get_pending_interest_rate_model_bounds<S: Storage>(
+    storage: &S,
+) -> StdResult<Option<PendingInterestRateModelBounds>> {
+    ReadonlySingleton::new(storage, PENDING_INTEREST_RATE_MODEL_BOUNDS_KEY).may_load()
+}
+
+/// Set the proposed bounds change, None clears it
+pub fn set_pending_interest_rate_model_bounds<S: Storage>(
+    storage: &mut S,
+    pending: Option<&PendingInterestRateModelBounds>,
+) -> StdResult<()> {
+    match pending {
+        Some(pending) => Singleton::new(storage, PENDING_INTEREST_RATE_MODEL_BOUNDS_KEY).save(pending),
+        None => {
+            singleton::<S, PendingInterestRateModelBounds>(storage, PENDING_INTEREST_RATE_MODEL_BOUNDS_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+/// Get the reserve factor change in progress, None once it has reached its target
+pub fn get_reserve_factor_ramp<S: Storage>(storage: &S) -> StdResult<Option<ParameterRamp>> {
+    ReadonlySingleton::new(storage, RESERVE_FACTOR_RAMP_KEY).may_load()
+}
+
+/// Set the reserve factor ramp, None clears it
+pub fn set_reserve_factor_ramp<S: Storage>(storage: &mut S, ramp: Option<&ParameterRamp>) -> StdResult<()> {
+    match ramp {
+        Some(ramp) => Singleton::new(storage, RESERVE_FACTOR_RAMP_KEY).save(ramp),
+        None => {
+            singleton::<S, ParameterRamp>(storage, RESERVE_FACTOR_RAMP_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+pub fn has_role<S: Storage>(storage: &S, role: Role, account: &CanonicalAddr) -> StdResult<bool> {
+    Ok(ReadonlyBucket::<S, bool>::multilevel(&[ROLE_PREFIX, role.as_str().as_bytes()], storage)
+        .may_load(account.as_slice())?
+        .unwrap_or(false))
+}
+
+/// Grant or revoke `role`
+pub fn set_role<S: Storage>(storage: &mut S, role: Role, account: &CanonicalAddr, granted: bool) -> StdResult<()> {
+    let mut members = Bucket::<S, bool>::multilevel(&[ROLE_PREFIX, role.as_str().as_bytes()], storage);
+    if granted {
+        members.save(account.as_slice(), &true)
+    } else {
+        members.remove(account.as_slice());
+        Ok(())
+    }
+}
+
+/// Get up to `limit` holders of `role`, in key order after `start_after`
+pub fn get_role_members<S: Storage>(
+    storage: &S,
+    role: Role,
+    start_after: Option<&CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<CanonicalAddr>> {
+    let start = calc_range_start(start_after.map(|account| account.as_slice()));
+    ReadonlyBucket::<S, bool>::multilevel(&[ROLE_PREFIX, role.as_str().as_bytes()], storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(key, _)| CanonicalAddr::from(key)))
+        .collect()
+}
+
+pub fn get_state_digest<S: Storage>(storage: &S) -> StdResult<StateDigest> {
+    Ok(ReadonlySingleton::new(storage, STATE_DIGEST_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_state_digest<S: Storage>(storage: &mut S, digest: &StateDigest) -> StdResult<()> {
+    Singleton::new(storage, STATE_DIGEST_KEY).save(digest)
+}
+
+pub fn get_global_pause<S: Storage>(storage: &S) -> StdResult<GlobalPause> {
+    Ok(ReadonlySingleton::new(storage, GLOBAL_PAUSE_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_global_pause<S: Storage>(storage: &mut S, pause: &GlobalPause) -> StdResult<()> {
+    Singleton::new(storage, GLOBAL_PAUSE_KEY).save(pause)
+}
+
+/// Get balance from address
+pub fn get_balance<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<u128> {
+    to_u128(&ReadonlyPrefixedStorage::new(BALANCE_PREFIX, store), owner.as_slice())
+}
+
+pub fn set_balance<S: Storage>(store: &mut S, owner: &CanonicalAddr, amount: u128) -> StdResult<()> {
+    if is_snapshot_pending(store, SnapshotSection::Balances, owner)? {
+        let balance = Uint128::from(get_balance(store, owner)?);
+        preserved_bucket(store, SnapshotSection::Balances).save(owner.as_slice(), &balance)?;
+    }
+    Bucket::<S, Uint128>::new(BALANCE_PREFIX, store).save(owner.as_slice(), &Uint128::from(amount))
+}
+
+// Reads an amount entry into u128
+// Returns zero if key does not exist
+pub fn to_u128<S: ReadonlyStorage>(store: &S, key: &[u8]) -> StdResult<u128> {
+    match store.get(key) {
+        Some(data) => decode_u128(&data),
+        None => Ok(0u128),
+    }
+}
+
+// Decodes an amount entry written by a Bucket, falling back to the raw
+// 16 byte big-endian layout written before balances moved to buckets
+pub fn decode_u128(data: &[u8]) -> StdResult<u128> {
+    match from_slice::<Uint128>(data) {
+        Ok(amount) => Ok(amount.u128()),
+        Err(_) => bytes_to_u128(data),
+    }
+}
+
+// Converts 16 bytes value into u128
+// Errors if data found that is not 16 bytes
+pub fn bytes_to_u128(data: &[u8]) -> StdResult<u128> {
+    match data.try_into() {
+        Ok(bytes) => Ok(u128::from_be_bytes(bytes)),
+        Err(_) => Err(StdError::generic_err(
+            "Corrupted data found. 16 byte expected.",
+        )),
+    }
+}
+
+/// Get allowance from address
+pub fn get_allowance<S: Storage>(
+    store: &S,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
+) -> StdResult<u128> {
+    let allowances_store = ReadonlyPrefixedStorage::multilevel(&[ALLOWANCE_PREFIX, owner.as_slice()], store);
+    to_u128(&allowances_store, spender.as_slice())
+}
+
+/// Set allowance from address
+pub fn set_allowance<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
     amount: u128,
 ) -> StdResult<()> {
-    let mut allowances_store = PrefixedStorage::new(ALLOWANCE_PREFIX, store);
-    let mut owner_store = PrefixedStorage::new(owner.as_slice(), &mut allowances_store);
-    owner_store.set(spender.as_slice(), &amount.to_be_bytes());
-    Ok(())
+    Bucket::<S, Uint128>::multilevel(&[ALLOWANCE_PREFIX, owner.as_slice()], store)
+        .save(spender.as_slice(), &Uint128::from(amount))?;
+
+    // Reverse index so spenders can enumerate the owners that granted them an allowance
+    let mut spender_index = Bucket::<S, bool>::multilevel(&[SPENDER_ALLOWANCE_PREFIX, spender.as_slice()], store);
+    if amount == 0 {
+        spender_index.remove(owner.as_slice());
+        Ok(())
+    } else {
+        spender_index.save(owner.as_slice(), &true)
+    }
+}
+
+/// Get up to `limit` owners that granted an allowance to the spender, in key order after `start_after`
+pub fn get_spender_allowance_owners<S: Storage>(
+    store: &S,
+    spender: &CanonicalAddr,
+    start_after: Option<&CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<CanonicalAddr>> {
+    let start = calc_range_start(start_after.map(|owner| owner.as_slice()));
+    ReadonlyBucket::<S, bool>::multilevel(&[SPENDER_ALLOWANCE_PREFIX, spender.as_slice()], store)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(key, _)| CanonicalAddr::from(key)))
+        .collect()
+}
+
+/// Get up to `limit` spenders with a non-zero allowance from owner, in key order after `start_after`
+pub fn get_owner_allowances<S: Storage>(
+    store: &S,
+    owner: &CanonicalAddr,
+    start_after: Option<&CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<(CanonicalAddr, u128)>> {
+    let start = calc_range_start(start_after.map(|spender| spender.as_slice()));
+    let allowances_store = ReadonlyPrefixedStorage::multilevel(&[ALLOWANCE_PREFIX, owner.as_slice()], store);
+    let mut allowances = vec![];
+    // Ranged raw so entries still in the legacy layout decode as well
+    for (key, value) in allowances_store.range(start.as_deref(), None, Order::Ascending) {
+        let allowance = decode_u128(&value)?;
+        if allowance > 0 {
+            allowances.push((CanonicalAddr::from(key), allowance));
+        }
+        if allowances.len() == limit {
+            break;
+        }
+    }
+    Ok(allowances)
+}
+
+/// Get up to `limit` accounts holding a balance entry, in key order after `start_after`
+pub fn get_accounts<S: Storage>(
+    store: &S,
+    start_after: Option<&CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<CanonicalAddr>> {
+    let start = calc_range_start(start_after.map(|account| account.as_slice()));
+    let balances_store = ReadonlyPrefixedStorage::new(BALANCE_PREFIX, store);
+    Ok(balances_store
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|(key, _)| CanonicalAddr::from(key))
+        .collect())
+}
+
+/// Get up to `limit` accounts with an outstanding borrow and their snapshot, in key order after `start_after`
+pub fn get_borrowers<S: Storage>(
+    store: &S,
+    start_after: Option<&CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<(CanonicalAddr, BorrowSnapshot)>> {
+    let start = calc_range_start(start_after.map(|borrower| borrower.as_slice()));
+    let borrows_store = ReadonlyPrefixedStorage::new(BORROW_PREFIX, store);
+    let mut borrowers = vec![];
+    for (key, _) in borrows_store.range(start.as_deref(), None, Order::Ascending) {
+        let borrower = CanonicalAddr::from(key);
+        match get_borrow_balance(store, &borrower) {
+            Some(snapshot) if !snapshot.principal.is_zero() => borrowers.push((borrower, snapshot)),
+            _ => {}
+        }
+        if borrowers.len() == limit {
+            break;
+        }
+    }
+    Ok(borrowers)
+}
+
+/// Get allowance expiration from address, allowances without one never expire
+pub fn get_allowance_expiration<S: Storage>(
+    store: &S,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
+) -> StdResult<Expiration> {
+    let expiration = ReadonlyBucket::multilevel(&[ALLOWANCE_EXPIRATION_PREFIX, owner.as_slice()], store)
+        .may_load(spender.as_slice())?;
+    Ok(expiration.unwrap_or_default())
+}
+
+/// Set allowance expiration from address
+pub fn set_allowance_expiration<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
+    expires: &Expiration,
+) -> StdResult<()> {
+    Bucket::multilevel(&[ALLOWANCE_EXPIRATION_PREFIX, owner.as_slice()], store)
+        .save(spender.as_slice(), expires)
+}
+
+// Loads a versioned per-account entry, falling back to the unversioned layout
+// written before the envelope was introduced
+fn may_load_versioned<S: ReadonlyStorage, V, L>(
+    store: &S,
+    prefix: &[u8],
+    key: &[u8],
+) -> StdResult<Option<V>>
+where
+    V: serde::de::DeserializeOwned + From<L>,
+    L: serde::de::DeserializeOwned,
+{
+    let data = match ReadonlyPrefixedStorage::new(prefix, store).get(key) {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    match from_slice::<V>(&data) {
+        Ok(versioned) => Ok(Some(versioned)),
+        Err(_) => Ok(from_slice::<Option<L>>(&data)?.map(V::from)),
+    }
+}
+
+/// Get announced large redemption of an account
+pub fn get_pending_redeem<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<Option<PendingRedeem>> {
+    ReadonlyBucket::new(PENDING_REDEEM_PREFIX, store).may_load(owner.as_slice())
+}
+
+/// Set or clear announced large redemption of an account
+pub fn set_pending_redeem<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    pending: Option<PendingRedeem>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(PENDING_REDEEM_PREFIX, store);
+    match pending {
+        Some(pending) => bucket.save(owner.as_slice(), &pending),
+        None => {
+            bucket.remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Get self-repay checkpoint of an account, None if not enabled
+pub fn get_self_repay<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<Option<SelfRepay>> {
+    ReadonlyBucket::new(SELF_REPAY_PREFIX, store).may_load(owner.as_slice())
+}
+
+/// Set self-repay checkpoint of an account, None disables self-repay
+pub fn set_self_repay<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    self_repay: Option<SelfRepay>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(SELF_REPAY_PREFIX, store);
+    match self_repay {
+        Some(self_repay) => bucket.save(owner.as_slice(), &self_repay),
+        None => {
+            bucket.remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Get yield redirect checkpoint of an account, None if not enabled
+pub fn get_yield_redirect<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<Option<YieldRedirect>> {
+    ReadonlyBucket::new(YIELD_REDIRECT_PREFIX, store).may_load(owner.as_slice())
+}
+
+/// Set yield redirect checkpoint of an account, None disables the redirect
+pub fn set_yield_redirect<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    yield_redirect: Option<YieldRedirect>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(YIELD_REDIRECT_PREFIX, store);
+    match yield_redirect {
+        Some(yield_redirect) => bucket.save(owner.as_slice(), &yield_redirect),
+        None => {
+            bucket.remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Reserve the next tranche id
+pub fn next_tranche_id<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    let id: u64 = ReadonlySingleton::new(storage, TRANCHE_COUNT_KEY).may_load()?.unwrap_or(0) + 1;
+    Singleton::new(storage, TRANCHE_COUNT_KEY).save(&id)?;
+    Ok(id)
+}
+
+/// Get tranche by id
+pub fn get_tranche<S: Storage>(storage: &S, id: u64) -> StdResult<Tranche> {
+    ReadonlyBucket::new(TRANCHE_PREFIX, storage).load(&id.to_be_bytes())
+}
+
+/// Save tranche and index it under its owner
+pub fn set_tranche<S: Storage>(storage: &mut S, tranche: &Tranche) -> StdResult<()> {
+    Bucket::new(TRANCHE_PREFIX, storage).save(&tranche.id.to_be_bytes(), tranche)?;
+    Bucket::multilevel(&[OWNER_TRANCHE_PREFIX, tranche.owner.as_slice()], storage)
+        .save(&tranche.id.to_be_bytes(), &true)
+}
+
+/// Remove tranche and its owner index entry
+pub fn remove_tranche<S: Storage>(storage: &mut S, tranche: &Tranche) {
+    Bucket::<S, Tranche>::new(TRANCHE_PREFIX, storage).remove(&tranche.id.to_be_bytes());
+    Bucket::<S, bool>::multilevel(&[OWNER_TRANCHE_PREFIX, tranche.owner.as_slice()], storage)
+        .remove(&tranche.id.to_be_bytes());
+}
+
+/// Reserve the next OTC offer id
+pub fn next_otc_offer_id<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    let id: u64 = ReadonlySingleton::new(storage, OTC_OFFER_COUNT_KEY).may_load()?.unwrap_or(0) + 1;
+    Singleton::new(storage, OTC_OFFER_COUNT_KEY).save(&id)?;
+    Ok(id)
+}
+
+/// Get OTC offer by id, None once bought or cancelled
+pub fn get_otc_offer<S: Storage>(storage: &S, id: u64) -> StdResult<Option<OtcOffer>> {
+    ReadonlyBucket::new(OTC_OFFER_PREFIX, storage).may_load(&id.to_be_bytes())
+}
+
+pub fn set_otc_offer<S: Storage>(storage: &mut S, offer: &OtcOffer) -> StdResult<()> {
+    Bucket::new(OTC_OFFER_PREFIX, storage).save(&offer.id.to_be_bytes(), offer)
+}
+
+pub fn remove_otc_offer<S: Storage>(storage: &mut S, id: u64) {
+    Bucket::<S, OtcOffer>::new(OTC_OFFER_PREFIX, storage).remove(&id.to_be_bytes());
+}
+
+/// Get open OTC offers in ascending id order, starting after the given id
+pub fn get_otc_offers<S: Storage>(storage: &S, start_after: Option<u64>, limit: usize) -> StdResult<Vec<OtcOffer>> {
+    let start = start_after.map(|id| (id + 1).to_be_bytes().to_vec());
+    ReadonlyBucket::<S, OtcOffer>::new(OTC_OFFER_PREFIX, storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, offer)| offer))
+        .collect()
+}
+
+/// Get tranche ids of an owner in ascending order, starting after the given id
+pub fn get_owner_tranche_ids<S: Storage>(
+    storage: &S,
+    owner: &CanonicalAddr,
+    start_after: Option<u64>,
+) -> StdResult<Vec<u64>> {
+    let start = start_after.map(|id| (id + 1).to_be_bytes().to_vec());
+    ReadonlyBucket::<S, bool>::multilevel(&[OWNER_TRANCHE_PREFIX, owner.as_slice()], storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .map(|item| item.and_then(|(key, _)| id_from_key(&key)))
+        .collect()
+}
+
+/// Append a liquidation to the log and index it under its borrower
+pub fn append_liquidation<S: Storage>(storage: &mut S, mut record: LiquidationRecord) -> StdResult<LiquidationRecord> {
+    record.id = ReadonlySingleton::new(storage, LIQUIDATION_COUNT_KEY).may_load()?.unwrap_or(0u64) + 1;
+    Singleton::new(storage, LIQUIDATION_COUNT_KEY).save(&record.id)?;
+    Bucket::new(LIQUIDATION_PREFIX, storage).save(&record.id.to_be_bytes(), &record)?;
+    Bucket::multilevel(&[BORROWER_LIQUIDATION_PREFIX, record.borrower.as_slice()], storage)
+        .save(&record.id.to_be_bytes(), &true)?;
+    Ok(record)
+}
+
+/// Append a privileged change to the audit log
+pub fn append_audit_record<S: Storage>(storage: &mut S, mut record: AuditRecord) -> StdResult<AuditRecord> {
+    record.id = get_audit_count(storage)? + 1;
+    Singleton::new(storage, AUDIT_COUNT_KEY).save(&record.id)?;
+    Bucket::new(AUDIT_PREFIX, storage).save(&record.id.to_be_bytes(), &record)?;
+    Ok(record)
+}
+
+pub fn get_audit_count<S: Storage>(storage: &S) -> StdResult<u64> {
+    Ok(ReadonlySingleton::new(storage, AUDIT_COUNT_KEY).may_load()?.unwrap_or(0u64))
+}
+
+/// Get up to `limit` audit records in ascending order after `start_after`
+pub fn get_audit_records<S: Storage>(storage: &S, start_after: Option<u64>, limit: usize) -> StdResult<Vec<AuditRecord>> {
+    let start = start_after.map(|id| (id + 1).to_be_bytes().to_vec());
+    ReadonlyBucket::<S, AuditRecord>::new(AUDIT_PREFIX, storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, record)| record))
+        .collect()
+}
+
+pub fn append_migration<S: Storage>(storage: &mut S, mut record: MigrationRecord) -> StdResult<MigrationRecord> {
+    record.id = get_migration_count(storage)? + 1;
+    Singleton::new(storage, MIGRATION_COUNT_KEY).save(&record.id)?;
+    Bucket::new(MIGRATION_PREFIX, storage).save(&record.id.to_be_bytes(), &record)?;
+    Ok(record)
+}
+
+pub fn get_migration_count<S: Storage>(storage: &S) -> StdResult<u64> {
+    Ok(ReadonlySingleton::new(storage, MIGRATION_COUNT_KEY).may_load()?.unwrap_or(0u64))
+}
+
+/// Get up to `limit` migration records in ascending order after `start_after`
+pub fn get_migrations<S: Storage>(storage: &S, start_after: Option<u64>, limit: usize) -> StdResult<Vec<MigrationRecord>> {
+    let start = start_after.map(|id| (id + 1).to_be_bytes().to_vec());
+    ReadonlyBucket::<S, MigrationRecord>::new(MIGRATION_PREFIX, storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, record)| record))
+        .collect()
+}
+
+/// Last block the account was found underwater in, if ever
+pub fn get_shortfall_block<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<Option<u64>> {
+    ReadonlyBucket::new(SHORTFALL_FLAG_PREFIX, storage).may_load(account.as_slice())
+}
+
+pub fn set_shortfall_block<S: Storage>(storage: &mut S, account: &CanonicalAddr, block_number: u64) -> StdResult<()> {
+    Bucket::new(SHORTFALL_FLAG_PREFIX, storage).save(account.as_slice(), &block_number)
+}
+
+/// Block the account was first found severely undercollateralized by BackstopLiquidate,
+/// None until then or once a liquidator or the backstop acted
+pub fn get_backstop_flag<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<Option<u64>> {
+    ReadonlyBucket::new(BACKSTOP_FLAG_PREFIX, storage).may_load(account.as_slice())
+}
+
+pub fn set_backstop_flag<S: Storage>(storage: &mut S, account: &CanonicalAddr, block_number: Option<u64>) -> StdResult<()> {
+    let mut bucket = Bucket::new(BACKSTOP_FLAG_PREFIX, storage);
+    match block_number {
+        Some(block_number) => bucket.save(account.as_slice(), &block_number),
+        None => {
+            bucket.remove(account.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Last block the account borrowed in, if ever
+pub fn get_last_borrow<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<Option<u64>> {
+    ReadonlyBucket::new(LAST_BORROW_PREFIX, storage).may_load(account.as_slice())
+}
+
+pub fn set_last_borrow<S: Storage>(storage: &mut S, account: &CanonicalAddr, block_number: u64) -> StdResult<()> {
+    Bucket::new(LAST_BORROW_PREFIX, storage).save(account.as_slice(), &block_number)
+}
+
+pub fn get_liquidation<S: Storage>(storage: &S, id: u64) -> StdResult<LiquidationRecord> {
+    ReadonlyBucket::new(LIQUIDATION_PREFIX, storage).load(&id.to_be_bytes())
+}
+
+/// Get up to `limit` liquidation ids in ascending order after `start_after`, optionally of one borrower
+pub fn get_liquidation_ids<S: Storage>(
+    storage: &S,
+    borrower: Option<&CanonicalAddr>,
+    start_after: Option<u64>,
+    limit: usize,
+) -> StdResult<Vec<u64>> {
+    let start = start_after.map(|id| (id + 1).to_be_bytes().to_vec());
+    match borrower {
+        Some(borrower) => ReadonlyBucket::<S, bool>::multilevel(&[BORROWER_LIQUIDATION_PREFIX, borrower.as_slice()], storage)
+            .range(start.as_deref(), None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.and_then(|(key, _)| id_from_key(&key)))
+            .collect(),
+        None => ReadonlyBucket::<S, LiquidationRecord>::new(LIQUIDATION_PREFIX, storage)
+            .range(start.as_deref(), None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.and_then(|(key, _)| id_from_key(&key)))
+            .collect(),
+    }
+}
+
+fn id_from_key(key: &[u8]) -> StdResult<u64> {
+    key.try_into()
+        .map(u64::from_be_bytes)
+        .map_err(|_| StdError::generic_err("Corrupted id key. 8 byte expected."))
 }
 
 pub fn get_borrow_balance<S: Storage>(store: &S, owner: &CanonicalAddr) -> Option<BorrowSnapshot> {
-    match ReadonlyBucket::new(BORROW_PREFIX, store).may_load(owner.as_slice()) {
-        Ok(Some(wrapped_reserves)) => Some(wrapped_reserves),
+    match may_load_versioned::<S, VersionedBorrowSnapshot, BorrowSnapshot>(store, BORROW_PREFIX, owner.as_slice()) {
+        Ok(Some(versioned)) => Some(versioned.into_latest()),
         _ => None,
     }
 }
@@ -132,11 +1579,462 @@ pub fn set_borrow_balance<S: Storage>(
     owner: &CanonicalAddr,
     snapshot: Option<BorrowSnapshot>,
 ) -> StdResult<()> {
-    match Bucket::new(BORROW_PREFIX, store).save(owner.as_slice(), &snapshot) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(StdError::generic_err(format!(
-            "Failed to write to the borrow_balance. key: {:?}, value: {:?}",
-            owner, snapshot
-        ))),
-    }
-}
\ No newline at end of file
+    if is_snapshot_pending(store, SnapshotSection::Borrows, owner)? {
+        let borrow = get_borrow_balance(store, owner);
+        preserved_bucket(store, SnapshotSection::Borrows).save(owner.as_slice(), &borrow)?;
+    }
+    let mut bucket = Bucket::<S, VersionedBorrowSnapshot>::new(BORROW_PREFIX, store);
+    match snapshot {
+        Some(snapshot) => match bucket.save(owner.as_slice(), &snapshot.clone().into()) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(StdError::generic_err(format!(
+                "Failed to write to the borrow_balance. key: {:?}, value: {:?}",
+                owner, snapshot
+            ))),
+        },
+        None => {
+            bucket.remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Get leaderboard stored under the given key, sorted by amount in descending order
+pub fn get_leaderboard<S: Storage>(storage: &S, key: &[u8]) -> StdResult<Vec<LeaderboardEntry>> {
+    Ok(ReadonlySingleton::new(storage, key).may_load()?.unwrap_or_default())
+}
+
+/// Update the position of an account on the leaderboard stored under the given key
+/// Accounts outside the top LEADERBOARD_SIZE are not tracked until they act again,
+/// and amounts are as of each account's last action
+pub fn update_leaderboard<S: Storage>(
+    storage: &mut S,
+    key: &[u8],
+    account: &CanonicalAddr,
+    amount: u128,
+) -> StdResult<()> {
+    let mut entries = get_leaderboard(storage, key)?;
+    entries.retain(|entry| entry.address != *account);
+    if amount > 0 {
+        entries.push(LeaderboardEntry {
+            address: account.clone(),
+            amount: Uint128::from(amount),
+        });
+        entries.sort_by_key(|entry| Reverse(entry.amount));
+        entries.truncate(LEADERBOARD_SIZE);
+    }
+    Singleton::new(storage, key).save(&entries)
+}
+
+/// Nothing is paused until the admin or guardian pauses an action
+pub fn get_pause_flags<S: Storage>(storage: &S) -> StdResult<PauseFlags> {
+    Ok(ReadonlySingleton::new(storage, PAUSE_FLAGS_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_pause_flags<S: Storage>(storage: &mut S, flags: &PauseFlags) -> StdResult<()> {
+    Singleton::new(storage, PAUSE_FLAGS_KEY).save(flags)
+}
+
+/// Admin proposed by TransferOwnership that has not accepted yet
+pub fn get_pending_admin<S: Storage>(storage: &S) -> StdResult<Option<CanonicalAddr>> {
+    ReadonlySingleton::new(storage, PENDING_ADMIN_KEY).may_load()
+}
+
+/// Set the pending admin, None clears it
+pub fn set_pending_admin<S: Storage>(storage: &mut S, pending_admin: Option<&CanonicalAddr>) -> StdResult<()> {
+    match pending_admin {
+        Some(pending_admin) => Singleton::new(storage, PENDING_ADMIN_KEY).save(pending_admin),
+        None => {
+            Singleton::<S, CanonicalAddr>::new(storage, PENDING_ADMIN_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+pub fn get_flash_loan<S: Storage>(storage: &S) -> StdResult<Option<FlashLoan>> {
+    ReadonlySingleton::new(storage, FLASH_LOAN_KEY).may_load()
+}
+
+/// Set the outstanding flash loan, None clears it
+pub fn set_flash_loan<S: Storage>(storage: &mut S, flash_loan: Option<&FlashLoan>) -> StdResult<()> {
+    match flash_loan {
+        Some(flash_loan) => Singleton::new(storage, FLASH_LOAN_KEY).save(flash_loan),
+        None => {
+            Singleton::<S, FlashLoan>::new(storage, FLASH_LOAN_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+/// Get utilization alert thresholds of the market, None if it alerts on none
+pub fn get_utilization_alerts<S: Storage>(storage: &S) -> StdResult<Option<UtilizationAlerts>> {
+    ReadonlySingleton::new(storage, UTILIZATION_ALERTS_KEY).may_load()
+}
+
+/// Set utilization alert thresholds of the market, None removes them
+pub fn set_utilization_alerts<S: Storage>(
+    storage: &mut S,
+    utilization_alerts: Option<&UtilizationAlerts>,
+) -> StdResult<()> {
+    match utilization_alerts {
+        Some(utilization_alerts) => Singleton::new(storage, UTILIZATION_ALERTS_KEY).save(utilization_alerts),
+        None => {
+            Singleton::<S, UtilizationAlerts>::new(storage, UTILIZATION_ALERTS_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+/// Get alert thresholds of an account, None if it watches none
+pub fn get_health_alerts<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<Option<HealthAlerts>> {
+    ReadonlyBucket::new(HEALTH_ALERTS_PREFIX, store).may_load(owner.as_slice())
+}
+
+/// Set alert thresholds of an account, None removes them
+pub fn set_health_alerts<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    health_alerts: Option<&HealthAlerts>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(HEALTH_ALERTS_PREFIX, store);
+    match health_alerts {
+        Some(health_alerts) => bucket.save(owner.as_slice(), health_alerts),
+        None => {
+            bucket.remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Pair swapping `offer_denom` into the underlying, None if not configured
+pub fn get_swap_pair<S: Storage>(storage: &S, offer_denom: &str) -> StdResult<Option<CanonicalAddr>> {
+    ReadonlyBucket::new(SWAP_PAIR_PREFIX, storage).may_load(offer_denom.as_bytes())
+}
+
+/// Set the pair for `offer_denom`, None removes it
+pub fn set_swap_pair<S: Storage>(storage: &mut S, offer_denom: &str, pair: Option<&CanonicalAddr>) -> StdResult<()> {
+    let mut bucket = Bucket::new(SWAP_PAIR_PREFIX, storage);
+    match pair {
+        Some(pair) => bucket.save(offer_denom.as_bytes(), pair),
+        None => {
+            bucket.remove(offer_denom.as_bytes());
+            Ok(())
+        }
+    }
+}
+
+pub fn get_swap_repay<S: Storage>(storage: &S) -> StdResult<Option<SwapRepay>> {
+    ReadonlySingleton::new(storage, SWAP_REPAY_KEY).may_load()
+}
+
+/// Set the pending swap repayment, None clears it
+pub fn set_swap_repay<S: Storage>(storage: &mut S, swap_repay: Option<&SwapRepay>) -> StdResult<()> {
+    match swap_repay {
+        Some(swap_repay) => Singleton::new(storage, SWAP_REPAY_KEY).save(swap_repay),
+        None => {
+            Singleton::<S, SwapRepay>::new(storage, SWAP_REPAY_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+pub fn get_buyback_epoch<S: Storage>(storage: &S) -> StdResult<BuybackEpoch> {
+    Ok(ReadonlySingleton::new(storage, BUYBACK_EPOCH_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_buyback_epoch<S: Storage>(storage: &mut S, epoch: &BuybackEpoch) -> StdResult<()> {
+    Singleton::new(storage, BUYBACK_EPOCH_KEY).save(epoch)
+}
+
+pub fn get_pending_buyback<S: Storage>(storage: &S) -> StdResult<Option<PendingBuyback>> {
+    ReadonlySingleton::new(storage, PENDING_BUYBACK_KEY).may_load()
+}
+
+/// Set the pending buyback, None clears it
+pub fn set_pending_buyback<S: Storage>(storage: &mut S, buyback: Option<&PendingBuyback>) -> StdResult<()> {
+    match buyback {
+        Some(buyback) => Singleton::new(storage, PENDING_BUYBACK_KEY).save(buyback),
+        None => {
+            Singleton::<S, PendingBuyback>::new(storage, PENDING_BUYBACK_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+/// Get a registered integrator, None if not registered
+pub fn get_integrator<S: Storage>(storage: &S, integrator: &CanonicalAddr) -> StdResult<Option<Integrator>> {
+    ReadonlyBucket::new(INTEGRATOR_PREFIX, storage).may_load(integrator.as_slice())
+}
+
+/// Set a registered integrator, None deregisters it
+pub fn set_integrator<S: Storage>(
+    storage: &mut S,
+    integrator: &CanonicalAddr,
+    info: Option<&Integrator>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(INTEGRATOR_PREFIX, storage);
+    match info {
+        Some(info) => bucket.save(integrator.as_slice(), info),
+        None => {
+            bucket.remove(integrator.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Raw entries stored under `prefix` in key order after `start_after`, for genesis export
+pub fn get_raw_entries<S: Storage>(
+    store: &S,
+    prefix: &[u8],
+    start_after: Option<&[u8]>,
+    limit: usize,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let start = calc_range_start(start_after);
+    ReadonlyPrefixedStorage::new(prefix, store)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .collect()
+}
+
+/// Overwrite a raw entry under `prefix`, bypassing any typed layout
+pub fn set_raw_entry<S: Storage>(store: &mut S, prefix: &[u8], key: &[u8], value: &[u8]) {
+    PrefixedStorage::new(prefix, store).set(key, value);
+}
+
+/// Snapshot still being captured
+pub fn get_pending_liquidity_snapshot<S: Storage>(storage: &S) -> StdResult<Option<LiquiditySnapshot>> {
+    ReadonlySingleton::new(storage, LIQUIDITY_SNAPSHOT_KEY).may_load()
+}
+
+pub fn get_liquidity_snapshot_count<S: Storage>(storage: &S) -> StdResult<u64> {
+    Ok(ReadonlySingleton::new(storage, LIQUIDITY_SNAPSHOT_COUNT_KEY).may_load()?.unwrap_or_default())
+}
+
+/// Get a snapshot by id, whether still being captured or done
+pub fn get_liquidity_snapshot<S: Storage>(storage: &S, id: u64) -> StdResult<Option<LiquiditySnapshot>> {
+    match get_pending_liquidity_snapshot(storage)? {
+        Some(snapshot) if snapshot.id == id => Ok(Some(snapshot)),
+        _ => ReadonlyBucket::new(LIQUIDITY_SNAPSHOT_PREFIX, storage).may_load(&id.to_be_bytes()),
+    }
+}
+
+/// Keeps `snapshot` pending until its capture is done, then files it under its id
+pub fn set_liquidity_snapshot<S: Storage>(storage: &mut S, snapshot: &LiquiditySnapshot) -> StdResult<()> {
+    Singleton::new(storage, LIQUIDITY_SNAPSHOT_COUNT_KEY).save(&snapshot.id)?;
+    if snapshot.section != SnapshotSection::Done {
+        return Singleton::new(storage, LIQUIDITY_SNAPSHOT_KEY).save(snapshot);
+    }
+    singleton::<S, LiquiditySnapshot>(storage, LIQUIDITY_SNAPSHOT_KEY).remove();
+    Bucket::new(LIQUIDITY_SNAPSHOT_PREFIX, storage).save(&snapshot.id.to_be_bytes(), snapshot)
+}
+
+fn is_snapshot_pending<S: Storage>(storage: &S, section: SnapshotSection, account: &CanonicalAddr) -> StdResult<bool> {
+    let snapshot = match get_pending_liquidity_snapshot(storage)? {
+        Some(snapshot) => snapshot,
+        None => return Ok(false),
+    };
+    if !snapshot.is_pending(section, account.as_slice()) {
+        return Ok(false);
+    }
+    // Only the first write after the snapshot block holds the value at that block
+    let preserved = ReadonlyPrefixedStorage::multilevel(
+        &[LIQUIDITY_SNAPSHOT_PRESERVED_PREFIX, section.as_str().as_bytes()],
+        storage,
+    );
+    Ok(preserved.get(account.as_slice()).is_none())
+}
+
+fn preserved_bucket<S: Storage, T: Serialize + serde::de::DeserializeOwned>(
+    storage: &mut S,
+    section: SnapshotSection,
+) -> Bucket<'_, S, T> {
+    Bucket::multilevel(&[LIQUIDITY_SNAPSHOT_PRESERVED_PREFIX, section.as_str().as_bytes()], storage)
+}
+
+/// Balances preserved for the pending snapshot, in key order after `start_after`
+pub fn get_preserved_balances<S: Storage>(
+    storage: &S,
+    start_after: Option<&[u8]>,
+    limit: usize,
+) -> StdResult<Vec<(Vec<u8>, Uint128)>> {
+    get_preserved(storage, SnapshotSection::Balances, start_after, limit)
+}
+
+/// Borrows preserved for the pending snapshot, None for accounts that had no borrow at the snapshot block
+pub fn get_preserved_borrows<S: Storage>(
+    storage: &S,
+    start_after: Option<&[u8]>,
+    limit: usize,
+) -> StdResult<Vec<(Vec<u8>, Option<BorrowSnapshot>)>> {
+    get_preserved(storage, SnapshotSection::Borrows, start_after, limit)
+}
+
+fn get_preserved<S: Storage, T: Serialize + serde::de::DeserializeOwned>(
+    storage: &S,
+    section: SnapshotSection,
+    start_after: Option<&[u8]>,
+    limit: usize,
+) -> StdResult<Vec<(Vec<u8>, T)>> {
+    let start = calc_range_start(start_after);
+    ReadonlyBucket::<S, T>::multilevel(&[LIQUIDITY_SNAPSHOT_PRESERVED_PREFIX, section.as_str().as_bytes()], storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .collect()
+}
+
+/// Drop a preserved value once the capture has taken it
+pub fn remove_preserved<S: Storage>(storage: &mut S, section: SnapshotSection, account: &[u8]) {
+    PrefixedStorage::multilevel(&[LIQUIDITY_SNAPSHOT_PRESERVED_PREFIX, section.as_str().as_bytes()], storage)
+        .remove(account);
+}
+
+/// Record the qToken balance or debt of `account` in snapshot `id`
+pub fn set_snapshot_entry<S: Storage>(
+    storage: &mut S,
+    id: u64,
+    section: SnapshotSection,
+    account: &CanonicalAddr,
+    amount: Uint128,
+) -> StdResult<()> {
+    Bucket::multilevel(&[LIQUIDITY_SNAPSHOT_ENTRY_PREFIX, &id.to_be_bytes(), section.as_str().as_bytes()], storage)
+        .save(account.as_slice(), &amount)
+}
+
+/// Captured entries of snapshot `id`, in key order after `start_after`
+pub fn get_snapshot_entries<S: Storage>(
+    storage: &S,
+    id: u64,
+    section: SnapshotSection,
+    start_after: Option<&CanonicalAddr>,
+    limit: usize,
+) -> StdResult<Vec<(CanonicalAddr, Uint128)>> {
+    let start = calc_range_start(start_after.map(|account| account.as_slice()));
+    ReadonlyBucket::<S, Uint128>::multilevel(
+        &[LIQUIDITY_SNAPSHOT_ENTRY_PREFIX, &id.to_be_bytes(), section.as_str().as_bytes()],
+        storage,
+    )
+    .range(start.as_deref(), None, Order::Ascending)
+    .take(limit)
+    .map(|item| item.map(|(key, amount)| (CanonicalAddr::from(key), amount)))
+    .collect()
+}
+
+/// Get storage compaction progress, at the first balance before it has started
+pub fn get_compaction<S: Storage>(storage: &S) -> StdResult<Compaction> {
+    Ok(ReadonlySingleton::new(storage, COMPACTION_KEY).may_load()?.unwrap_or(Compaction {
+        section: CompactionSection::Balances,
+        start_after: None,
+        rewritten: 0,
+    }))
+}
+
+pub fn set_compaction<S: Storage>(storage: &mut S, compaction: &Compaction) -> StdResult<()> {
+    Singleton::new(storage, COMPACTION_KEY).save(compaction)
+}
+
+/// Owner and spender of a raw key under ALLOWANCE_PREFIX, which nests the
+/// length prefixed owner namespace before the spender
+pub fn split_allowance_key(key: &[u8]) -> StdResult<(CanonicalAddr, CanonicalAddr)> {
+    if key.len() < 2 {
+        return Err(StdError::generic_err("Corrupted allowance key found"));
+    }
+    let owner_len = u16::from_be_bytes([key[0], key[1]]) as usize;
+    if key.len() < 2 + owner_len {
+        return Err(StdError::generic_err("Corrupted allowance key found"));
+    }
+    Ok((
+        CanonicalAddr::from(&key[2..2 + owner_len]),
+        CanonicalAddr::from(&key[2 + owner_len..]),
+    ))
+}
+
+pub fn get_genesis_import<S: Storage>(storage: &S) -> StdResult<Option<GenesisImport>> {
+    ReadonlySingleton::new(storage, GENESIS_IMPORT_KEY).may_load()
+}
+
+/// Set the genesis import in progress, None closes it
+pub fn set_genesis_import<S: Storage>(storage: &mut S, genesis_import: Option<&GenesisImport>) -> StdResult<()> {
+    match genesis_import {
+        Some(genesis_import) => Singleton::new(storage, GENESIS_IMPORT_KEY).save(genesis_import),
+        None => {
+            Singleton::<S, GenesisImport>::new(storage, GENESIS_IMPORT_KEY).remove();
+            Ok(())
+        }
+    }
+}
+
+/// Terminal wind-down of the market, None while it operates normally
+pub fn get_settlement<S: Storage>(storage: &S) -> StdResult<Option<Settlement>> {
+    ReadonlySingleton::new(storage, SETTLEMENT_KEY).may_load()
+}
+
+pub fn set_settlement<S: Storage>(storage: &mut S, settlement: &Settlement) -> StdResult<()> {
+    Singleton::new(storage, SETTLEMENT_KEY).save(settlement)
+}
+
+/// Underlying an account has claimed since settlement
+pub fn get_settlement_claimed<S: Storage>(storage: &S, account: &CanonicalAddr) -> StdResult<Uint128> {
+    Ok(ReadonlyBucket::new(SETTLEMENT_CLAIMED_PREFIX, storage).may_load(account.as_slice())?.unwrap_or_default())
+}
+
+pub fn set_settlement_claimed<S: Storage>(storage: &mut S, account: &CanonicalAddr, claimed: Uint128) -> StdResult<()> {
+    Bucket::new(SETTLEMENT_CLAIMED_PREFIX, storage).save(account.as_slice(), &claimed)
+}
+
+/// Part of the reserves set aside for insurance refunds
+pub fn get_insurance_pool<S: Storage>(storage: &S) -> StdResult<Uint128> {
+    Ok(ReadonlySingleton::new(storage, INSURANCE_POOL_KEY).may_load()?.unwrap_or_default())
+}
+
+pub fn set_insurance_pool<S: Storage>(storage: &mut S, pool: Uint128) -> StdResult<()> {
+    Singleton::new(storage, INSURANCE_POOL_KEY).save(&pool)
+}
+
+pub fn get_insurance_checkpoint<S: Storage>(storage: &S) -> StdResult<Option<PriceCheckpoint>> {
+    ReadonlySingleton::new(storage, INSURANCE_CHECKPOINT_KEY).may_load()
+}
+
+pub fn set_insurance_checkpoint<S: Storage>(storage: &mut S, checkpoint: &PriceCheckpoint) -> StdResult<()> {
+    Singleton::new(storage, INSURANCE_CHECKPOINT_KEY).save(checkpoint)
+}
+
+/// Get insured position of an account, None if it did not opt in
+pub fn get_insured_position<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<Option<InsuredPosition>> {
+    ReadonlyBucket::new(INSURED_PREFIX, store).may_load(owner.as_slice())
+}
+
+pub fn get_interest_deferral<S: Storage>(store: &S, owner: &CanonicalAddr) -> StdResult<Option<InterestDeferral>> {
+    ReadonlyBucket::new(INTEREST_DEFERRAL_PREFIX, store).may_load(owner.as_slice())
+}
+
+/// Set interest deferral of an account, None ends it
+pub fn set_interest_deferral<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    deferral: Option<&InterestDeferral>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(INTEREST_DEFERRAL_PREFIX, store);
+    match deferral {
+        Some(deferral) => bucket.save(owner.as_slice(), deferral),
+        None => {
+            bucket.remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
+
+/// Set insured position of an account, None opts it out
+pub fn set_insured_position<S: Storage>(
+    store: &mut S,
+    owner: &CanonicalAddr,
+    position: Option<&InsuredPosition>,
+) -> StdResult<()> {
+    let mut bucket = Bucket::new(INSURED_PREFIX, store);
+    match position {
+        Some(position) => bucket.save(owner.as_slice(), position),
+        None => {
+            bucket.remove(owner.as_slice());
+            Ok(())
+        }
+    }
+}
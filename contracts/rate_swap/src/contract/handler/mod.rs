@@ -0,0 +1,26 @@
+use cosmwasm_std::{Api, Empty, Env, Extern, HandleResponse, Querier, StdResult, Storage};
+
+use crate::msg::HandleMsg;
+
+mod swap;
+
+/// General handler for contract tx input
+/// tx inputs are defined HandleMsg enum in msg.rs file
+pub fn handle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: HandleMsg,
+) -> StdResult<HandleResponse<Empty>> {
+    match msg {
+        HandleMsg::Propose {
+            side,
+            notional,
+            fixed_rate,
+            duration,
+            taker_margin,
+        } => swap::try_propose(deps, env, side, notional, fixed_rate, duration, taker_margin),
+        HandleMsg::Accept { id } => swap::try_accept(deps, env, id),
+        HandleMsg::Cancel { id } => swap::try_cancel(deps, env, id),
+        HandleMsg::Settle { id } => swap::try_settle(deps, env, id),
+    }
+}
@@ -0,0 +1,405 @@
+use cosmwasm_bignumber::Uint256;
+use cosmwasm_std::{
+    log, to_binary, Api, BankMsg, Coin, CosmosMsg, Env, Extern, HandleResponse,
+    HumanAddr, Querier, QueryRequest, StdError, StdResult, Storage, Uint128, WasmQuery,
+};
+
+use crate::msg::{MarketAccrualStateResponse, MarketQueryMsg};
+use crate::state::{get_config, get_swap, next_swap_id, set_swap, Side, Swap, SwapStatus};
+
+/// Scale of the market's fixed point rates, 10^8
+const RATE_SCALE: u128 = 100_000_000;
+
+pub fn try_propose<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    side: Side,
+    notional: Uint128,
+    fixed_rate: Uint128,
+    duration: u64,
+    taker_margin: Uint128,
+) -> StdResult<HandleResponse> {
+    if notional.is_zero() || duration == 0 || taker_margin.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Notional, duration and taker margin must be non-zero: notional: {}, duration: {}, taker_margin: {}",
+            notional, duration, taker_margin
+        )));
+    }
+
+    let config = get_config(&deps.storage)?;
+    let maker_margin = sent_margin(&env, &config.denom)?;
+
+    let id = next_swap_id(&mut deps.storage)?;
+    let swap = Swap {
+        id,
+        maker: deps.api.canonical_address(&env.message.sender)?,
+        maker_side: side,
+        taker: None,
+        notional,
+        fixed_rate,
+        duration,
+        maker_margin,
+        taker_margin,
+        maturity: 0,
+        last_index: Uint128::zero(),
+        last_block: 0,
+        status: SwapStatus::Open,
+    };
+    set_swap(&mut deps.storage, &swap)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "propose"),
+            log("swap_id", id),
+            log("maker", env.message.sender.as_str()),
+            log("notional", notional),
+            log("fixed_rate", fixed_rate),
+            log("duration", duration),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_accept<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let mut swap = get_swap(&deps.storage, id)?;
+    if swap.status != SwapStatus::Open {
+        return Err(StdError::generic_err(format!("Swap is not open: swap_id: {}", id)));
+    }
+
+    let taker = deps.api.canonical_address(&env.message.sender)?;
+    if taker == swap.maker {
+        return Err(StdError::generic_err("Maker cannot take its own swap"));
+    }
+
+    let taker_margin = sent_margin(&env, &config.denom)?;
+    if taker_margin != swap.taker_margin {
+        return Err(StdError::generic_err(format!(
+            "Taker margin mismatch: sent: {}, required: {}",
+            taker_margin, swap.taker_margin
+        )));
+    }
+
+    let index = query_borrow_index(deps, &config.market, env.block.height)?;
+    swap.taker = Some(taker);
+    swap.status = SwapStatus::Active;
+    swap.last_index = index.borrow_index;
+    swap.last_block = env.block.height;
+    swap.maturity = env.block.height + swap.duration;
+    set_swap(&mut deps.storage, &swap)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "accept"),
+            log("swap_id", id),
+            log("taker", env.message.sender.as_str()),
+            log("start_index", index.borrow_index),
+            log("maturity", swap.maturity),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_cancel<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let mut swap = get_swap(&deps.storage, id)?;
+    if swap.status != SwapStatus::Open {
+        return Err(StdError::generic_err(format!("Swap is not open: swap_id: {}", id)));
+    }
+    if deps.api.canonical_address(&env.message.sender)? != swap.maker {
+        return Err(StdError::unauthorized());
+    }
+
+    swap.status = SwapStatus::Cancelled;
+    let refund = swap.maker_margin;
+    swap.maker_margin = Uint128::zero();
+    set_swap(&mut deps.storage, &swap)?;
+
+    let res = HandleResponse {
+        messages: vec![payout(&env, &env.message.sender, &config.denom, refund)],
+        log: vec![
+            log("action", "cancel"),
+            log("swap_id", id),
+            log("refund", refund),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn try_settle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let config = get_config(&deps.storage)?;
+    let mut swap = get_swap(&deps.storage, id)?;
+    if swap.status != SwapStatus::Active {
+        return Err(StdError::generic_err(format!("Swap is not active: swap_id: {}", id)));
+    }
+
+    let current_block = env.block.height;
+    let settle_block = current_block.min(swap.maturity);
+    if current_block < swap.maturity && settle_block < swap.last_block + config.settlement_interval {
+        return Err(StdError::generic_err(format!(
+            "Settlement interval has not passed: last_block: {}, settlement_interval: {}",
+            swap.last_block, config.settlement_interval
+        )));
+    }
+
+    // Floating leg follows the market borrow index between the two checkpoints,
+    // fixed leg accrues linearly per block like the market rates
+    let index = query_borrow_index(deps, &config.market, settle_block)?;
+    let elapsed = settle_block - swap.last_block;
+    let fixed_leg = mul_div(
+        Uint256::from(swap.notional),
+        Uint256::from(swap.fixed_rate) * Uint256::from(elapsed),
+        Uint256::from(RATE_SCALE),
+    );
+    let floating_leg = if index.borrow_index > swap.last_index && !swap.last_index.is_zero() {
+        mul_div(
+            Uint256::from(swap.notional),
+            Uint256::from(index.borrow_index.u128() - swap.last_index.u128()),
+            Uint256::from(swap.last_index),
+        )
+    } else {
+        0
+    };
+
+    // Net amount moves from the paying side's margin to the other side's margin
+    let fixed_payer_is_maker = swap.maker_side == Side::PayFixed;
+    let maker_pays = (fixed_leg > floating_leg) == fixed_payer_is_maker;
+    let net = fixed_leg.abs_diff(floating_leg);
+    let (payer_margin, receiver_margin) = if maker_pays {
+        (&mut swap.maker_margin, &mut swap.taker_margin)
+    } else {
+        (&mut swap.taker_margin, &mut swap.maker_margin)
+    };
+    let transferred = net.min(payer_margin.u128());
+    *payer_margin = Uint128::from(payer_margin.u128() - transferred);
+    *receiver_margin += Uint128::from(transferred);
+    let payer_exhausted = payer_margin.is_zero();
+
+    swap.last_index = index.borrow_index;
+    swap.last_block = settle_block;
+
+    // Close at maturity, or early once one side can no longer pay
+    let mut messages = vec![];
+    if settle_block >= swap.maturity || payer_exhausted {
+        swap.status = SwapStatus::Closed;
+        let maker = deps.api.human_address(&swap.maker)?;
+        let taker = match &swap.taker {
+            Some(taker) => deps.api.human_address(taker)?,
+            None => return Err(StdError::generic_err("Active swap has no taker")),
+        };
+        if !swap.maker_margin.is_zero() {
+            messages.push(payout(&env, &maker, &config.denom, swap.maker_margin));
+        }
+        if !swap.taker_margin.is_zero() {
+            messages.push(payout(&env, &taker, &config.denom, swap.taker_margin));
+        }
+        swap.maker_margin = Uint128::zero();
+        swap.taker_margin = Uint128::zero();
+    }
+    set_swap(&mut deps.storage, &swap)?;
+
+    let res = HandleResponse {
+        messages,
+        log: vec![
+            log("action", "settle"),
+            log("swap_id", id),
+            log("settle_block", settle_block),
+            log("borrow_index", index.borrow_index),
+            log("fixed_leg", fixed_leg),
+            log("floating_leg", floating_leg),
+            log("net_to_maker", if maker_pays { 0 } else { transferred }),
+            log("net_to_taker", if maker_pays { transferred } else { 0 }),
+            log("closed", swap.status == SwapStatus::Closed),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+// The stored index only moves when the market accrues, so it is projected to the
+// checkpoint block instead of read as last accrued
+fn query_borrow_index<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    market: &HumanAddr,
+    block_height: u64,
+) -> StdResult<MarketAccrualStateResponse> {
+    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: market.clone(),
+        msg: to_binary(&MarketQueryMsg::AccrualState { block_height })?,
+    }))
+}
+
+// Margin must be posted as a single non-zero coin of the settlement denom
+fn sent_margin(env: &Env, denom: &str) -> StdResult<Uint128> {
+    match env.message.sent_funds.as_slice() {
+        [coin] if coin.denom == denom && !coin.amount.is_zero() => Ok(coin.amount),
+        _ => Err(StdError::generic_err(format!(
+            "Margin must be sent as a single non-zero {} coin",
+            denom
+        ))),
+    }
+}
+
+fn payout(env: &Env, recipient: &HumanAddr, denom: &str, amount: Uint128) -> CosmosMsg {
+    CosmosMsg::Bank(BankMsg::Send {
+        from_address: env.contract.address.clone(),
+        to_address: recipient.clone(),
+        amount: vec![Coin {
+            denom: denom.to_string(),
+            amount,
+        }],
+    })
+}
+
+// a * b / c with 256 bit intermediates
+fn mul_div(a: Uint256, b: Uint256, c: Uint256) -> u128 {
+    a.multiply_ratio(b.0, c.0).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockStorage};
+    use cosmwasm_std::{coins, from_binary, from_slice, Empty, QuerierResult, SystemError};
+
+    use crate::contract::{handle, init};
+    use crate::msg::{HandleMsg, InitMsg};
+
+    /// Market answering AccrualState from a table of borrow indices by block
+    struct IndexQuerier {
+        indices: HashMap<u64, Uint128>,
+    }
+
+    impl Querier for IndexQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            let block_height = match request {
+                QueryRequest::Wasm(WasmQuery::Smart { msg, .. }) => match from_binary(&msg).unwrap() {
+                    MarketQueryMsg::AccrualState { block_height } => block_height,
+                },
+                _ => return Err(SystemError::UnsupportedRequest { kind: "non-wasm".to_string() }),
+            };
+            let borrow_index = self.indices[&block_height];
+            Ok(to_binary(&MarketAccrualStateResponse { last_accrual_block: block_height, borrow_index }))
+        }
+    }
+
+    fn env_at(sender: &str, height: u64, funds: &[Coin]) -> Env {
+        let mut env = mock_env(sender, funds);
+        env.block.height = height;
+        env
+    }
+
+    /// Swap 1 of 1_000_000 paying 10 / 10^8 per block fixed for 200 blocks, accepted at 20_000
+    /// The index rises 0.002% over the first 100 blocks and stays flat after
+    fn active_swap(taker_margin: u128) -> Extern<MockStorage, MockApi, IndexQuerier> {
+        let deps = mock_dependencies(20, &[]);
+        let mut indices = HashMap::new();
+        indices.insert(20_000, Uint128::from(100_000_000u128));
+        indices.insert(20_100, Uint128::from(100_002_000u128));
+        indices.insert(20_200, Uint128::from(100_002_000u128));
+        let mut deps = Extern { storage: deps.storage, api: deps.api, querier: IndexQuerier { indices } };
+        init(&mut deps, mock_env("admin", &[]), InitMsg {
+            market: HumanAddr::from("market"),
+            denom: "uusd".to_string(),
+            settlement_interval: 100,
+        })
+        .unwrap();
+        handle(&mut deps, env_at("maker", 20_000, &coins(100, "uusd")), HandleMsg::Propose {
+            side: Side::PayFixed,
+            notional: Uint128::from(1_000_000u128),
+            fixed_rate: Uint128::from(10u128),
+            duration: 200,
+            taker_margin: Uint128::from(taker_margin),
+        })
+        .unwrap();
+        handle(&mut deps, env_at("taker", 20_000, &coins(taker_margin, "uusd")), HandleMsg::Accept { id: 1 }).unwrap();
+        deps
+    }
+
+    #[test]
+    fn settlement_nets_the_legs_and_pays_out_at_maturity() {
+        let mut deps = active_swap(50);
+        // Floating 20 against fixed 10, the taker pays the maker the difference
+        let res = handle(&mut deps, env_at("keeper", 20_100, &[]), HandleMsg::Settle { id: 1 }).unwrap();
+        assert!(res.log.contains(&log("fixed_leg", 10)));
+        assert!(res.log.contains(&log("floating_leg", 20)));
+        assert!(res.log.contains(&log("net_to_maker", 10)));
+        let swap = get_swap(&deps.storage, 1).unwrap();
+        assert_eq!((swap.maker_margin, swap.taker_margin), (Uint128::from(110u128), Uint128::from(40u128)));
+
+        assert_eq!(
+            handle(&mut deps, env_at("keeper", 20_150, &[]), HandleMsg::Settle { id: 1 }).unwrap_err(),
+            StdError::generic_err("Settlement interval has not passed: last_block: 20100, settlement_interval: 100")
+        );
+        // Settled up to maturity only, with a flat index the maker owes the fixed leg
+        let res = handle(&mut deps, env_at("keeper", 20_300, &[]), HandleMsg::Settle { id: 1 }).unwrap();
+        assert!(res.log.contains(&log("settle_block", 20_200)));
+        assert!(res.log.contains(&log("net_to_taker", 10)));
+        assert_eq!(res.messages, vec![
+            payout(&env_at("keeper", 20_300, &[]), &HumanAddr::from("maker"), "uusd", Uint128::from(100u128)),
+            payout(&env_at("keeper", 20_300, &[]), &HumanAddr::from("taker"), "uusd", Uint128::from(50u128)),
+        ]);
+        assert_eq!(get_swap(&deps.storage, 1).unwrap().status, SwapStatus::Closed);
+    }
+
+    #[test]
+    fn exhausted_margin_closes_the_swap_early() {
+        let mut deps = active_swap(5);
+        let res = handle(&mut deps, env_at("keeper", 20_100, &[]), HandleMsg::Settle { id: 1 }).unwrap();
+        assert!(res.log.contains(&log("net_to_maker", 5)));
+        assert!(res.log.contains(&log("closed", true)));
+        assert_eq!(res.messages, vec![payout(
+            &env_at("keeper", 20_100, &[]),
+            &HumanAddr::from("maker"),
+            "uusd",
+            Uint128::from(105u128)
+        )]);
+        assert!(handle(&mut deps, env_at("keeper", 20_200, &[]), HandleMsg::Settle { id: 1 }).is_err());
+    }
+
+    #[test]
+    fn only_the_exact_taker_margin_accepts() {
+        let mut deps = active_swap(50);
+        handle(&mut deps, env_at("maker", 20_000, &coins(100, "uusd")), HandleMsg::Propose {
+            side: Side::PayFloating,
+            notional: Uint128::from(1_000u128),
+            fixed_rate: Uint128::from(10u128),
+            duration: 200,
+            taker_margin: Uint128::from(50u128),
+        })
+        .unwrap();
+        assert_eq!(
+            handle(&mut deps, env_at("taker", 20_000, &coins(49, "uusd")), HandleMsg::Accept { id: 2 }).unwrap_err(),
+            StdError::generic_err("Taker margin mismatch: sent: 49, required: 50")
+        );
+        assert_eq!(
+            handle(&mut deps, env_at("maker", 20_000, &coins(50, "uusd")), HandleMsg::Accept { id: 2 }).unwrap_err(),
+            StdError::generic_err("Maker cannot take its own swap")
+        );
+        assert_eq!(
+            handle(&mut deps, env_at("taker", 20_000, &[]), HandleMsg::Cancel { id: 2 }).unwrap_err(),
+            StdError::unauthorized()
+        );
+        handle(&mut deps, env_at("maker", 20_000, &[]), HandleMsg::Cancel { id: 2 }).unwrap();
+    }
+}
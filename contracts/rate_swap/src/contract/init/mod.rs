@@ -0,0 +1,25 @@
+use cosmwasm_std::{Api, Env, Extern, InitResponse, Querier, StdError, StdResult, Storage};
+
+use crate::msg::InitMsg;
+use crate::state::{set_config, Config};
+
+/// Contract instantiation tx
+/// tx inputs are specified in InitMsg in msg.rs file
+pub fn init<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    msg: InitMsg,
+) -> StdResult<InitResponse> {
+    if msg.settlement_interval == 0 {
+        return Err(StdError::generic_err("Settlement interval must be at least one block"));
+    }
+
+    let init_config = Config {
+        market: msg.market,
+        denom: msg.denom,
+        settlement_interval: msg.settlement_interval,
+    };
+    set_config(&mut deps.storage, &init_config)?;
+
+    Ok(InitResponse::default())
+}
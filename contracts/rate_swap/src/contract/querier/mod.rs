@@ -0,0 +1,44 @@
+use cosmwasm_std::{to_binary, Api, Binary, Extern, Querier, StdResult, Storage};
+
+use crate::msg::{ConfigResponse, QueryMsg, SwapResponse};
+use crate::state::{get_config, get_swap};
+
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => {
+            let config = get_config(&deps.storage)?;
+            let out = to_binary(&ConfigResponse {
+                market: config.market,
+                denom: config.denom,
+                settlement_interval: config.settlement_interval,
+            })?;
+            Ok(out)
+        }
+        QueryMsg::Swap { id } => {
+            let swap = get_swap(&deps.storage, id)?;
+            let taker = match swap.taker {
+                Some(taker) => Some(deps.api.human_address(&taker)?),
+                None => None,
+            };
+            let out = to_binary(&SwapResponse {
+                id: swap.id,
+                maker: deps.api.human_address(&swap.maker)?,
+                maker_side: swap.maker_side,
+                taker,
+                notional: swap.notional,
+                fixed_rate: swap.fixed_rate,
+                duration: swap.duration,
+                maker_margin: swap.maker_margin,
+                taker_margin: swap.taker_margin,
+                maturity: swap.maturity,
+                last_index: swap.last_index,
+                last_block: swap.last_block,
+                status: swap.status,
+            })?;
+            Ok(out)
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{HumanAddr, Uint128};
+
+use crate::state::{Side, SwapStatus};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InitMsg {
+    /// q_native market whose borrow index is the floating leg
+    pub market: HumanAddr,
+    /// Native denom margins are posted and settled in
+    pub denom: String,
+    /// Minimum number of blocks between two settlements of the same swap
+    pub settlement_interval: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleMsg {
+    /// Offer a swap, the attached funds are the maker margin
+    Propose {
+        side: Side,
+        notional: Uint128,
+        fixed_rate: Uint128,
+        duration: u64,
+        taker_margin: Uint128,
+    },
+    /// Take an open offer, the attached funds must equal its taker margin
+    Accept {
+        id: u64,
+    },
+    /// Withdraw an offer nobody has accepted yet
+    Cancel {
+        id: u64,
+    },
+    /// Exchange the cashflows accrued since the last settlement, callable by anyone
+    Settle {
+        id: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Config {},
+    Swap {
+        id: u64,
+    },
+}
+
+/// Query understood by the q_native market
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketQueryMsg {
+    /// Market state as if accrued at `block_height`
+    AccrualState {
+        block_height: u64,
+    },
+}
+
+/// Fields of the market's accrual state the swaps settle against
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MarketAccrualStateResponse {
+    pub last_accrual_block: u64,
+    pub borrow_index: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub market: HumanAddr,
+    pub denom: String,
+    pub settlement_interval: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SwapResponse {
+    pub id: u64,
+    pub maker: HumanAddr,
+    pub maker_side: Side,
+    pub taker: Option<HumanAddr>,
+    pub notional: Uint128,
+    pub fixed_rate: Uint128,
+    pub duration: u64,
+    pub maker_margin: Uint128,
+    pub taker_margin: Uint128,
+    pub maturity: u64,
+    pub last_index: Uint128,
+    pub last_block: u64,
+    pub status: SwapStatus,
+}
@@ -0,0 +1,81 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{CanonicalAddr, HumanAddr, StdResult, Storage, Uint128};
+use cosmwasm_storage::{Bucket, ReadonlyBucket, ReadonlySingleton, Singleton};
+
+pub static CONFIG_KEY: &[u8] = b"config";
+pub static SWAP_COUNT_KEY: &[u8] = b"swap_count";
+pub static SWAP_PREFIX: &[u8] = b"swap";
+
+/// Config struct
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub market: HumanAddr,
+    pub denom: String,
+    pub settlement_interval: u64,
+}
+
+/// Leg paid by a party
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    PayFixed,
+    PayFloating,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapStatus {
+    Open,
+    Active,
+    Closed,
+    Cancelled,
+}
+
+/// Swap struct
+/// `fixed_rate` is per block and scaled by 10^8 like the market rates,
+/// margins move between the parties at every settlement
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Swap {
+    pub id: u64,
+    pub maker: CanonicalAddr,
+    pub maker_side: Side,
+    pub taker: Option<CanonicalAddr>,
+    pub notional: Uint128,
+    pub fixed_rate: Uint128,
+    pub duration: u64,
+    pub maker_margin: Uint128,
+    pub taker_margin: Uint128,
+    pub maturity: u64,
+    pub last_index: Uint128,
+    pub last_block: u64,
+    pub status: SwapStatus,
+}
+
+/// Get config
+pub fn get_config<S: Storage>(storage: &S) -> StdResult<Config> {
+    ReadonlySingleton::new(storage, CONFIG_KEY).load()
+}
+
+/// Set config
+pub fn set_config<S: Storage>(storage: &mut S, config: &Config) -> StdResult<()> {
+    Singleton::new(storage, CONFIG_KEY).save(config)
+}
+
+/// Reserve the next swap id
+pub fn next_swap_id<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    let id: u64 = ReadonlySingleton::new(storage, SWAP_COUNT_KEY).may_load()?.unwrap_or(0) + 1;
+    Singleton::new(storage, SWAP_COUNT_KEY).save(&id)?;
+    Ok(id)
+}
+
+/// Get swap by id
+pub fn get_swap<S: Storage>(storage: &S, id: u64) -> StdResult<Swap> {
+    ReadonlyBucket::new(SWAP_PREFIX, storage).load(&id.to_be_bytes())
+}
+
+/// Set swap
+pub fn set_swap<S: Storage>(storage: &mut S, swap: &Swap) -> StdResult<()> {
+    Bucket::new(SWAP_PREFIX, storage).save(&swap.id.to_be_bytes(), swap)
+}
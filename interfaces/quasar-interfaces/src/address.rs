@@ -0,0 +1,18 @@
+use cosmwasm_std::{Api, CanonicalAddr, HumanAddr, StdError, StdResult};
+
+/// Canonical form of a user supplied address, the only key balances, allowances
+/// and borrows are stored under
+/// Fails unless the address humanizes back to exactly the given string, so an
+/// alternate spelling such as an upper case bech32 address cannot reach a key
+/// whose queries and events show a different address
+pub fn canonicalize_address<A: Api>(api: &A, address: &HumanAddr) -> StdResult<CanonicalAddr> {
+    let canonical = api.canonical_address(address)?;
+    let normalized = api.human_address(&canonical)?;
+    if &normalized != address {
+        return Err(StdError::generic_err(format!(
+            "Address is not in normalized form: address: {}, normalized: {}",
+            address, normalized
+        )));
+    }
+    Ok(canonical)
+}
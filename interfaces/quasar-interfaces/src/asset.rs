@@ -0,0 +1,50 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Api, CanonicalAddr, HumanAddr, StdResult, Uint128};
+
+use crate::address::canonicalize_address;
+
+/// Native bank denom or CW20 token, in the terraswap layout
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    Token { contract_addr: HumanAddr },
+    NativeToken { denom: String },
+}
+
+impl AssetInfo {
+    pub fn to_raw<A: Api>(&self, api: &A) -> StdResult<AssetInfoRaw> {
+        match self {
+            AssetInfo::Token { contract_addr } => Ok(AssetInfoRaw::Token {
+                contract_addr: canonicalize_address(api, contract_addr)?,
+            }),
+            AssetInfo::NativeToken { denom } => Ok(AssetInfoRaw::NativeToken { denom: denom.clone() }),
+        }
+    }
+}
+
+/// AssetInfo as stored
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfoRaw {
+    Token { contract_addr: CanonicalAddr },
+    NativeToken { denom: String },
+}
+
+impl AssetInfoRaw {
+    pub fn to_normal<A: Api>(&self, api: &A) -> StdResult<AssetInfo> {
+        match self {
+            AssetInfoRaw::Token { contract_addr } => Ok(AssetInfo::Token {
+                contract_addr: api.human_address(contract_addr)?,
+            }),
+            AssetInfoRaw::NativeToken { denom } => Ok(AssetInfo::NativeToken { denom: denom.clone() }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
@@ -0,0 +1,74 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_bignumber::Uint256;
+use cosmwasm_std::{to_binary, CosmosMsg, HumanAddr, StdResult, Uint128, WasmMsg};
+
+/// Market operations reported to the comptroller once the market applied them
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketAction {
+    Mint,
+    Redeem,
+    Borrow,
+    RepayBorrow,
+    /// qTokens left the account by transfer, send, burn, term lock or OTC listing
+    Transfer,
+    /// qTokens were seized from the account by a liquidation
+    Seize,
+}
+
+/// Execute message a market sends to its comptroller
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComptrollerHookMsg {
+    /// Runs after the market handler in the same tx, an error reverts the operation.
+    /// `amount` is in qTokens for redemptions, transfers and seizes and in the underlying otherwise
+    MarketHook {
+        action: MarketAction,
+        account: HumanAddr,
+        amount: Uint128,
+    },
+    /// Sent after the market applied a liquidation repaying `repay_amount` of its underlying,
+    /// for the comptroller to have `collateral_market` seize the borrower's qTokens
+    SeizeCollateral {
+        collateral_market: HumanAddr,
+        borrower: HumanAddr,
+        liquidator: HumanAddr,
+        repay_amount: Uint128,
+    },
+}
+
+impl ComptrollerHookMsg {
+    pub fn into_cosmos_msg(self, comptroller: HumanAddr) -> StdResult<CosmosMsg> {
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: comptroller,
+            msg: to_binary(&self)?,
+            send: vec![],
+        }))
+    }
+}
+
+/// Queries a comptroller answers for its markets
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ComptrollerQueryMsg {
+    /// Borrow capacity left, or debt over the limit, across all entered markets
+    AccountLiquidity { address: HumanAddr },
+    /// Shortfall liquidations are measured against at `block`, collateral factor cuts
+    /// still in their grace period count at the factor before the cut
+    LiquidationShortfall { address: HumanAddr, block: u64 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountLiquidityResponse {
+    pub quote: String,
+    pub liquidity: Uint256,
+    pub shortfall: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidationShortfallResponse {
+    pub quote: String,
+    pub shortfall: Uint256,
+}
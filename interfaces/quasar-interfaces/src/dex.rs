@@ -0,0 +1,65 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    to_binary, Coin, CosmosMsg, Decimal, HumanAddr, Querier, QueryRequest, StdResult, Uint128, WasmMsg,
+    WasmQuery,
+};
+
+use crate::asset::Asset;
+use crate::oracle::PairQueryMsg;
+
+/// Terraswap pair execute messages, sent to the pair swapping an asset for a market
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairHandleMsg {
+    /// Native offer assets are attached, the ask asset goes to `to` or the sender
+    Swap {
+        offer_asset: Asset,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<HumanAddr>,
+    },
+}
+
+impl PairHandleMsg {
+    pub fn into_cosmos_msg(self, pair: HumanAddr, send: Vec<Coin>) -> StdResult<CosmosMsg> {
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: pair,
+            msg: to_binary(&self)?,
+            send,
+        }))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    pub return_amount: Uint128,
+    pub spread_amount: Uint128,
+    pub commission_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReverseSimulationResponse {
+    pub offer_amount: Uint128,
+    pub spread_amount: Uint128,
+    pub commission_amount: Uint128,
+}
+
+/// Ask amount `pair` returns for `offer_asset` at its current reserves
+pub fn query_simulation<Q: Querier>(querier: &Q, pair: HumanAddr, offer_asset: Asset) -> StdResult<Uint128> {
+    let res: SimulationResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair,
+        msg: to_binary(&PairQueryMsg::Simulation { offer_asset })?,
+    }))?;
+    Ok(res.return_amount)
+}
+
+/// Offer amount `pair` needs to return `ask_asset` at its current reserves
+pub fn query_reverse_simulation<Q: Querier>(querier: &Q, pair: HumanAddr, ask_asset: Asset) -> StdResult<Uint128> {
+    let res: ReverseSimulationResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair,
+        msg: to_binary(&PairQueryMsg::ReverseSimulation { ask_asset })?,
+    }))?;
+    Ok(res.offer_amount)
+}
@@ -0,0 +1,8 @@
+pub mod address;
+pub mod asset;
+pub mod comptroller;
+pub mod dex;
+pub mod market;
+pub mod oracle;
+pub mod pagination;
+pub mod strategy;
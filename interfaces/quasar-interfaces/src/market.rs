@@ -0,0 +1,101 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{to_binary, CosmosMsg, HumanAddr, StdResult, Uint128, WasmMsg};
+
+/// Execute messages q_native markets accept from their comptroller
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketHandleMsg {
+    /// Cache the protocol-wide pause, updates with an epoch not above the cached one are ignored
+    SyncGlobalPause { paused: bool, epoch: u64 },
+    /// Seize qTokens worth `repay_value` of the market's underlying plus its liquidation
+    /// incentive from `borrower` for a liquidation repaid in another market
+    SeizeCollateral {
+        borrower: HumanAddr,
+        liquidator: HumanAddr,
+        repay_value: Uint128,
+    },
+}
+
+impl MarketHandleMsg {
+    pub fn into_cosmos_msg(self, market: HumanAddr) -> StdResult<CosmosMsg> {
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: market,
+            msg: to_binary(&self)?,
+            send: vec![],
+        }))
+    }
+}
+
+/// Execute message a market sends to its registered balance hook, once per account
+/// whose qToken balance changed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceHookMsg {
+    /// Runs after the market handler in the same tx, an error reverts the operation
+    BalanceChanged {
+        account: HumanAddr,
+        balance: Uint128,
+        total_supply: Uint128,
+    },
+}
+
+impl BalanceHookMsg {
+    pub fn into_cosmos_msg(self, hook: HumanAddr) -> StdResult<CosmosMsg> {
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: hook,
+            msg: to_binary(&self)?,
+            send: vec![],
+        }))
+    }
+}
+
+/// Query a market sends to its transfer policy before qTokens reach an account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferPolicyQueryMsg {
+    /// `from` is None for newly minted qTokens
+    CheckTransfer {
+        from: Option<HumanAddr>,
+        to: HumanAddr,
+        amount: Uint128,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransferPolicyResponse {
+    pub allowed: bool,
+    /// Shown to the sender when the transfer is refused
+    pub reason: Option<String>,
+}
+
+/// Queries q_native markets answer for other contracts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketQueryMsg {
+    AccountSnapshot { address: HumanAddr },
+    MarketTotals {},
+}
+
+/// Position of an account in one market, valued at the last accrual
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountSnapshotResponse {
+    /// qToken balance
+    pub balance: Uint128,
+    /// Debt in the underlying
+    pub borrow_balance: Uint128,
+    /// Underlying per qToken, scaled by 10^8
+    pub exchange_rate: Uint128,
+    /// Market-wide debt, checked against borrow caps
+    pub total_borrows: Uint128,
+}
+
+/// Market-wide balances in the underlying, as of the last accrual
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct MarketTotalsResponse {
+    /// Underlying held, including cash deployed to a cash strategy
+    pub cash: Uint128,
+    pub total_borrows: Uint128,
+    pub total_reserves: Uint128,
+}
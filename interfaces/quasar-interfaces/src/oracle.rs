@@ -0,0 +1,153 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_bignumber::{Decimal256, Uint256};
+use cosmwasm_std::{to_binary, HumanAddr, Querier, QueryRequest, StdError, StdResult, Uint128, WasmQuery};
+
+use crate::asset::Asset;
+
+/// Price feed flavours markets can read from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleKind {
+    /// TeFi oracle hub, `price { base, quote }`
+    TeFi,
+    /// Band std reference, `get_reference_data { base_symbol, quote_symbol }`
+    Band,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TeFiQueryMsg {
+    Price { base: String, quote: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TeFiPriceResponse {
+    pub rate: Decimal256,
+    pub last_updated_base: u64,
+    pub last_updated_quote: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BandQueryMsg {
+    GetReferenceData {
+        base_symbol: String,
+        quote_symbol: String,
+    },
+}
+
+const BAND_RATE_SCALE: u128 = 1_000_000_000_000_000_000;
+
+/// Band rates are scaled by 10^18
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BandReferenceData {
+    pub rate: Uint128,
+    pub last_updated_base: u64,
+    pub last_updated_quote: u64,
+}
+
+/// Price of one unit of `base` in `quote` with its last update time
+/// Updated at is the older of the two legs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OraclePrice {
+    pub rate: Decimal256,
+    pub last_updated: u64,
+}
+
+pub fn query_price<Q: Querier>(
+    querier: &Q,
+    oracle: HumanAddr,
+    kind: &OracleKind,
+    base: String,
+    quote: String,
+) -> StdResult<OraclePrice> {
+    match kind {
+        OracleKind::TeFi => {
+            let res: TeFiPriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: oracle,
+                msg: to_binary(&TeFiQueryMsg::Price { base, quote })?,
+            }))?;
+            Ok(OraclePrice {
+                rate: res.rate,
+                last_updated: res.last_updated_base.min(res.last_updated_quote),
+            })
+        }
+        OracleKind::Band => {
+            let res: BandReferenceData = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: oracle,
+                msg: to_binary(&BandQueryMsg::GetReferenceData {
+                    base_symbol: base,
+                    quote_symbol: quote,
+                })?,
+            }))?;
+            Ok(OraclePrice {
+                rate: Decimal256::from_ratio(Uint256::from(res.rate).0, Uint256::from(BAND_RATE_SCALE).0),
+                last_updated: res.last_updated_base.min(res.last_updated_quote),
+            })
+        }
+    }
+}
+
+/// Terraswap pair queries, for the pool's reserves and swap quotes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairQueryMsg {
+    Pool {},
+    /// Ask amount returned for swapping `offer_asset`
+    Simulation { offer_asset: Asset },
+    /// Offer amount needed to receive `ask_asset`
+    ReverseSimulation { ask_asset: Asset },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolResponse {
+    pub assets: [Asset; 2],
+    pub total_share: Uint128,
+}
+
+/// Price of one LP share of `pair` in `quote`, `symbols` name the pool assets in pool order.
+/// Uses the fair reserves 2 * sqrt(r0 * p0 * r1 * p1) instead of the spot reserves,
+/// so skewing the pool within a block cannot inflate the share price
+pub fn query_lp_price<Q: Querier>(
+    querier: &Q,
+    oracle: HumanAddr,
+    kind: &OracleKind,
+    pair: HumanAddr,
+    symbols: &[String; 2],
+    quote: String,
+) -> StdResult<OraclePrice> {
+    let pool: PoolResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair.clone(),
+        msg: to_binary(&PairQueryMsg::Pool {})?,
+    }))?;
+    if pool.total_share.is_zero() {
+        return Err(StdError::generic_err(format!("LP pool has no shares: pair: {}", pair)));
+    }
+
+    let price_0 = query_price(querier, oracle.clone(), kind, symbols[0].clone(), quote.clone())?;
+    let price_1 = query_price(querier, oracle, kind, symbols[1].clone(), quote)?;
+    let value_0 = Uint256::from(pool.assets[0].amount) * price_0.rate;
+    let value_1 = Uint256::from(pool.assets[1].amount) * price_1.rate;
+    let fair_value = Uint256::from(2u64) * isqrt(value_0 * value_1);
+
+    Ok(OraclePrice {
+        rate: Decimal256::from_ratio(fair_value.0, Uint256::from(pool.total_share).0),
+        last_updated: price_0.last_updated.min(price_1.last_updated),
+    })
+}
+
+/// Integer square root rounded down, Newton's method
+fn isqrt(n: Uint256) -> Uint256 {
+    if n.is_zero() {
+        return n;
+    }
+    let mut x = n.0;
+    let mut y = (x + 1u64.into()) >> 1;
+    while y < x {
+        x = y;
+        y = (x + n.0 / x) >> 1;
+    }
+    Uint256::from(x)
+}
@@ -0,0 +1,65 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Page size used when a request does not specify a limit
+pub const DEFAULT_LIMIT: u32 = 10;
+/// Largest page size any list query returns
+pub const MAX_LIMIT: u32 = 30;
+
+/// Pagination parameters accepted by every list query
+/// `start_after` is exclusive and uses the same key format as `PageResponse::next_key`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PageRequest {
+    pub start_after: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl PageRequest {
+    /// Page size clamped to MAX_LIMIT
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize
+    }
+}
+
+/// Pagination metadata returned alongside every list response
+/// `next_key` is None on the last page, `total` is a hint and may be omitted
+/// when counting would require a full scan
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PageResponse {
+    pub next_key: Option<String>,
+    pub total: Option<u64>,
+}
+
+/// Collects one page from an iterator of (key, item) pairs already positioned
+/// after `start_after`, setting `next_key` only when more items remain
+pub fn paginate<T, I>(items: I, limit: usize, total: Option<u64>) -> (Vec<T>, PageResponse)
+where
+    I: Iterator<Item = (String, T)>,
+{
+    let mut items = items.peekable();
+    let mut page = Vec::with_capacity(limit);
+    let mut last_key = None;
+    while page.len() < limit {
+        match items.next() {
+            Some((key, item)) => {
+                last_key = Some(key);
+                page.push(item);
+            }
+            None => break,
+        }
+    }
+    let next_key = match items.peek() {
+        Some(_) => last_key,
+        None => None,
+    };
+    (page, PageResponse { next_key, total })
+}
+
+/// Exclusive range start for raw storage keys
+pub fn calc_range_start(start_after: Option<&[u8]>) -> Option<Vec<u8>> {
+    start_after.map(|key| {
+        let mut start = key.to_vec();
+        start.push(0);
+        start
+    })
+}
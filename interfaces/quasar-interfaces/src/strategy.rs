@@ -0,0 +1,25 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{to_binary, Coin, CosmosMsg, HumanAddr, StdResult, Uint128, WasmMsg};
+
+/// Execute messages a market sends to the strategy holding its warm cash
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyHandleMsg {
+    /// Deploy the attached funds
+    Deposit {},
+    /// Send `amount` back to the calling market before returning, failing reverts
+    /// the market operation that needed it
+    Withdraw { amount: Uint128 },
+}
+
+impl StrategyHandleMsg {
+    pub fn into_cosmos_msg(self, strategy: HumanAddr, send: Vec<Coin>) -> StdResult<CosmosMsg> {
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: strategy,
+            msg: to_binary(&self)?,
+            send,
+        }))
+    }
+}
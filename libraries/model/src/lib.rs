@@ -0,0 +1,14 @@
+//! Reference model of a q_native market in plain integers
+//!
+//! The model keeps no storage and sends no messages, so random sequences of
+//! actions can be applied to it and to the contract and the two states compared
+//! after every step. A rejected action leaves the model unchanged, like a
+//! reverted transaction leaves the contract unchanged
+
+pub mod market;
+pub mod math;
+pub mod rate_model;
+
+pub use crate::market::{BorrowSnapshot, Market, ModelError, ModelResult, Params};
+pub use crate::math::{mul_div, truncate, SCALE};
+pub use crate::rate_model::{utilization_rate, RateModel};
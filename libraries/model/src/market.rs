@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+
+use crate::math::{mul_div, truncate, SCALE};
+use crate::rate_model::{utilization_rate, RateModel};
+
+/// Market parameters fixed for a run, scaled by 10^8 like the market's config
+#[derive(Clone, Debug, PartialEq)]
+pub struct Params {
+    /// Underlying per qToken while no qTokens exist
+    pub initial_exchange_rate: u128,
+    pub reserve_factor: u128,
+    pub collateral_factor: u128,
+    pub max_borrow_rate: u128,
+    pub rate_model: RateModel,
+}
+
+/// Debt of an account as of the borrow index it was last touched at
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BorrowSnapshot {
+    pub principal: u128,
+    pub interest_index: u128,
+}
+
+impl BorrowSnapshot {
+    /// Debt grown to `borrow_index`
+    pub fn balance(&self, borrow_index: u128) -> u128 {
+        match self.principal {
+            0 => 0,
+            principal => mul_div(principal, borrow_index, self.interest_index),
+        }
+    }
+}
+
+/// Reasons the market rejects an action
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelError {
+    /// The rate model asks for more than max_borrow_rate
+    BorrowRateTooHigh,
+    /// Reserves exceed cash plus borrows
+    NegativeExchangeRate,
+    ZeroAmount,
+    InsufficientCash,
+    InsufficientBalance,
+    InsufficientCollateral,
+    NothingToRepay,
+}
+
+pub type ModelResult<T> = Result<T, ModelError>;
+
+/// A standalone market, every account collateralized by the qTokens it holds here
+#[derive(Clone, Debug, PartialEq)]
+pub struct Market {
+    pub params: Params,
+    /// Block of the last accrual
+    pub block: u64,
+    pub cash: u128,
+    pub total_borrows: u128,
+    pub total_reserves: u128,
+    pub total_supply: u128,
+    pub borrow_index: u128,
+    /// Fractions of a unit earlier accruals truncated, scaled by 10^8
+    pub interest_remainder: u128,
+    pub reserves_remainder: u128,
+    pub index_remainder: u128,
+    pub balances: BTreeMap<String, u128>,
+    pub borrows: BTreeMap<String, BorrowSnapshot>,
+}
+
+impl Market {
+    /// Empty market created at `block`
+    pub fn new(params: Params, borrow_index: u128, block: u64) -> Self {
+        Market {
+            params,
+            block,
+            cash: 0,
+            total_borrows: 0,
+            total_reserves: 0,
+            total_supply: 0,
+            borrow_index,
+            interest_remainder: 0,
+            reserves_remainder: 0,
+            index_remainder: 0,
+            balances: BTreeMap::new(),
+            borrows: BTreeMap::new(),
+        }
+    }
+
+    /// Underlying per qToken, scaled by 10^8
+    pub fn exchange_rate(&self) -> ModelResult<u128> {
+        if self.total_supply == 0 {
+            return Ok(self.params.initial_exchange_rate);
+        }
+        let net = (self.cash + self.total_borrows)
+            .checked_sub(self.total_reserves)
+            .ok_or(ModelError::NegativeExchangeRate)?;
+        Ok(mul_div(net, SCALE, self.total_supply))
+    }
+
+    pub fn balance_of(&self, account: &str) -> u128 {
+        self.balances.get(account).copied().unwrap_or(0)
+    }
+
+    /// Debt of `account` at the current borrow index
+    pub fn borrow_balance_of(&self, account: &str) -> u128 {
+        self.borrows.get(account).map_or(0, |snapshot| snapshot.balance(self.borrow_index))
+    }
+
+    /// Most underlying a holder of `tokens` qTokens may owe
+    pub fn borrow_limit(&self, tokens: u128, exchange_rate: u128) -> u128 {
+        truncate(mul_div(tokens, exchange_rate, SCALE) * self.params.collateral_factor)
+    }
+
+    /// Accrues interest up to `height`
+    pub fn accrue(&mut self, height: u64) -> ModelResult<()> {
+        self.apply(|market| market.accrue_interest(height))
+    }
+
+    /// Supplies `amount` underlying from `account`, returning the qTokens minted
+    pub fn mint(&mut self, account: &str, amount: u128, height: u64) -> ModelResult<u128> {
+        self.apply(|market| {
+            if amount == 0 {
+                return Err(ModelError::ZeroAmount);
+            }
+            market.accrue_interest(height)?;
+            let tokens = mul_div(amount, SCALE, market.exchange_rate()?);
+            market.total_supply += tokens;
+            market.cash += amount;
+            *market.balances.entry(account.to_string()).or_default() += tokens;
+            Ok(tokens)
+        })
+    }
+
+    /// Burns `tokens` qTokens of `account`, returning the underlying paid out
+    pub fn redeem(&mut self, account: &str, tokens: u128, height: u64) -> ModelResult<u128> {
+        self.apply(|market| {
+            if tokens == 0 {
+                return Err(ModelError::ZeroAmount);
+            }
+            market.accrue_interest(height)?;
+            let underlying = mul_div(tokens, market.exchange_rate()?, SCALE);
+            market.redeem_fresh(account, tokens, underlying)?;
+            Ok(underlying)
+        })
+    }
+
+    /// Pays `amount` underlying to `account` for its qTokens, returning the qTokens burned
+    /// The burn rounds up, so the redemption never takes more than the qTokens are worth
+    pub fn redeem_underlying(&mut self, account: &str, amount: u128, height: u64) -> ModelResult<u128> {
+        self.apply(|market| {
+            if amount == 0 {
+                return Err(ModelError::ZeroAmount);
+            }
+            market.accrue_interest(height)?;
+            let exchange_rate = market.exchange_rate()?;
+            let mut tokens = mul_div(amount, SCALE, exchange_rate);
+            if mul_div(tokens, exchange_rate, SCALE) < amount {
+                tokens += 1;
+            }
+            market.redeem_fresh(account, tokens, amount)?;
+            Ok(tokens)
+        })
+    }
+
+    /// Lends `amount` underlying to `account` against its qTokens
+    pub fn borrow(&mut self, account: &str, amount: u128, height: u64) -> ModelResult<()> {
+        self.apply(|market| {
+            market.accrue_interest(height)?;
+            if market.cash < amount {
+                return Err(ModelError::InsufficientCash);
+            }
+            let new_debt = market.borrow_balance_of(account) + amount;
+            if new_debt > market.borrow_limit(market.balance_of(account), market.exchange_rate()?) {
+                return Err(ModelError::InsufficientCollateral);
+            }
+            market.cash -= amount;
+            market.total_borrows += amount;
+            let snapshot = BorrowSnapshot { principal: new_debt, interest_index: market.borrow_index };
+            market.borrows.insert(account.to_string(), snapshot);
+            Ok(())
+        })
+    }
+
+    /// Repays up to `amount` of the debt of `account`, returning the part applied
+    /// Anything above the debt is refunded and never reaches cash
+    pub fn repay(&mut self, account: &str, amount: u128, height: u64) -> ModelResult<u128> {
+        self.apply(|market| {
+            if amount == 0 {
+                return Err(ModelError::ZeroAmount);
+            }
+            market.accrue_interest(height)?;
+            let debt = market.borrow_balance_of(account);
+            if debt == 0 {
+                return Err(ModelError::NothingToRepay);
+            }
+            let repaid = amount.min(debt);
+            market.cash += repaid;
+            market.total_borrows = market.total_borrows.saturating_sub(repaid);
+            let snapshot = BorrowSnapshot { principal: debt - repaid, interest_index: market.borrow_index };
+            market.borrows.insert(account.to_string(), snapshot);
+            Ok(repaid)
+        })
+    }
+
+    /// Burns `tokens` of `account` for `underlying` of cash on an accrued market
+    fn redeem_fresh(&mut self, account: &str, tokens: u128, underlying: u128) -> ModelResult<()> {
+        if self.cash < underlying {
+            return Err(ModelError::InsufficientCash);
+        }
+        if self.total_supply < tokens || self.balance_of(account) < tokens {
+            return Err(ModelError::InsufficientBalance);
+        }
+        self.cash -= underlying;
+        self.total_supply -= tokens;
+        *self.balances.entry(account.to_string()).or_default() -= tokens;
+
+        // The qTokens left must still cover the debt, valued after the redemption
+        let debt = self.borrow_balance_of(account);
+        if debt > 0 && debt > self.borrow_limit(self.balance_of(account), self.exchange_rate()?) {
+            return Err(ModelError::InsufficientCollateral);
+        }
+        Ok(())
+    }
+
+    /// Runs `action` on a copy and keeps it only when the action succeeds
+    fn apply<T>(&mut self, action: impl FnOnce(&mut Market) -> ModelResult<T>) -> ModelResult<T> {
+        let mut next = self.clone();
+        let result = action(&mut next)?;
+        *self = next;
+        Ok(result)
+    }
+
+    /// Simple interest at the rate of the utilization held since the last accrual
+    fn accrue_interest(&mut self, height: u64) -> ModelResult<()> {
+        let utilization = utilization_rate(self.cash, self.total_borrows, self.total_reserves);
+        let borrow_rate = self.params.rate_model.borrow_rate(utilization);
+        if borrow_rate > self.params.max_borrow_rate {
+            return Err(ModelError::BorrowRateTooHigh);
+        }
+        let interest_factor = borrow_rate * u128::from(height.saturating_sub(self.block));
+
+        let interest = carry(self.total_borrows * interest_factor, &mut self.interest_remainder);
+        let reserve_take = carry(interest * self.params.reserve_factor, &mut self.reserves_remainder);
+        let index_delta = carry(self.borrow_index * interest_factor, &mut self.index_remainder);
+
+        self.total_borrows += interest;
+        self.total_reserves += reserve_take;
+        self.borrow_index += index_delta;
+        self.block = height;
+        self.exchange_rate().map(|_| ())
+    }
+}
+
+/// Whole units of a value scaled by 10^8 plus the carried fraction, keeping the new fraction
+fn carry(scaled: u128, remainder: &mut u128) -> u128 {
+    let total = scaled + *remainder;
+    *remainder = total % SCALE;
+    total / SCALE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "alice";
+    const BOB: &str = "bob";
+
+    fn market() -> Market {
+        let params = Params {
+            initial_exchange_rate: SCALE,
+            reserve_factor: 10_000_000,
+            collateral_factor: 75_000_000,
+            max_borrow_rate: 5_000,
+            rate_model: RateModel::Linear { base_rate_per_block: 0, multiplier_per_block: 1_000, min_borrow_rate_per_block: 0 },
+        };
+        Market::new(params, SCALE, 100)
+    }
+
+    #[test]
+    fn accrual_carries_truncated_fractions() {
+        let mut market = market();
+        market.mint(ALICE, 1_000, 100).unwrap();
+        market.borrow(ALICE, 750, 100).unwrap();
+
+        // 75% utilization charges 750 per block per 10^8 of debt
+        market.accrue(101).unwrap();
+        assert_eq!(market.total_borrows, 750);
+        assert_eq!(market.interest_remainder, 562_500);
+        assert_eq!(market.borrow_index, SCALE + 750);
+
+        // The fractions add up to a whole unit of interest after enough blocks
+        market.accrue(279).unwrap();
+        assert_eq!(market.total_borrows, 751);
+        assert_eq!(market.total_reserves, 0);
+        assert_eq!(market.reserves_remainder, 10_000_000);
+    }
+
+    #[test]
+    fn rejected_actions_leave_the_market_unchanged() {
+        let mut market = market();
+        market.mint(ALICE, 1_000, 100).unwrap();
+        market.mint(BOB, 1_000, 100).unwrap();
+        let before = market.clone();
+
+        assert_eq!(market.borrow(ALICE, 751, 150), Err(ModelError::InsufficientCollateral));
+        assert_eq!(market.redeem(ALICE, 1_001, 150), Err(ModelError::InsufficientBalance));
+        assert_eq!(market.repay(ALICE, 10, 150), Err(ModelError::NothingToRepay));
+        assert_eq!(market, before);
+    }
+
+    #[test]
+    fn redeem_keeps_the_debt_covered() {
+        let mut market = market();
+        market.mint(ALICE, 1_000, 100).unwrap();
+        market.borrow(ALICE, 600, 100).unwrap();
+
+        assert_eq!(market.redeem(ALICE, 201, 100), Err(ModelError::InsufficientCollateral));
+        assert_eq!(market.redeem(ALICE, 200, 100), Ok(200));
+        assert_eq!(market.redeem_underlying(ALICE, 1, 100), Err(ModelError::InsufficientCollateral));
+        assert_eq!(market.repay(ALICE, 1_000, 100), Ok(600));
+        assert_eq!(market.borrow_balance_of(ALICE), 0);
+        assert_eq!(market.cash, 800);
+    }
+
+    #[test]
+    fn redeem_underlying_burns_rounded_up_tokens() {
+        let mut market = market();
+        market.params.initial_exchange_rate = 150_000_000;
+        market.mint(ALICE, 150, 100).unwrap();
+
+        assert_eq!(market.redeem_underlying(ALICE, 100, 100), Ok(67));
+        assert_eq!(market.balance_of(ALICE), 33);
+        assert_eq!(market.cash, 50);
+        assert_eq!(market.redeem_underlying(ALICE, 51, 100), Err(ModelError::InsufficientCash));
+    }
+}
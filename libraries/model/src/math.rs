@@ -0,0 +1,70 @@
+/// Fixed point scale of rates, factors and exchange rates, 10^8
+pub const SCALE: u128 = 100_000_000;
+
+/// A value multiplied by a scaled factor, back in whole units
+pub fn truncate(a: u128) -> u128 {
+    a / SCALE
+}
+
+/// a * b / c with the product taken in 256 bits, truncated
+/// Panics on a zero divisor or a quotient above u128, as the market does
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    assert!(c != 0, "mul_div by zero");
+    let (hi, lo) = widening_mul(a, b);
+    let mut quotient = 0u128;
+    let mut remainder = 0u128;
+    for bit in (0..256).rev() {
+        let next = match bit >= 128 {
+            true => (hi >> (bit - 128)) & 1,
+            false => (lo >> bit) & 1,
+        };
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | next;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            assert!(bit < 128, "mul_div overflow");
+            quotient |= 1 << bit;
+        }
+    }
+    quotient
+}
+
+/// High and low halves of the 256 bit product
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & MASK);
+    let (b_hi, b_lo) = (b >> 64, b & MASK);
+    let lo_lo = a_lo * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_lo = a_hi * b_lo;
+    let hi_hi = a_hi * b_hi;
+    let middle = (lo_lo >> 64) + (lo_hi & MASK) + (hi_lo & MASK);
+    let lo = (lo_lo & MASK) | ((middle & MASK) << 64);
+    let hi = hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (middle >> 64);
+    (hi, lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_matches_narrow_products() {
+        assert_eq!(mul_div(7, 3, 2), 10);
+        assert_eq!(mul_div(1_000_000, SCALE, 3 * SCALE), 333_333);
+        assert_eq!(mul_div(0, u128::MAX, 1), 0);
+    }
+
+    #[test]
+    fn mul_div_keeps_products_above_u128() {
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        assert_eq!(mul_div(u128::MAX, SCALE, 2 * SCALE), u128::MAX / 2);
+        assert_eq!(mul_div(1 << 100, 1 << 100, 1 << 120), 1 << 80);
+    }
+
+    #[test]
+    #[should_panic(expected = "mul_div overflow")]
+    fn mul_div_rejects_quotients_above_u128() {
+        mul_div(u128::MAX, 2, 1);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::math::{mul_div, truncate, SCALE};
+
+/// Borrow rate curve per block, in the units of the market's InterestRateModel
+/// A missing floor is a `min_borrow_rate_per_block` of 0
+#[derive(Clone, Debug, PartialEq)]
+pub enum RateModel {
+    JumpRate {
+        base_rate_per_block: u128,
+        multiplier_per_block: u128,
+        jump_multiplier_per_block: u128,
+        kink: u128,
+        min_borrow_rate_per_block: u128,
+    },
+    Linear {
+        base_rate_per_block: u128,
+        multiplier_per_block: u128,
+        min_borrow_rate_per_block: u128,
+    },
+}
+
+impl RateModel {
+    /// Borrow rate per block at `utilization`, never below the floor
+    pub fn borrow_rate(&self, utilization: u128) -> u128 {
+        let (curve_rate, floor) = match *self {
+            RateModel::JumpRate {
+                base_rate_per_block,
+                multiplier_per_block,
+                jump_multiplier_per_block,
+                kink,
+                min_borrow_rate_per_block,
+            } => {
+                let rate = match utilization <= kink {
+                    true => truncate(utilization * multiplier_per_block) + base_rate_per_block,
+                    false => {
+                        truncate(kink * multiplier_per_block)
+                            + base_rate_per_block
+                            + truncate((utilization - kink) * jump_multiplier_per_block)
+                    }
+                };
+                (rate, min_borrow_rate_per_block)
+            }
+            RateModel::Linear { base_rate_per_block, multiplier_per_block, min_borrow_rate_per_block } => {
+                (truncate(utilization * multiplier_per_block) + base_rate_per_block, min_borrow_rate_per_block)
+            }
+        };
+        curve_rate.max(floor)
+    }
+}
+
+/// Share of supplied underlying that is borrowed, scaled by 10^8
+pub fn utilization_rate(cash: u128, borrows: u128, reserves: u128) -> u128 {
+    let supplied = (cash + borrows).saturating_sub(reserves);
+    if borrows == 0 || supplied == 0 {
+        return 0;
+    }
+    mul_div(borrows, SCALE, supplied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jump_rate_bends_at_the_kink() {
+        let model = RateModel::JumpRate {
+            base_rate_per_block: 10,
+            multiplier_per_block: 1_000,
+            jump_multiplier_per_block: 10_000,
+            kink: 80_000_000,
+            min_borrow_rate_per_block: 0,
+        };
+        assert_eq!(model.borrow_rate(0), 10);
+        assert_eq!(model.borrow_rate(80_000_000), 810);
+        assert_eq!(model.borrow_rate(90_000_000), 1_810);
+    }
+
+    #[test]
+    fn floor_applies_below_the_curve() {
+        let model = RateModel::Linear { base_rate_per_block: 0, multiplier_per_block: 1_000, min_borrow_rate_per_block: 50 };
+        assert_eq!(model.borrow_rate(0), 50);
+        assert_eq!(model.borrow_rate(SCALE), 1_000);
+    }
+
+    #[test]
+    fn utilization_ignores_reserves_above_supply() {
+        assert_eq!(utilization_rate(100, 0, 0), 0);
+        assert_eq!(utilization_rate(50, 50, 0), 50_000_000);
+        assert_eq!(utilization_rate(0, 10, 20), 0);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+
+use cosmwasm_std::{Api, CanonicalAddr, StdError, StdResult};
+
+use q_native::msg::{ExportGenesisResponse, GenesisChunk};
+
+use crate::replay::ReplayState;
+
+/// A field the replay and the snapshot disagree on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    pub field: String,
+    pub replayed: String,
+    pub exported: String,
+}
+
+/// Reads a JSON array of ExportGenesis responses, in the order they were queried
+pub fn parse_snapshot(json: &[u8]) -> StdResult<Vec<ExportGenesisResponse>> {
+    serde_json::from_slice(json).map_err(|err| StdError::parse_err("Vec<ExportGenesisResponse>", err.to_string()))
+}
+
+/// State held in the chunks of one export, with addresses humanized by `api`
+/// Allowances are not replayed and are skipped
+pub fn snapshot_state<A: Api>(api: &A, chunks: &[ExportGenesisResponse]) -> StdResult<ReplayState> {
+    let mut snapshot = ReplayState::default();
+    for response in chunks {
+        match &response.chunk {
+            GenesisChunk::Market { config, state } => {
+                snapshot.total_supply = config.total_supply.u128();
+                snapshot.total_borrows = state.total_borrows.u128();
+                snapshot.total_reserves = state.total_reserves.u128();
+                snapshot.cash = state.cash.u128();
+                snapshot.borrow_index = state.borrow_index.u128();
+                snapshot.last_height = state.block_number;
+            }
+            GenesisChunk::Balances { balances } => {
+                for entry in balances.iter() {
+                    let address = api.human_address(&CanonicalAddr::from(entry.address.as_slice()))?;
+                    snapshot.balances.insert(address.to_string(), entry.balance.u128());
+                }
+            }
+            GenesisChunk::Borrows { borrows } => {
+                for entry in borrows.iter() {
+                    let address = api.human_address(&CanonicalAddr::from(entry.address.as_slice()))?;
+                    snapshot.borrows.insert(address.to_string(), entry.snapshot.clone());
+                }
+            }
+            GenesisChunk::Allowances { .. } => {}
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Every field `replayed` and `exported` disagree on, empty if they match
+/// Accounts missing on one side count as holding zero
+pub fn diff(replayed: &ReplayState, exported: &ReplayState) -> Vec<Mismatch> {
+    let mut mismatches = vec![];
+    compare(&mut mismatches, "total_supply", &replayed.total_supply, &exported.total_supply);
+    compare(&mut mismatches, "total_borrows", &replayed.total_borrows, &exported.total_borrows);
+    compare(&mut mismatches, "total_reserves", &replayed.total_reserves, &exported.total_reserves);
+    compare(&mut mismatches, "cash", &replayed.cash, &exported.cash);
+    compare(&mut mismatches, "borrow_index", &replayed.borrow_index, &exported.borrow_index);
+
+    let accounts: BTreeSet<&String> = replayed.balances.keys().chain(exported.balances.keys()).collect();
+    for account in accounts {
+        compare(
+            &mut mismatches,
+            &format!("balance.{}", account),
+            &replayed.balances.get(account).copied().unwrap_or(0),
+            &exported.balances.get(account).copied().unwrap_or(0),
+        );
+    }
+
+    let borrowers: BTreeSet<&String> = replayed.borrows.keys().chain(exported.borrows.keys()).collect();
+    for borrower in borrowers {
+        let replayed_borrow = replayed.borrows.get(borrower).filter(|snapshot| !snapshot.principal.is_zero());
+        let exported_borrow = exported.borrows.get(borrower).filter(|snapshot| !snapshot.principal.is_zero());
+        compare(&mut mismatches, &format!("borrow.{}", borrower), &replayed_borrow, &exported_borrow);
+    }
+    mismatches
+}
+
+fn compare<T: PartialEq + Debug>(mismatches: &mut Vec<Mismatch>, field: &str, replayed: &T, exported: &T) {
+    if replayed != exported {
+        mismatches.push(Mismatch {
+            field: field.to_string(),
+            replayed: format!("{:?}", replayed),
+            exported: format!("{:?}", exported),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::parse_events;
+
+    const EVENTS: &[u8] = br#"[
+        {"height": 10, "attributes": [
+            {"key": "action", "value": "mint"}, {"key": "recipient", "value": "alice"},
+            {"key": "minted_amount", "value": "1000"}, {"key": "amount", "value": "1000"}]},
+        {"height": 11, "attributes": [
+            {"key": "action", "value": "transfer"}, {"key": "sender", "value": "alice"},
+            {"key": "recipient", "value": "bob"}, {"key": "amount", "value": "1000"}]},
+        {"height": 12, "attributes": [
+            {"key": "accrual_borrow_index_delta", "value": "5"}, {"key": "accrual_interest", "value": "0"},
+            {"key": "accrual_reserve_take", "value": "0"},
+            {"key": "action", "value": "borrow"}, {"key": "sender", "value": "bob"},
+            {"key": "new_account_borrow", "value": "400"}, {"key": "new_total_borrows", "value": "400"},
+            {"key": "amount", "value": "400"}]}
+    ]"#;
+
+    fn replayed() -> ReplayState {
+        let mut state = ReplayState::new(0, 100);
+        state.replay(&parse_events(EVENTS).unwrap()).unwrap();
+        state
+    }
+
+    #[test]
+    fn replay_matching_the_snapshot_has_no_mismatches() {
+        let state = replayed();
+        // Alice transferred everything away, a snapshot holding her at zero still matches
+        let mut exported = state.clone();
+        exported.balances.insert("alice".to_string(), 0);
+        assert_eq!(diff(&state, &exported), vec![]);
+        assert_eq!(state.balances.get("bob"), Some(&1_000));
+        assert_eq!(state.cash, 600);
+        assert_eq!(state.borrows["bob"].interest_index.u128(), 105);
+    }
+
+    #[test]
+    fn every_disagreeing_field_is_reported() {
+        let state = replayed();
+        let mut exported = state.clone();
+        exported.cash = 601;
+        exported.balances.insert("carol".to_string(), 1);
+        exported.borrows.remove("bob");
+        assert_eq!(diff(&state, &exported), vec![
+            Mismatch { field: "cash".to_string(), replayed: "600".to_string(), exported: "601".to_string() },
+            Mismatch { field: "balance.carol".to_string(), replayed: "0".to_string(), exported: "1".to_string() },
+            Mismatch {
+                field: "borrow.bob".to_string(),
+                replayed: format!("{:?}", state.borrows.get("bob")),
+                exported: "None".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn repaid_borrows_match_missing_ones() {
+        let mut state = replayed();
+        let repay = br#"[{"height": 13, "attributes": [
+            {"key": "action", "value": "repay_borrow"}, {"key": "sender", "value": "bob"},
+            {"key": "repay_amount", "value": "400"}, {"key": "new_account_borrow", "value": "0"}]}]"#;
+        state.replay(&parse_events(repay).unwrap()).unwrap();
+        let mut exported = state.clone();
+        exported.borrows.remove("bob");
+        assert_eq!(diff(&state, &exported), vec![]);
+    }
+}
@@ -0,0 +1,54 @@
+use cosmwasm_std::{LogAttribute, StdError, StdResult};
+use serde::{Deserialize, Serialize};
+
+/// Logs of one handler run, in the order the handler emitted them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReplayEvent {
+    pub height: u64,
+    pub attributes: Vec<LogAttribute>,
+}
+
+impl ReplayEvent {
+    /// First value logged under `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.key == key)
+            .map(|attribute| attribute.value.as_str())
+    }
+
+    pub fn get_str(&self, key: &str) -> StdResult<&str> {
+        match self.get(key) {
+            Some(value) => Ok(value),
+            None => Err(StdError::generic_err(format!(
+                "Event is missing an attribute: height: {}, key: {}",
+                self.height, key
+            ))),
+        }
+    }
+
+    pub fn get_u128(&self, key: &str) -> StdResult<u128> {
+        parse_u128(self.height, key, self.get_str(key)?)
+    }
+
+    pub fn may_get_u128(&self, key: &str) -> StdResult<Option<u128>> {
+        match self.get(key) {
+            Some(value) => Ok(Some(parse_u128(self.height, key, value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse_u128(height: u64, key: &str, value: &str) -> StdResult<u128> {
+    value.parse::<u128>().map_err(|_| {
+        StdError::generic_err(format!(
+            "Attribute is not an integer: height: {}, key: {}, value: {}",
+            height, key, value
+        ))
+    })
+}
+
+/// Reads a JSON array of events, e.g. `[{"height": 1, "attributes": [{"key": "action", "value": "mint"}]}]`
+pub fn parse_events(json: &[u8]) -> StdResult<Vec<ReplayEvent>> {
+    serde_json::from_slice(json).map_err(|err| StdError::parse_err("Vec<ReplayEvent>", err.to_string()))
+}
@@ -0,0 +1,14 @@
+//! Rebuilds the state of a q_native market from the logs its handlers emitted
+//! and diffs it against an ExportGenesis snapshot of the same market
+//!
+//! Events are replayed in order onto a starting state, either an earlier
+//! snapshot or an empty market, and the result is compared field by field
+//! with the snapshot taken at the last replayed height
+
+pub mod diff;
+pub mod event;
+pub mod replay;
+
+pub use crate::diff::{diff, parse_snapshot, snapshot_state, Mismatch};
+pub use crate::event::{parse_events, ReplayEvent};
+pub use crate::replay::ReplayState;
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{StdError, StdResult, Uint128};
+
+use q_native::state::BorrowSnapshot;
+
+use crate::event::ReplayEvent;
+
+/// Market state as far as the event log describes it, keyed by human address
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplayState {
+    pub balances: BTreeMap<String, u128>,
+    pub borrows: BTreeMap<String, BorrowSnapshot>,
+    pub total_supply: u128,
+    pub total_borrows: u128,
+    pub total_reserves: u128,
+    pub cash: u128,
+    pub borrow_index: u128,
+    /// Actions that changed state the replay does not model, with their counts
+    /// A diff is only conclusive while this is empty
+    pub unreplayed: BTreeMap<String, u64>,
+    pub last_height: u64,
+}
+
+impl ReplayState {
+    /// A freshly instantiated market
+    pub fn new(total_supply: u128, borrow_index: u128) -> Self {
+        ReplayState {
+            total_supply,
+            borrow_index,
+            ..ReplayState::default()
+        }
+    }
+
+    pub fn replay(&mut self, events: &[ReplayEvent]) -> StdResult<()> {
+        for event in events {
+            self.apply(event)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the logs of one handler run
+    /// Premiums settled inside borrow, repay and liquidate are not logged, so
+    /// markets with insured borrowers report them as unreplayed set_insured
+    pub fn apply(&mut self, event: &ReplayEvent) -> StdResult<()> {
+        if event.height < self.last_height {
+            return Err(StdError::generic_err(format!(
+                "Events are out of order: height: {}, last_height: {}",
+                event.height, self.last_height
+            )));
+        }
+        self.last_height = event.height;
+
+        // The accrual ran before the action, whatever the order of the logs
+        if let Some(index_delta) = event.may_get_u128("accrual_borrow_index_delta")? {
+            self.borrow_index += index_delta;
+            self.total_borrows += event.get_u128("accrual_interest")?;
+            self.total_reserves += event.get_u128("accrual_reserve_take")?;
+        }
+        // Logged by market_log once the action applied
+        if let Some(borrow_index) = event.may_get_u128("borrow_index")? {
+            self.borrow_index = borrow_index;
+        }
+
+        let action = match event.get("action") {
+            Some(action) => action,
+            None => return Ok(()),
+        };
+        match action {
+            "mint" => {
+                let minted = event.get_u128("minted_amount")?;
+                self.credit(event.get_str("recipient")?, minted);
+                self.total_supply += minted;
+                self.cash += event.get_u128("amount")?;
+            }
+            "redeem" => {
+                let redeem_tokens = event.get_u128("redeem_tokens")?;
+                self.debit(event.get_str("sender")?, redeem_tokens)?;
+                self.total_supply = checked_sub("total_supply", self.total_supply, redeem_tokens)?;
+                self.cash = checked_sub("cash", self.cash, event.get_u128("redeem_native")?)?;
+            }
+            "transfer" | "transfer_from" | "send" | "send_from" => {
+                let amount = event.get_u128("amount")?;
+                self.debit(event.get_str("sender")?, amount)?;
+                self.credit(event.get_str("recipient")?, amount);
+            }
+            "settle_yield_redirect" => {
+                let amount = event.get_u128("donated_tokens")?;
+                if amount > 0 {
+                    self.debit(event.get_str("account")?, amount)?;
+                    self.credit(event.get_str("beneficiary")?, amount);
+                }
+            }
+            "burn" | "burn_from" => {
+                let amount = event.get_u128("amount")?;
+                self.debit(event.get_str("from")?, amount)?;
+                self.total_supply = checked_sub("total_supply", self.total_supply, amount)?;
+            }
+            "borrow" => {
+                self.set_borrow(event.get_str("sender")?, event.get_u128("new_account_borrow")?);
+                self.total_borrows = event.get_u128("new_total_borrows")?;
+                self.cash = checked_sub("cash", self.cash, event.get_u128("amount")?)?;
+            }
+            "repay_borrow" => {
+                let repay_amount = event.get_u128("repay_amount")?;
+                self.set_borrow(event.get_str("sender")?, event.get_u128("new_account_borrow")?);
+                self.total_borrows = self.total_borrows.saturating_sub(repay_amount);
+                self.cash += repay_amount;
+            }
+            "liquidate" => {
+                let borrower = event.get_str("borrower")?;
+                let repay_amount = event.get_u128("repay_amount")?;
+                let refund = event.may_get_u128("insurance_refund")?.unwrap_or(0);
+                self.set_borrow(borrower, event.get_u128("new_account_borrow")?);
+                self.total_borrows = self.total_borrows.saturating_sub(repay_amount);
+                self.total_reserves = checked_sub(
+                    "total_reserves",
+                    self.total_reserves + event.get_u128("protocol_seize_amount")?,
+                    refund,
+                )?;
+                self.cash = checked_sub("cash", self.cash + repay_amount, refund)?;
+
+                let protocol_seize_tokens = event.get_u128("protocol_seize_tokens")?;
+                self.debit(borrower, event.get_u128("seize_tokens")?)?;
+                self.credit(event.get_str("sender")?, event.get_u128("liquidator_seize_tokens")?);
+                self.total_supply = checked_sub("total_supply", self.total_supply, protocol_seize_tokens)?;
+            }
+            // The debt side of the liquidation was repaid in another market
+            "seize_collateral" => {
+                let protocol_seize_tokens = event.get_u128("protocol_seize_tokens")?;
+                self.debit(event.get_str("borrower")?, event.get_u128("seize_tokens")?)?;
+                self.credit(event.get_str("liquidator")?, event.get_u128("liquidator_seize_tokens")?);
+                self.total_supply = checked_sub("total_supply", self.total_supply, protocol_seize_tokens)?;
+                self.total_reserves += event.get_u128("protocol_seize_amount")?;
+            }
+            "close_dust_borrow" => {
+                self.borrows.remove(event.get_str("account")?);
+                self.total_borrows = self.total_borrows.saturating_sub(event.get_u128("written_off")?);
+                self.total_reserves = event.get_u128("new_total_reserves")?;
+            }
+            "add_reserves" => {
+                self.cash += event.get_u128("amount")?;
+                self.total_reserves = event.get_u128("new_total_reserves")?;
+            }
+            "reduce_reserves" => {
+                self.cash = checked_sub("cash", self.cash, event.get_u128("amount")?)?;
+                self.total_reserves = event.get_u128("new_total_reserves")?;
+            }
+            "buyback" => {
+                self.cash = checked_sub("cash", self.cash, event.get_u128("amount")?)?;
+                self.total_reserves = event.get_u128("new_total_reserves")?;
+            }
+            "skim" => {
+                self.cash += event.get_u128("skimmed_amount")?;
+                self.total_reserves = event.get_u128("new_total_reserves")?;
+            }
+            "finish_flash_loan" => {
+                self.cash += event.get_u128("fee")?;
+                self.total_reserves += event.get_u128("reserve_fee")?;
+            }
+            // Move qTokens into term deposits or OTC escrow, settle yield or premiums, replace
+            // the market wholesale or settle it, none of which the logs fully describe
+            "lock_deposit" | "unlock_deposit" | "list_otc" | "buy_otc" | "cancel_otc" | "settle_self_repay"
+            | "set_insured" | "settle_premium" | "import_genesis" | "enter_settlement" | "claim_settlement" => {
+                *self.unreplayed.entry(action.to_string()).or_insert(0) += 1;
+            }
+            // Everything else leaves the replayed fields alone, the accrual aside
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn credit(&mut self, address: &str, amount: u128) {
+        *self.balances.entry(address.to_string()).or_insert(0) += amount;
+    }
+
+    fn debit(&mut self, address: &str, amount: u128) -> StdResult<()> {
+        let balance = self.balances.get(address).copied().unwrap_or(0);
+        let balance = checked_sub(address, balance, amount)?;
+        match balance {
+            0 => self.balances.remove(address),
+            _ => self.balances.insert(address.to_string(), balance),
+        };
+        Ok(())
+    }
+
+    /// Borrow snapshots are rewritten at the current index, as the market does
+    fn set_borrow(&mut self, address: &str, principal: u128) {
+        self.borrows.insert(address.to_string(), BorrowSnapshot {
+            principal: Uint128::from(principal),
+            interest_index: Uint128::from(self.borrow_index),
+        });
+    }
+}
+
+fn checked_sub(field: &str, a: u128, b: u128) -> StdResult<u128> {
+    a.checked_sub(b).ok_or_else(|| {
+        StdError::generic_err(format!(
+            "Replay underflow: field: {}, value: {}, subtracted: {}",
+            field, a, b
+        ))
+    })
+}